@@ -0,0 +1,108 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{FORMATS, SEARCHES};
+use harail::HaError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Persistent defaults, read from config.toml in the harail directory of the user's configuration directory
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The database to use when none is given on the command line
+    database: Option<String>,
+    /// Station names by alias, e.g. home and work. The home station is where routes start by default.
+    stations: HashMap<String, String>,
+    /// The output format to use when --format isn't given
+    format: Option<String>,
+    /// The kind of route search to perform when find is given none, one of best, multiple, pareto or delayed-leave
+    search: Option<String>,
+}
+
+/// The user's home directory, used to expand ~ in paths
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+impl Config {
+    /// Where the configuration file is looked for, following the XDG base directory specification
+    pub fn path() -> Option<PathBuf> {
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".config")))?;
+        Some(dir.join("harail").join("config.toml"))
+    }
+
+    /// Reads the configuration file, or returns an empty configuration if there is none
+    pub fn load() -> Result<Self, HaError> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(_) => {
+                return Err(HaError::UsageError(format!(
+                    "Could not read configuration file {}",
+                    path.display()
+                )))
+            }
+        };
+        let config: Config = toml::from_str(&contents).map_err(|e| {
+            HaError::UsageError(format!(
+                "Could not parse configuration file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        if let Some(format) = config.format.as_deref().filter(|f| !FORMATS.contains(f)) {
+            return Err(HaError::UsageError(format!(
+                "Unknown format {} in configuration file",
+                format
+            )));
+        }
+        if let Some(search) = config.search.as_deref().filter(|s| !SEARCHES.contains(s)) {
+            return Err(HaError::UsageError(format!(
+                "Unknown search {} in configuration file",
+                search
+            )));
+        }
+        Ok(config)
+    }
+
+    /// The default database path, with a leading ~ standing for the home directory
+    pub fn database(&self) -> Option<PathBuf> {
+        let database = self.database.as_deref()?;
+        match (database.strip_prefix("~/"), home_dir()) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(database)),
+        }
+    }
+
+    /// The name of the station the given alias stands for, or the given name if it isn't an alias
+    pub fn station<'a>(&'a self, name: &'a str) -> &'a str {
+        self.stations.get(name).map_or(name, String::as_str)
+    }
+
+    /// The name of the home station, where routes start when find is given only a destination
+    pub fn home(&self) -> Option<&str> {
+        self.stations.get("home").map(String::as_str)
+    }
+
+    pub fn format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+
+    pub fn search(&self) -> Option<&str> {
+        self.search.as_deref()
+    }
+}
@@ -0,0 +1,110 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::export::gtfs_time;
+use crate::fields::{self, Field};
+use crate::station_name;
+use chrono_tz::Tz;
+use harail::{RailroadData, Route, Station, Train};
+use std::error::Error;
+use std::io::Write;
+
+/// Writes one row per train ride, numbering the routes from 1 so that the rides of each route can be told apart, with
+/// times in the given timezone
+pub fn write_routes<W: Write>(
+    output: W,
+    routes: &[Route],
+    timezone: Tz,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record([
+        "route",
+        "train_id",
+        "train_number",
+        "from",
+        "depart",
+        "to",
+        "arrive",
+    ])?;
+    for (i, route) in routes.iter().enumerate() {
+        for part in route.parts() {
+            writer.write_record([
+                (i + 1).to_string(),
+                part.train().id().to_owned(),
+                part.train().short_name().unwrap_or_default().to_owned(),
+                part.start().station().name().to_owned(),
+                harail::localize_in(part.start().departure(), timezone)
+                    .naive_local()
+                    .to_string(),
+                part.end().station().name().to_owned(),
+                harail::localize_in(part.end().arrival(), timezone)
+                    .naive_local()
+                    .to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one row per route with the selected fields, named by the header row, with times in the given timezone
+pub fn write_route_fields<W: Write>(
+    output: W,
+    routes: &[Route],
+    fields: &[Field],
+    timezone: Tz,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(fields.iter().map(Field::name))?;
+    for route in routes {
+        writer.write_record(fields::route_to_cells(route, fields, timezone))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_stations<W: Write>(output: W, stations: &[&Station]) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(["station_id", "station_name", "lat", "lon"])?;
+    for station in stations {
+        writer.write_record([
+            station.id().to_string(),
+            station.name().to_owned(),
+            station
+                .lat()
+                .map_or_else(String::new, |lat| lat.to_string()),
+            station
+                .lon()
+                .map_or_else(String::new, |lon| lon.to_string()),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one row per train, with the times of its first departure and last arrival given like GTFS does
+pub fn write_trains<W: Write>(
+    output: W,
+    data: &RailroadData,
+    trains: &[&Train],
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(["train_id", "train_number", "from", "depart", "to", "arrive"])?;
+    for train in trains {
+        let first = train.stops().next();
+        let last = train.stops().last();
+        writer.write_record([
+            train.id().to_owned(),
+            train.short_name().unwrap_or_default().to_owned(),
+            first.map_or_else(String::new, |s| station_name(data, s.station())),
+            first.map_or_else(String::new, |s| gtfs_time(s.departure_offset())),
+            last.map_or_else(String::new, |s| station_name(data, s.station())),
+            last.map_or_else(String::new, |s| gtfs_time(s.arrival_offset())),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::error::Error;
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use tempfile::TempPath;
+
+/// Whether a path given on the command line is a URL to download rather than a local file
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// A downloaded file, which is deleted when dropped
+pub struct Download(TempPath);
+
+impl Download {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+/// Downloads a file into a newly created temporary file, reporting progress on stderr when it is a terminal
+pub fn download(url: &str) -> Result<Download, Box<dyn Error>> {
+    // GTFS feeds are large, so downloads may take longer than reqwest's default timeout
+    let client = reqwest::blocking::Client::builder().timeout(None).build()?;
+    let mut response = client.get(url).send()?.error_for_status()?;
+    let total = response.content_length();
+    // The file is newly created under a random name, so links planted in the shared temporary directory are never followed
+    let (file, path) = tempfile::Builder::new()
+        .prefix("harail-gtfs-")
+        .tempfile()?
+        .into_parts();
+    let mut file = BufWriter::new(file);
+    let progress = io::stderr().is_terminal();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut done = 0u64;
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])?;
+        done += read as u64;
+        if progress {
+            report(done, total);
+        }
+    }
+    file.flush()?;
+    if progress {
+        eprintln!();
+    }
+    Ok(Download(path))
+}
+
+fn report(done: u64, total: Option<u64>) {
+    const MB: f64 = 1024.0 * 1024.0;
+    match total {
+        Some(total) if total > 0 => eprint!(
+            "\rDownloading GTFS: {:.1}/{:.1} MB ({}%)",
+            done as f64 / MB,
+            total as f64 / MB,
+            done * 100 / total
+        ),
+        _ => eprint!("\rDownloading GTFS: {:.1} MB", done as f64 / MB),
+    }
+}
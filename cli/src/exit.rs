@@ -0,0 +1,70 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The exit codes of the CLI, which are documented in the README so that scripts can tell failures apart.
+
+use harail::HaError;
+use std::error::Error;
+use std::fmt;
+
+/// Any failure not covered by a more specific code, as well as validate finding problems
+pub const FAILURE: u8 = 1;
+/// Bad arguments or configuration; clap exits with this code for arguments it rejects itself
+pub const USAGE: u8 = 2;
+pub const STATION_NOT_FOUND: u8 = 3;
+pub const NO_ROUTE: u8 = 4;
+/// The database is missing, unreadable or empty, or the GTFS feed given to parse-gtfs could not be loaded
+pub const BAD_DATABASE: u8 = 5;
+pub const TRAIN_NOT_FOUND: u8 = 6;
+
+/// A failure which scripts may want to handle differently from others
+#[derive(Debug)]
+pub enum Failure {
+    StationNotFound(String),
+    /// A station name matching several stations, along with the names of the best matches
+    AmbiguousStation(String, Vec<String>),
+    NoRoute,
+    BadDatabase(String),
+    TrainNotFound(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::StationNotFound(name) => write!(f, "Could not find station {}", name),
+            Failure::AmbiguousStation(name, candidates) => write!(
+                f,
+                "Station {} is ambiguous, did you mean one of: {}?",
+                name,
+                candidates.join(", ")
+            ),
+            Failure::NoRoute => write!(f, "No such route"),
+            Failure::BadDatabase(message) => write!(f, "{}", message),
+            Failure::TrainNotFound(train) => write!(f, "Could not find train {}", train),
+        }
+    }
+}
+
+impl Error for Failure {}
+
+/// The exit code for an error returned by the CLI
+pub fn code(error: &(dyn Error + 'static)) -> u8 {
+    if let Some(failure) = error.downcast_ref::<Failure>() {
+        return match failure {
+            Failure::StationNotFound(_) | Failure::AmbiguousStation(..) => STATION_NOT_FOUND,
+            Failure::NoRoute => NO_ROUTE,
+            Failure::BadDatabase(_) => BAD_DATABASE,
+            Failure::TrainNotFound(_) => TRAIN_NOT_FOUND,
+        };
+    }
+    match error.downcast_ref::<HaError>() {
+        Some(HaError::UsageError(_)) => USAGE,
+        Some(HaError::DatabaseTooOld | HaError::DatabaseTooNew(_) | HaError::DatabaseError(_)) => {
+            BAD_DATABASE
+        }
+        _ => FAILURE,
+    }
+}
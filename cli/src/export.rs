@@ -0,0 +1,126 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use harail::{HaDuration, RailroadData, Station, Train, JSON};
+use jzon::{object, JsonValue};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Formats an offset from the start of the service day like GTFS does, e.g. 25:10:00 for 1:10 the next day
+pub fn gtfs_time(offset: HaDuration) -> String {
+    let seconds = offset.to_chrono().num_seconds();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
+fn export_csv(dir: &Path, stations: &[&Station], trains: &[&Train]) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(dir.join("stations.csv"))?;
+    writer.write_record(["station_id", "station_name", "lat", "lon"])?;
+    for station in stations {
+        writer.write_record([
+            station.id().to_string(),
+            station.name().to_owned(),
+            station
+                .lat()
+                .map_or_else(String::new, |lat| lat.to_string()),
+            station
+                .lon()
+                .map_or_else(String::new, |lon| lon.to_string()),
+        ])?;
+    }
+    writer.flush()?;
+
+    let mut writer = csv::Writer::from_path(dir.join("trains.csv"))?;
+    writer.write_record(["train_id", "shape_id", "dates"])?;
+    for train in trains {
+        let dates: Vec<_> = train.dates().map(|d| d.to_string()).collect();
+        writer.write_record([
+            train.id().as_str(),
+            train.shape().map_or("", String::as_str),
+            dates.join(" ").as_str(),
+        ])?;
+    }
+    writer.flush()?;
+
+    let mut writer = csv::Writer::from_path(dir.join("stop_times.csv"))?;
+    writer.write_record([
+        "train_id",
+        "stop_sequence",
+        "station_id",
+        "arrival_time",
+        "departure_time",
+    ])?;
+    for train in trains {
+        for (i, stop) in train.stops().enumerate() {
+            writer.write_record([
+                train.id().to_owned(),
+                (i + 1).to_string(),
+                stop.station().to_string(),
+                gtfs_time(stop.arrival_offset()),
+                gtfs_time(stop.departure_offset()),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn export_json(dir: &Path, stations: &[&Station], trains: &[&Train]) -> Result<(), Box<dyn Error>> {
+    let json = JsonValue::Array(stations.iter().map(|s| s.to_json()).collect());
+    fs::write(dir.join("stations.json"), json.dump())?;
+
+    let json = JsonValue::Array(
+        trains
+            .iter()
+            .map(|t| {
+                object! {
+                    id: t.id().as_str(),
+                    shape: t.shape().map(String::as_str),
+                    dates: t.dates().map(|d| d.to_string()).collect::<Vec<_>>(),
+                }
+            })
+            .collect(),
+    );
+    fs::write(dir.join("trains.json"), json.dump())?;
+
+    let json = JsonValue::Array(
+        trains
+            .iter()
+            .flat_map(|t| {
+                t.stops().enumerate().map(move |(i, stop)| {
+                    object! {
+                        train: t.id().as_str(),
+                        sequence: i + 1,
+                        station: stop.station(),
+                        arrival: gtfs_time(stop.arrival_offset()),
+                        departure: gtfs_time(stop.departure_offset()),
+                    }
+                })
+            })
+            .collect(),
+    );
+    fs::write(dir.join("stop_times.json"), json.dump())?;
+    Ok(())
+}
+
+/// Writes the database's stations, trains and stop times to files in the given directory, as CSV or JSON
+pub fn export(data: &RailroadData, dir: &Path, json: bool) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let mut stations: Vec<_> = data.stations().collect();
+    stations.sort_by_key(|s| s.id());
+    let mut trains: Vec<_> = data.trains().collect();
+    trains.sort_by_key(|t| t.id());
+    if json {
+        export_json(dir, &stations, &trains)
+    } else {
+        export_csv(dir, &stations, &trains)
+    }
+}
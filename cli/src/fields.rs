@@ -0,0 +1,145 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use chrono::{Duration, NaiveDateTime};
+use chrono_tz::Tz;
+use harail::{HaError, Route};
+use jzon::JsonValue;
+
+/// A column which can be selected for output with --fields
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Field {
+    Departure,
+    Arrival,
+    Train,
+    Changes,
+    Duration,
+}
+
+impl Field {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Departure => "departure",
+            Field::Arrival => "arrival",
+            Field::Train => "train",
+            Field::Changes => "changes",
+            Field::Duration => "duration",
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Self, HaError> {
+        match name.trim() {
+            "departure" => Ok(Field::Departure),
+            "arrival" => Ok(Field::Arrival),
+            "train" => Ok(Field::Train),
+            "changes" => Ok(Field::Changes),
+            "duration" => Ok(Field::Duration),
+            other => Err(HaError::UsageError(format!("Unknown field {}", other))),
+        }
+    }
+
+    /// The header of the field's column in tables
+    pub fn title(&self) -> &'static str {
+        match self {
+            Field::Departure => "Departure",
+            Field::Arrival => "Arrival",
+            Field::Train => "Train",
+            Field::Changes => "Changes",
+            Field::Duration => "Duration",
+        }
+    }
+
+    /// Parses a comma separated list of field names
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, HaError> {
+        list.split(',').map(Self::from_name).collect()
+    }
+}
+
+fn departure(route: &Route) -> Option<NaiveDateTime> {
+    route.parts().next().map(|p| p.start().departure())
+}
+
+fn arrival(route: &Route) -> Option<NaiveDateTime> {
+    route.parts().last().map(|p| p.end().arrival())
+}
+
+fn changes(route: &Route) -> usize {
+    route.parts().count().saturating_sub(1)
+}
+
+fn duration(route: &Route) -> Duration {
+    match (departure(route), arrival(route)) {
+        (Some(departure), Some(arrival)) => arrival - departure,
+        _ => Duration::zero(),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
+fn to_rfc3339(time: NaiveDateTime, timezone: Tz) -> String {
+    harail::localize_in(time, timezone).to_rfc3339()
+}
+
+fn to_text(time: NaiveDateTime, timezone: Tz) -> String {
+    harail::localize_in(time, timezone)
+        .naive_local()
+        .to_string()
+}
+
+/// Renders each of the selected fields of a route as text, with times in the given timezone
+pub fn route_to_cells(route: &Route, fields: &[Field], timezone: Tz) -> Vec<String> {
+    fields
+        .iter()
+        .map(|field| match field {
+            Field::Departure => departure(route).map_or_else(String::new, |t| to_text(t, timezone)),
+            Field::Arrival => arrival(route).map_or_else(String::new, |t| to_text(t, timezone)),
+            Field::Train => route
+                .parts()
+                .map(|p| p.train().id().as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            Field::Changes => changes(route).to_string(),
+            Field::Duration => format_duration(duration(route)),
+        })
+        .collect()
+}
+
+/// Renders the selected fields of a route as tab separated text, with times in the given timezone
+pub fn route_to_text(route: &Route, fields: &[Field], timezone: Tz) -> String {
+    route_to_cells(route, fields, timezone).join("\t")
+}
+
+/// Renders the selected fields of a route as a JSON object, with times in the given timezone
+pub fn route_to_json(route: &Route, fields: &[Field], timezone: Tz) -> JsonValue {
+    let mut result = JsonValue::new_object();
+    for field in fields {
+        result[field.name()] = match field {
+            Field::Departure => {
+                departure(route).map_or(JsonValue::Null, |t| to_rfc3339(t, timezone).into())
+            }
+            Field::Arrival => {
+                arrival(route).map_or(JsonValue::Null, |t| to_rfc3339(t, timezone).into())
+            }
+            Field::Train => JsonValue::Array(
+                route
+                    .parts()
+                    .map(|p| p.train().id().to_owned().into())
+                    .collect(),
+            ),
+            Field::Changes => changes(route).into(),
+            Field::Duration => duration(route).num_seconds().into(),
+        };
+    }
+    result
+}
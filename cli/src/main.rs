@@ -4,28 +4,166 @@
 * License, v. 2.0. If a copy of the MPL was not distributed with this
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use bincode::{deserialize_from, serialize_into};
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
-use clap::{Arg, Command};
-use harail::{HaError, RailroadData, JSON};
-use jzon::JsonValue;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use exit::Failure;
+use fields::Field;
+use harail::pricing::{FareTable, PricingModel, ZoneTable};
+use harail::{
+    ActionWeights, AgencyFilter, Engine, GtfsOptions, HaError, Mode, PreparedGraph, RailroadData,
+    RouteOrder, RoutingOptions, Station, StationId, Stay, Stop, Zoned, JSON,
+};
+use jzon::{object, JsonValue};
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing_subscriber::EnvFilter;
+
+mod config;
+mod csv_output;
+mod download;
+mod exit;
+mod export;
+mod fields;
+mod stats;
+mod table;
+#[cfg(test)]
+mod tests;
 
 const JSON_SPACES: u16 = 4;
+const MODES: [&str; 6] = ["light_rail", "subway", "rail", "bus", "ferry", "other"];
+const FORMATS: [&str; 3] = ["text", "csv", "table"];
+const SEARCHES: [&str; 4] = ["best", "multiple", "pareto", "delayed-leave"];
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn load_pricing(zones: &Path, fares: &Path) -> Result<PricingModel, Box<dyn Error>> {
+    let zones_file = File::open(zones)
+        .map_err(|_| HaError::UsageError("Could not open zones file".to_owned()))?;
+    let zones = ZoneTable::from_csv(&zones.display().to_string(), BufReader::new(zones_file))?;
+    let fares_file = File::open(fares)
+        .map_err(|_| HaError::UsageError("Could not open fares file".to_owned()))?;
+    let fares = FareTable::from_csv(&fares.display().to_string(), BufReader::new(fares_file))?;
+    Ok(PricingModel::new(zones, fares))
+}
+
+fn load_database(path: &Path) -> Result<RailroadData, Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|_| Failure::BadDatabase("Could not open database file".to_owned()))?;
+    Ok(RailroadData::load(BufReader::new(file))?)
+}
+
+/// The name of a station, or its ID if it isn't in the database
+pub fn station_name(data: &RailroadData, id: StationId) -> String {
+    data.station(id)
+        .map_or_else(|| id.to_string(), |s| s.name().to_owned())
+}
+
+/// The most stations suggested when a station name is ambiguous
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Finds the station with the given name, alias or ID, or else the single station whose name starts with, sounds like
+/// or is a few typos away from it, in Hebrew or Latin letters. Names matching several stations fail, suggesting them.
+fn resolve_station<'a>(data: &'a RailroadData, name: &str) -> Result<&'a Station, Failure> {
+    if let Some(station) = data
+        .find_station(name)
+        .or_else(|| name.parse().ok().and_then(|id| data.station(id)))
+    {
+        return Ok(station);
+    }
+    let candidates = harail::search_stations(data, name, MAX_SUGGESTIONS);
+    match candidates[..] {
+        [] => Err(Failure::StationNotFound(name.to_owned())),
+        [station] => Ok(station),
+        _ => Err(Failure::AmbiguousStation(
+            name.to_owned(),
+            candidates.iter().map(|s| s.name().to_owned()).collect(),
+        )),
+    }
+}
+
+/// Parses a date given in DD/MM/YYYY format
+fn parse_date(date: &str) -> Result<NaiveDate, HaError> {
+    NaiveDate::parse_from_str(date, "%d/%m/%Y")
+        .map_err(|_| HaError::UsageError("Failed to parse date".to_owned()))
+}
+
+/// The time given by the --date and --time options, defaulting to today and the given time
+fn parse_date_time(
+    matches: &ArgMatches,
+    default_time: NaiveTime,
+) -> Result<NaiveDateTime, HaError> {
+    Ok(NaiveDateTime::new(
+        if let Some(date) = matches.get_one::<String>("date") {
+            parse_date(date)?
+        } else {
+            harail::to_feed_time(&Utc::now()).date()
+        },
+        if let Some(time) = matches.get_one::<String>("time") {
+            NaiveTime::parse_from_str(time, "%H:%M:%S")
+                .map_err(|_| HaError::UsageError("Failed to parse time".to_owned()))?
+        } else {
+            default_time
+        },
+    ))
+}
+
+/// The --format option given to a subcommand, or the configured format, or text if neither was given
+fn output_format<'a>(matches: &'a ArgMatches, config: &'a Config) -> &'a str {
+    matches
+        .get_one::<String>("format")
+        .map(String::as_str)
+        .or_else(|| config.format())
+        .unwrap_or("text")
+}
+
+/// The --format option of the subcommands which print lists
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .value_name("FORMAT")
+        .value_parser(FORMATS)
+        .help("Output format, one of text, csv or table (default: the configured format or text, or JSON with --json)")
+}
+
+/// The --direction option of the subcommands which list trains
+fn direction_arg() -> Arg {
+    Arg::new("direction")
+        .long("direction")
+        .value_name("DIRECTION_ID")
+        .value_parser(clap::value_parser!(u8).range(0..=1))
+        .help("Only list trains whose GTFS direction_id is DIRECTION_ID, 0 or 1")
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit::code(e.as_ref()))
+        }
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    // Logs go to stderr so that they don't mix with the program's output
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
     let matches = Command::new("HaRail")
         .version(VERSION.unwrap_or_default())
         .author("Yuval Deutscher")
         .about("Because the Israel Railways app sucks™")
+        .after_help("Exit codes: 0 success, 1 other failure, 2 usage error, 3 station not found, 4 no route found, 5 bad database, 6 train not found")
         .arg(
             Arg::new("DATABASE")
-                .help("The HaRail database to use")
-                .required(true)
+                .help("The HaRail database to use (default: the database in the configuration file)")
                 .index(1),
         )
         .arg(
@@ -34,22 +172,98 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .long("json")
                 .help("Output in JSON format"),
         )
-        .subcommand(Command::new("list-stations").about("Lists all stations"))
-        .subcommand(Command::new("list-trains").about("Lists all trains"))
+        .arg(
+            Arg::new("timezone")
+                .long("timezone")
+                .value_name("TIMEZONE")
+                .help("Show times in the given IANA timezone, e.g. UTC or Europe/London (default: Asia/Jerusalem, the timezone in which dates and times are given)"),
+        )
+        .subcommand(
+            Command::new("list-stations")
+                .about("Lists all stations")
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("search-station")
+                .about("Find stations by a partial, misspelled or transliterated name, best matches first")
+                .arg(
+                    Arg::new("QUERY")
+                        .help("The station name to search for, in Hebrew or Latin letters")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .short('n')
+                        .long("limit")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10")
+                        .help("Maximum number of stations to list"),
+                )
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("list-trains")
+                .about("Lists all trains, or the trains with the given number")
+                .arg(
+                    Arg::new("NUMBER")
+                        .help("Only list the trains passengers know by this number, or the train with this identifier")
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("date")
+                        .short('d')
+                        .long("date")
+                        .value_name("DATE")
+                        .conflicts_with("today")
+                        .help("Only list trains running on the given date, in DD/MM/YYYY format"),
+                )
+                .arg(
+                    Arg::new("today")
+                        .long("today")
+                        .action(ArgAction::SetTrue)
+                        .help("Only list trains running today"),
+                )
+                .arg(
+                    Arg::new("station")
+                        .long("station")
+                        .value_name("STATION")
+                        .help("Only list trains stopping at the given station"),
+                )
+                .arg(direction_arg())
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("show-train")
+                .about("Shows a train's stops on a date, with their times and platforms")
+                .arg(
+                    Arg::new("TRAIN")
+                        .help("The number passengers know the train by, or its identifier")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("date")
+                        .short('d')
+                        .long("date")
+                        .value_name("DATE")
+                        .help("Specify date in DD/MM/YYYY format (default: today)"),
+                ),
+        )
         .subcommand(
             Command::new("find")
                 .about("Find paths between stations")
                 .arg(
                     Arg::new("START_STATION")
-                        .help("The ID of the starting station")
+                        .help("The name, prefix, ID or configured alias of the starting station, or of the destination station when no other station is given")
                         .index(1)
                         .required(true),
                 )
                 .arg(
                     Arg::new("DEST_STATION")
-                        .help("The ID of the destination station")
-                        .index(2)
-                        .required(true),
+                        .help("The name, prefix, ID or configured alias of the destination station (if omitted, routes start at the configured home station)")
+                        .index(2),
                 )
                 .arg(
                     Arg::new("date")
@@ -83,53 +297,362 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .short('m')
                         .long("multiple")
                         .help("Show multiple train options"),
+                )
+                .arg(
+                    Arg::new("pareto")
+                        .short('p')
+                        .long("pareto")
+                        .action(ArgAction::SetTrue)
+                        .help("Show the fastest route for each number of train switches"),
+                )
+                .arg(
+                    Arg::new("diverse")
+                        .long("diverse")
+                        .action(ArgAction::SetTrue)
+                        .help("When showing multiple train options, list meaningfully different routes first and near-duplicates last"),
+                )
+                .arg(
+                    Arg::new("arrive-by")
+                        .short('a')
+                        .long("arrive-by")
+                        .action(ArgAction::SetTrue)
+                        .help("Treat the given date and time as the latest arrival time, and leave as late as possible"),
+                )
+                .arg(
+                    Arg::new("stay")
+                        .long("stay")
+                        .value_name("MINUTES")
+                        .conflicts_with_all(["arrive-by", "pareto", "multiple"])
+                        .help("Plan a round trip back to the starting station, staying at the destination for at least MINUTES and returning as early as possible"),
+                )
+                .arg(
+                    Arg::new("return-by")
+                        .long("return-by")
+                        .value_name("TIME")
+                        .conflicts_with_all(["arrive-by", "pareto", "multiple"])
+                        .help("Plan a round trip back to the starting station by TIME in HH:MM:SS format on the day of leaving, staying at the destination for as long as possible unless --stay is given"),
+                )
+                .arg(
+                    Arg::new("max-transfers")
+                        .long("max-transfers")
+                        .value_name("N")
+                        .help("Only find routes with at most N train switches"),
+                )
+                .arg(
+                    Arg::new("transfer-penalty")
+                        .long("transfer-penalty")
+                        .value_name("MINUTES")
+                        .help("Count each train boarded as MINUTES of travel time, to prefer fewer train switches over arriving earlier (default: 1 minute)"),
+                )
+                .arg(
+                    Arg::new("min-transfer")
+                        .long("min-transfer")
+                        .value_name("MINUTES")
+                        .help("Leave at least MINUTES between leaving a train and boarding another (default: the stations' own minimum transfer times)"),
+                )
+                .arg(
+                    Arg::new("slack")
+                        .long("slack")
+                        .value_name("MINUTES")
+                        .help("Prefer train switches leaving MINUTES to spare beyond the minimum transfer time, arriving up to 10 minutes later rather than switching trains tightly"),
+                )
+                .arg(
+                    Arg::new("engine")
+                        .long("engine")
+                        .value_name("ENGINE")
+                        .value_parser(["graph", "astar", "raptor"])
+                        .help("Search algorithm to use, one of graph, astar or raptor (default: graph)"),
+                )
+                .arg(
+                    Arg::new("avoid-station")
+                        .long("avoid-station")
+                        .value_name("STATION")
+                        .action(ArgAction::Append)
+                        .help("Never board or leave a train at STATION (may be repeated)"),
+                )
+                .arg(
+                    Arg::new("avoid-train")
+                        .long("avoid-train")
+                        .value_name("TRAIN")
+                        .action(ArgAction::Append)
+                        .help("Never use the train with the identifier or number TRAIN (may be repeated)"),
+                )
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .value_name("MODE")
+                        .value_parser(MODES)
+                        .action(ArgAction::Append)
+                        .help("Only use trains of MODE, one of light_rail, subway, rail, bus, ferry or other (may be repeated, all modes by default)"),
+                )
+                .arg(
+                    Arg::new("wheelchair")
+                        .long("wheelchair")
+                        .action(ArgAction::SetTrue)
+                        .help("Only use trains and stations which are not marked as inaccessible to wheelchair users"),
+                )
+                .arg(
+                    Arg::new("bikes")
+                        .long("bikes")
+                        .action(ArgAction::SetTrue)
+                        .help("Only use trains which allow taking a bicycle along"),
+                )
+                .arg(format_arg())
+                .arg(
+                    Arg::new("fields")
+                        .long("fields")
+                        .value_name("FIELDS")
+                        .help("Print only the given comma separated fields of each route, out of departure, arrival, train, changes and duration"),
+                )
+                .arg(
+                    Arg::new("zones")
+                        .long("zones")
+                        .value_name("ZONES_CSV")
+                        .requires("fares")
+                        .help("Station to fare zone table, used to estimate the Rav-Kav fare"),
+                )
+                .arg(
+                    Arg::new("fares")
+                        .long("fares")
+                        .value_name("FARES_CSV")
+                        .requires("zones")
+                        .help("Price by number of zones table, used to estimate the Rav-Kav fare"),
                 ),
         )
+        .subcommand(
+            Command::new("departures")
+                .about("List the next trains leaving a station")
+                .arg(
+                    Arg::new("STATION")
+                        .help("The name of the station")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("date")
+                        .short('d')
+                        .long("date")
+                        .value_name("DATE")
+                        .help("Specify date in DD/MM/YYYY format (default: today)"),
+                )
+                .arg(
+                    Arg::new("time")
+                        .short('t')
+                        .long("time")
+                        .value_name("TIME")
+                        .help("Specify time in HH:MM:SS format (default: now)"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .short('n')
+                        .long("limit")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10")
+                        .help("Number of departures to list"),
+                )
+                .arg(direction_arg()),
+        )
         .subcommand(
             Command::new("parse-gtfs")
                 .about("Parse a GTFS database")
                 .arg(
                     Arg::new("GTFS_PATH")
-                        .help("The GTFS database to parse, in zip file or directory form")
+                        .help("The GTFS database to parse, in zip file, tar.gz file or directory form, or an HTTP(S) URL of a zip or tar.gz file to download")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("compression-level")
+                        .short('z')
+                        .long("compression-level")
+                        .value_name("LEVEL")
+                        .value_parser(clap::value_parser!(i32).range(0..=22))
+                        .default_value("3")
+                        .help("Zstd compression level of the database, from 1 (fastest) to 22 (smallest), or 0 to store it uncompressed"),
+                )
+                .arg(
+                    Arg::new("agency")
+                        .short('a')
+                        .long("agency")
+                        .value_name("AGENCY")
+                        .action(ArgAction::Append)
+                        .help("Name or ID of an agency whose trips to load (may be repeated, Israel Railways by default)"),
+                )
+                .arg(
+                    Arg::new("all-agencies")
+                        .long("all-agencies")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("agency")
+                        .help("Load the trips of every agency in the feed"),
+                )
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .value_name("MODE")
+                        .value_parser(MODES)
+                        .action(ArgAction::Append)
+                        .help("Only load trips of MODE, one of light_rail, subway, rail, bus, ferry or other (may be repeated, all modes by default)"),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export stations, trains and stop times to CSV files, or JSON files with --json")
+                .arg(
+                    Arg::new("DIRECTORY")
+                        .help("The directory to write stations, trains and stop_times files into")
                         .index(1)
                         .required(true),
                 ),
         )
+        .subcommand(
+            Command::new("validate").about(
+                "Check the database for dangling station references, trains without stops or dates and stop times going back in time",
+            ),
+        )
+        .subcommand(
+            Command::new("stats").about(
+                "Print statistics about the database, such as its dates, trips per day and stops per train",
+            ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two databases, listing the trains which were added or removed, or whose stop times or dates changed")
+                .arg(
+                    Arg::new("OLD")
+                        .help("The database before the change")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("NEW")
+                        .help("The database after the change")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("station")
+                        .long("station")
+                        .value_name("STATION")
+                        .help("Only list trains stopping at the given station in either database"),
+                ),
+        )
         .subcommand(
             Command::new("date-info")
                 .about("Print information regarding the database start and expiration dates"),
         )
+        .subcommand(
+            Command::new("save-graph")
+                .about("Prepare the routing graph for a time window and save it next to the database, for the server to load at startup")
+                .arg(
+                    Arg::new("date")
+                        .short('d')
+                        .long("date")
+                        .value_name("DATE")
+                        .help("Specify the first date of the window in DD/MM/YYYY format (default: today)"),
+                )
+                .arg(
+                    Arg::new("length")
+                        .short('l')
+                        .long("length")
+                        .value_name("LENGTH")
+                        .value_parser(clap::value_parser!(i64).range(1..))
+                        .default_value("1")
+                        .help("Specify length, in days, of the window"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("The file to save the graph to (default: the database path with a .graph extension)"),
+                ),
+        )
         .get_matches();
 
-    let path = Path::new(matches.get_one::<String>("DATABASE").unwrap());
+    let timezone: Tz = match matches.get_one::<String>("timezone") {
+        Some(timezone) => timezone
+            .parse()
+            .map_err(|_| HaError::UsageError(format!("Unknown timezone {}", timezone)))?,
+        None => harail::FEED_TIMEZONE,
+    };
+    let config = Config::load()?;
+    if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        let old = load_database(Path::new(diff_matches.get_one::<String>("OLD").unwrap()))?;
+        let new = load_database(Path::new(diff_matches.get_one::<String>("NEW").unwrap()))?;
+        let mut changes = harail::diff(&old, &new);
+        if let Some(name) = diff_matches.get_one::<String>("station") {
+            // Stations which were removed are looked up in the old database
+            let station = match resolve_station(&new, config.station(name)) {
+                Err(Failure::StationNotFound(_)) => resolve_station(&old, config.station(name)),
+                found => found,
+            }?
+            .id();
+            let stops_at = |data: &RailroadData, train: &str| {
+                data.train(train)
+                    .is_some_and(|t| t.stops().any(|s| s.station() == station))
+            };
+            changes.retain(|c| stops_at(&old, c.train()) || stops_at(&new, c.train()));
+        }
+        if matches.contains_id("json") {
+            let json = JsonValue::Array(changes.iter().map(|c| c.to_json()).collect());
+            println!("{}", json.pretty(JSON_SPACES));
+        } else {
+            changes.iter().for_each(|c| println!("{}", c));
+            println!("{} changes found", changes.len());
+        }
+        return Ok(());
+    }
+    let path = match matches.get_one::<String>("DATABASE") {
+        Some(path) => PathBuf::from(path),
+        None => config.database().ok_or_else(|| {
+            HaError::UsageError("No database given, and none is configured".to_owned())
+        })?,
+    };
 
     if let Some(matches) = matches.subcommand_matches("parse-gtfs") {
-        let gtfs_path = Path::new(matches.get_one::<String>("GTFS_PATH").unwrap());
-        let load_result = if gtfs_path.is_dir() {
-            RailroadData::from_gtfs_directory(gtfs_path)
+        let gtfs_path = matches.get_one::<String>("GTFS_PATH").unwrap();
+        let downloaded = if download::is_url(gtfs_path) {
+            Some(download::download(gtfs_path)?)
+        } else {
+            None
+        };
+        let gtfs_path = downloaded
+            .as_ref()
+            .map_or_else(|| Path::new(gtfs_path), |d| d.path());
+        let agencies = if matches.get_flag("all-agencies") {
+            AgencyFilter::All
+        } else if let Some(agencies) = matches.get_many::<String>("agency") {
+            AgencyFilter::Matching(agencies.cloned().collect())
         } else {
-            RailroadData::from_gtfs_zip(gtfs_path)
+            AgencyFilter::default()
         };
-        let data = load_result
-            .map_err(|_| HaError::UsageError("Could not load GTFS database".to_owned()))?;
-        let file = File::create(path).map_err(|_| {
+        let mut options = GtfsOptions::new().with_agencies(agencies);
+        if let Some(modes) = matches.get_many::<String>("mode") {
+            options = options.with_modes(modes.map(|m| Mode::from_name(m).unwrap()));
+        }
+        let data = RailroadData::from_gtfs_path_with_options(gtfs_path, &options)
+            .map_err(|e| Failure::BadDatabase(format!("Could not load GTFS database: {}", e)))?;
+        let file = File::create(&path).map_err(|_| {
             HaError::UsageError("Could not open database file for writing".to_owned())
         })?;
         let writer = BufWriter::new(file);
-        serialize_into(writer, &data)
-            .map_err(|_| HaError::UsageError("Could not serialize database".to_owned()))?;
+        match *matches.get_one::<i32>("compression-level").unwrap() {
+            0 => data.save(writer)?,
+            level => data.save_compressed(writer, level)?,
+        }
         return Ok(());
     }
 
-    let file = File::open(path)
-        .map_err(|_| HaError::UsageError("Could not open database file".to_owned()))?;
-    let reader = BufReader::new(file);
-    let data: RailroadData = deserialize_from(reader)
-        .map_err(|_| HaError::UsageError("Could not deserialize database".to_owned()))?;
-    if matches.subcommand_matches("list-stations").is_some() {
+    let data = load_database(&path)?;
+    if let Some(list_matches) = matches.subcommand_matches("list-stations") {
         let mut stations: Vec<_> = data.stations().collect();
         stations.sort_by_key(|s| s.id());
-        if matches.contains_id("json") {
+        if output_format(list_matches, &config) == "csv" {
+            csv_output::write_stations(io::stdout(), &stations)?;
+        } else if output_format(list_matches, &config) == "table" {
+            print!("{}", table::stations(&stations));
+        } else if matches.contains_id("json") {
             let json = JsonValue::Array(stations.into_iter().map(|s| s.to_json()).collect());
             println!("{}", json.pretty(JSON_SPACES));
         } else {
@@ -138,13 +661,72 @@ fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
-    if matches.subcommand_matches("list-trains").is_some() {
-        let mut trains: Vec<_> = data.trains().collect();
+    if let Some(search_matches) = matches.subcommand_matches("search-station") {
+        let stations = harail::search_stations(
+            &data,
+            search_matches.get_one::<String>("QUERY").unwrap(),
+            *search_matches.get_one::<usize>("limit").unwrap(),
+        );
+        if stations.is_empty() {
+            return Err(Box::new(Failure::StationNotFound(
+                search_matches
+                    .get_one::<String>("QUERY")
+                    .unwrap()
+                    .to_owned(),
+            )));
+        }
+        match output_format(search_matches, &config) {
+            "csv" => csv_output::write_stations(io::stdout(), &stations)?,
+            "table" => print!("{}", table::stations(&stations)),
+            _ if matches.contains_id("json") => {
+                let json = JsonValue::Array(stations.into_iter().map(|s| s.to_json()).collect());
+                println!("{}", json.pretty(JSON_SPACES));
+            }
+            _ => stations.into_iter().for_each(|s| println!("{}", s)),
+        }
+        return Ok(());
+    }
+
+    if let Some(list_matches) = matches.subcommand_matches("list-trains") {
+        let mut trains: Vec<_> = match list_matches.get_one::<String>("NUMBER") {
+            Some(number) => data.find_trains(number),
+            None => data.trains().collect(),
+        };
+        if let Some(&direction) = list_matches.get_one::<u8>("direction") {
+            trains.retain(|t| t.direction_id() == Some(direction));
+        }
+        let date = match list_matches.get_one::<String>("date") {
+            Some(date) => Some(parse_date(date)?),
+            None if list_matches.get_flag("today") => {
+                Some(harail::to_feed_time(&Utc::now()).date())
+            }
+            None => None,
+        };
+        if let Some(date) = date {
+            trains.retain(|t| t.runs_on(date));
+        }
+        if let Some(name) = list_matches.get_one::<String>("station") {
+            let station = resolve_station(&data, config.station(name))?.id();
+            trains.retain(|t| t.stops().any(|s| s.station() == station));
+        }
         trains.sort_by_key(|t| t.id());
+        match output_format(list_matches, &config) {
+            "csv" => {
+                csv_output::write_trains(io::stdout(), &data, &trains)?;
+                return Ok(());
+            }
+            "table" => {
+                print!("{}", table::trains(&data, &trains));
+                return Ok(());
+            }
+            _ => {}
+        }
         trains.into_iter().for_each(|t| {
             println!(
-                "{} : {} ({}) -> {} ({})",
+                "{}{} : {} ({}) -> {} ({})",
                 t.id(),
+                t.short_name()
+                    .map_or_else(String::new, |n| format!(" [train {}]", n)),
                 t.stops().next().unwrap().station(),
                 t.stops().next().unwrap().departure_offset(),
                 t.stops().last().unwrap().station(),
@@ -154,69 +736,371 @@ fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
+    if let Some(export_matches) = matches.subcommand_matches("export") {
+        let dir = Path::new(export_matches.get_one::<String>("DIRECTORY").unwrap());
+        export::export(&data, dir, matches.contains_id("json"))?;
+        return Ok(());
+    }
+
+    if matches.subcommand_matches("validate").is_some() {
+        let issues = harail::validate(&data);
+        if matches.contains_id("json") {
+            let mut counts = JsonValue::new_object();
+            for issue in &issues {
+                counts[issue.kind()] = (counts[issue.kind()].as_usize().unwrap_or(0) + 1).into();
+            }
+            let json = object! {
+                valid: issues.is_empty(),
+                stations: data.stations().count(),
+                trains: data.trains().count(),
+                counts: counts,
+                issues: JsonValue::Array(issues.iter().map(|i| i.to_json()).collect()),
+            };
+            println!("{}", json.pretty(JSON_SPACES));
+        } else {
+            issues.iter().for_each(|i| println!("{}", i));
+            println!("{} problems found", issues.len());
+        }
+        // Exit with an error when problems are found, so that scripts can reject bad feeds
+        if !issues.is_empty() {
+            std::process::exit(exit::FAILURE.into());
+        }
+        return Ok(());
+    }
+
+    if matches.subcommand_matches("stats").is_some() {
+        let stats = stats::Stats::new(&data);
+        if matches.contains_id("json") {
+            println!("{}", stats.to_json().pretty(JSON_SPACES));
+        } else {
+            print!("{}", stats);
+        }
+        return Ok(());
+    }
+
     if matches.subcommand_matches("date-info").is_some() {
         let db_start = data
             .start_date()
-            .ok_or_else(|| HaError::UsageError("Empty database".to_owned()))?;
+            .ok_or_else(|| Failure::BadDatabase("Empty database".to_owned()))?;
         let db_end = data
             .end_date()
-            .ok_or_else(|| HaError::UsageError("Empty database".to_owned()))?;
+            .ok_or_else(|| Failure::BadDatabase("Empty database".to_owned()))?;
         println!("{} - {}", db_start, db_end);
         return Ok(());
     }
 
+    if let Some(save_matches) = matches.subcommand_matches("save-graph") {
+        let date = match save_matches.get_one::<String>("date") {
+            Some(date) => parse_date(date)?,
+            None => harail::to_feed_time(&Utc::now()).date(),
+        };
+        let start_time = date.and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+        let days = *save_matches.get_one::<i64>("length").unwrap();
+        let end_time = start_time + chrono::Duration::days(days);
+        let output = save_matches
+            .get_one::<String>("output")
+            .map_or_else(|| path.with_extension("graph"), PathBuf::from);
+        let graph = PreparedGraph::new(&data, start_time, end_time, &RoutingOptions::new());
+        let file = File::create(&output)
+            .map_err(|_| HaError::UsageError("Could not open graph file for writing".to_owned()))?;
+        graph.save(BufWriter::new(file))?;
+        return Ok(());
+    }
+
+    if let Some(departures_matches) = matches.subcommand_matches("departures") {
+        let time = parse_date_time(departures_matches, harail::to_feed_time(&Utc::now()).time())?;
+        let name = departures_matches.get_one::<String>("STATION").unwrap();
+        let station = resolve_station(&data, config.station(name))?;
+        let limit = *departures_matches.get_one::<usize>("limit").unwrap();
+        let direction = departures_matches.get_one::<u8>("direction").copied();
+        let departures =
+            harail::get_departures_in_direction(&data, station, time, limit, direction);
+        if matches.contains_id("json") {
+            let json =
+                JsonValue::Array(departures.iter().map(|d| d.to_json_in(timezone)).collect());
+            println!("{}", json.pretty(JSON_SPACES));
+        } else {
+            departures
+                .iter()
+                .for_each(|d| println!("{}", d.in_timezone(timezone)));
+        }
+        return Ok(());
+    }
+
+    if let Some(show_matches) = matches.subcommand_matches("show-train") {
+        let date = match show_matches.get_one::<String>("date") {
+            Some(date) => parse_date(date)?,
+            None => harail::to_feed_time(&Utc::now()).date(),
+        };
+        let number = show_matches.get_one::<String>("TRAIN").unwrap();
+        let train = data
+            .find_train(number, date)
+            .ok_or_else(|| Failure::TrainNotFound(number.to_owned()))?;
+        let stops: Vec<_> = train
+            .stops()
+            .map(|s| Stop::from_stop_schedule(&data, s, date))
+            .collect();
+        if matches.contains_id("json") {
+            let json = JsonValue::Array(stops.iter().map(|s| s.to_json_in(timezone)).collect());
+            println!("{}", json.pretty(JSON_SPACES));
+        } else {
+            println!("Train {} on {}", train.number(), date);
+            stops
+                .iter()
+                .for_each(|s| println!("{}", s.in_timezone(timezone)));
+        }
+        return Ok(());
+    }
+
     if let Some(find_matches) = matches.subcommand_matches("find") {
-        let start_time = NaiveDateTime::new(
-            if let Some(date) = find_matches.get_one::<String>("date") {
-                NaiveDate::parse_from_str(date, "%d/%m/%Y")
-                    .map_err(|_| HaError::UsageError("Failed to parse date".to_owned()))?
-            } else {
-                chrono::Local::now().date_naive()
-            },
-            if let Some(time) = find_matches.get_one::<String>("time") {
-                NaiveTime::parse_from_str(time, "%H:%M:%S")
-                    .map_err(|_| HaError::UsageError("Failed to parse time".to_owned()))?
-            } else {
-                NaiveTime::from_hms_opt(0, 0, 0).unwrap()
-            },
-        );
+        let start_time = parse_date_time(find_matches, NaiveTime::from_hms_opt(0, 0, 0).unwrap())?;
         let n_days = find_matches
             .get_one::<String>("length")
             .map_or_else(|| Ok(1), |x| x.parse())
             .map_err(|_| HaError::UsageError("Failed to parse length".to_owned()))?;
-        let end_time = start_time + chrono::Duration::days(n_days);
-        let start_station = data
-            .find_station(find_matches.get_one::<String>("START_STATION").unwrap())
-            .ok_or_else(|| HaError::UsageError("Could not find source station".to_owned()))?;
-        let end_station = data
-            .find_station(find_matches.get_one::<String>("DEST_STATION").unwrap())
-            .ok_or_else(|| HaError::UsageError("Could not find dest station".to_owned()))?;
-        let routes = if find_matches.contains_id("multiple") {
-            harail::get_multiple_routes(&data, start_time, start_station, end_time, end_station)
+        let arrive_by = find_matches.get_flag("arrive-by");
+        let (start_time, end_time) = if arrive_by {
+            (start_time - chrono::Duration::days(n_days), start_time)
+        } else {
+            (start_time, start_time + chrono::Duration::days(n_days))
+        };
+        let first_station = find_matches.get_one::<String>("START_STATION").unwrap();
+        // With a single station, it is the destination and the route starts at home
+        let (start_name, end_name) = match find_matches.get_one::<String>("DEST_STATION") {
+            Some(end_name) => (config.station(first_station), config.station(end_name)),
+            None => (
+                config.home().ok_or_else(|| {
+                    HaError::UsageError(
+                        "No destination station given, and no home station is configured"
+                            .to_owned(),
+                    )
+                })?,
+                config.station(first_station),
+            ),
+        };
+        let start_station = resolve_station(&data, start_name)?;
+        let end_station = resolve_station(&data, end_name)?;
+        let max_transfers = find_matches
+            .get_one::<String>("max-transfers")
+            .map(|x| x.parse())
+            .transpose()
+            .map_err(|_| HaError::UsageError("Failed to parse max transfers".to_owned()))?;
+        let min_transfer = find_matches
+            .get_one::<String>("min-transfer")
+            .map(|x| x.parse())
+            .transpose()
+            .map_err(|_| HaError::UsageError("Failed to parse minimum transfer time".to_owned()))?;
+        let slack = find_matches
+            .get_one::<String>("slack")
+            .map(|x| x.parse())
+            .transpose()
+            .map_err(|_| HaError::UsageError("Failed to parse transfer slack".to_owned()))?;
+        let transfer_penalty: Option<u64> = find_matches
+            .get_one::<String>("transfer-penalty")
+            .map(|x| x.parse())
+            .transpose()
+            .map_err(|_| HaError::UsageError("Failed to parse transfer penalty".to_owned()))?;
+        let avoided_stations = find_matches
+            .get_many::<String>("avoid-station")
+            .into_iter()
+            .flatten()
+            .map(|name| resolve_station(&data, config.station(name)).map(|station| station.id()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let avoided_trains = find_matches
+            .get_many::<String>("avoid-train")
+            .into_iter()
+            .flatten()
+            .flat_map(|number| match data.find_trains(number).as_slice() {
+                [] => vec![number.to_owned()],
+                trains => trains.iter().map(|t| t.id().to_owned()).collect(),
+            });
+        let engine = match find_matches.get_one::<String>("engine").map(String::as_str) {
+            Some("astar") => Engine::AStar,
+            Some("raptor") => Engine::Raptor,
+            _ => Engine::TimeExpanded,
+        };
+        let mut options = RoutingOptions::new()
+            .with_engine(engine)
+            .with_max_transfers(max_transfers)
+            .with_min_transfer_time(
+                min_transfer.map(|m: u64| std::time::Duration::from_secs(m * 60)),
+            )
+            .with_transfer_slack(slack.map(|m: u64| std::time::Duration::from_secs(m * 60)))
+            .with_avoided_stations(avoided_stations)
+            .with_avoided_trains(avoided_trains)
+            .with_wheelchair(find_matches.get_flag("wheelchair"))
+            .with_bikes(find_matches.get_flag("bikes"));
+        if find_matches.get_flag("diverse") {
+            options = options.with_order(RouteOrder::Diverse);
+        }
+        if let Some(minutes) = transfer_penalty {
+            options = options.with_weights(
+                ActionWeights::new().with_board(std::time::Duration::from_secs(minutes * 60)),
+            );
+        }
+        if let Some(modes) = find_matches.get_many::<String>("mode") {
+            options = options.with_modes(modes.map(|m| Mode::from_name(m).unwrap()));
+        }
+        if find_matches.contains_id("stay") || find_matches.contains_id("return-by") {
+            let stay = find_matches
+                .get_one::<String>("stay")
+                .map(|x| x.parse())
+                .transpose()
+                .map_err(|_| HaError::UsageError("Failed to parse stay".to_owned()))?
+                .map_or(Stay::Longest, |minutes| {
+                    Stay::AtLeast(chrono::Duration::minutes(minutes))
+                });
+            let end_time = match find_matches.get_one::<String>("return-by") {
+                Some(time) => NaiveDateTime::new(
+                    start_time.date(),
+                    NaiveTime::parse_from_str(time, "%H:%M:%S").map_err(|_| {
+                        HaError::UsageError("Failed to parse return time".to_owned())
+                    })?,
+                ),
+                None => end_time,
+            };
+            let trip = harail::get_round_trip(
+                &data,
+                start_time,
+                start_station,
+                end_station,
+                end_time,
+                stay,
+                &options,
+            )
+            .ok_or(Failure::NoRoute)?;
+            if matches.contains_id("json") {
+                println!("{}", trip.to_json_in(timezone).pretty(JSON_SPACES));
+            } else {
+                print!("{}", trip.in_timezone(timezone));
+            }
+            return Ok(());
+        }
+        // Search flags take precedence over the configured search
+        let search = if find_matches.get_flag("pareto") {
+            "pareto"
+        } else if find_matches.contains_id("multiple") {
+            "multiple"
         } else if find_matches.contains_id("delayed-leave") {
-            vec![harail::get_latest_good_single_route(
+            "delayed-leave"
+        } else {
+            config.search().unwrap_or("best")
+        };
+        let routes: Vec<_> = if arrive_by {
+            harail::get_best_route_arriving_by_with_options(
                 &data,
                 start_time,
                 start_station,
                 end_time,
                 end_station,
+                &options,
             )
-            .ok_or_else(|| HaError::UsageError("No such route".to_owned()))?]
+            .into_iter()
+            .collect()
+        } else if search == "pareto" {
+            harail::get_pareto_routes_with_options(
+                &data,
+                start_time,
+                start_station,
+                end_time,
+                end_station,
+                &options,
+            )
+        } else if search == "multiple" {
+            harail::get_multiple_routes_with_options(
+                &data,
+                start_time,
+                start_station,
+                end_time,
+                end_station,
+                &options,
+            )
+        } else if search == "delayed-leave" {
+            harail::get_latest_good_single_route_with_options(
+                &data,
+                start_time,
+                start_station,
+                end_time,
+                end_station,
+                &options,
+            )
+            .into_iter()
+            .collect()
         } else {
-            vec![harail::get_best_single_route(
+            harail::get_best_single_route_with_options(
                 &data,
                 start_time,
                 start_station,
                 end_time,
                 end_station,
+                &options,
             )
-            .ok_or_else(|| HaError::UsageError("No such route".to_owned()))?]
+            .into_iter()
+            .collect()
         };
-        if matches.contains_id("json") {
-            let json = JsonValue::Array(routes.into_iter().map(|r| r.to_json()).collect());
+        if routes.is_empty() {
+            return Err(Box::new(Failure::NoRoute));
+        }
+        let pricing = match (
+            find_matches.get_one::<String>("zones"),
+            find_matches.get_one::<String>("fares"),
+        ) {
+            (Some(zones), Some(fares)) => Some(load_pricing(Path::new(zones), Path::new(fares))?),
+            _ => None,
+        };
+        let fares: Vec<_> = routes
+            .iter()
+            .map(|r| pricing.as_ref().and_then(|p| p.price(r)))
+            .collect();
+        if let Some(fields) = find_matches.get_one::<String>("fields") {
+            let fields = Field::parse_list(fields)?;
+            if output_format(find_matches, &config) == "csv" {
+                csv_output::write_route_fields(io::stdout(), &routes, &fields, timezone)?;
+            } else if output_format(find_matches, &config) == "table" {
+                print!("{}", table::route_fields(&routes, &fields, timezone));
+            } else if matches.contains_id("json") {
+                let json = JsonValue::Array(
+                    routes
+                        .iter()
+                        .map(|r| fields::route_to_json(r, &fields, timezone))
+                        .collect(),
+                );
+                println!("{}", json.pretty(JSON_SPACES));
+            } else {
+                routes
+                    .iter()
+                    .for_each(|r| println!("{}", fields::route_to_text(r, &fields, timezone)));
+            }
+        } else if output_format(find_matches, &config) == "csv" {
+            csv_output::write_routes(io::stdout(), &routes, timezone)?;
+        } else if output_format(find_matches, &config) == "table" {
+            print!("{}", table::routes(&routes, timezone));
+        } else if matches.contains_id("json") {
+            let json = JsonValue::Array(
+                routes
+                    .into_iter()
+                    .zip(fares)
+                    .map(|(r, fare)| {
+                        let mut json = r.to_json_in(timezone);
+                        if let Some(fare) = fare {
+                            json["fare"] = fare.to_json();
+                        }
+                        json
+                    })
+                    .collect(),
+            );
             println!("{}", json.pretty(JSON_SPACES));
         } else {
-            routes.into_iter().for_each(|r| println!("{}", r));
+            routes.into_iter().zip(fares).for_each(|(r, fare)| {
+                print!("{}", r.in_timezone(timezone));
+                if let Some(price) = r.price() {
+                    println!("Price: {}", price);
+                }
+                if let Some(fare) = fare {
+                    println!("Fare: {}", fare);
+                }
+                println!();
+            });
         }
         return Ok(());
     }
@@ -0,0 +1,159 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use chrono::{Duration, NaiveDate};
+use harail::{RailroadData, Train};
+use jzon::{object, JsonValue};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Figures describing a database, to sanity check a freshly parsed feed
+pub struct Stats<'a> {
+    stations: usize,
+    trains: usize,
+    trips_per_day: BTreeMap<NaiveDate, usize>,
+    longest: Option<(&'a Train, Duration)>,
+    shortest: Option<(&'a Train, Duration)>,
+    stops_per_train: BTreeMap<usize, usize>,
+}
+
+/// The time from a train's first departure to its last arrival
+fn trip_duration(train: &Train) -> Option<Duration> {
+    let first = train.stops().next()?.departure_offset().to_chrono();
+    let last = train.stops().last()?.arrival_offset().to_chrono();
+    Some(last - first)
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!(
+        "{}:{:02}",
+        duration.num_hours(),
+        duration.num_minutes() % 60
+    )
+}
+
+impl<'a> Stats<'a> {
+    pub fn new(data: &'a RailroadData) -> Self {
+        let mut trains: Vec<_> = data.trains().collect();
+        // Ties between trip lengths are broken by train ID, so that the output is stable
+        trains.sort_by_key(|t| t.id());
+        let mut trips_per_day = BTreeMap::new();
+        let mut stops_per_train = BTreeMap::new();
+        let mut longest: Option<(&Train, Duration)> = None;
+        let mut shortest: Option<(&Train, Duration)> = None;
+        for &train in &trains {
+            for date in train.dates() {
+                *trips_per_day.entry(*date).or_insert(0) += 1;
+            }
+            *stops_per_train.entry(train.stops().count()).or_insert(0) += 1;
+            if let Some(duration) = trip_duration(train) {
+                if longest.is_none_or(|(_, longest)| duration > longest) {
+                    longest = Some((train, duration));
+                }
+                if shortest.is_none_or(|(_, shortest)| duration < shortest) {
+                    shortest = Some((train, duration));
+                }
+            }
+        }
+        Stats {
+            stations: data.stations().count(),
+            trains: trains.len(),
+            trips_per_day,
+            longest,
+            shortest,
+            stops_per_train,
+        }
+    }
+
+    /// The first and last dates with service, and the number of days between them without any
+    fn coverage(&self) -> Option<(NaiveDate, NaiveDate, i64)> {
+        let first = *self.trips_per_day.keys().next()?;
+        let last = *self.trips_per_day.keys().next_back()?;
+        let days = (last - first).num_days() + 1;
+        Some((first, last, days - self.trips_per_day.len() as i64))
+    }
+
+    /// The fewest, average and most trips on a day with service
+    fn daily_trips(&self) -> Option<(usize, f64, usize)> {
+        let min = *self.trips_per_day.values().min()?;
+        let max = *self.trips_per_day.values().max()?;
+        let total: usize = self.trips_per_day.values().sum();
+        Some((min, total as f64 / self.trips_per_day.len() as f64, max))
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let trip = |trip: Option<(&Train, Duration)>| {
+            trip.map(|(train, duration)| {
+                object! {
+                    train: train.id().as_str(),
+                    minutes: duration.num_minutes(),
+                }
+            })
+        };
+        let mut json = object! {
+            stations: self.stations,
+            trains: self.trains,
+            longest_trip: trip(self.longest),
+            shortest_trip: trip(self.shortest),
+            trips_per_day: JsonValue::new_object(),
+            stops_per_train: JsonValue::new_object(),
+        };
+        if let Some((first, last, gaps)) = self.coverage() {
+            json["first_date"] = first.to_string().into();
+            json["last_date"] = last.to_string().into();
+            json["days_without_service"] = gaps.into();
+        }
+        for (date, trips) in &self.trips_per_day {
+            json["trips_per_day"][date.to_string()] = (*trips).into();
+        }
+        for (stops, trains) in &self.stops_per_train {
+            json["stops_per_train"][stops.to_string()] = (*trains).into();
+        }
+        json
+    }
+}
+
+impl fmt::Display for Stats<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Stations: {}", self.stations)?;
+        writeln!(f, "Trains: {}", self.trains)?;
+        if let Some((first, last, gaps)) = self.coverage() {
+            writeln!(
+                f,
+                "Dates: {} - {} ({} days without service)",
+                first, last, gaps
+            )?;
+        }
+        if let Some((min, average, max)) = self.daily_trips() {
+            writeln!(
+                f,
+                "Trips per day: {} - {} ({:.1} on average)",
+                min, max, average
+            )?;
+        }
+        if let Some((train, duration)) = self.longest {
+            writeln!(
+                f,
+                "Longest trip: {} ({})",
+                train.id(),
+                format_duration(duration)
+            )?;
+        }
+        if let Some((train, duration)) = self.shortest {
+            writeln!(
+                f,
+                "Shortest trip: {} ({})",
+                train.id(),
+                format_duration(duration)
+            )?;
+        }
+        writeln!(f, "Stops per train:")?;
+        for (stops, trains) in &self.stops_per_train {
+            writeln!(f, "  {:>3} stops: {} trains", stops, trains)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::export::gtfs_time;
+use crate::fields::{self, Field};
+use crate::station_name;
+use chrono::{Duration, NaiveDateTime};
+use chrono_tz::Tz;
+use harail::{RailroadData, Route, Station, Train};
+use std::fmt;
+
+/// Marks a ride which is boarded by switching trains, in place of the route number
+const TRANSFER_MARKER: &str = "↳";
+
+/// Rows of text printed in aligned columns under a header
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(headers: Vec<&'static str>) -> Self {
+        Table {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// The width of each column, in characters rather than bytes so that Hebrew station names line up
+    fn widths(&self) -> Vec<usize> {
+        self.headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .map(|cell| cell.chars().count())
+                    .chain(std::iter::once(header.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+}
+
+fn write_row<'a>(
+    f: &mut fmt::Formatter<'_>,
+    cells: impl Iterator<Item = &'a str>,
+    widths: &[usize],
+) -> fmt::Result {
+    let line = cells
+        .zip(widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join("  ");
+    writeln!(f, "{}", line.trim_end())
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let widths = self.widths();
+        write_row(f, self.headers.iter().copied(), &widths)?;
+        let rules: Vec<_> = widths.iter().map(|width| "-".repeat(*width)).collect();
+        write_row(f, rules.iter().map(String::as_str), &widths)?;
+        for row in &self.rows {
+            write_row(f, row.iter().map(String::as_str), &widths)?;
+        }
+        Ok(())
+    }
+}
+
+fn format_time(time: NaiveDateTime, timezone: Tz) -> String {
+    harail::localize_in(time, timezone)
+        .format("%d/%m/%Y %H:%M")
+        .to_string()
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!(
+        "{}:{:02}",
+        duration.num_hours(),
+        duration.num_minutes() % 60
+    )
+}
+
+/// A station's name followed by the platform, if known
+fn stop_name(station: &Station, platform: Option<&str>) -> String {
+    match platform {
+        Some(platform) => format!("{} [{}]", station.name(), platform),
+        None => station.name().to_owned(),
+    }
+}
+
+/// One row per train ride, where the rides following the first one of a route are marked as transfers along with the
+/// time spent waiting for them, with times in the given timezone
+pub fn routes(routes: &[Route], timezone: Tz) -> Table {
+    let mut table = Table::new(vec![
+        "#", "Train", "From", "Depart", "To", "Arrive", "Ride", "Wait",
+    ]);
+    for (i, route) in routes.iter().enumerate() {
+        let mut last_arrival = None;
+        for part in route.parts() {
+            let departure = part.start().departure();
+            let arrival = part.end().arrival();
+            table.push(vec![
+                match last_arrival {
+                    Some(_) => TRANSFER_MARKER.to_owned(),
+                    None => (i + 1).to_string(),
+                },
+                part.train().number().to_owned(),
+                stop_name(part.start().station(), part.start().platform()),
+                format_time(departure, timezone),
+                stop_name(part.end().station(), part.end().platform()),
+                format_time(arrival, timezone),
+                format_duration(arrival - departure),
+                last_arrival.map_or_else(String::new, |last| format_duration(departure - last)),
+            ]);
+            last_arrival = Some(arrival);
+        }
+    }
+    table
+}
+
+/// One row per route with the selected fields, with times in the given timezone
+pub fn route_fields(routes: &[Route], fields: &[Field], timezone: Tz) -> Table {
+    let mut table = Table::new(fields.iter().map(Field::title).collect());
+    for route in routes {
+        table.push(fields::route_to_cells(route, fields, timezone));
+    }
+    table
+}
+
+pub fn stations(stations: &[&Station]) -> Table {
+    let mut table = Table::new(vec!["ID", "Name", "Lat", "Lon"]);
+    for station in stations {
+        table.push(vec![
+            station.id().to_string(),
+            station.name().to_owned(),
+            station
+                .lat()
+                .map_or_else(String::new, |lat| lat.to_string()),
+            station
+                .lon()
+                .map_or_else(String::new, |lon| lon.to_string()),
+        ]);
+    }
+    table
+}
+
+pub fn trains(data: &RailroadData, trains: &[&Train]) -> Table {
+    let mut table = Table::new(vec!["Train", "From", "Depart", "To", "Arrive"]);
+    for train in trains {
+        let first = train.stops().next();
+        let last = train.stops().last();
+        table.push(vec![
+            train.number().to_owned(),
+            first.map_or_else(String::new, |s| station_name(data, s.station())),
+            first.map_or_else(String::new, |s| gtfs_time(s.departure_offset())),
+            last.map_or_else(String::new, |s| station_name(data, s.station())),
+            last.map_or_else(String::new, |s| gtfs_time(s.arrival_offset())),
+        ]);
+    }
+    table
+}
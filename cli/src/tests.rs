@@ -0,0 +1,205 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::exit::{self, Failure};
+use super::fields::{self, Field};
+use super::{csv_output, resolve_station, table};
+use chrono::NaiveDate;
+use harail::{
+    HaDuration, HaError, RailroadData, Route, RoutePart, Station, Stop, StopSchedule, Train,
+    FEED_TIMEZONE,
+};
+
+fn test_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
+}
+
+/// Train 1 rides from stat_a to stat_b, and train 2 from stat_b to stat_c
+fn data() -> RailroadData {
+    RailroadData::from_stations_trains(
+        vec![
+            Station::new(100, "stat_a"),
+            Station::new(200, "stat_b"),
+            Station::new(300, "stat_c"),
+        ],
+        vec![
+            Train::from_stops_date(
+                "1",
+                vec![
+                    StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                    StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                ],
+                test_date(),
+            ),
+            Train::from_stops_date(
+                "2",
+                vec![
+                    StopSchedule::new(200, HaDuration::from_hms(10, 45, 00), None),
+                    StopSchedule::new(300, HaDuration::from_hms(11, 15, 00), None),
+                ],
+                test_date(),
+            ),
+        ],
+    )
+}
+
+/// The route riding train 1 and then switching to train 2
+fn route(data: &RailroadData) -> Route<'_> {
+    let part = |id: &str| {
+        let train = data.train(id).unwrap();
+        let stops: Vec<_> = train
+            .stops()
+            .map(|s| Stop::from_stop_schedule(data, s, test_date()))
+            .collect();
+        RoutePart::new(train, stops[0], stops[1])
+    };
+    Route::from_parts(vec![part("1"), part("2")])
+}
+
+#[test]
+fn parse_fields() {
+    assert_eq!(
+        Field::parse_list("departure, train,duration").unwrap(),
+        vec![Field::Departure, Field::Train, Field::Duration]
+    );
+    assert!(matches!(
+        Field::parse_list("departure,platform"),
+        Err(HaError::UsageError(_))
+    ));
+}
+
+#[test]
+fn route_fields() {
+    let data = data();
+    let route = route(&data);
+    let all = [
+        Field::Departure,
+        Field::Arrival,
+        Field::Train,
+        Field::Changes,
+        Field::Duration,
+    ];
+    assert_eq!(
+        fields::route_to_text(&route, &all, FEED_TIMEZONE),
+        "2000-01-01 10:00:00\t2000-01-01 11:15:00\t1,2\t1\t1:15:00"
+    );
+    assert_eq!(
+        fields::route_to_text(&route, &[Field::Departure], chrono_tz::UTC),
+        "2000-01-01 08:00:00"
+    );
+    assert_eq!(
+        fields::route_to_json(&route, &[Field::Train, Field::Duration], FEED_TIMEZONE).dump(),
+        r#"{"train":["1","2"],"duration":4500}"#
+    );
+}
+
+#[test]
+fn csv_routes() {
+    let data = data();
+    let routes = [route(&data)];
+    let mut output = Vec::new();
+    csv_output::write_routes(&mut output, &routes, FEED_TIMEZONE).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "route,train_id,train_number,from,depart,to,arrive\n\
+         1,1,,stat_a,2000-01-01 10:00:00,stat_b,2000-01-01 10:30:00\n\
+         1,2,,stat_b,2000-01-01 10:45:00,stat_c,2000-01-01 11:15:00\n"
+    );
+
+    let mut output = Vec::new();
+    csv_output::write_route_fields(
+        &mut output,
+        &routes,
+        &[Field::Departure, Field::Changes],
+        FEED_TIMEZONE,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "departure,changes\n2000-01-01 10:00:00,1\n"
+    );
+}
+
+#[test]
+fn table_routes() {
+    let data = data();
+    let routes = [route(&data)];
+    assert_eq!(
+        table::routes(&routes, FEED_TIMEZONE).to_string(),
+        "#  Train  From    Depart            To      Arrive            Ride  Wait\n\
+         -  -----  ------  ----------------  ------  ----------------  ----  ----\n\
+         1  1      stat_a  01/01/2000 10:00  stat_b  01/01/2000 10:30  0:30\n\
+         ↳  2      stat_b  01/01/2000 10:45  stat_c  01/01/2000 11:15  0:30  0:15\n"
+    );
+    assert_eq!(
+        table::route_fields(&routes, &[Field::Train, Field::Changes], FEED_TIMEZONE).to_string(),
+        "Train  Changes\n-----  -------\n1,2    1\n"
+    );
+}
+
+#[test]
+fn resolve_stations() {
+    let data = RailroadData::from_stations_trains(
+        vec![
+            Station::new(3700, "תל אביב - סבידור מרכז"),
+            Station::new(4600, "תל אביב - השלום"),
+            Station::new(3400, "Herzliya"),
+        ],
+        vec![],
+    );
+    let resolve = |name: &str| resolve_station(&data, name).map(|s| s.id());
+    assert_eq!(resolve("Herzliya").unwrap(), 3400);
+    assert_eq!(resolve("3700").unwrap(), 3700);
+    assert_eq!(resolve("herz").unwrap(), 3400);
+    assert_eq!(resolve("herzilya").unwrap(), 3400);
+    match resolve("tel aviv") {
+        Err(Failure::AmbiguousStation(name, candidates)) => {
+            assert_eq!(name, "tel aviv");
+            assert_eq!(candidates.len(), 2);
+        }
+        other => panic!("expected an ambiguous station, got {:?}", other),
+    }
+    assert!(matches!(
+        resolve("jerusalem"),
+        Err(Failure::StationNotFound(_))
+    ));
+}
+
+#[test]
+fn exit_codes() {
+    let code = |error: Box<dyn std::error::Error>| exit::code(error.as_ref());
+    assert_eq!(
+        code(Box::new(Failure::StationNotFound("x".to_owned()))),
+        exit::STATION_NOT_FOUND
+    );
+    assert_eq!(
+        code(Box::new(Failure::AmbiguousStation(
+            "x".to_owned(),
+            vec!["x1".to_owned(), "x2".to_owned()]
+        ))),
+        exit::STATION_NOT_FOUND
+    );
+    assert_eq!(code(Box::new(Failure::NoRoute)), exit::NO_ROUTE);
+    assert_eq!(
+        code(Box::new(Failure::BadDatabase("x".to_owned()))),
+        exit::BAD_DATABASE
+    );
+    assert_eq!(
+        code(Box::new(Failure::TrainNotFound("x".to_owned()))),
+        exit::TRAIN_NOT_FOUND
+    );
+    assert_eq!(
+        code(Box::new(HaError::UsageError("x".to_owned()))),
+        exit::USAGE
+    );
+    assert_eq!(code(Box::new(HaError::DatabaseTooOld)), exit::BAD_DATABASE);
+    assert_eq!(
+        code(Box::new(HaError::DatabaseTooNew(99))),
+        exit::BAD_DATABASE
+    );
+    assert_eq!(code(Box::new(HaError::BudgetExceeded)), exit::FAILURE);
+    assert_eq!(code(Box::new(std::io::Error::other("x"))), exit::FAILURE);
+}
@@ -0,0 +1,135 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use chrono::NaiveDate;
+use harail::{HaDuration, RailroadData, Station, StopSchedule, Train};
+use std::fs::File;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+/// A directory holding a database where train 1 rides from stat_a to stat_c on 01/01/2000, and an empty configuration
+/// directory, so that the user's own configuration isn't read
+fn setup() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let data = RailroadData::from_stations_trains(
+        vec![
+            Station::new(100, "stat_a"),
+            Station::new(200, "stat_b"),
+            Station::new(300, "stat_c"),
+        ],
+        vec![Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            NaiveDate::from_ymd_opt(2000, 1, 1).unwrap(),
+        )],
+    );
+    data.save(File::create(dir.path().join("harail.db")).unwrap())
+        .unwrap();
+    std::fs::create_dir(dir.path().join("config")).unwrap();
+    dir
+}
+
+/// Runs the CLI on the database in the given directory, returning its exit code
+fn run(dir: &Path, database: &str, args: &[&str]) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_harail_cli"))
+        .arg(dir.join(database))
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn success() {
+    let dir = setup();
+    assert_eq!(
+        run(
+            dir.path(),
+            "harail.db",
+            &["find", "stat_a", "stat_c", "--date", "01/01/2000"]
+        ),
+        0
+    );
+}
+
+#[test]
+fn usage_error() {
+    let dir = setup();
+    assert_eq!(
+        run(
+            dir.path(),
+            "harail.db",
+            &["find", "stat_a", "stat_c", "--date", "32/01/2000"]
+        ),
+        2
+    );
+    // Arguments rejected by clap itself exit with the same code
+    assert_eq!(run(dir.path(), "harail.db", &["no-such-command"]), 2);
+}
+
+#[test]
+fn station_not_found() {
+    let dir = setup();
+    assert_eq!(
+        run(
+            dir.path(),
+            "harail.db",
+            &["find", "stat_a", "jerusalem", "--date", "01/01/2000"]
+        ),
+        3
+    );
+    assert_eq!(
+        run(dir.path(), "harail.db", &["departures", "jerusalem"]),
+        3
+    );
+}
+
+#[test]
+fn ambiguous_station() {
+    let dir = setup();
+    assert_eq!(
+        run(
+            dir.path(),
+            "harail.db",
+            &["find", "stat", "stat_c", "--date", "01/01/2000"]
+        ),
+        3
+    );
+}
+
+#[test]
+fn no_route() {
+    let dir = setup();
+    assert_eq!(
+        run(
+            dir.path(),
+            "harail.db",
+            &["find", "stat_c", "stat_a", "--date", "01/01/2000"]
+        ),
+        4
+    );
+}
+
+#[test]
+fn bad_database() {
+    let dir = setup();
+    assert_eq!(run(dir.path(), "missing.db", &["list-stations"]), 5);
+    std::fs::write(dir.path().join("garbage.db"), b"HARAILDB garbage").unwrap();
+    assert_eq!(run(dir.path(), "garbage.db", &["list-stations"]), 5);
+}
+
+#[test]
+fn train_not_found() {
+    let dir = setup();
+    assert_eq!(run(dir.path(), "harail.db", &["show-train", "999"]), 6);
+}
@@ -6,7 +6,7 @@
 
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use harail::RailroadData;
+use harail::{PreparedGraph, RailroadData, RoutingOptions};
 use std::path::Path;
 
 fn database_load(c: &mut Criterion) {
@@ -114,6 +114,44 @@ fn graph_processing(c: &mut Criterion) {
             )
         })
     });
+
+    // Leaves out building the graph, to measure the shortest path searches alone.
+    // Searches along a train's route, so that the searches have paths to find.
+    let graph = PreparedGraph::new(&data, start_time, end_time, &RoutingOptions::new());
+    let train = data.trains().min_by_key(|t| t.id()).unwrap();
+    let start_station = data
+        .station(train.stops().next().unwrap().station())
+        .unwrap();
+    let end_station = data
+        .station(train.stops().last().unwrap().station())
+        .unwrap();
+
+    c.bench_function("10 day path finding on a prepared graph", |b| {
+        b.iter(|| {
+            graph.multiple_routes(
+                black_box(start_time),
+                black_box(start_station),
+                black_box(end_station),
+            )
+        })
+    });
+
+    let contracted = PreparedGraph::new(
+        &data,
+        start_time,
+        end_time,
+        &RoutingOptions::new().with_contraction(true),
+    );
+
+    c.bench_function("10 day path finding on a contracted prepared graph", |b| {
+        b.iter(|| {
+            contracted.multiple_routes(
+                black_box(start_time),
+                black_box(start_station),
+                black_box(end_station),
+            )
+        })
+    });
 }
 
 criterion_group!(benches, database_load, graph_building, graph_processing);
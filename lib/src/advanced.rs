@@ -0,0 +1,177 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The routing graph searched by the time-expanded and A* engines, for searches the get_* functions don't cover.
+//!
+//! Enabled by the advanced feature. Each node of the graph is a [`Singularity`]: a train at a station at some time, or
+//! the station itself at that time. Passengers wait at stations, board trains, ride them from stop to stop and leave
+//! them again, each an [`Action`] weighted by the time it takes plus the penalties of the routing options' action
+//! weights. Any path between station nodes is a valid route, which [`RoutingGraph::route`] turns into a [`Route`].
+//!
+//! The graph and its types may change between minor versions as the engines do.
+//!
+//! Examples:
+//! ```
+//! use chrono::NaiveDate;
+//! use harail::advanced::RoutingGraph;
+//! use harail::{HaDuration, RailroadData, RoutingOptions, Station, StopSchedule, Train};
+//!
+//! let date = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+//! let stops = vec![
+//!     StopSchedule::new(100, HaDuration::from_hms(10, 0, 0), None),
+//!     StopSchedule::new(200, HaDuration::from_hms(10, 30, 0), None),
+//! ];
+//! let data = RailroadData::from_stations_trains(
+//!     vec![Station::new(100, "A"), Station::new(200, "B")],
+//!     vec![Train::from_stops_date("1", stops, date)],
+//! );
+//! let start = date.and_hms_opt(0, 0, 0).unwrap();
+//! let graph = RoutingGraph::new(&data, start, start + chrono::Duration::days(1), &RoutingOptions::new());
+//! let origin = graph.station_nodes(data.station(100).unwrap())[0];
+//! let path = graph
+//!     .find_path(&origin, |n| n.station().id() == 200 && n.train().is_none())
+//!     .unwrap();
+//! assert_eq!(graph.route(path).parts().count(), 1);
+//! ```
+
+use crate::budget::Budget;
+use crate::graph::Weight;
+use crate::{build_route, RailroadData, RailroadGraph, Route, RoutingOptions, Station, Train};
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+pub use crate::{Action, Singularity};
+
+impl<'a> Singularity<'a> {
+    /// The station of the node
+    pub fn station(&self) -> &'a Station {
+        self.station
+    }
+
+    /// The time of the node
+    pub fn time(&self) -> NaiveDateTime {
+        self.time
+    }
+
+    /// The train the node is on, or None for a node of the station itself, where routes start and end
+    pub fn train(&self) -> Option<&'a Train> {
+        self.train
+    }
+}
+
+impl Action<'_> {
+    /// The cost of the edge in seconds, as searches add it up
+    pub fn cost(&self) -> i64 {
+        self.weight()
+    }
+}
+
+/// The routing graph of the train runs in a time window, for searching with custom predicates, extracting subgraphs or
+/// implementing other search strategies.
+///
+/// Limits on the work of searches in the routing options apply to building the graph and to each search separately.
+pub struct RoutingGraph<'a> {
+    data: &'a RailroadData,
+    graph: RailroadGraph<'a>,
+    options: RoutingOptions,
+}
+
+impl<'a> RoutingGraph<'a> {
+    /// Builds the graph of the train runs between start_time and end_time, subject to the given routing options
+    pub fn new(
+        data: &'a RailroadData,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        options: &RoutingOptions,
+    ) -> Self {
+        let budget = Budget::new(options, None);
+        RoutingGraph {
+            data,
+            graph: RailroadGraph::from_data(data, start_time, end_time, options, Some(&budget)),
+            options: options.clone(),
+        }
+    }
+
+    /// Every node of the graph, in no particular order
+    pub fn nodes(&self) -> impl Iterator<Item = &Singularity<'a>> {
+        self.graph.nodes().map(|n| n.id())
+    }
+
+    /// The edges leaving the given node, along with the nodes they lead to
+    pub fn edges(
+        &self,
+        node: &Singularity<'a>,
+    ) -> impl Iterator<Item = (&Action<'a>, &Singularity<'a>)> {
+        self.graph.get(node).into_iter().flat_map(|n| n.edges())
+    }
+
+    /// The nodes of the station itself, at which routes from the station start and routes to it end, by time
+    pub fn station_nodes(&self, station: &Station) -> Vec<Singularity<'a>> {
+        let mut nodes: Vec<Singularity<'a>> = self
+            .nodes()
+            .filter(|n| n.station == station && n.train.is_none())
+            .copied()
+            .collect();
+        nodes.sort_unstable_by_key(|n| n.time);
+        nodes
+    }
+
+    /// Finds the cheapest path from the origin to any node matching the predicate, as the edges taken and the nodes
+    /// they lead to
+    pub fn find_path<P: Fn(&Singularity<'a>) -> bool>(
+        &self,
+        origin: &Singularity<'a>,
+        predicate: P,
+    ) -> Option<Vec<(Action<'a>, Singularity<'a>)>> {
+        let budget = Budget::new(&self.options, None);
+        self.graph
+            .find_shortest_path(origin, predicate, Some(&budget))
+    }
+
+    /// Finds the cheapest path like find_path, guided by a heuristic (A* search).
+    ///
+    /// The heuristic must never overestimate the remaining cost to a matching node, and must be consistent along edges.
+    /// Nodes for which the heuristic returns None are assumed not to lead to any matching node, and are never explored.
+    pub fn find_path_guided<
+        P: Fn(&Singularity<'a>) -> bool,
+        H: Fn(&Singularity<'a>) -> Option<i64>,
+    >(
+        &self,
+        origin: &Singularity<'a>,
+        predicate: P,
+        heuristic: H,
+    ) -> Option<Vec<(Action<'a>, Singularity<'a>)>> {
+        let budget = Budget::new(&self.options, None);
+        self.graph
+            .find_shortest_path_astar(origin, predicate, heuristic, Some(&budget))
+    }
+
+    /// The cost of the cheapest path from the origin to every node reachable from it
+    pub fn distances(&self, origin: &Singularity<'a>) -> HashMap<Singularity<'a>, i64> {
+        self.graph.shortest_distances(origin)
+    }
+
+    /// The graph of the nodes for which keep returns true, and of the edges between them
+    pub fn subgraph<K: Fn(&Singularity<'a>) -> bool>(&self, keep: K) -> Self {
+        let mut graph = RailroadGraph::new();
+        for node in self.graph.nodes().filter(|n| keep(n.id())) {
+            graph.get_or_insert(node.id());
+            for (action, dest) in node.edges().filter(|&(_, dest)| keep(dest)) {
+                graph.get_or_insert(node.id()).connect(*action, *dest);
+            }
+        }
+        RoutingGraph {
+            data: self.data,
+            graph,
+            options: self.options.clone(),
+        }
+    }
+
+    /// The route taking the given path, which starts at a station node and ends at one
+    pub fn route(&self, path: Vec<(Action<'a>, Singularity<'a>)>) -> Route<'a> {
+        build_route(self.data, path)
+    }
+}
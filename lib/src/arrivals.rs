@@ -0,0 +1,99 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::budget::Budget;
+use crate::{
+    localize_in, observe_build, Engine, PreparedGraph, RailroadData, Route, RoutingOptions,
+    Station, Zoned, FEED_TIMEZONE, JSON,
+};
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use jzon::JsonValue;
+use std::fmt;
+
+/// The earliest arrival at a station from an origin, along with the route arriving then
+pub struct Arrival<'a> {
+    station: &'a Station,
+    time: NaiveDateTime,
+    route: Route<'a>,
+}
+
+impl<'a> Arrival<'a> {
+    pub(crate) fn new(station: &'a Station, time: NaiveDateTime, route: Route<'a>) -> Self {
+        Arrival {
+            station,
+            time,
+            route,
+        }
+    }
+
+    /// The station arrived at
+    pub fn station(&self) -> &'a Station {
+        self.station
+    }
+
+    /// The earliest time the station can be reached at
+    pub fn time(&self) -> NaiveDateTime {
+        self.time
+    }
+
+    /// The route reaching the station at that time, which has no parts for the origin itself
+    pub fn route(&self) -> &Route<'a> {
+        &self.route
+    }
+}
+
+impl Zoned for Arrival<'_> {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        object! {
+            station: self.station.id(),
+            station_name: self.station.name().to_owned(),
+            arrival: localize_in(self.time, timezone).to_rfc3339(),
+            route: self.route.to_json_in(timezone),
+        }
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({} trains)",
+            localize_in(self.time, timezone).time(),
+            self.station.name(),
+            self.route.parts().count()
+        )
+    }
+}
+
+impl JSON for Arrival<'_> {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for Arrival<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+/// Finds the earliest arrival at every station reachable from the source station, leaving at or after start_time.
+///
+/// All arrivals are found by a single search, so the routes to the stations form a tree rooted at the source station.
+/// Arrivals are ordered by arrival time, starting with the source station itself. Stations which cannot be reached
+/// by end_time are left out. The maximum number of train switches and the engine of the routing options are ignored.
+pub fn get_earliest_arrivals<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    options: &RoutingOptions,
+) -> Vec<Arrival<'a>> {
+    let budget = Budget::new(options, None);
+    observe_build(options, Engine::TimeExpanded, || {
+        PreparedGraph::build(data, start_time, end_time, options, Some(&budget))
+    })
+    .arrivals(start_time, start_station, Some(&budget))
+}
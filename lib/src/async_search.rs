@@ -0,0 +1,71 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::budget::Budget;
+use crate::{HaError, OwnedRoute, RailroadData, RoutingOptions, SearchKind, StationId};
+use chrono::NaiveDateTime;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Raises the cancel flag when dropped, so that a search whose future was dropped stops early
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Finds routes between stations without blocking the calling async executor.
+///
+/// The search runs on tokio's blocking thread pool, and the found routes are returned as owned objects.
+/// If the returned future is dropped before completion, the search is cancelled cooperatively and the blocking thread is freed shortly after.
+/// Searches running out of the budget set in the routing options fail with HaError::BudgetExceeded.
+pub async fn find_routes_async(
+    data: Arc<RailroadData>,
+    search: SearchKind,
+    start_time: NaiveDateTime,
+    start_station: StationId,
+    end_time: NaiveDateTime,
+    end_station: StationId,
+    options: RoutingOptions,
+) -> Result<Vec<OwnedRoute>, HaError> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let guard = CancelOnDrop(Arc::clone(&cancel));
+    let result = tokio::task::spawn_blocking(move || {
+        let start_station = data
+            .station(start_station)
+            .ok_or_else(|| HaError::UsageError("Could not find source station".to_owned()))?;
+        let end_station = data
+            .station(end_station)
+            .ok_or_else(|| HaError::UsageError("Could not find dest station".to_owned()))?;
+        let budget = Budget::new(&options, Some(&cancel));
+        let routes = crate::search_routes(
+            &data,
+            search,
+            start_time,
+            start_station,
+            end_time,
+            end_station,
+            &options,
+            Some(&budget),
+        );
+        if budget.cancelled() {
+            return Err(HaError::Cancelled);
+        }
+        if budget.exceeded() {
+            return Err(HaError::BudgetExceeded);
+        }
+        Ok(routes.iter().map(OwnedRoute::from).collect())
+    })
+    .await;
+    drop(guard);
+    match result {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(HaError::Cancelled),
+    }
+}
@@ -0,0 +1,73 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::RoutingOptions;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// How many nodes are expanded between looks at the clock, which is slow compared to expanding a node
+const CLOCK_INTERVAL: usize = 1024;
+
+/// The work a search may still do: it stops once it is cancelled, or once it expanded as many nodes or ran for as long
+/// as its routing options allow
+pub struct Budget<'c> {
+    cancel: Option<&'c AtomicBool>,
+    max_expanded_nodes: Option<usize>,
+    deadline: Option<Instant>,
+    expanded_nodes: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+impl<'c> Budget<'c> {
+    /// The budget of a search starting now, which also stops as soon as the cancel flag, if given, is raised
+    pub fn new(options: &RoutingOptions, cancel: Option<&'c AtomicBool>) -> Self {
+        Budget {
+            cancel,
+            max_expanded_nodes: options.max_expanded_nodes(),
+            deadline: options.time_limit().map(|limit| Instant::now() + limit),
+            expanded_nodes: AtomicUsize::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether the search should stop, because it was cancelled or ran out of budget
+    pub fn stopped(&self) -> bool {
+        if self.cancelled() || self.exceeded() {
+            return true;
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.exceeded.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    /// Counts nodes expanded by the search, returning whether the search should stop
+    pub fn expand(&self, nodes: usize) -> bool {
+        let before = self.expanded_nodes.fetch_add(nodes, Ordering::Relaxed);
+        let expanded = before + nodes;
+        if self.max_expanded_nodes.is_some_and(|max| expanded > max) {
+            self.exceeded.store(true, Ordering::Relaxed);
+            return true;
+        }
+        if before / CLOCK_INTERVAL != expanded / CLOCK_INTERVAL {
+            return self.stopped();
+        }
+        self.cancelled() || self.exceeded()
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_some_and(|c| c.load(Ordering::Relaxed))
+    }
+
+    /// Whether the search ran out of budget, so that it may have missed routes
+    pub fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,439 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{HaError, RailroadData};
+use std::io::{ErrorKind, Read, Write};
+
+/// The first bytes of every HaRail database file
+const MAGIC: &[u8; 8] = b"HARAILDB";
+
+/// The version of the database file format written by this version of HaRail.
+///
+/// Bump it whenever the serialized layout of RailroadData changes, and teach `RailroadData::load` to convert files of
+/// the previous version when possible.
+pub const DATABASE_VERSION: u32 = 10;
+
+/// Files written before the format was versioned have no header, and are treated as this version
+const UNVERSIONED: u32 = 1;
+
+/// How the data following the header is stored
+const UNCOMPRESSED: u32 = 0;
+const ZSTD: u32 = 1;
+
+fn io_error(e: impl ToString) -> HaError {
+    HaError::DatabaseError(e.to_string())
+}
+
+fn write_header<W: Write>(writer: &mut W, compression: u32) -> Result<(), HaError> {
+    writer.write_all(MAGIC).map_err(io_error)?;
+    writer
+        .write_all(&DATABASE_VERSION.to_le_bytes())
+        .map_err(io_error)?;
+    writer
+        .write_all(&compression.to_le_bytes())
+        .map_err(io_error)
+}
+
+fn deserialize<R: Read, T: serde::de::DeserializeOwned>(reader: R) -> Result<T, HaError> {
+    bincode::deserialize_from(reader).map_err(io_error)
+}
+
+/// The layouts of databases written by older versions, which are converted when loaded
+mod legacy {
+    use crate::fares::FareRules;
+    use crate::{HaDuration, Mode, ShapeId, StationId, TrainId};
+    use chrono::{NaiveDate, NaiveDateTime};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    /// Stations had no wheelchair accessibility up to version 5
+    #[derive(Deserialize)]
+    pub struct StationV5 {
+        id: StationId,
+        name: String,
+        lat: Option<f64>,
+        lon: Option<f64>,
+    }
+
+    impl From<StationV5> for crate::Station {
+        fn from(old: StationV5) -> Self {
+            StationV7 {
+                id: old.id,
+                name: old.name,
+                lat: old.lat,
+                lon: old.lon,
+                wheelchair_boarding: None,
+            }
+            .into()
+        }
+    }
+
+    /// Stations had no fare zone up to version 7
+    #[derive(Deserialize)]
+    pub struct StationV7 {
+        id: StationId,
+        name: String,
+        lat: Option<f64>,
+        lon: Option<f64>,
+        wheelchair_boarding: Option<bool>,
+    }
+
+    impl From<StationV7> for crate::Station {
+        fn from(old: StationV7) -> Self {
+            let station = match (old.lat, old.lon) {
+                (Some(lat), Some(lon)) => {
+                    crate::Station::with_coordinates(old.id, &old.name, lat, lon)
+                }
+                _ => crate::Station::new(old.id, &old.name),
+            };
+            match old.wheelchair_boarding {
+                Some(boarding) => station.with_wheelchair_boarding(boarding),
+                None => station,
+            }
+        }
+    }
+
+    /// Stops had no platform up to version 4
+    #[derive(Deserialize)]
+    pub struct StopScheduleV4 {
+        station: StationId,
+        arrival_offset: HaDuration,
+        departure_offset: HaDuration,
+    }
+
+    impl From<StopScheduleV4> for crate::StopSchedule {
+        fn from(old: StopScheduleV4) -> Self {
+            crate::StopSchedule::new(old.station, old.arrival_offset, Some(old.departure_offset))
+        }
+    }
+
+    /// Trains had no mode up to version 3, and were all Israel Railways trains
+    #[derive(Deserialize)]
+    pub struct TrainV3 {
+        id: TrainId,
+        stops: Vec<StopScheduleV4>,
+        dates: Vec<NaiveDate>,
+        shape: Option<ShapeId>,
+    }
+
+    impl From<TrainV3> for crate::Train {
+        fn from(old: TrainV3) -> Self {
+            TrainV4 {
+                id: old.id,
+                stops: old.stops,
+                dates: old.dates,
+                shape: old.shape,
+                mode: Mode::Rail,
+            }
+            .into()
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct TrainV4 {
+        id: TrainId,
+        stops: Vec<StopScheduleV4>,
+        dates: Vec<NaiveDate>,
+        shape: Option<ShapeId>,
+        mode: Mode,
+    }
+
+    impl From<TrainV4> for crate::Train {
+        fn from(old: TrainV4) -> Self {
+            TrainV5 {
+                id: old.id,
+                stops: old.stops.into_iter().map(Into::into).collect(),
+                dates: old.dates,
+                shape: old.shape,
+                mode: old.mode,
+            }
+            .into()
+        }
+    }
+
+    /// Trains had no wheelchair accessibility up to version 5
+    #[derive(Deserialize)]
+    pub struct TrainV5 {
+        id: TrainId,
+        stops: Vec<crate::StopSchedule>,
+        dates: Vec<NaiveDate>,
+        shape: Option<ShapeId>,
+        mode: Mode,
+    }
+
+    impl From<TrainV5> for crate::Train {
+        fn from(old: TrainV5) -> Self {
+            TrainV6 {
+                id: old.id,
+                stops: old.stops,
+                dates: old.dates,
+                shape: old.shape,
+                mode: old.mode,
+                wheelchair_accessible: None,
+            }
+            .into()
+        }
+    }
+
+    /// Trains had no bicycle permission up to version 6
+    #[derive(Deserialize)]
+    pub struct TrainV6 {
+        id: TrainId,
+        stops: Vec<crate::StopSchedule>,
+        dates: Vec<NaiveDate>,
+        shape: Option<ShapeId>,
+        mode: Mode,
+        wheelchair_accessible: Option<bool>,
+    }
+
+    impl From<TrainV6> for crate::Train {
+        fn from(old: TrainV6) -> Self {
+            TrainV7 {
+                id: old.id,
+                stops: old.stops,
+                dates: old.dates,
+                shape: old.shape,
+                mode: old.mode,
+                wheelchair_accessible: old.wheelchair_accessible,
+                bikes_allowed: None,
+            }
+            .into()
+        }
+    }
+
+    /// Trains had no GTFS route up to version 7
+    #[derive(Deserialize)]
+    pub struct TrainV7 {
+        id: TrainId,
+        stops: Vec<crate::StopSchedule>,
+        dates: Vec<NaiveDate>,
+        shape: Option<ShapeId>,
+        mode: Mode,
+        wheelchair_accessible: Option<bool>,
+        bikes_allowed: Option<bool>,
+    }
+
+    impl From<TrainV7> for crate::Train {
+        fn from(old: TrainV7) -> Self {
+            let mut train =
+                crate::Train::from_stops_dates(&old.id, old.stops, old.dates).with_mode(old.mode);
+            if let Some(shape) = old.shape {
+                train = train.with_shape(&shape);
+            }
+            if let Some(accessible) = old.wheelchair_accessible {
+                train = train.with_wheelchair_accessible(accessible);
+            }
+            if let Some(allowed) = old.bikes_allowed {
+                train = train.with_bikes_allowed(allowed);
+            }
+            train
+        }
+    }
+
+    /// Trains had no short name up to version 8
+    #[derive(Deserialize)]
+    pub struct TrainV8 {
+        id: TrainId,
+        stops: Vec<crate::StopSchedule>,
+        dates: Vec<NaiveDate>,
+        shape: Option<ShapeId>,
+        mode: Mode,
+        wheelchair_accessible: Option<bool>,
+        bikes_allowed: Option<bool>,
+        route_id: Option<u64>,
+    }
+
+    impl From<TrainV8> for crate::Train {
+        fn from(old: TrainV8) -> Self {
+            let route_id = old.route_id;
+            let train: crate::Train = TrainV7 {
+                id: old.id,
+                stops: old.stops,
+                dates: old.dates,
+                shape: old.shape,
+                mode: old.mode,
+                wheelchair_accessible: old.wheelchair_accessible,
+                bikes_allowed: old.bikes_allowed,
+            }
+            .into();
+            match route_id {
+                Some(route_id) => train.with_route_id(route_id),
+                None => train,
+            }
+        }
+    }
+
+    /// Databases had no fares up to version 7
+    #[derive(Deserialize)]
+    pub struct RailroadData<S, T> {
+        stations: HashMap<StationId, S>,
+        trains: HashMap<TrainId, T>,
+        transfers: HashMap<StationId, HaDuration>,
+        shapes: HashMap<ShapeId, Vec<(f64, f64)>>,
+        built: Option<NaiveDateTime>,
+    }
+
+    impl<S: Into<crate::Station>, T: Into<crate::Train>> From<RailroadData<S, T>>
+        for crate::RailroadData
+    {
+        fn from(old: RailroadData<S, T>) -> Self {
+            let stations = old.stations.into_values().map(Into::into).collect();
+            let trains = old.trains.into_values().map(Into::into).collect();
+            let mut data = crate::RailroadData::from_stations_trains(stations, trains);
+            for (station, duration) in old.transfers {
+                data.set_min_transfer_time(station, duration);
+            }
+            for (id, points) in old.shapes {
+                data.set_shape(&id, points);
+            }
+            data.set_build_time(old.built);
+            data
+        }
+    }
+
+    /// Trains had no direction up to version 9
+    #[derive(Deserialize)]
+    pub struct TrainV9 {
+        id: TrainId,
+        stops: Vec<crate::StopSchedule>,
+        dates: Vec<NaiveDate>,
+        shape: Option<ShapeId>,
+        mode: Mode,
+        wheelchair_accessible: Option<bool>,
+        bikes_allowed: Option<bool>,
+        route_id: Option<u64>,
+        short_name: Option<String>,
+    }
+
+    impl From<TrainV9> for crate::Train {
+        fn from(old: TrainV9) -> Self {
+            let short_name = old.short_name;
+            let train: crate::Train = TrainV8 {
+                id: old.id,
+                stops: old.stops,
+                dates: old.dates,
+                shape: old.shape,
+                mode: old.mode,
+                wheelchair_accessible: old.wheelchair_accessible,
+                bikes_allowed: old.bikes_allowed,
+                route_id: old.route_id,
+            }
+            .into();
+            match short_name {
+                Some(short_name) => train.with_short_name(&short_name),
+                None => train,
+            }
+        }
+    }
+
+    /// The layout of version 8 and 9 databases, whose trains are converted
+    #[derive(Deserialize)]
+    pub struct RailroadDataV8<T> {
+        stations: HashMap<StationId, crate::Station>,
+        trains: HashMap<TrainId, T>,
+        transfers: HashMap<StationId, HaDuration>,
+        shapes: HashMap<ShapeId, Vec<(f64, f64)>>,
+        built: Option<NaiveDateTime>,
+        fares: FareRules,
+    }
+
+    impl<T: Into<crate::Train>> From<RailroadDataV8<T>> for crate::RailroadData {
+        fn from(old: RailroadDataV8<T>) -> Self {
+            let mut data: crate::RailroadData = RailroadData {
+                stations: old.stations,
+                trains: old.trains,
+                transfers: old.transfers,
+                shapes: old.shapes,
+                built: old.built,
+            }
+            .into();
+            data.set_fares(old.fares);
+            data
+        }
+    }
+}
+
+impl RailroadData {
+    /// Writes the database in HaRail's file format: a magic number, the format version and the compression method,
+    /// followed by the data itself
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), HaError> {
+        write_header(&mut writer, UNCOMPRESSED)?;
+        bincode::serialize_into(writer, self).map_err(io_error)
+    }
+
+    /// Writes the database like `save`, compressing the data with zstd at the given level
+    #[cfg(feature = "zstd")]
+    pub fn save_compressed<W: Write>(&self, mut writer: W, level: i32) -> Result<(), HaError> {
+        write_header(&mut writer, ZSTD)?;
+        let mut encoder = zstd::Encoder::new(writer, level).map_err(io_error)?;
+        bincode::serialize_into(&mut encoder, self).map_err(io_error)?;
+        encoder.finish().map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Reads a database written by `save` or `save_compressed`, converting databases written in older versions of the
+    /// format
+    pub fn load<R: Read>(mut reader: R) -> Result<Self, HaError> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => HaError::DatabaseTooOld,
+            _ => io_error(e),
+        })?;
+        if &header[..8] != MAGIC {
+            return Self::migrate(UNVERSIONED, (&header[..]).chain(reader));
+        }
+        let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        Self::migrate(version, reader)
+    }
+
+    fn migrate<R: Read>(version: u32, mut reader: R) -> Result<Self, HaError> {
+        match version {
+            3..=DATABASE_VERSION => {
+                let mut compression = [0u8; 4];
+                reader.read_exact(&mut compression).map_err(io_error)?;
+                match u32::from_le_bytes(compression) {
+                    UNCOMPRESSED => Self::upgrade(version, reader),
+                    #[cfg(feature = "zstd")]
+                    ZSTD => Self::upgrade(version, zstd::Decoder::new(reader).map_err(io_error)?),
+                    #[cfg(not(feature = "zstd"))]
+                    ZSTD => Err(HaError::DatabaseError(
+                        "the database is compressed with zstd, which this build of HaRail cannot read"
+                            .to_owned(),
+                    )),
+                    compression => Err(HaError::DatabaseError(format!(
+                        "unknown compression method {}",
+                        compression
+                    ))),
+                }
+            }
+            // Version 2 files have no compression method in their header, and are never compressed
+            2 => Self::upgrade(version, reader),
+            // Unversioned files have the same layout as version 2, unless they predate the last change to it
+            UNVERSIONED => Self::upgrade(version, reader).map_err(|_| HaError::DatabaseTooOld),
+            version if version > DATABASE_VERSION => Err(HaError::DatabaseTooNew(version)),
+            _ => Err(HaError::DatabaseTooOld),
+        }
+    }
+
+    /// Reads the data following the header, in the layout of the given version
+    fn upgrade<R: Read>(version: u32, reader: R) -> Result<Self, HaError> {
+        match version {
+            DATABASE_VERSION => deserialize(reader),
+            9 => deserialize::<_, legacy::RailroadDataV8<legacy::TrainV9>>(reader).map(Self::from),
+            8 => deserialize::<_, legacy::RailroadDataV8<legacy::TrainV8>>(reader).map(Self::from),
+            7 => deserialize::<_, legacy::RailroadData<legacy::StationV7, legacy::TrainV7>>(reader)
+                .map(Self::from),
+            6 => deserialize::<_, legacy::RailroadData<legacy::StationV7, legacy::TrainV6>>(reader)
+                .map(Self::from),
+            5 => deserialize::<_, legacy::RailroadData<legacy::StationV5, legacy::TrainV5>>(reader)
+                .map(Self::from),
+            4 => deserialize::<_, legacy::RailroadData<legacy::StationV5, legacy::TrainV4>>(reader)
+                .map(Self::from),
+            _ => deserialize::<_, legacy::RailroadData<legacy::StationV5, legacy::TrainV3>>(reader)
+                .map(Self::from),
+        }
+    }
+}
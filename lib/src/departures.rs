@@ -0,0 +1,166 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::gtfs_rt::{Occupancy, RealtimeUpdates};
+use crate::{localize_in, RailroadData, Station, Stop, Train, Zoned, FEED_TIMEZONE, JSON};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use jzon::JsonValue;
+use std::fmt;
+
+/// A train leaving a station, as shown on a departures board
+pub struct Departure<'a> {
+    train: &'a Train,
+    stop: Stop<'a>,
+    destination: &'a Station,
+    date: NaiveDate,
+}
+
+impl<'a> Departure<'a> {
+    /// The departing train
+    pub fn train(&self) -> &Train {
+        self.train
+    }
+
+    /// The train's stop at the departure station
+    pub fn stop(&self) -> Stop<'_> {
+        self.stop
+    }
+
+    /// The last station of the departing train
+    pub fn destination(&self) -> &Station {
+        self.destination
+    }
+
+    /// The expected crowding of the departing train, if the real-time updates give it
+    pub fn occupancy(&self, realtime: &RealtimeUpdates) -> Option<Occupancy> {
+        realtime.occupancy(self.train, self.stop.station().id(), self.stop.departure())
+    }
+
+    /// The time the train is expected to leave the station according to the real-time updates, or None if its run was
+    /// cancelled or it skips the station
+    pub fn expected_departure(
+        &self,
+        data: &RailroadData,
+        realtime: &RealtimeUpdates,
+    ) -> Option<NaiveDateTime> {
+        realtime
+            .stops(data, self.train, self.date)?
+            .into_iter()
+            .find(|s| s.station.id() == self.stop.station.id())
+            .map(|s| s.departure)
+    }
+}
+
+impl Zoned for Departure<'_> {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        let departure = localize_in(self.stop.departure(), timezone);
+        let mut result = object! {
+            train: self.train.id().to_owned(),
+            departure: departure.to_rfc3339(),
+            destination: self.destination.id(),
+            destination_name: self.destination.name().to_owned(),
+        };
+        if let Some(number) = self.train.short_name() {
+            result["train_number"] = number.into();
+        }
+        if let Some(direction_id) = self.train.direction_id() {
+            result["direction_id"] = direction_id.into();
+        }
+        if let Some(platform) = self.stop.platform() {
+            result["platform"] = platform.into();
+        }
+        result
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        write!(
+            f,
+            "{} {} to {}",
+            localize_in(self.stop.departure(), timezone).time(),
+            self.train.number(),
+            self.destination.name()
+        )
+    }
+}
+
+impl JSON for Departure<'_> {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for Departure<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+/// Finds the next trains leaving the given station, starting at the given time.
+///
+/// Departures are ordered by departure time, and at most limit departures are returned.
+/// Trains are not considered as leaving the station where they terminate.
+pub fn get_departures<'a>(
+    data: &'a RailroadData,
+    station: &Station,
+    from: NaiveDateTime,
+    limit: usize,
+) -> Vec<Departure<'a>> {
+    get_departures_in_direction(data, station, from, limit, None)
+}
+
+/// Finds the next trains leaving the given station like get_departures, only listing trains with the given GTFS
+/// direction_id if one is given.
+///
+/// On a line served in both directions, this lists the trains going one way, as on one side of a station board.
+/// Trains whose direction is unknown are left out when filtering.
+pub fn get_departures_in_direction<'a>(
+    data: &'a RailroadData,
+    station: &Station,
+    from: NaiveDateTime,
+    limit: usize,
+    direction_id: Option<u8>,
+) -> Vec<Departure<'a>> {
+    // Trains running past midnight belong to the previous service date
+    let first_date = (from - Duration::days(1)).date();
+    let last_date = (from + Duration::days(1)).date();
+    let mut result = Vec::new();
+    for train in data
+        .trains()
+        .filter(|t| direction_id.is_none() || t.direction_id() == direction_id)
+    {
+        let stops: Vec<_> = train.stops().collect();
+        let (last, stops) = match stops.split_last() {
+            Some(x) => x,
+            None => continue,
+        };
+        let destination = data.station(last.station()).unwrap();
+        for stop in stops.iter().filter(|s| s.station() == station.id()) {
+            for date in train
+                .dates()
+                .filter(|&x| x >= &first_date && x <= &last_date)
+            {
+                let stop = Stop::from_stop_schedule(data, stop, *date);
+                if stop.departure() >= from {
+                    result.push(Departure {
+                        train,
+                        stop,
+                        destination,
+                        date: *date,
+                    });
+                }
+            }
+        }
+    }
+    result.sort_by(|a, b| {
+        a.stop
+            .departure()
+            .cmp(&b.stop.departure())
+            .then_with(|| a.train.id().cmp(b.train.id()))
+    });
+    result.truncate(limit);
+    result
+}
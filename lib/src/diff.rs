@@ -0,0 +1,172 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{RailroadData, StopSchedule, Train, TrainId, JSON};
+use chrono::NaiveDate;
+use jzon::JsonValue;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A difference in the schedule of a train between two versions of a database
+#[derive(PartialEq, Eq, Debug)]
+pub enum Change {
+    /// A train is only in the new database
+    TrainAdded { train: TrainId },
+    /// A train is only in the old database
+    TrainRemoved { train: TrainId },
+    /// A train stops at different stations or times.
+    ///
+    /// The stop is given by the index of the first of the train's stops which differs.
+    StopsChanged { train: TrainId, stop: usize },
+    /// A train runs on different dates
+    DatesChanged {
+        train: TrainId,
+        added: Vec<NaiveDate>,
+        removed: Vec<NaiveDate>,
+    },
+}
+
+impl Change {
+    /// A short name of the kind of change, as used in JSON
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::TrainAdded { .. } => "train_added",
+            Change::TrainRemoved { .. } => "train_removed",
+            Change::StopsChanged { .. } => "stops_changed",
+            Change::DatesChanged { .. } => "dates_changed",
+        }
+    }
+
+    /// The train which changed
+    pub fn train(&self) -> &TrainId {
+        match self {
+            Change::TrainAdded { train }
+            | Change::TrainRemoved { train }
+            | Change::StopsChanged { train, .. }
+            | Change::DatesChanged { train, .. } => train,
+        }
+    }
+}
+
+fn format_dates(dates: &[NaiveDate]) -> String {
+    dates
+        .iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::TrainAdded { train } => write!(f, "train {} was added", train),
+            Change::TrainRemoved { train } => write!(f, "train {} was removed", train),
+            Change::StopsChanged { train, stop } => {
+                write!(f, "train {} changed its stops from stop {}", train, stop)
+            }
+            Change::DatesChanged {
+                train,
+                added,
+                removed,
+            } => {
+                write!(f, "train {} changed its dates", train)?;
+                if !added.is_empty() {
+                    write!(f, ", now running on {}", format_dates(added))?;
+                }
+                if !removed.is_empty() {
+                    write!(f, ", no longer running on {}", format_dates(removed))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl JSON for Change {
+    fn to_json(&self) -> JsonValue {
+        let mut json = object! {
+            kind: self.kind(),
+            train: self.train().as_str(),
+        };
+        match self {
+            Change::StopsChanged { stop, .. } => json["stop"] = (*stop).into(),
+            Change::DatesChanged { added, removed, .. } => {
+                json["added"] = added
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .into();
+                json["removed"] = removed
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .into();
+            }
+            Change::TrainAdded { .. } | Change::TrainRemoved { .. } => {}
+        }
+        json
+    }
+}
+
+fn same_stop(old: &StopSchedule, new: &StopSchedule) -> bool {
+    old.station() == new.station()
+        && old.arrival_offset().to_chrono() == new.arrival_offset().to_chrono()
+        && old.departure_offset().to_chrono() == new.departure_offset().to_chrono()
+}
+
+/// The changes to a train which is in both databases
+fn train_changes(old: &Train, new: &Train) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut old_stops = old.stops();
+    let mut new_stops = new.stops();
+    let mut stop = 0;
+    loop {
+        match (old_stops.next(), new_stops.next()) {
+            (None, None) => break,
+            (Some(old), Some(new)) if same_stop(old, new) => stop += 1,
+            _ => {
+                changes.push(Change::StopsChanged {
+                    train: new.id().clone(),
+                    stop,
+                });
+                break;
+            }
+        }
+    }
+    let old_dates: BTreeSet<_> = old.dates().copied().collect();
+    let new_dates: BTreeSet<_> = new.dates().copied().collect();
+    if old_dates != new_dates {
+        changes.push(Change::DatesChanged {
+            train: new.id().clone(),
+            added: new_dates.difference(&old_dates).copied().collect(),
+            removed: old_dates.difference(&new_dates).copied().collect(),
+        });
+    }
+    changes
+}
+
+/// Compares the schedules of two versions of a database, returning the changes ordered by train.
+///
+/// Trains are matched by their ID, as GTFS feeds keep the IDs of trips from one version to the next.
+pub fn diff(old: &RailroadData, new: &RailroadData) -> Vec<Change> {
+    let mut trains: BTreeMap<&TrainId, (Option<&Train>, Option<&Train>)> = BTreeMap::new();
+    for train in old.trains() {
+        trains.entry(train.id()).or_default().0 = Some(train);
+    }
+    for train in new.trains() {
+        trains.entry(train.id()).or_default().1 = Some(train);
+    }
+    let mut changes = Vec::new();
+    for (id, versions) in trains {
+        match versions {
+            (Some(old), Some(new)) => changes.extend(train_changes(old, new)),
+            (None, Some(_)) => changes.push(Change::TrainAdded { train: id.clone() }),
+            (Some(_), None) => changes.push(Change::TrainRemoved { train: id.clone() }),
+            (None, None) => {}
+        }
+    }
+    changes
+}
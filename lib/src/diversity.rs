@@ -0,0 +1,78 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::explanation::cost;
+use crate::{Route, StationId};
+use std::collections::HashSet;
+
+/// What tells a route apart from others to passengers: the trains and lines it rides and where it switches trains
+#[derive(PartialEq, Eq, Hash)]
+enum Feature<'a> {
+    Train(&'a str),
+    Line(u64),
+    Transfer(StationId),
+}
+
+fn features<'a>(route: &Route<'a>) -> HashSet<Feature<'a>> {
+    let mut result = HashSet::new();
+    for part in &route.parts {
+        result.insert(Feature::Train(part.train.id()));
+        if let Some(line) = part.train.route_id() {
+            result.insert(Feature::Line(line));
+        }
+    }
+    for part in route.parts.iter().skip(1) {
+        result.insert(Feature::Transfer(part.start.station().id()));
+    }
+    result
+}
+
+/// How much two routes have in common, from 0 for routes sharing no train, line or transfer station to 1 for routes
+/// which are the same to passengers
+fn similarity(a: &HashSet<Feature>, b: &HashSet<Feature>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Orders routes so that meaningfully different options come first and near-duplicates last.
+///
+/// The fastest route comes first, followed each time by the route least similar to the routes before it, so routes
+/// riding other lines or switching trains at other stations move up, while routes riding the same trains as an
+/// earlier route, e.g. only boarding them one stop later, move down. Equally similar routes are ordered from the
+/// fastest, keeping their order when they are just as fast.
+pub fn rank_by_diversity(routes: &mut Vec<Route>) {
+    let features: Vec<_> = routes.iter().map(features).collect();
+    let costs: Vec<_> = routes.iter().map(cost).collect();
+    let mut remaining: Vec<usize> = (0..routes.len()).collect();
+    let mut order: Vec<usize> = Vec::with_capacity(routes.len());
+    while !remaining.is_empty() {
+        let closeness = |i: usize| {
+            order
+                .iter()
+                .map(|&j| similarity(&features[i], &features[j]))
+                .fold(0.0, f64::max)
+        };
+        let (position, _) = remaining
+            .iter()
+            .enumerate()
+            .min_by(|&(_, &a), &(_, &b)| {
+                closeness(a)
+                    .total_cmp(&closeness(b))
+                    .then_with(|| costs[a].cmp(&costs[b]))
+            })
+            .unwrap();
+        order.push(remaining.remove(position));
+    }
+    let mut routes_by_index: Vec<_> = routes.drain(..).map(Some).collect();
+    routes.extend(
+        order
+            .into_iter()
+            .map(|i| routes_by_index[i].take().unwrap()),
+    );
+}
@@ -5,4 +5,92 @@ pub enum HaError {
     UsageError(String),
     #[error("GTFS parse failed: {0}")]
     GTFSError(String),
+    #[error("GTFS parse failed: {0}")]
+    Gtfs(#[from] GtfsError),
+    #[error("Search cancelled")]
+    Cancelled,
+    #[error("Search budget exceeded")]
+    BudgetExceeded,
+    #[error("Not a HaRail database, or one written by an older version of HaRail; re-run parse-gtfs to rebuild it")]
+    DatabaseTooOld,
+    #[error("Database format version {0} is newer than this version of HaRail supports")]
+    DatabaseTooNew(u32),
+    #[error("Database I/O failed: {0}")]
+    DatabaseError(String),
+}
+
+/// A problem with a GTFS feed, or with the zone and fare tables of a pricing model, along with the file, line and
+/// column it was found at, so that the file can be fixed
+#[derive(Error, Debug)]
+pub enum GtfsError {
+    #[error("{file} not found")]
+    MissingFile { file: String },
+    #[error("{file}: missing column {column}")]
+    MissingColumn { file: String, column: &'static str },
+    #[error("{file}, line {line}: missing {column}")]
+    MissingValue {
+        file: String,
+        line: u64,
+        column: &'static str,
+    },
+    #[error("{file}, line {line}: invalid {column} {value:?}")]
+    InvalidValue {
+        file: String,
+        line: u64,
+        column: &'static str,
+        value: String,
+    },
+    #[error("{file}: {message}")]
+    Invalid { file: String, message: String },
+    #[error("{file}: {source}")]
+    Csv {
+        file: String,
+        #[source]
+        source: csv::Error,
+    },
+    #[error("{file}: {source}")]
+    Io {
+        file: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{file}: not a valid archive: {message}")]
+    Archive { file: String, message: String },
+}
+
+impl GtfsError {
+    /// The file of the feed, or the feed itself, in which the problem was found
+    pub fn file(&self) -> &str {
+        match self {
+            GtfsError::MissingFile { file }
+            | GtfsError::MissingColumn { file, .. }
+            | GtfsError::MissingValue { file, .. }
+            | GtfsError::InvalidValue { file, .. }
+            | GtfsError::Invalid { file, .. }
+            | GtfsError::Csv { file, .. }
+            | GtfsError::Io { file, .. }
+            | GtfsError::Archive { file, .. } => file,
+        }
+    }
+
+    /// The line of the file at which the problem was found, counting the header as line 1, if it concerns a single line
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            GtfsError::MissingValue { line, .. } | GtfsError::InvalidValue { line, .. } => {
+                Some(*line)
+            }
+            GtfsError::Csv { source, .. } => source.position().map(|p| p.line()),
+            _ => None,
+        }
+    }
+
+    /// The column in which the problem was found, if it concerns a single column
+    pub fn column(&self) -> Option<&'static str> {
+        match self {
+            GtfsError::MissingColumn { column, .. }
+            | GtfsError::MissingValue { column, .. }
+            | GtfsError::InvalidValue { column, .. } => Some(column),
+            _ => None,
+        }
+    }
 }
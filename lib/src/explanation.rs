@@ -0,0 +1,114 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{Route, JSON};
+use chrono::Duration;
+use jzon::JsonValue;
+use serde::{Deserialize, Serialize};
+
+/// Why a route was chosen: how its time is spent, and how it compares to the other routes found by the same search, so
+/// that clients can show e.g. "fastest" or "fewest changes" badges
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub struct Explanation {
+    ride_seconds: i64,
+    /// The seconds waited at each train switch
+    wait_seconds: Vec<i64>,
+    transfers: usize,
+    fastest: bool,
+    fewest_transfers: bool,
+    tied: bool,
+}
+
+impl Explanation {
+    /// The time spent riding trains, including the time trains wait at their stops
+    pub fn ride_time(&self) -> Duration {
+        Duration::seconds(self.ride_seconds)
+    }
+
+    /// The time spent waiting at each train switch, in order
+    pub fn waits(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.wait_seconds.iter().map(|&s| Duration::seconds(s))
+    }
+
+    /// The total time spent waiting at train switches
+    pub fn wait_time(&self) -> Duration {
+        Duration::seconds(self.wait_seconds.iter().sum())
+    }
+
+    /// The number of train switches
+    pub fn transfers(&self) -> usize {
+        self.transfers
+    }
+
+    /// Whether no other route found by the search takes less time from departure to arrival
+    pub fn is_fastest(&self) -> bool {
+        self.fastest
+    }
+
+    /// Whether no other route found by the search switches trains fewer times
+    pub fn has_fewest_transfers(&self) -> bool {
+        self.fewest_transfers
+    }
+
+    /// Whether another route found by the search takes as long and switches trains as many times
+    pub fn is_tied(&self) -> bool {
+        self.tied
+    }
+}
+
+impl JSON for Explanation {
+    fn to_json(&self) -> JsonValue {
+        object! {
+            ride_seconds: self.ride_seconds,
+            wait_seconds: self.wait_seconds.clone(),
+            transfers: self.transfers,
+            fastest: self.fastest,
+            fewest_transfers: self.fewest_transfers,
+            tied: self.tied,
+        }
+    }
+}
+
+/// The time from departure to arrival and the number of train switches of a route
+pub(crate) fn cost(route: &Route) -> (Duration, usize) {
+    let duration = match (route.parts.first(), route.parts.last()) {
+        (Some(first), Some(last)) => last.end.arrival() - first.start.departure(),
+        _ => Duration::zero(),
+    };
+    (duration, route.parts.len().saturating_sub(1))
+}
+
+/// Explains each of the routes found by a search, comparing them with each other.
+///
+/// Routes which were explained before are explained again, relative to the given routes.
+pub fn explain(routes: &mut [Route]) {
+    let costs: Vec<_> = routes.iter().map(cost).collect();
+    let fastest = costs.iter().map(|c| c.0).min();
+    let fewest_transfers = costs.iter().map(|c| c.1).min();
+    for (i, route) in routes.iter_mut().enumerate() {
+        let ride_seconds = route
+            .parts
+            .iter()
+            .map(|p| (p.end.arrival() - p.start.departure()).num_seconds())
+            .sum();
+        let wait_seconds = route
+            .parts
+            .windows(2)
+            .map(|w| (w[1].start.departure() - w[0].end.arrival()).num_seconds())
+            .collect();
+        route.explanation = Some(Explanation {
+            ride_seconds,
+            wait_seconds,
+            transfers: costs[i].1,
+            fastest: Some(costs[i].0) == fastest,
+            fewest_transfers: Some(costs[i].1) == fewest_transfers,
+            tied: costs
+                .iter()
+                .enumerate()
+                .any(|(j, c)| j != i && *c == costs[i]),
+        });
+    }
+}
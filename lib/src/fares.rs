@@ -0,0 +1,139 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Ticket prices given by the fare_attributes.txt and fare_rules.txt files of a GTFS feed.
+//!
+//! Each train ride is charged the cheapest fare whose rules match the ride's GTFS route and the fare zones of the
+//! stations it is boarded and left at, and a route is charged the sum of its rides. Transfer allowances are not taken
+//! into account, and rules restricting a fare by the zones a ride passes through (contains_id) are ignored.
+
+use crate::gtfs::reader::GtfsReader;
+use crate::pricing::Fare;
+use crate::{GtfsError, Route, Station, Train};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::prelude::*;
+
+/// A condition under which a fare applies, as given by a line of fare_rules.txt
+#[derive(Serialize, Deserialize)]
+struct FareRule {
+    fare_id: String,
+    route_id: Option<u64>,
+    origin_id: Option<String>,
+    destination_id: Option<String>,
+}
+
+impl FareRule {
+    fn matches(&self, train: &Train, start: &Station, end: &Station) -> bool {
+        let matches = |rule: &Option<String>, zone: Option<&str>| {
+            rule.as_deref().is_none_or(|rule| Some(rule) == zone)
+        };
+        self.route_id
+            .is_none_or(|route| Some(route) == train.route_id())
+            && matches(&self.origin_id, start.fare_zone())
+            && matches(&self.destination_id, end.fare_zone())
+    }
+}
+
+/// The fares of a GTFS feed and the rules by which they apply
+#[derive(Default, Serialize, Deserialize)]
+pub struct FareRules {
+    prices: HashMap<String, Fare>,
+    rules: Vec<FareRule>,
+    /// The fares which have rules, as opposed to ones applying to every ride
+    restricted: HashSet<String>,
+}
+
+/// Parses a GTFS price, given in shekels, e.g. 5.5 for 550 agorot
+fn parse_price(price: &str) -> Option<Fare> {
+    let (shekels, agorot) = price.split_once('.').unwrap_or((price, ""));
+    let agorot = format!("{:0<2}", agorot);
+    if agorot.len() > 2 {
+        return None;
+    }
+    Some(Fare::from_agorot(
+        shekels.parse::<u32>().ok()? * 100 + agorot.parse::<u32>().ok()?,
+    ))
+}
+
+impl FareRules {
+    /// Create a new FareRules object without any fares
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the fares of fare_attributes.txt
+    pub(crate) fn parse_attributes<R: Read>(&mut self, reader: R) -> Result<(), GtfsError> {
+        let mut reader = GtfsReader::new("fare_attributes.txt", reader);
+        let [fare_id, price] = reader.columns(["fare_id", "price"])?;
+        for record in reader.records() {
+            let record = record?;
+            let fare_id = record.get(fare_id)?;
+            let value = record.get(price)?;
+            let fare = parse_price(value).ok_or_else(|| record.invalid(price, value))?;
+            self.prices.insert(fare_id.to_owned(), fare);
+        }
+        Ok(())
+    }
+
+    /// Loads the rules of fare_rules.txt. Fares without any rules apply to every ride.
+    pub(crate) fn parse_rules<R: Read>(&mut self, reader: R) -> Result<(), GtfsError> {
+        let mut reader = GtfsReader::new("fare_rules.txt", reader);
+        let [fare_id] = reader.columns(["fare_id"])?;
+        let [route_id, origin_id, destination_id, contains_id] =
+            reader.optional_columns(["route_id", "origin_id", "destination_id", "contains_id"])?;
+        for record in reader.records() {
+            let record = record?;
+            let fare_id = record.get(fare_id)?;
+            self.restricted.insert(fare_id.to_owned());
+            // The fare is still restricted by the ignored rule, so that it isn't applied to every ride
+            if record.optional(contains_id).is_some() {
+                continue;
+            }
+            self.rules.push(FareRule {
+                fare_id: fare_id.to_owned(),
+                route_id: record.parse_optional(route_id)?,
+                origin_id: record.optional(origin_id).map(str::to_owned),
+                destination_id: record.optional(destination_id).map(str::to_owned),
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the feed had no fares
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    /// Gets the cheapest fare of a ride on the given train between the given stations, if any fare applies to it
+    pub fn ride_price(&self, train: &Train, start: &Station, end: &Station) -> Option<Fare> {
+        self.prices
+            .iter()
+            .filter(|(fare_id, _)| {
+                !self.restricted.contains(*fare_id)
+                    || self
+                        .rules
+                        .iter()
+                        .any(|r| &r.fare_id == *fare_id && r.matches(train, start, end))
+            })
+            .map(|(_, price)| *price)
+            .min()
+    }
+
+    /// Gets the price of the given route, i.e. the sum of the prices of its rides.
+    ///
+    /// Returns None if there are no fares, or if no fare applies to one of the rides.
+    pub fn price(&self, route: &Route) -> Option<Fare> {
+        if self.is_empty() {
+            return None;
+        }
+        route
+            .parts()
+            .map(|part| self.ride_price(part.train(), part.start().station(), part.end().station()))
+            .try_fold(0, |total, price| Some(total + price?.agorot()))
+            .map(Fare::from_agorot)
+    }
+}
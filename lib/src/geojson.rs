@@ -0,0 +1,121 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{OwnedRoute, RailroadData, Route, Station, StationId};
+use jzon::JsonValue;
+
+fn station_point(station: &Station) -> Option<(f64, f64)> {
+    Some((station.lat()?, station.lon()?))
+}
+
+fn nearest_point(points: &[(f64, f64)], target: (f64, f64)) -> Option<usize> {
+    // Squared differences of degrees are good enough to pick the closest point of a single line
+    let distance = |p: &(f64, f64)| (p.0 - target.0).powi(2) + (p.1 - target.1).powi(2);
+    points
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map(|(i, _)| i)
+}
+
+/// The (latitude, longitude) points of a train's path between two of its stations.
+///
+/// The train's shape is used when known, and otherwise the path goes straight between the coordinates of the stations the train stops at.
+fn part_points(
+    data: &RailroadData,
+    train_id: &str,
+    start: StationId,
+    end: StationId,
+) -> Vec<(f64, f64)> {
+    let train = match data.train(train_id) {
+        Some(train) => train,
+        None => return Vec::new(),
+    };
+    let stops: Vec<StationId> = train.stops().map(|s| s.station()).collect();
+    if stops.is_empty() {
+        return Vec::new();
+    }
+    let first = stops.iter().position(|&s| s == start).unwrap_or(0);
+    let last = stops[first..]
+        .iter()
+        .position(|&s| s == end)
+        .map_or(stops.len().saturating_sub(1), |i| first + i);
+
+    if let (Some(shape), Some(from), Some(to)) = (
+        train.shape().and_then(|id| data.shape(id)),
+        data.station(start).and_then(station_point),
+        data.station(end).and_then(station_point),
+    ) {
+        if let Some(from) = nearest_point(shape, from) {
+            if let Some(to) = nearest_point(&shape[from..], to) {
+                return shape[from..=from + to].to_vec();
+            }
+        }
+    }
+    stops[first..=last]
+        .iter()
+        .filter_map(|&id| data.station(id))
+        .filter_map(station_point)
+        .collect()
+}
+
+fn geojson<'a>(
+    data: &RailroadData,
+    parts: impl Iterator<Item = (&'a str, StationId, StationId)>,
+) -> JsonValue {
+    let features: Vec<JsonValue> = parts
+        .map(|(train, start, end)| {
+            let coordinates: Vec<JsonValue> = part_points(data, train, start, end)
+                .into_iter()
+                // GeoJSON positions are ordered longitude first
+                .map(|(lat, lon)| jzon::array![lon, lat])
+                .collect();
+            object! {
+                type: "Feature",
+                geometry: object! {
+                    type: "LineString",
+                    coordinates: coordinates,
+                },
+                properties: object! {
+                    train: train,
+                    start_station: start,
+                    end_station: end,
+                },
+            }
+        })
+        .collect();
+    object! {
+        type: "FeatureCollection",
+        features: features,
+    }
+}
+
+impl Route<'_> {
+    /// Exports the path of the route as a GeoJSON FeatureCollection, with a LineString feature for each train ride
+    pub fn to_geojson(&self, data: &RailroadData) -> JsonValue {
+        geojson(
+            data,
+            self.parts().map(|p| {
+                (
+                    p.train().id().as_str(),
+                    p.start().station().id(),
+                    p.end().station().id(),
+                )
+            }),
+        )
+    }
+}
+
+impl OwnedRoute {
+    /// Exports the path of the route as a GeoJSON FeatureCollection, with a LineString feature for each train ride
+    pub fn to_geojson(&self, data: &RailroadData) -> JsonValue {
+        geojson(
+            data,
+            self.parts()
+                .map(|p| (p.train().as_str(), p.start().station(), p.end().station())),
+        )
+    }
+}
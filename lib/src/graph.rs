@@ -4,24 +4,30 @@
 * License, v. 2.0. If a copy of the MPL was not distributed with this
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use priority_queue::PriorityQueue;
-use std::collections::HashMap;
+use crate::budget::Budget;
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::hash::Hash;
+use tracing::instrument;
 
 pub trait Weight {
     fn weight(&self) -> i64;
 }
 
-pub struct Node<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> {
+pub struct Node<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight> {
     id: N,
     edges: HashMap<E, N>,
+    /// The indices of the shortcuts leaving the node
+    shortcuts: Vec<usize>,
 }
 
-impl<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> Node<N, E> {
+impl<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight> Node<N, E> {
     pub fn new(id: N) -> Self {
         Node {
             id,
             edges: HashMap::new(),
+            shortcuts: Vec::new(),
         }
     }
 
@@ -36,21 +42,247 @@ impl<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> Node<N, E> {
     pub fn connect(&mut self, edge: E, dest: N) {
         self.edges.insert(edge, dest);
     }
+
+    /// Removes the edges leaving the node for which keep returns false
+    pub fn retain_edges<K: FnMut(&E, &N) -> bool>(&mut self, mut keep: K) {
+        self.edges.retain(|e, n| keep(e, n));
+    }
+}
+
+/// An edge leaving a node, or a shortcut standing for a chain of edges through nodes removed by `Graph::contract`
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Link<E> {
+    Edge(E),
+    Shortcut(usize),
+}
+
+/// A path through a removed node, which searches take as a single edge.
+///
+/// The links into and out of the removed node may be shortcuts themselves, so that long chains are stored in space
+/// proportional to their number of nodes.
+struct Shortcut<N, E> {
+    dest: N,
+    weight: i64,
+    /// The removed node
+    via: N,
+    first: Link<E>,
+    second: Link<E>,
+}
+
+/// A priority queue of integer priorities which never drop below the priority of the item popped last, as in Dijkstra's
+/// algorithm over non-negative integer weights.
+///
+/// Items are kept in a bucket per priority, starting from the priority popped last (Dial's algorithm), so pushing and
+/// popping take time logarithmic only in the number of items of the same priority, rather than in the number of all
+/// queued items. The buckets span the range of the queued priorities, which in a search is bounded by the heaviest edge.
+///
+/// Items of equal priority are popped least first, so that searches settle nodes in the same order in every run
+/// rather than in the order their edges happen to be stored in.
+pub struct BucketQueue<T: Ord + Hash + Copy> {
+    /// The priority of the first bucket
+    base: i64,
+    buckets: VecDeque<BinaryHeap<Reverse<T>>>,
+    /// The current priority of each queued item, as lowering a priority leaves the item's old entry in its bucket
+    priorities: HashMap<T, i64>,
+}
+
+impl<T: Ord + Hash + Copy> BucketQueue<T> {
+    pub fn new() -> Self {
+        BucketQueue {
+            base: 0,
+            buckets: VecDeque::new(),
+            priorities: HashMap::new(),
+        }
+    }
+
+    /// Queues the item with the given priority, or lowers the priority of the item if it is already queued.
+    ///
+    /// A priority below the one popped last, which an inconsistent A* heuristic could ask for, is raised to it.
+    pub fn push(&mut self, item: T, priority: i64) {
+        let priority = priority.max(self.base);
+        match self.priorities.entry(item) {
+            Entry::Occupied(mut e) if *e.get() > priority => {
+                e.insert(priority);
+            }
+            Entry::Occupied(_) => return,
+            Entry::Vacant(e) => {
+                e.insert(priority);
+            }
+        }
+        let index = (priority - self.base) as usize;
+        if index >= self.buckets.len() {
+            self.buckets.resize_with(index + 1, BinaryHeap::new);
+        }
+        self.buckets[index].push(Reverse(item));
+    }
+
+    /// Whether the item is queued, i.e. was pushed and not yet popped
+    pub fn is_queued(&self, item: &T) -> bool {
+        self.priorities.contains_key(item)
+    }
+
+    /// Removes an item of the lowest priority, returning it along with its priority
+    pub fn pop(&mut self) -> Option<(T, i64)> {
+        while let Some(bucket) = self.buckets.front_mut() {
+            while let Some(Reverse(item)) = bucket.pop() {
+                // Entries left behind by lowered priorities are skipped
+                if self.priorities.get(&item) == Some(&self.base) {
+                    self.priorities.remove(&item);
+                    return Some((item, self.base));
+                }
+            }
+            self.buckets.pop_front();
+            self.base += 1;
+        }
+        None
+    }
 }
 
-struct NodeDistance<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> {
+struct NodeDistance<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight> {
     best_cost: i64,
-    best_prev_edge: Option<(N, E)>,
+    best_prev_edge: Option<(N, Link<E>)>,
+}
+
+/// The shortest paths from an origin to every node reachable from it
+pub struct ShortestPathTree<'g, N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight> {
+    graph: &'g Graph<N, E>,
+    origin: N,
+    distances: HashMap<N, NodeDistance<N, E>>,
+}
+
+impl<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight> ShortestPathTree<'_, N, E> {
+    /// The nodes reachable from the origin, including the origin itself
+    pub fn reached(&self) -> impl Iterator<Item = &N> {
+        self.distances
+            .iter()
+            .filter(|(_, d)| d.best_cost != i64::MAX)
+            .map(|(n, _)| n)
+    }
+
+    /// The shortest path from the origin to the given node, if it is reachable
+    pub fn path_to(&self, node: &N) -> Option<Vec<(E, N)>> {
+        if self.distances.get(node)?.best_cost == i64::MAX {
+            return None;
+        }
+        Some(
+            self.graph
+                .dijkstra_backtrace(self.origin, *node, &self.distances),
+        )
+    }
+}
+
+/// A path found by a search before its shortcuts are unpacked, as the links taken and the nodes they lead to
+#[derive(Clone)]
+struct LinkPath<N, E> {
+    cost: i64,
+    /// The nodes along the path, starting with its origin
+    nodes: Vec<N>,
+    links: Vec<Link<E>>,
+}
+
+/// The loopless paths from an origin to the nodes matching a predicate, shortest first, found by Yen's algorithm.
+///
+/// Each path is found by searching from every node of the path before it, avoiding the links which the paths found
+/// so far take from that node after the same start, so each path takes one more search per node of the path before it.
+pub struct ShortestPaths<
+    'g,
+    'b,
+    N: Ord + Hash + Copy,
+    E: Ord + Hash + Copy + Weight,
+    T: Fn(&N) -> bool,
+> {
+    graph: &'g Graph<N, E>,
+    predicate: T,
+    budget: Option<&'b Budget<'b>>,
+    /// The paths returned so far
+    found: Vec<LinkPath<N, E>>,
+    /// The paths branching off the returned paths, of which the shortest is returned next
+    candidates: Vec<LinkPath<N, E>>,
+    /// The links of every path returned or queued, which are not queued again
+    seen: HashSet<Vec<Link<E>>>,
+}
+
+impl<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight, T: Fn(&N) -> bool>
+    ShortestPaths<'_, '_, N, E, T>
+{
+    /// Queues the paths branching off the given path, the last one returned, at each of its nodes
+    fn branch(&mut self, path: &LinkPath<N, E>) {
+        let mut root_cost = 0;
+        for (i, &spur) in path.nodes.iter().enumerate().take(path.links.len()) {
+            let root = &path.links[..i];
+            let avoided_links: HashSet<Link<E>> = self
+                .found
+                .iter()
+                .filter(|p| p.links.len() > i && p.links[..i] == *root)
+                .map(|p| p.links[i])
+                .collect();
+            let avoided_nodes: HashSet<N> = path.nodes[..i].iter().copied().collect();
+            let spur_path = self.graph.find_shortest_path_avoiding(
+                spur,
+                &self.predicate,
+                &avoided_nodes,
+                &avoided_links,
+                self.budget,
+            );
+            if self.budget.is_some_and(|b| b.stopped()) {
+                return;
+            }
+            if let Some(spur_path) = spur_path {
+                let mut links = root.to_vec();
+                links.extend(spur_path.links);
+                if self.seen.insert(links.clone()) {
+                    let mut nodes = path.nodes[..i].to_vec();
+                    nodes.extend(spur_path.nodes);
+                    self.candidates.push(LinkPath {
+                        cost: root_cost + spur_path.cost,
+                        nodes,
+                        links,
+                    });
+                }
+            }
+            root_cost += self.graph.weight(path.links[i]);
+        }
+    }
+}
+
+impl<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight, T: Fn(&N) -> bool> Iterator
+    for ShortestPaths<'_, '_, N, E, T>
+{
+    type Item = Vec<(E, N)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(last) = self.found.last().cloned() {
+            self.branch(&last);
+        }
+        if self.budget.is_some_and(|b| b.stopped()) {
+            return None;
+        }
+        // Of equally short candidates, the one queued first is returned first
+        let (index, _) = self
+            .candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, p)| p.cost)?;
+        let path = self.candidates.remove(index);
+        let mut result = Vec::new();
+        for (link, dest) in path.links.iter().zip(&path.nodes[1..]) {
+            self.graph.unpack(*link, *dest, &mut result);
+        }
+        self.found.push(path);
+        Some(result)
+    }
 }
 
-pub struct Graph<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> {
+pub struct Graph<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight> {
     nodes: HashMap<N, Node<N, E>>,
+    shortcuts: Vec<Shortcut<N, E>>,
 }
 
-impl<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> Graph<N, E> {
+impl<N: Ord + Hash + Copy, E: Ord + Hash + Copy + Weight> Graph<N, E> {
     pub fn new() -> Self {
         Graph {
             nodes: HashMap::new(),
+            shortcuts: Vec::new(),
         }
     }
 
@@ -70,6 +302,159 @@ impl<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> Graph<N, E> {
         self.nodes.values()
     }
 
+    /// Removes a node along with the edges leaving it. Edges leading to the node are left for the caller to remove.
+    ///
+    /// Nodes of a contracted graph may be stood for by shortcuts, so they must not be removed.
+    pub fn remove(&mut self, id: &N) -> Option<Node<N, E>> {
+        debug_assert!(self.shortcuts.is_empty());
+        self.nodes.remove(id)
+    }
+
+    /// The edges and shortcuts leaving a node, along with the nodes they lead to
+    fn links<'g>(&'g self, node: &'g Node<N, E>) -> impl Iterator<Item = (Link<E>, &'g N)> + 'g {
+        node.edges().map(|(e, n)| (Link::Edge(*e), n)).chain(
+            node.shortcuts
+                .iter()
+                .map(|&i| (Link::Shortcut(i), &self.shortcuts[i].dest)),
+        )
+    }
+
+    fn weight(&self, link: Link<E>) -> i64 {
+        match link {
+            Link::Edge(edge) => edge.weight(),
+            Link::Shortcut(i) => self.shortcuts[i].weight,
+        }
+    }
+
+    /// Appends the edges a link stands for to a path, given the node the link leads to
+    fn unpack(&self, link: Link<E>, dest: N, path: &mut Vec<(E, N)>) {
+        match link {
+            Link::Edge(edge) => path.push((edge, dest)),
+            Link::Shortcut(i) => {
+                let shortcut = &self.shortcuts[i];
+                self.unpack(shortcut.first, shortcut.via, path);
+                self.unpack(shortcut.second, dest, path);
+            }
+        }
+    }
+
+    /// Orders two links leading to the same node by the edges they stand for
+    fn link_order(&self, a: Link<E>, b: Link<E>, dest: N) -> Ordering {
+        if a == b {
+            return Ordering::Equal;
+        }
+        let edges = |link| {
+            let mut edges = Vec::new();
+            self.unpack(link, dest, &mut edges);
+            edges
+        };
+        edges(a).cmp(&edges(b))
+    }
+
+    /// Records that a node was reached at the given cost over a link from the previous node, returning whether that
+    /// lowered the node's cost, in which case it needs to be queued at the new cost.
+    ///
+    /// Of the paths reaching a node at the same cost before it is settled, the one from the least previous node is kept,
+    /// and of links from the same node, the one standing for the least edges. Equally short paths are thus chosen the
+    /// same way in every run, rather than by the order of the graph's hash maps.
+    fn relax<P: Ord + Hash + Copy>(
+        &self,
+        distance: &mut NodeDistance<P, E>,
+        cost: i64,
+        prev: (P, Link<E>),
+        dest: N,
+        settled: bool,
+    ) -> bool {
+        if cost < distance.best_cost {
+            distance.best_cost = cost;
+            distance.best_prev_edge = Some(prev);
+            return true;
+        }
+        if cost == distance.best_cost && !settled {
+            if let Some(current) = distance.best_prev_edge {
+                let order = prev
+                    .0
+                    .cmp(&current.0)
+                    .then_with(|| self.link_order(prev.1, current.1, dest));
+                if order == Ordering::Less {
+                    distance.best_prev_edge = Some(prev);
+                }
+            }
+        }
+        false
+    }
+
+    /// The resources consumed by the edges a link stands for
+    fn consumed<R: Fn(&E) -> u32>(&self, link: Link<E>, resource: &R) -> u32 {
+        match link {
+            Link::Edge(edge) => resource(&edge),
+            Link::Shortcut(i) => {
+                let shortcut = &self.shortcuts[i];
+                self.consumed(shortcut.first, resource) + self.consumed(shortcut.second, resource)
+            }
+        }
+    }
+
+    /// Removes the nodes for which keep returns false, replacing the paths through them with shortcuts.
+    ///
+    /// Every path between kept nodes is still taken by searches at the same cost, as a chain of shortcuts, and
+    /// searches unpack the shortcuts into the original edges. Removed nodes can no longer be searched from or to.
+    #[instrument(level = "debug", skip_all)]
+    pub fn contract<K: Fn(&N) -> bool>(&mut self, keep: K) {
+        let mut incoming: HashMap<N, Vec<(N, Link<E>)>> = HashMap::new();
+        for node in self.nodes.values() {
+            for (link, dest) in self.links(node) {
+                incoming.entry(*dest).or_default().push((node.id, link));
+            }
+        }
+        let removed: Vec<N> = self.nodes.keys().filter(|n| !keep(n)).copied().collect();
+        for id in removed {
+            let node = self.nodes.remove(&id).unwrap();
+            let outgoing: Vec<(Link<E>, N)> = self
+                .links(&node)
+                .map(|(link, dest)| (link, *dest))
+                .filter(|(_, dest)| *dest != id)
+                .collect();
+            for (_, dest) in &outgoing {
+                if let Some(sources) = incoming.get_mut(dest) {
+                    sources.retain(|(source, _)| *source != id);
+                }
+            }
+            for (source, in_link) in incoming.remove(&id).unwrap_or_default() {
+                if source == id {
+                    continue;
+                }
+                let source_node = self.nodes.get_mut(&source).unwrap();
+                match in_link {
+                    Link::Edge(edge) => {
+                        source_node.edges.remove(&edge);
+                    }
+                    Link::Shortcut(i) => source_node.shortcuts.retain(|&j| j != i),
+                }
+                for &(out_link, dest) in &outgoing {
+                    // Paths leading back to where they started are never shortest
+                    if dest == source {
+                        continue;
+                    }
+                    let weight = self.weight(in_link) + self.weight(out_link);
+                    let index = self.shortcuts.len();
+                    self.shortcuts.push(Shortcut {
+                        dest,
+                        weight,
+                        via: id,
+                        first: in_link,
+                        second: out_link,
+                    });
+                    self.nodes.get_mut(&source).unwrap().shortcuts.push(index);
+                    incoming
+                        .entry(dest)
+                        .or_default()
+                        .push((source, Link::Shortcut(index)));
+                }
+            }
+        }
+    }
+
     fn dijkstra_init(&self, origin: &Node<N, E>) -> HashMap<N, NodeDistance<N, E>> {
         let mut result = HashMap::new();
         for n in self.nodes.keys() {
@@ -85,32 +470,39 @@ impl<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> Graph<N, E> {
         result
     }
 
-    fn dijkstra_core<T: Fn(&N) -> bool>(
+    #[instrument(level = "debug", skip_all)]
+    fn dijkstra_core<T: Fn(&N) -> bool, H: Fn(&N) -> Option<i64>>(
         &self,
         origin: &Node<N, E>,
         predicate: T,
+        heuristic: H,
         distances: &mut HashMap<N, NodeDistance<N, E>>,
+        budget: Option<&Budget>,
     ) -> Option<N> {
-        let mut pq: PriorityQueue<N, i64> = PriorityQueue::new();
+        let mut pq: BucketQueue<N> = BucketQueue::new();
         pq.push(origin.id, 0);
-        while let Some((n, pr)) = pq.pop() {
+        while let Some((n, _)) = pq.pop() {
+            if budget.is_some_and(|b| b.expand(1)) {
+                return None;
+            }
             if predicate(&n) {
                 return Some(n);
             }
             let node = self.nodes.get(&n).unwrap();
-            let node_best_cost = -pr;
-            debug_assert_eq!(distances[&n].best_cost, node_best_cost);
-            for (edge, n_dest) in node.edges() {
-                let weight = edge.weight();
+            let node_best_cost = distances[&n].best_cost;
+            for (link, n_dest) in self.links(node) {
+                let weight = self.weight(link);
                 assert!(weight >= 0);
+                // Nodes without an estimate cannot lead to a matching node
+                let estimate = match heuristic(n_dest) {
+                    Some(estimate) => estimate,
+                    None => continue,
+                };
                 let cost = node_best_cost + weight;
                 let node_dest_distance = distances.get_mut(n_dest).unwrap();
-                if cost < node_dest_distance.best_cost {
-                    node_dest_distance.best_cost = cost;
-                    node_dest_distance.best_prev_edge = Some((n, *edge));
-                    if pq.change_priority(n_dest, -cost).is_none() {
-                        pq.push(*n_dest, -cost);
-                    }
+                let settled = !pq.is_queued(n_dest);
+                if self.relax(node_dest_distance, cost, (n, link), *n_dest, settled) {
+                    pq.push(*n_dest, cost + estimate);
                 }
             }
         }
@@ -121,27 +513,392 @@ impl<N: Eq + Hash + Copy, E: Eq + Hash + Copy + Weight> Graph<N, E> {
         &self,
         origin: N,
         found: N,
-        distances: HashMap<N, NodeDistance<N, E>>,
+        distances: &HashMap<N, NodeDistance<N, E>>,
     ) -> Vec<(E, N)> {
-        let mut result = Vec::new();
+        let mut links = Vec::new();
         let mut curr = found;
         while curr != origin {
-            let (prev, edge) = distances[&curr].best_prev_edge.unwrap();
-            result.push((edge, curr));
+            let (prev, link) = distances[&curr].best_prev_edge.unwrap();
+            links.push((link, curr));
             curr = prev;
         }
-        result.reverse();
+        let mut result = Vec::new();
+        for (link, dest) in links.into_iter().rev() {
+            self.unpack(link, dest, &mut result);
+        }
         result
     }
 
+    /// Finds the shortest path from origin to any node matching the predicate.
+    ///
+    /// The search gives up and returns None as soon as the budget, if given, runs out or is cancelled.
     pub fn find_shortest_path<T: Fn(&N) -> bool>(
         &self,
         origin: &N,
         predicate: T,
+        budget: Option<&Budget>,
+    ) -> Option<Vec<(E, N)>> {
+        self.find_shortest_path_astar(origin, predicate, |_| Some(0), budget)
+    }
+
+    /// Finds the shortest path from origin to any node matching the predicate, guided by a heuristic (A* search).
+    ///
+    /// The heuristic must never overestimate the remaining cost to a matching node, and must be consistent along edges.
+    /// Nodes for which the heuristic returns None are assumed not to lead to any matching node, and are never explored.
+    pub fn find_shortest_path_astar<T: Fn(&N) -> bool, H: Fn(&N) -> Option<i64>>(
+        &self,
+        origin: &N,
+        predicate: T,
+        heuristic: H,
+        budget: Option<&Budget>,
     ) -> Option<Vec<(E, N)>> {
         let origin = self.get(origin)?;
         let mut distances = self.dijkstra_init(origin);
-        let found = self.dijkstra_core(origin, predicate, &mut distances)?;
-        Some(self.dijkstra_backtrace(origin.id, found, distances))
+        let found = self.dijkstra_core(origin, predicate, heuristic, &mut distances, budget)?;
+        Some(self.dijkstra_backtrace(origin.id, found, &distances))
+    }
+
+    /// Finds the shortest path from origin to any node matching the predicate which neither passes through the avoided
+    /// nodes nor takes the avoided links out of origin
+    fn find_shortest_path_avoiding<T: Fn(&N) -> bool>(
+        &self,
+        origin: N,
+        predicate: &T,
+        avoided_nodes: &HashSet<N>,
+        avoided_links: &HashSet<Link<E>>,
+        budget: Option<&Budget>,
+    ) -> Option<LinkPath<N, E>> {
+        let mut distances: HashMap<N, NodeDistance<N, E>> = HashMap::new();
+        let mut pq: BucketQueue<N> = BucketQueue::new();
+        distances.insert(
+            origin,
+            NodeDistance {
+                best_cost: 0,
+                best_prev_edge: None,
+            },
+        );
+        pq.push(origin, 0);
+        while let Some((n, node_best_cost)) = pq.pop() {
+            if budget.is_some_and(|b| b.expand(1)) {
+                return None;
+            }
+            if predicate(&n) {
+                let mut nodes = vec![n];
+                let mut links = Vec::new();
+                while let Some((prev, link)) = distances[nodes.last().unwrap()].best_prev_edge {
+                    nodes.push(prev);
+                    links.push(link);
+                }
+                nodes.reverse();
+                links.reverse();
+                return Some(LinkPath {
+                    cost: node_best_cost,
+                    nodes,
+                    links,
+                });
+            }
+            for (link, n_dest) in self.links(self.nodes.get(&n).unwrap()) {
+                if avoided_nodes.contains(n_dest) || (n == origin && avoided_links.contains(&link))
+                {
+                    continue;
+                }
+                let weight = self.weight(link);
+                assert!(weight >= 0);
+                let cost = node_best_cost + weight;
+                let dest_distance = distances.entry(*n_dest).or_insert(NodeDistance {
+                    best_cost: i64::MAX,
+                    best_prev_edge: None,
+                });
+                let settled = !pq.is_queued(n_dest);
+                if self.relax(dest_distance, cost, (n, link), *n_dest, settled) {
+                    pq.push(*n_dest, cost);
+                }
+            }
+        }
+        None
+    }
+
+    /// Iterates over the loopless paths from origin to nodes matching the predicate, shortest first (Yen's algorithm).
+    ///
+    /// Paths end at the first matching node they reach. The iteration ends as soon as the budget, if given, runs out
+    /// or is cancelled.
+    pub fn shortest_paths<'b, T: Fn(&N) -> bool>(
+        &self,
+        origin: &N,
+        predicate: T,
+        budget: Option<&'b Budget<'b>>,
+    ) -> ShortestPaths<'_, 'b, N, E, T> {
+        let first = self.get(origin).and_then(|origin| {
+            self.find_shortest_path_avoiding(
+                origin.id,
+                &predicate,
+                &HashSet::new(),
+                &HashSet::new(),
+                budget,
+            )
+        });
+        ShortestPaths {
+            graph: self,
+            predicate,
+            budget,
+            found: Vec::new(),
+            seen: first.iter().map(|p| p.links.clone()).collect(),
+            candidates: first.into_iter().collect(),
+        }
+    }
+
+    /// Finds the shortest paths from origin to every node reachable from it, in a single search.
+    ///
+    /// The search gives up and returns None as soon as the budget, if given, runs out or is cancelled.
+    pub fn shortest_path_tree(
+        &self,
+        origin: &N,
+        budget: Option<&Budget>,
+    ) -> Option<ShortestPathTree<'_, N, E>> {
+        self.shortest_path_tree_until(origin, |_| false, budget)
+    }
+
+    /// Finds the shortest paths from origin to the nodes reachable from it, until the search settles a node for which
+    /// stop returns true.
+    ///
+    /// Nodes are passed to stop in the order they are settled, so the paths to the nodes passed so far are the shortest.
+    #[instrument(level = "debug", skip_all)]
+    pub fn shortest_path_tree_until<T: Fn(&N) -> bool>(
+        &self,
+        origin: &N,
+        stop: T,
+        budget: Option<&Budget>,
+    ) -> Option<ShortestPathTree<'_, N, E>> {
+        let origin = self.get(origin)?;
+        let mut distances = self.dijkstra_init(origin);
+        self.dijkstra_core(origin, stop, |_| Some(0), &mut distances, budget);
+        if budget.is_some_and(|b| b.stopped()) {
+            return None;
+        }
+        Some(ShortestPathTree {
+            graph: self,
+            origin: origin.id,
+            distances,
+        })
+    }
+
+    /// Finds the cost of the shortest path from origin to every reachable node
+    #[instrument(level = "debug", skip_all)]
+    pub fn shortest_distances(&self, origin: &N) -> HashMap<N, i64> {
+        let origin = match self.get(origin) {
+            Some(origin) => origin,
+            None => return HashMap::new(),
+        };
+        let mut distances = self.dijkstra_init(origin);
+        self.dijkstra_core(origin, |_| false, |_| Some(0), &mut distances, None);
+        distances
+            .into_iter()
+            .filter(|(_, d)| d.best_cost != i64::MAX)
+            .map(|(n, d)| (n, d.best_cost))
+            .collect()
+    }
+
+    /// Finds the shortest path from origin to any node matching the predicate, among paths consuming at most limit resources.
+    ///
+    /// Each edge consumes the amount of resources given by the resource function.
+    /// The search runs over (node, consumed resources) states, so it may explore each node up to limit + 1 times.
+    /// A state is skipped once the same node was already reached with fewer consumed resources, since it can never lead to a better path.
+    #[instrument(level = "debug", skip_all)]
+    pub fn find_shortest_path_limited<T: Fn(&N) -> bool, R: Fn(&E) -> u32>(
+        &self,
+        origin: &N,
+        predicate: T,
+        resource: R,
+        limit: u32,
+        budget: Option<&Budget>,
+    ) -> Option<Vec<(E, N)>> {
+        let origin = self.get(origin)?.id;
+        let mut distances: HashMap<(N, u32), NodeDistance<(N, u32), E>> = HashMap::new();
+        let mut least_settled: HashMap<N, u32> = HashMap::new();
+        let mut pq: BucketQueue<(N, u32)> = BucketQueue::new();
+        distances.insert(
+            (origin, 0),
+            NodeDistance {
+                best_cost: 0,
+                best_prev_edge: None,
+            },
+        );
+        pq.push((origin, 0), 0);
+        while let Some(((n, used), node_best_cost)) = pq.pop() {
+            if budget.is_some_and(|b| b.expand(1)) {
+                return None;
+            }
+            if least_settled.get(&n).is_some_and(|&least| least <= used) {
+                continue;
+            }
+            least_settled.insert(n, used);
+            if predicate(&n) {
+                let mut links = Vec::new();
+                let mut curr = (n, used);
+                while let Some((prev, link)) = distances[&curr].best_prev_edge {
+                    links.push((link, curr.0));
+                    curr = prev;
+                }
+                let mut result = Vec::new();
+                for (link, dest) in links.into_iter().rev() {
+                    self.unpack(link, dest, &mut result);
+                }
+                return Some(result);
+            }
+            for (link, n_dest) in self.links(self.nodes.get(&n).unwrap()) {
+                let consumed = self.consumed(link, &resource);
+                let dest_used = used + consumed;
+                if dest_used > limit {
+                    continue;
+                }
+                let weight = self.weight(link);
+                assert!(weight >= 0);
+                let cost = node_best_cost + weight;
+                let dest = (*n_dest, dest_used);
+                let dest_distance = distances.entry(dest).or_insert(NodeDistance {
+                    best_cost: i64::MAX,
+                    best_prev_edge: None,
+                });
+                let settled = !pq.is_queued(&dest);
+                if self.relax(dest_distance, cost, ((n, used), link), *n_dest, settled) {
+                    pq.push(dest, cost);
+                }
+            }
+        }
+        None
+    }
+
+    /// The links into each node, along with the nodes they leave from
+    fn reverse_links(&self) -> HashMap<N, Vec<(Link<E>, N)>> {
+        let mut reverse_links: HashMap<N, Vec<(Link<E>, N)>> = HashMap::new();
+        for node in self.nodes.values() {
+            for (link, dest) in self.links(node) {
+                reverse_links
+                    .entry(*dest)
+                    .or_default()
+                    .push((link, node.id));
+            }
+        }
+        reverse_links
+    }
+
+    /// Finds the shortest path to target from any node matching the predicate, by searching backwards from target.
+    ///
+    /// The returned path starts at the found node, which is not included in it, and ends at target.
+    /// The search gives up and returns None as soon as the budget, if given, runs out or is cancelled.
+    #[instrument(level = "debug", skip_all)]
+    pub fn find_shortest_path_reverse<T: Fn(&N) -> bool>(
+        &self,
+        target: &N,
+        predicate: T,
+        budget: Option<&Budget>,
+    ) -> Option<(N, Vec<(E, N)>)> {
+        let target = self.get(target)?.id;
+        let reverse_links = self.reverse_links();
+        // Here best_prev_edge holds the next node and edge on the way to target
+        let mut distances: HashMap<N, NodeDistance<N, E>> = HashMap::new();
+        let mut pq: BucketQueue<N> = BucketQueue::new();
+        distances.insert(
+            target,
+            NodeDistance {
+                best_cost: 0,
+                best_prev_edge: None,
+            },
+        );
+        pq.push(target, 0);
+        while let Some((n, node_best_cost)) = pq.pop() {
+            if budget.is_some_and(|b| b.expand(1)) {
+                return None;
+            }
+            if predicate(&n) {
+                let mut result = Vec::new();
+                let mut curr = n;
+                while let Some((next, link)) = distances[&curr].best_prev_edge {
+                    self.unpack(link, next, &mut result);
+                    curr = next;
+                }
+                return Some((n, result));
+            }
+            for (link, n_src) in reverse_links.get(&n).into_iter().flatten() {
+                let weight = self.weight(*link);
+                assert!(weight >= 0);
+                let cost = node_best_cost + weight;
+                let src_distance = distances.entry(*n_src).or_insert(NodeDistance {
+                    best_cost: i64::MAX,
+                    best_prev_edge: None,
+                });
+                let settled = !pq.is_queued(n_src);
+                if self.relax(src_distance, cost, (n, *link), n, settled) {
+                    pq.push(*n_src, cost);
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds the shortest path to target from any node matching the predicate, among paths consuming at most limit
+    /// resources, by searching backwards from target.
+    ///
+    /// Resources are consumed as in find_shortest_path_limited, and the returned path is as in find_shortest_path_reverse.
+    #[instrument(level = "debug", skip_all)]
+    pub fn find_shortest_path_reverse_limited<T: Fn(&N) -> bool, R: Fn(&E) -> u32>(
+        &self,
+        target: &N,
+        predicate: T,
+        resource: R,
+        limit: u32,
+        budget: Option<&Budget>,
+    ) -> Option<(N, Vec<(E, N)>)> {
+        let target = self.get(target)?.id;
+        let reverse_links = self.reverse_links();
+        // Here best_prev_edge holds the next state and edge on the way to target
+        let mut distances: HashMap<(N, u32), NodeDistance<(N, u32), E>> = HashMap::new();
+        let mut least_settled: HashMap<N, u32> = HashMap::new();
+        let mut pq: BucketQueue<(N, u32)> = BucketQueue::new();
+        distances.insert(
+            (target, 0),
+            NodeDistance {
+                best_cost: 0,
+                best_prev_edge: None,
+            },
+        );
+        pq.push((target, 0), 0);
+        while let Some(((n, used), node_best_cost)) = pq.pop() {
+            if budget.is_some_and(|b| b.expand(1)) {
+                return None;
+            }
+            if least_settled.get(&n).is_some_and(|&least| least <= used) {
+                continue;
+            }
+            least_settled.insert(n, used);
+            if predicate(&n) {
+                let mut result = Vec::new();
+                let mut curr = (n, used);
+                while let Some((next, link)) = distances[&curr].best_prev_edge {
+                    self.unpack(link, next.0, &mut result);
+                    curr = next;
+                }
+                return Some((n, result));
+            }
+            for (link, n_src) in reverse_links.get(&n).into_iter().flatten() {
+                let src_used = used + self.consumed(*link, &resource);
+                if src_used > limit {
+                    continue;
+                }
+                let weight = self.weight(*link);
+                assert!(weight >= 0);
+                let cost = node_best_cost + weight;
+                let src = (*n_src, src_used);
+                let src_distance = distances.entry(src).or_insert(NodeDistance {
+                    best_cost: i64::MAX,
+                    best_prev_edge: None,
+                });
+                let settled = !pq.is_queued(&src);
+                if self.relax(src_distance, cost, ((n, used), *link), n, settled) {
+                    pq.push(src, cost);
+                }
+            }
+        }
+        None
     }
 }
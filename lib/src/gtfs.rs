@@ -5,23 +5,29 @@
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 mod opener;
+pub(crate) mod reader;
 
-use crate::HaError;
+use crate::fares::FareRules;
 use crate::JSON;
-use chrono::{Datelike, Duration, NaiveDate};
+use crate::{AgencyFilter, GtfsError, GtfsOptions};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
 use jzon::JsonValue;
+use reader::{Column, GtfsReader, GtfsRecord};
 use serde::de::Visitor;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
-use std::error::Error;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
+#[cfg(feature = "archives")]
 use std::fs::File;
 use std::hash::{Hash, Hasher};
 use std::io::prelude::*;
+#[cfg(feature = "archives")]
 use std::io::BufReader;
 use std::path::Path;
 use std::result::Result;
+use tracing::{info, instrument, warn};
+#[cfg(feature = "archives")]
 use zip::ZipArchive;
 
 /// A unique identifier type for trains in the database
@@ -30,33 +36,18 @@ pub type TrainId = String;
 /// A unique identifier type for stations in the database
 pub type StationId = u64;
 
-macro_rules! headers {
-    ($h:expr, $( $x:ident ), +) => {{
-        $(
-        let mut $x : Option<usize> = None;
-        )+
-        for (i, h) in $h.iter().enumerate() {
-            match h {
-                $(
-                stringify!($x) => $x = Some(i),
-                )+
-                &_ => {}
-            }
-        }
-        $(
-        if $x.is_none() {
-            return Err(Box::new(HaError::GTFSError(format!("{} header not found", stringify!($x)))));
-        }
-        )+
-        ($( $x.unwrap(), )+)
-    }}
-}
+/// A unique identifier type for train path shapes in the database
+pub type ShapeId = String;
 
 /// Represents a database train station entry
 #[derive(Serialize, Deserialize)]
 pub struct Station {
     id: StationId,
     name: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    wheelchair_boarding: Option<bool>,
+    fare_zone: Option<String>,
 }
 
 impl PartialEq for Station {
@@ -81,10 +72,21 @@ impl fmt::Display for Station {
 
 impl JSON for Station {
     fn to_json(&self) -> JsonValue {
-        object! {
+        let mut result = object! {
             id: self.id,
             name: self.name.to_owned()
+        };
+        if let (Some(lat), Some(lon)) = (self.lat, self.lon) {
+            result["lat"] = lat.into();
+            result["lon"] = lon.into();
         }
+        if let Some(wheelchair_boarding) = self.wheelchair_boarding {
+            result["wheelchair_boarding"] = wheelchair_boarding.into();
+        }
+        if let Some(fare_zone) = &self.fare_zone {
+            result["fare_zone"] = fare_zone.as_str().into();
+        }
+        result
     }
 }
 
@@ -94,9 +96,37 @@ impl Station {
         Self {
             id,
             name: name.to_owned(),
+            lat: None,
+            lon: None,
+            wheelchair_boarding: None,
+            fare_zone: None,
+        }
+    }
+
+    /// Create a new Station object with WGS84 coordinates
+    pub fn with_coordinates(id: StationId, name: &str, lat: f64, lon: f64) -> Self {
+        Self {
+            id,
+            name: name.to_owned(),
+            lat: Some(lat),
+            lon: Some(lon),
+            wheelchair_boarding: None,
+            fare_zone: None,
         }
     }
 
+    /// Sets whether wheelchair users can board trains at the station
+    pub fn with_wheelchair_boarding(mut self, accessible: bool) -> Self {
+        self.wheelchair_boarding = Some(accessible);
+        self
+    }
+
+    /// Sets the GTFS fare zone of the station
+    pub fn with_fare_zone(mut self, zone: &str) -> Self {
+        self.fare_zone = Some(zone.to_owned());
+        self
+    }
+
     /// Gets the station identifier
     pub fn id(&self) -> StationId {
         self.id
@@ -106,6 +136,36 @@ impl Station {
     pub fn name(&self) -> &String {
         &self.name
     }
+
+    /// Gets the station latitude, if known
+    pub fn lat(&self) -> Option<f64> {
+        self.lat
+    }
+
+    /// Gets the station longitude, if known
+    pub fn lon(&self) -> Option<f64> {
+        self.lon
+    }
+
+    /// Whether wheelchair users can board trains at the station, if known
+    pub fn wheelchair_boarding(&self) -> Option<bool> {
+        self.wheelchair_boarding
+    }
+
+    /// The GTFS fare zone of the station, by which fare rules select fares, if it has one
+    pub fn fare_zone(&self) -> Option<&str> {
+        self.fare_zone.as_deref()
+    }
+
+    /// Gets the great-circle distance in meters from the station to the given WGS84 coordinates, if the station's are known
+    pub fn distance_to(&self, lat: f64, lon: f64) -> Option<f64> {
+        const EARTH_RADIUS: f64 = 6_371_000.0;
+        let (lat1, lat2) = (self.lat?.to_radians(), lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (lon - self.lon?).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        Some(2.0 * EARTH_RADIUS * a.sqrt().asin())
+    }
 }
 
 /// Represents a duration in seconds. Used instead of chrono::Duration since the latter doesn't support serde.
@@ -196,6 +256,7 @@ pub struct StopSchedule {
     station: StationId,
     arrival_offset: HaDuration,
     departure_offset: HaDuration,
+    platform: Option<String>,
 }
 
 impl StopSchedule {
@@ -209,9 +270,16 @@ impl StopSchedule {
             station,
             arrival_offset,
             departure_offset: departure_offset.unwrap_or(arrival_offset),
+            platform: None,
         }
     }
 
+    /// Sets the platform the train stops at
+    pub fn with_platform(mut self, platform: &str) -> Self {
+        self.platform = Some(platform.to_owned());
+        self
+    }
+
     /// The station at which the train stopped
     pub fn station(&self) -> StationId {
         self.station
@@ -228,12 +296,134 @@ impl StopSchedule {
     pub fn departure_offset(&self) -> HaDuration {
         self.departure_offset
     }
+
+    /// The platform the train stops at, if the feed specifies one
+    pub fn platform(&self) -> Option<&str> {
+        self.platform.as_deref()
+    }
 }
 
+/// The kind of vehicle a train is, as given by the GTFS route_type of its route
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Copy, Clone, Debug, Default)]
+pub enum Mode {
+    /// Light rail and trams
+    LightRail,
+    Subway,
+    #[default]
+    Rail,
+    Bus,
+    Ferry,
+    /// Cable cars, funiculars, monorails and anything else
+    Other,
+}
+
+impl Mode {
+    /// The mode of a GTFS route_type, either a basic type or an extended one
+    pub fn from_route_type(route_type: u32) -> Self {
+        match route_type {
+            0 | 900..=999 => Mode::LightRail,
+            1 | 400..=499 => Mode::Subway,
+            2 | 100..=199 => Mode::Rail,
+            3 | 11 | 200..=299 | 700..=899 => Mode::Bus,
+            4 | 1000..=1299 => Mode::Ferry,
+            _ => Mode::Other,
+        }
+    }
+
+    /// The name of the mode, as accepted by from_name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::LightRail => "light_rail",
+            Mode::Subway => "subway",
+            Mode::Rail => "rail",
+            Mode::Bus => "bus",
+            Mode::Ferry => "ferry",
+            Mode::Other => "other",
+        }
+    }
+
+    /// The mode with the given name, if any
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Mode::LightRail,
+            Mode::Subway,
+            Mode::Rail,
+            Mode::Bus,
+            Mode::Ferry,
+            Mode::Other,
+        ]
+        .into_iter()
+        .find(|m| m.name() == name)
+    }
+}
+
+/// A train whose stops are still being read from stop_times.txt
 struct PrototypeTrain {
     id: TrainId,
     stops: Vec<Option<StopSchedule>>,
     dates: Vec<NaiveDate>,
+    details: TripDetails,
+}
+
+impl PrototypeTrain {
+    fn new(id: &str, dates: Vec<NaiveDate>, details: TripDetails) -> Self {
+        PrototypeTrain {
+            id: id.to_owned(),
+            stops: Vec::new(),
+            dates,
+            details,
+        }
+    }
+
+    /// Reopens a train which was already built, for feeds in which its stops are not all together
+    fn from_train(train: Train) -> Self {
+        PrototypeTrain {
+            id: train.id,
+            stops: train.stops.into_iter().map(Some).collect(),
+            dates: train.dates,
+            details: TripDetails {
+                shape: train.shape,
+                mode: train.mode,
+                wheelchair_accessible: train.wheelchair_accessible,
+                bikes_allowed: train.bikes_allowed,
+                route_id: train.route_id,
+                short_name: train.short_name,
+                direction_id: train.direction_id,
+            },
+        }
+    }
+
+    /// Puts a stop at the given index, i.e. its stop_sequence less one
+    fn set_stop(&mut self, index: usize, stop: StopSchedule) {
+        match self.stops.len().cmp(&index) {
+            Ordering::Greater => self.stops[index] = Some(stop),
+            Ordering::Less => {
+                self.stops.resize_with(index + 1, || None);
+                self.stops[index] = Some(stop);
+            }
+            Ordering::Equal => self.stops.push(Some(stop)),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.stops.iter().all(|x| x.is_some())
+    }
+
+    /// Builds the train, which must be complete
+    fn into_train(self) -> Train {
+        Train {
+            shape: self.details.shape,
+            mode: self.details.mode,
+            wheelchair_accessible: self.details.wheelchair_accessible,
+            bikes_allowed: self.details.bikes_allowed,
+            route_id: self.details.route_id,
+            short_name: self.details.short_name,
+            direction_id: self.details.direction_id,
+            id: self.id,
+            stops: self.stops.into_iter().map(|x| x.unwrap()).collect(),
+            dates: self.dates,
+        }
+    }
 }
 
 /// Represents a single train's schedule
@@ -246,6 +436,13 @@ pub struct Train {
     id: TrainId,
     stops: Vec<StopSchedule>,
     dates: Vec<NaiveDate>,
+    shape: Option<ShapeId>,
+    mode: Mode,
+    wheelchair_accessible: Option<bool>,
+    bikes_allowed: Option<bool>,
+    route_id: Option<u64>,
+    short_name: Option<String>,
+    direction_id: Option<u8>,
 }
 
 impl PartialEq for Train {
@@ -269,6 +466,13 @@ impl Train {
             id: id.to_owned(),
             stops: Vec::new(),
             dates: Vec::new(),
+            shape: None,
+            mode: Mode::default(),
+            wheelchair_accessible: None,
+            bikes_allowed: None,
+            route_id: None,
+            short_name: None,
+            direction_id: None,
         }
     }
 
@@ -278,6 +482,13 @@ impl Train {
             id: id.to_owned(),
             stops,
             dates: vec![date],
+            shape: None,
+            mode: Mode::default(),
+            wheelchair_accessible: None,
+            bikes_allowed: None,
+            route_id: None,
+            short_name: None,
+            direction_id: None,
         }
     }
 
@@ -287,14 +498,73 @@ impl Train {
             id: id.to_owned(),
             stops,
             dates,
+            shape: None,
+            mode: Mode::default(),
+            wheelchair_accessible: None,
+            bikes_allowed: None,
+            route_id: None,
+            short_name: None,
+            direction_id: None,
         }
     }
 
+    /// Sets the shape describing the path the train travels along
+    pub fn with_shape(mut self, shape: &str) -> Self {
+        self.shape = Some(shape.to_owned());
+        self
+    }
+
+    /// Sets the kind of vehicle the train is, which is rail unless set otherwise
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets whether the train can carry wheelchair users
+    pub fn with_wheelchair_accessible(mut self, accessible: bool) -> Self {
+        self.wheelchair_accessible = Some(accessible);
+        self
+    }
+
+    /// Sets whether bicycles may be taken on the train
+    pub fn with_bikes_allowed(mut self, allowed: bool) -> Self {
+        self.bikes_allowed = Some(allowed);
+        self
+    }
+
+    /// Sets the GTFS route the train's trip belongs to
+    pub fn with_route_id(mut self, route_id: u64) -> Self {
+        self.route_id = Some(route_id);
+        self
+    }
+
+    /// Sets the number passengers know the train by, i.e. its trip's short name
+    pub fn with_short_name(mut self, short_name: &str) -> Self {
+        self.short_name = Some(short_name.to_owned());
+        self
+    }
+
+    /// Sets the GTFS direction of the train's trip, 0 or 1, telling the trips of a line in opposite directions apart
+    pub fn with_direction_id(mut self, direction_id: u8) -> Self {
+        self.direction_id = Some(direction_id);
+        self
+    }
+
     /// Get the train identifier
     pub fn id(&self) -> &TrainId {
         &self.id
     }
 
+    /// The trip's short name, which passengers know the train by, if the feed gives one
+    pub fn short_name(&self) -> Option<&str> {
+        self.short_name.as_deref()
+    }
+
+    /// The number passengers know the train by: its trip's short name, or its identifier if the feed gives no short name
+    pub fn number(&self) -> &str {
+        self.short_name.as_deref().unwrap_or(&self.id)
+    }
+
     /// Iterate over the train stops
     pub fn stops(&self) -> impl Iterator<Item = &StopSchedule> {
         self.stops.iter()
@@ -304,6 +574,68 @@ impl Train {
     pub fn dates(&self) -> impl Iterator<Item = &NaiveDate> {
         self.dates.iter()
     }
+
+    /// Whether the train runs on the given service day
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        self.dates.contains(&date)
+    }
+
+    /// Iterate over the service days of the train's runs which may stop between start and end.
+    ///
+    /// Stop times are offsets from the midnight starting their run's service day, and go past 24:00:00 for runs which
+    /// cross midnight, so a run may stop on the days following its service day. Runs are picked by whole days, so some
+    /// of them may only stop outside of the given times.
+    pub fn service_dates_between(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> impl Iterator<Item = NaiveDate> + '_ {
+        let first_departure = self
+            .stops
+            .first()
+            .map_or_else(Duration::zero, |s| s.departure_offset().to_chrono());
+        let last_arrival = self
+            .stops
+            .last()
+            .map_or_else(Duration::zero, |s| s.arrival_offset().to_chrono());
+        let first_date = (start - last_arrival).date();
+        let last_date = (end - first_departure).date();
+        self.dates
+            .iter()
+            .copied()
+            .filter(move |&date| date >= first_date && date <= last_date)
+    }
+
+    /// Get the identifier of the shape describing the path the train travels along, if known
+    pub fn shape(&self) -> Option<&ShapeId> {
+        self.shape.as_ref()
+    }
+
+    /// Get the kind of vehicle the train is
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Whether the train can carry wheelchair users, if known
+    pub fn wheelchair_accessible(&self) -> Option<bool> {
+        self.wheelchair_accessible
+    }
+
+    /// Whether bicycles may be taken on the train, if known
+    pub fn bikes_allowed(&self) -> Option<bool> {
+        self.bikes_allowed
+    }
+
+    /// The GTFS route the train's trip belongs to, by which fare rules select fares, if known
+    pub fn route_id(&self) -> Option<u64> {
+        self.route_id
+    }
+
+    /// The GTFS direction of the train's trip, 0 or 1, if known. Trains of the same route with the same direction travel
+    /// the same way along their line.
+    pub fn direction_id(&self) -> Option<u8> {
+        self.direction_id
+    }
 }
 
 /// A database of all available trains and stations
@@ -311,9 +643,27 @@ impl Train {
 pub struct RailroadData {
     stations: HashMap<StationId, Station>,
     trains: HashMap<TrainId, Train>,
+    transfers: HashMap<StationId, HaDuration>,
+    shapes: HashMap<ShapeId, Vec<(f64, f64)>>,
+    built: Option<NaiveDateTime>,
+    fares: FareRules,
 }
 
-type TripsResult = HashMap<String, Option<Vec<NaiveDate>>>;
+type TripsResult = HashMap<String, Vec<NaiveDate>>;
+
+/// What is known about a trip from trips.txt and routes.txt, besides its dates
+#[derive(Default)]
+struct TripDetails {
+    shape: Option<ShapeId>,
+    mode: Mode,
+    wheelchair_accessible: Option<bool>,
+    bikes_allowed: Option<bool>,
+    route_id: Option<u64>,
+    short_name: Option<String>,
+    direction_id: Option<u8>,
+}
+/// The station and platform code of each stop which is a platform within a station
+type Platforms = HashMap<u64, (StationId, Option<String>)>;
 
 impl RailroadData {
     /// Create a new RailroadData object
@@ -321,6 +671,10 @@ impl RailroadData {
         RailroadData {
             stations: HashMap::new(),
             trains: HashMap::new(),
+            transfers: HashMap::new(),
+            shapes: HashMap::new(),
+            built: None,
+            fares: FareRules::new(),
         }
     }
 
@@ -346,6 +700,102 @@ impl RailroadData {
         self.trains.get(id)
     }
 
+    /// The platforms at which trains stop at the given station, sorted
+    pub fn platforms(&self, station: StationId) -> Vec<&str> {
+        let platforms: BTreeSet<_> = self
+            .trains
+            .values()
+            .flat_map(|t| t.stops())
+            .filter(|s| s.station() == station)
+            .filter_map(|s| s.platform())
+            .collect();
+        platforms.into_iter().collect()
+    }
+
+    /// The trains stopping at the given station on their runs of the given service day, sorted by identifier
+    pub fn trains_stopping_at(&self, station: StationId, date: NaiveDate) -> Vec<&Train> {
+        let mut trains: Vec<_> = self
+            .trains
+            .values()
+            .filter(|t| t.runs_on(date) && t.stops().any(|s| s.station() == station))
+            .collect();
+        trains.sort_by_key(|t| t.id());
+        trains
+    }
+
+    /// Finds the trains passengers know by the given number, or the train with the given identifier if there is one.
+    ///
+    /// A train number is usually reused by a different trip on each day the train runs, so several trains may be found.
+    pub fn find_trains(&self, number: &str) -> Vec<&Train> {
+        if let Some(train) = self.train(number) {
+            return vec![train];
+        }
+        let mut trains: Vec<_> = self
+            .trains
+            .values()
+            .filter(|t| t.short_name() == Some(number))
+            .collect();
+        trains.sort_by_key(|t| t.id());
+        trains
+    }
+
+    /// Finds the train with the given identifier, or else the train with the given number which runs on the given date
+    pub fn find_train(&self, number: &str, date: NaiveDate) -> Option<&Train> {
+        self.train(number).or_else(|| {
+            self.trains
+                .values()
+                .find(|t| t.short_name() == Some(number) && t.runs_on(date))
+        })
+    }
+
+    /// Gets the minimum time needed to change trains at the given station, if one is specified
+    pub fn min_transfer_time(&self, station: StationId) -> Option<HaDuration> {
+        self.transfers.get(&station).copied()
+    }
+
+    /// Sets the minimum time needed to change trains at the given station
+    pub fn set_min_transfer_time(&mut self, station: StationId, duration: HaDuration) {
+        self.transfers.insert(station, duration);
+    }
+
+    /// Gets the points, as (latitude, longitude) pairs, of the shape with the given identifier
+    pub fn shape(&self, id: &str) -> Option<&[(f64, f64)]> {
+        self.shapes.get(id).map(|points| points.as_slice())
+    }
+
+    /// Sets the points, as (latitude, longitude) pairs, of the shape with the given identifier
+    pub fn set_shape(&mut self, id: &str, points: Vec<(f64, f64)>) {
+        self.shapes.insert(id.to_owned(), points);
+    }
+
+    /// The time, in UTC, at which the database was built from a GTFS feed, if it was
+    pub fn build_time(&self) -> Option<NaiveDateTime> {
+        self.built
+    }
+
+    pub(crate) fn set_build_time(&mut self, built: Option<NaiveDateTime>) {
+        self.built = built;
+    }
+
+    /// The fares of the GTFS feed the database was built from, which are empty if it had none
+    pub fn fares(&self) -> &FareRules {
+        &self.fares
+    }
+
+    /// Sets the fares by which routes are priced
+    pub fn set_fares(&mut self, fares: FareRules) {
+        self.fares = fares;
+    }
+
+    /// The first and last dates on which any train runs, or None if there are no trains
+    pub fn date_range(&self) -> Option<(NaiveDate, NaiveDate)> {
+        let mut dates = self.trains.values().flat_map(|t| t.dates());
+        let first = *dates.next()?;
+        Some(dates.fold((first, first), |(min, max), &date| {
+            (min.min(date), max.max(date))
+        }))
+    }
+
     /// Iterates over the stations in the database
     pub fn stations(&self) -> impl Iterator<Item = &Station> {
         self.stations.values()
@@ -358,6 +808,9 @@ impl RailroadData {
 
     /// Finds a station with the given name.
     ///
+    /// Names spelled differently than in the feed, in case, punctuation, vowel signs or Hebrew final letters, are found
+    /// as well, as are stations' built-in aliases such as "Tel Aviv Savidor".
+    ///
     /// Examples:
     /// ```
     /// use harail::{RailroadData, Station};
@@ -367,7 +820,10 @@ impl RailroadData {
     /// assert_eq!(100, station.id());
     /// ```
     pub fn find_station(&self, name: &str) -> Option<&Station> {
-        self.stations.values().find(|&station| station.name == name)
+        self.stations
+            .values()
+            .find(|&station| station.name == name)
+            .or_else(|| crate::station_search::find_station(self, name))
     }
 
     /// Gets the start date of the database
@@ -404,77 +860,157 @@ impl RailroadData {
         result
     }
 
-    fn parse_agency<R: Read>(reader: R) -> Result<u64, Box<dyn Error>> {
-        let mut reader = csv::Reader::from_reader(reader);
-        let (agency_id, agency_name) = headers!(reader.headers()?, agency_id, agency_name);
-        for result in reader.records() {
-            let record = result?;
-            let agency_name = record.get(agency_name).ok_or("agency_name")?;
-            if agency_name == "רכבת ישראל" {
-                let agency_id: u64 = record
-                    .get(agency_id)
-                    .ok_or_else(|| HaError::GTFSError("agency_id".to_owned()))?
-                    .parse()?;
-                return Ok(agency_id);
+    /// Finds the IDs of the agencies selected by the filter, or None if all agencies are selected
+    #[instrument(level = "debug", skip_all)]
+    fn parse_agency<R: Read>(
+        reader: R,
+        filter: &AgencyFilter,
+    ) -> Result<Option<HashSet<String>>, GtfsError> {
+        let names = match filter {
+            AgencyFilter::All => return Ok(None),
+            AgencyFilter::Matching(names) => names,
+        };
+        let mut reader = GtfsReader::new("agency.txt", reader);
+        let [agency_id, agency_name] = reader.columns(["agency_id", "agency_name"])?;
+        let mut ids = HashSet::new();
+        for record in reader.records() {
+            let record = record?;
+            let agency_name = record.get(agency_name)?;
+            let agency_id = record.get(agency_id)?;
+            if names.iter().any(|n| n == agency_name || n == agency_id) {
+                ids.insert(agency_id.to_owned());
             }
         }
-        Err(Box::new(HaError::GTFSError("not found".to_owned())))
-    }
-
-    fn parse_routes<R: Read>(reader: R, irw_id: u64) -> Result<HashSet<u64>, Box<dyn Error>> {
-        let mut reader = csv::Reader::from_reader(reader);
-        let (route_id, agency_id) = headers!(reader.headers()?, route_id, agency_id);
-        let mut set = HashSet::new();
-        let irw_id_str = irw_id.to_string();
-        for result in reader.records() {
-            let record = result?;
-            let agency_id = record
-                .get(agency_id)
-                .ok_or_else(|| HaError::GTFSError("agency_id".to_owned()))?;
-            if agency_id == irw_id_str {
-                let route_id: u64 = record
-                    .get(route_id)
-                    .ok_or_else(|| HaError::GTFSError("route_id".to_owned()))?
-                    .parse()?;
-                set.insert(route_id);
+        if ids.is_empty() {
+            return Err(reader.error(format!("no agency named {}", names.join(" or "))));
+        }
+        Ok(Some(ids))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    fn parse_routes<R: Read>(
+        reader: R,
+        agencies: Option<HashSet<String>>,
+        options: &GtfsOptions,
+    ) -> Result<HashMap<u64, Mode>, GtfsError> {
+        let mut reader = GtfsReader::new("routes.txt", reader);
+        let [route_id] = reader.columns(["route_id"])?;
+        let [route_type] = reader.optional_columns(["route_type"])?;
+        // Agencies are only needed when filtering by them
+        let agency_id = match agencies {
+            Some(_) => Some(reader.columns(["agency_id"])?[0]),
+            None => None,
+        };
+        let mut map = HashMap::new();
+        for record in reader.records() {
+            let record = record?;
+            if let (Some(agencies), Some(agency_id)) = (&agencies, agency_id) {
+                if !agencies.contains(record.get(agency_id)?) {
+                    continue;
+                }
+            }
+            // Routes without a type are taken to be trains, as in feeds which only have Israel Railways routes
+            let mode = record
+                .parse_optional(route_type)?
+                .map_or(Mode::Rail, Mode::from_route_type);
+            if !options.loads_mode(mode) {
+                continue;
             }
+            map.insert(record.parse(route_id)?, mode);
         }
-        Ok(set)
+        Ok(map)
     }
 
+    /// Finds the stops which are platforms within a station, i.e. have a parent_station, and stops which have a platform
+    /// code of their own.
+    ///
+    /// Platforms belong to the station at the top of their parent_station hierarchy, so boarding areas (location_type 4)
+    /// belong to the station of their platform, whose platform code they take unless they have one of their own.
+    /// Entrances and generic nodes (location_type 2 and 3), at which trains don't stop, are left out.
+    #[instrument(level = "debug", skip_all)]
+    fn parse_platforms<R: Read>(reader: R) -> Result<Platforms, GtfsError> {
+        let mut reader = GtfsReader::new("stops.txt", reader);
+        let [stop_id] = reader.columns(["stop_id"])?;
+        let [parent_station, platform_code, location_type] =
+            reader.optional_columns(["parent_station", "platform_code", "location_type"])?;
+        let mut parents: HashMap<u64, (Option<StationId>, Option<String>)> = HashMap::new();
+        for record in reader.records() {
+            let record = record?;
+            if matches!(record.optional(location_type), Some("2" | "3")) {
+                continue;
+            }
+            let parent_station: Option<StationId> = record.parse_optional(parent_station)?;
+            let platform_code = record.optional(platform_code);
+            if parent_station.is_none() && platform_code.is_none() {
+                continue;
+            }
+            let stop_id: u64 = record.parse(stop_id)?;
+            parents.insert(stop_id, (parent_station, platform_code.map(str::to_owned)));
+        }
+        let mut platforms = HashMap::new();
+        for (&stop_id, (parent_station, platform_code)) in &parents {
+            let mut station = parent_station.unwrap_or(stop_id);
+            let mut platform_code = platform_code.clone();
+            // GTFS nests stops at most two levels deep, from boarding areas to platforms to stations
+            for _ in 0..2 {
+                match parents.get(&station) {
+                    Some((Some(parent), code)) => {
+                        platform_code = platform_code.or_else(|| code.clone());
+                        station = *parent;
+                    }
+                    _ => break,
+                }
+            }
+            platforms.insert(stop_id, (station, platform_code));
+        }
+        Ok(platforms)
+    }
+
+    #[instrument(level = "debug", skip_all)]
     fn parse_stops<R: Read>(
         &mut self,
         reader: R,
         irw_stops: HashSet<StationId>,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut reader = csv::Reader::from_reader(reader);
-        let (stop_id, stop_name) = headers!(reader.headers()?, stop_id, stop_name);
-        for result in reader.records() {
-            let record = result?;
-            let stop_id: u64 = record
-                .get(stop_id)
-                .ok_or_else(|| HaError::GTFSError("stop_id".to_owned()))?
-                .parse()?;
+    ) -> Result<(), GtfsError> {
+        let mut reader = GtfsReader::new("stops.txt", reader);
+        let [stop_id, stop_name] = reader.columns(["stop_id", "stop_name"])?;
+        let [stop_lat, stop_lon, wheelchair_boarding, zone_id] =
+            reader.optional_columns(["stop_lat", "stop_lon", "wheelchair_boarding", "zone_id"])?;
+        for record in reader.records() {
+            let record = record?;
+            let stop_id: u64 = record.parse(stop_id)?;
             if !irw_stops.contains(&stop_id) {
                 continue;
             }
-            let stop_name = record
-                .get(stop_name)
-                .ok_or_else(|| HaError::GTFSError("stop_name".to_owned()))?;
-            self.stations
-                .insert(stop_id, Station::new(stop_id, stop_name));
+            let mut station = Station::new(stop_id, record.get(stop_name)?);
+            // Coordinates are only conditionally required by GTFS, so they are kept only if both are present
+            if let (Some(lat), Some(lon)) = (
+                record.parse_optional::<f64>(stop_lat)?,
+                record.parse_optional::<f64>(stop_lon)?,
+            ) {
+                station.lat = Some(lat);
+                station.lon = Some(lon);
+            }
+            station.wheelchair_boarding =
+                Self::parse_gtfs_accessibility(&record, wheelchair_boarding)?;
+            station.fare_zone = record.optional(zone_id).map(str::to_owned);
+            self.stations.insert(stop_id, station);
         }
         Ok(())
     }
 
-    fn parse_gtfs_date(date: &str) -> Result<NaiveDate, Box<dyn Error>> {
-        let date_num: u32 = date.parse()?;
-        Ok(NaiveDate::from_ymd_opt(
-            (date_num / 10000) as i32,
-            (date_num % 10000) / 100,
-            date_num % 100,
-        )
-        .ok_or_else(|| HaError::GTFSError("date".to_owned()))?)
+    fn parse_gtfs_date(record: &GtfsRecord, column: Column) -> Result<NaiveDate, GtfsError> {
+        let date = record.get(column)?;
+        date.parse::<u32>()
+            .ok()
+            .and_then(|date_num| {
+                NaiveDate::from_ymd_opt(
+                    (date_num / 10000) as i32,
+                    (date_num % 10000) / 100,
+                    date_num % 100,
+                )
+            })
+            .ok_or_else(|| record.invalid(column, date))
     }
 
     fn parse_gtfs_daymap(period: (NaiveDate, NaiveDate), daymap: [bool; 7]) -> Vec<NaiveDate> {
@@ -489,58 +1025,27 @@ impl RailroadData {
         result
     }
 
-    fn parse_calendar<R: Read>(reader: R) -> Result<HashMap<u64, Vec<NaiveDate>>, Box<dyn Error>> {
-        let mut reader = csv::Reader::from_reader(reader);
-        let (
-            service_id,
-            sunday,
-            monday,
-            tuesday,
-            wednesday,
-            thursday,
-            friday,
-            saturday,
-            start_date,
-            end_date,
-        ) = headers!(
-            reader.headers()?,
-            service_id,
-            sunday,
-            monday,
-            tuesday,
-            wednesday,
-            thursday,
-            friday,
-            saturday,
-            start_date,
-            end_date
-        );
+    #[instrument(level = "debug", skip_all)]
+    fn parse_calendar<R: Read>(reader: R) -> Result<HashMap<u64, Vec<NaiveDate>>, GtfsError> {
+        let mut reader = GtfsReader::new("calendar.txt", reader);
+        let [service_id, start_date, end_date] =
+            reader.columns(["service_id", "start_date", "end_date"])?;
+        let weekdays = reader.columns([
+            "sunday",
+            "monday",
+            "tuesday",
+            "wednesday",
+            "thursday",
+            "friday",
+            "saturday",
+        ])?;
         let mut map = HashMap::new();
-        for result in reader.records() {
-            let record = result?;
-            let service_id: u64 = record
-                .get(service_id)
-                .ok_or_else(|| HaError::GTFSError("service_id".to_owned()))?
-                .parse()?;
-            let start_date = Self::parse_gtfs_date(
-                record
-                    .get(start_date)
-                    .ok_or_else(|| HaError::GTFSError("start_date".to_owned()))?,
-            )?;
-            let end_date = Self::parse_gtfs_date(
-                record
-                    .get(end_date)
-                    .ok_or_else(|| HaError::GTFSError("end_date".to_owned()))?,
-            )?;
-            let daymap = [
-                record.get(sunday).unwrap_or("0") == "1",
-                record.get(monday).unwrap_or("0") == "1",
-                record.get(tuesday).unwrap_or("0") == "1",
-                record.get(wednesday).unwrap_or("0") == "1",
-                record.get(thursday).unwrap_or("0") == "1",
-                record.get(friday).unwrap_or("0") == "1",
-                record.get(saturday).unwrap_or("0") == "1",
-            ];
+        for record in reader.records() {
+            let record = record?;
+            let service_id: u64 = record.parse(service_id)?;
+            let start_date = Self::parse_gtfs_date(&record, start_date)?;
+            let end_date = Self::parse_gtfs_date(&record, end_date)?;
+            let daymap = weekdays.map(|day| record.optional(Some(day)) == Some("1"));
             map.insert(
                 service_id,
                 Self::parse_gtfs_daymap((start_date, end_date), daymap),
@@ -549,168 +1054,481 @@ impl RailroadData {
         Ok(map)
     }
 
+    #[instrument(level = "debug", skip_all)]
     fn parse_trips<R: Read>(
         reader: R,
-        irw_routes: HashSet<u64>,
+        irw_routes: HashMap<u64, Mode>,
         services: HashMap<u64, Vec<NaiveDate>>,
-    ) -> Result<TripsResult, Box<dyn Error>> {
-        let mut reader = csv::Reader::from_reader(reader);
-        let (route_id, trip_id, service_id) =
-            headers!(reader.headers()?, route_id, trip_id, service_id);
+    ) -> Result<(TripsResult, HashMap<String, TripDetails>), GtfsError> {
+        let mut reader = GtfsReader::new("trips.txt", reader);
+        let [route_id, trip_id, service_id] =
+            reader.columns(["route_id", "trip_id", "service_id"])?;
+        let [shape_id, wheelchair_accessible, bikes_allowed, trip_short_name, direction_id] =
+            reader.optional_columns([
+                "shape_id",
+                "wheelchair_accessible",
+                "bikes_allowed",
+                "trip_short_name",
+                "direction_id",
+            ])?;
         let mut map = HashMap::new();
-        for result in reader.records() {
-            let record = result?;
-            let route_id: u64 = record
-                .get(route_id)
-                .ok_or_else(|| HaError::GTFSError("route_id".to_owned()))?
-                .parse()?;
-            if !irw_routes.contains(&route_id) {
-                continue;
-            }
-            let service_id: u64 = record
-                .get(service_id)
-                .ok_or_else(|| HaError::GTFSError("service_id".to_owned()))?
-                .parse()?;
+        let mut details = HashMap::new();
+        for record in reader.records() {
+            let record = record?;
+            let route_id: u64 = record.parse(route_id)?;
+            let mode = match irw_routes.get(&route_id) {
+                Some(mode) => *mode,
+                None => continue,
+            };
+            let service_id: u64 = record.parse(service_id)?;
             if let Some(dates) = services.get(&service_id) {
-                let trip_id = record
-                    .get(trip_id)
-                    .ok_or_else(|| HaError::GTFSError("trip_id".to_owned()))?;
-                map.insert(trip_id.to_owned(), Some(dates.clone()));
+                let trip_id = record.get(trip_id)?;
+                if map.insert(trip_id.to_owned(), dates.clone()).is_some() {
+                    warn!("Duplicate trip {}, keeping its last definition", trip_id);
+                }
+                details.insert(
+                    trip_id.to_owned(),
+                    TripDetails {
+                        shape: record.optional(shape_id).map(str::to_owned),
+                        mode,
+                        wheelchair_accessible: Self::parse_gtfs_accessibility(
+                            &record,
+                            wheelchair_accessible,
+                        )?,
+                        bikes_allowed: Self::parse_gtfs_accessibility(&record, bikes_allowed)?,
+                        route_id: Some(route_id),
+                        short_name: record.optional(trip_short_name).map(str::to_owned),
+                        direction_id: Self::parse_gtfs_direction(&record, direction_id)?,
+                    },
+                );
             }
         }
-        Ok(map)
+        Ok((map, details))
     }
 
-    fn parse_gtfs_time(time_str: &str) -> Result<HaDuration, Box<dyn Error>> {
-        let (mut h, mut m, mut s): (u32, u32, u32) = (0, 0, 0);
-        for (state, part) in time_str.split(':').enumerate() {
-            match state {
-                0 => h = part.parse()?,
-                1 => m = part.parse()?,
-                2 => s = part.parse()?,
-                _ => {
-                    return Err(Box::new(HaError::GTFSError(
-                        "Invalid date format".to_owned(),
-                    )))
-                }
-            };
+    /// Parses a GTFS accessibility field such as wheelchair_boarding or bikes_allowed, where 1 means accessible, 2 means inaccessible and
+    /// 0 or an empty field means unknown
+    fn parse_gtfs_accessibility(
+        record: &GtfsRecord,
+        column: Option<Column>,
+    ) -> Result<Option<bool>, GtfsError> {
+        let column = match column {
+            Some(column) => column,
+            None => return Ok(None),
+        };
+        match record.optional(Some(column)) {
+            None | Some("0") => Ok(None),
+            Some("1") => Ok(Some(true)),
+            Some("2") => Ok(Some(false)),
+            Some(value) => Err(record.invalid(column, value)),
+        }
+    }
+
+    /// Parses a trip's direction_id, which is 0 or 1, or empty if unknown
+    fn parse_gtfs_direction(
+        record: &GtfsRecord,
+        column: Option<Column>,
+    ) -> Result<Option<u8>, GtfsError> {
+        let column = match column {
+            Some(column) => column,
+            None => return Ok(None),
+        };
+        match record.optional(Some(column)) {
+            None => Ok(None),
+            Some("0") => Ok(Some(0)),
+            Some("1") => Ok(Some(1)),
+            Some(value) => Err(record.invalid(column, value)),
+        }
+    }
+
+    fn parse_gtfs_time(record: &GtfsRecord, column: Column) -> Result<HaDuration, GtfsError> {
+        let time_str = record.get(column)?;
+        let mut hms = [0u32; 3];
+        let mut parts = time_str.split(':');
+        for (value, part) in hms.iter_mut().zip(&mut parts) {
+            *value = part.parse().map_err(|_| record.invalid(column, time_str))?;
+        }
+        if parts.next().is_some() {
+            return Err(record.invalid(column, time_str));
         }
-        Ok(HaDuration::from_hms(h, m, s))
+        Ok(HaDuration::from_hms(hms[0], hms[1], hms[2]))
     }
 
+    /// Reads the stops of the trains, one line at a time.
+    ///
+    /// Feeds normally list the stops of each trip together, so only the trip being read is kept aside and it is built
+    /// into a train as soon as the next trip starts. Trips whose stops are scattered across the file are still supported,
+    /// by keeping them aside until they are complete or reopening them once built, at the cost of more time and memory.
+    #[instrument(level = "debug", skip_all)]
     fn parse_stop_times<R: Read>(
         &mut self,
         reader: R,
-        mut trips: HashMap<String, Option<Vec<NaiveDate>>>,
-    ) -> Result<HashSet<u64>, Box<dyn Error>> {
-        let mut reader = csv::Reader::from_reader(reader);
-        let (trip_id, arrival_time, departure_time, stop_id, stop_sequence) = headers!(
-            reader.headers()?,
-            trip_id,
-            arrival_time,
-            departure_time,
-            stop_id,
-            stop_sequence
-        );
+        mut trips: TripsResult,
+        mut trip_details: HashMap<String, TripDetails>,
+        platforms: &Platforms,
+    ) -> Result<HashSet<u64>, GtfsError> {
+        let mut reader = GtfsReader::new("stop_times.txt", reader);
+        let [trip_id, arrival_time, departure_time, stop_id, stop_sequence] = reader.columns([
+            "trip_id",
+            "arrival_time",
+            "departure_time",
+            "stop_id",
+            "stop_sequence",
+        ])?;
         let mut stations = HashSet::new();
-        let mut proto_trains = HashMap::new();
-        for result in reader.records() {
-            let record = result?;
-            let trip_id = record
-                .get(trip_id)
-                .ok_or_else(|| HaError::GTFSError("trip_id".to_owned()))?;
-            if !trips.contains_key(trip_id) {
+        let mut current: Option<PrototypeTrain> = None;
+        // Trips which were interrupted by another trip before all of their stops were read
+        let mut pending: HashMap<TrainId, PrototypeTrain> = HashMap::new();
+        let mut record = reader.record();
+        while reader.read(&mut record)? {
+            let id = record.get(trip_id)?;
+            if current.as_ref().is_none_or(|train| train.id != id) {
+                let next = if let Some(dates) = trips.remove(id) {
+                    PrototypeTrain::new(id, dates, trip_details.remove(id).unwrap_or_default())
+                } else if let Some(train) = pending.remove(id) {
+                    train
+                } else if let Some(train) = self.trains.remove(id) {
+                    PrototypeTrain::from_train(train)
+                } else {
+                    continue;
+                };
+                if let Some(train) = current.replace(next) {
+                    self.finish_train(train, &mut pending);
+                }
+            }
+            let arrival_datetime = Self::parse_gtfs_time(&record, arrival_time)?;
+            let departure_datetime = Self::parse_gtfs_time(&record, departure_time)?;
+            let stop_id: u64 = record.parse(stop_id)?;
+            let sequence: u64 = record.parse(stop_sequence)?;
+            if sequence == 0 {
+                return Err(record.invalid(stop_sequence, "0"));
+            }
+            // Trains stopping at a platform are stored as stopping at its station, on that platform
+            let (stop_id, platform) = match platforms.get(&stop_id) {
+                Some((station, platform)) => (*station, platform.as_deref()),
+                None => (stop_id, None),
+            };
+            let mut stop = StopSchedule::new(stop_id, arrival_datetime, Some(departure_datetime));
+            if let Some(platform) = platform {
+                stop = stop.with_platform(platform);
+            }
+            // The line's trip is the current one by now, as lines of trips which aren't loaded were skipped
+            current
+                .as_mut()
+                .unwrap()
+                .set_stop(sequence as usize - 1, stop);
+            stations.insert(stop_id);
+        }
+        if let Some(train) = current {
+            self.finish_train(train, &mut pending);
+        }
+        if let Some(id) = pending.keys().next() {
+            return Err(reader.error(format!("partial train: {}", id)));
+        }
+        Ok(stations)
+    }
+
+    /// Adds a train whose stops were read, or keeps it aside if some of its stops are yet to be read
+    fn finish_train(
+        &mut self,
+        train: PrototypeTrain,
+        pending: &mut HashMap<TrainId, PrototypeTrain>,
+    ) {
+        if train.is_complete() {
+            self.trains.insert(train.id.clone(), train.into_train());
+        } else {
+            pending.insert(train.id.clone(), train);
+        }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    fn parse_transfers<R: Read>(
+        &mut self,
+        reader: R,
+        platforms: &Platforms,
+    ) -> Result<(), GtfsError> {
+        let mut reader = GtfsReader::new("transfers.txt", reader);
+        let [from_stop_id, to_stop_id, transfer_type, min_transfer_time] = reader.columns([
+            "from_stop_id",
+            "to_stop_id",
+            "transfer_type",
+            "min_transfer_time",
+        ])?;
+        for record in reader.records() {
+            let record = record?;
+            // Only transfers within the same station with a minimum transfer time (type 2) affect train changes
+            if record.optional(Some(transfer_type)) != Some("2") {
                 continue;
             }
-            let arrival_time = record
-                .get(arrival_time)
-                .ok_or_else(|| HaError::GTFSError("arrival_time".to_owned()))?;
-            let arrival_datetime = Self::parse_gtfs_time(arrival_time)?;
-            let departure_time = record
-                .get(departure_time)
-                .ok_or_else(|| HaError::GTFSError("departure_time".to_owned()))?;
-            let departure_datetime = Self::parse_gtfs_time(departure_time)?;
-            let stop_id: u64 = record
-                .get(stop_id)
-                .ok_or_else(|| HaError::GTFSError("stop_id".to_owned()))?
-                .parse()?;
-            let stop_sequence: u64 = record
-                .get(stop_sequence)
-                .ok_or_else(|| HaError::GTFSError("stop_sequence".to_owned()))?
-                .parse()?;
-            if stop_sequence == 0 {
-                return Err(Box::new(HaError::GTFSError(
-                    "stop_sequence == 0".to_owned(),
-                )));
+            // Transfers between platforms are transfers within their station
+            let station =
+                |stop: StationId| platforms.get(&stop).map_or(stop, |(station, _)| *station);
+            let from_station = station(record.parse(from_stop_id)?);
+            let to_station = station(record.parse(to_stop_id)?);
+            if from_station != to_station || !self.stations.contains_key(&from_station) {
+                continue;
             }
-            let stop_seq_index = stop_sequence as usize - 1;
-            let stop = StopSchedule::new(stop_id, arrival_datetime, Some(departure_datetime));
-            if !proto_trains.contains_key(trip_id) {
-                // We take ownership of the dates vector from inside the trips table by replacing it with None.
-                // This should never panic because insert will never return None since we validated trips.contains_key(trip_id) before,
-                // and the optional vec is always set to Some by parse_trips, and only replaced once by us (we validate !proto_trains.contains_key(trip_id) here)
-                let dates = trips.insert(trip_id.to_owned(), None).unwrap().unwrap();
-                proto_trains.insert(
-                    trip_id.to_owned(),
-                    PrototypeTrain {
-                        id: trip_id.to_owned(),
-                        stops: Vec::new(),
-                        dates,
-                    },
-                );
+            let min_transfer_time: u64 = record.parse(min_transfer_time)?;
+            // Trains may arrive at and leave from any of the station's platforms, so the longest transfer is kept
+            let transfer = self
+                .transfers
+                .entry(from_station)
+                .or_insert(HaDuration::from_seconds(min_transfer_time));
+            transfer.seconds = transfer.seconds.max(min_transfer_time);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    fn parse_frequencies<R: Read>(&mut self, reader: R) -> Result<(), GtfsError> {
+        let mut reader = GtfsReader::new("frequencies.txt", reader);
+        let [trip_id, start_time, end_time, headway_secs] =
+            reader.columns(["trip_id", "start_time", "end_time", "headway_secs"])?;
+        let mut frequencies: HashMap<String, Vec<(HaDuration, HaDuration, u64)>> = HashMap::new();
+        for record in reader.records() {
+            let record = record?;
+            let trip_id = record.get(trip_id)?;
+            if !self.trains.contains_key(trip_id) {
+                continue;
             }
-            let train = proto_trains.get_mut(trip_id).unwrap();
-            match train.stops.len().cmp(&stop_seq_index) {
-                Ordering::Greater => train.stops[stop_seq_index] = Some(stop),
-                Ordering::Less => {
-                    train.stops.resize_with(stop_seq_index + 1, || None);
-                    train.stops[stop_seq_index] = Some(stop);
+            let start_time = Self::parse_gtfs_time(&record, start_time)?;
+            let end_time = Self::parse_gtfs_time(&record, end_time)?;
+            let headway: u64 = record.parse(headway_secs)?;
+            if headway == 0 {
+                return Err(record.invalid(headway_secs, "0"));
+            }
+            frequencies
+                .entry(trip_id.to_owned())
+                .or_default()
+                .push((start_time, end_time, headway));
+        }
+        // The stop times of a frequency based trip only serve as a template for the relative times between stops,
+        // so the template train is replaced by one concrete train per departure
+        for (trip_id, periods) in frequencies {
+            let template = self.trains.remove(&trip_id).unwrap();
+            let first_departure = match template.stops.first() {
+                Some(stop) => stop.departure_offset.seconds,
+                None => continue,
+            };
+            for (start_time, end_time, headway_secs) in periods {
+                let mut departure = start_time.seconds;
+                while departure < end_time.seconds {
+                    let shift = |offset: HaDuration| {
+                        HaDuration::from_seconds(offset.seconds + departure - first_departure)
+                    };
+                    let id = format!(
+                        "{}_{:02}:{:02}:{:02}",
+                        trip_id,
+                        departure / 3600,
+                        (departure % 3600) / 60,
+                        departure % 60
+                    );
+                    let train = Train {
+                        id: id.clone(),
+                        stops: template
+                            .stops
+                            .iter()
+                            .map(|stop| StopSchedule {
+                                station: stop.station,
+                                arrival_offset: shift(stop.arrival_offset),
+                                departure_offset: shift(stop.departure_offset),
+                                platform: stop.platform.clone(),
+                            })
+                            .collect(),
+                        dates: template.dates.clone(),
+                        shape: template.shape.clone(),
+                        mode: template.mode,
+                        wheelchair_accessible: template.wheelchair_accessible,
+                        bikes_allowed: template.bikes_allowed,
+                        route_id: template.route_id,
+                        short_name: template.short_name.clone(),
+                        direction_id: template.direction_id,
+                    };
+                    self.trains.insert(id, train);
+                    departure += headway_secs;
                 }
-                Ordering::Equal => train.stops.push(Some(stop)),
             }
-            stations.insert(stop_id);
         }
-        for (id, ptrain) in proto_trains {
-            if ptrain.stops.iter().any(|x| x.is_none()) {
-                return Err(Box::new(HaError::GTFSError(format!(
-                    "partial train: {}",
-                    id
-                ))));
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    fn parse_shapes<R: Read>(&mut self, reader: R) -> Result<(), GtfsError> {
+        let mut reader = GtfsReader::new("shapes.txt", reader);
+        let [shape_id, shape_pt_lat, shape_pt_lon, shape_pt_sequence] = reader.columns([
+            "shape_id",
+            "shape_pt_lat",
+            "shape_pt_lon",
+            "shape_pt_sequence",
+        ])?;
+        let used: HashSet<&str> = self
+            .trains
+            .values()
+            .filter_map(|t| t.shape().map(|s| s.as_str()))
+            .collect();
+        let mut points: HashMap<ShapeId, Vec<(u64, f64, f64)>> = HashMap::new();
+        for record in reader.records() {
+            let record = record?;
+            let shape_id = record.get(shape_id)?;
+            if !used.contains(shape_id) {
+                continue;
             }
-            let train = Train {
-                id: ptrain.id,
-                stops: ptrain.stops.into_iter().map(|x| x.unwrap()).collect(),
-                dates: ptrain.dates,
-            };
-            self.trains.insert(id, train);
+            let lat: f64 = record.parse(shape_pt_lat)?;
+            let lon: f64 = record.parse(shape_pt_lon)?;
+            let sequence: u64 = record.parse(shape_pt_sequence)?;
+            points
+                .entry(shape_id.to_owned())
+                .or_default()
+                .push((sequence, lat, lon));
         }
-        Ok(stations)
+        for (shape_id, mut shape) in points {
+            shape.sort_unstable_by_key(|(sequence, _, _)| *sequence);
+            self.shapes.insert(
+                shape_id,
+                shape.into_iter().map(|(_, lat, lon)| (lat, lon)).collect(),
+            );
+        }
+        Ok(())
     }
 
-    fn load_gtfs<T: for<'a> opener::FileOpener<'a>>(mut opener: T) -> Result<Self, Box<dyn Error>> {
-        let irw_id = Self::parse_agency(opener.open("agency.txt")?)?;
-        let irw_routes = Self::parse_routes(opener.open("routes.txt")?, irw_id)?;
+    #[instrument(skip_all)]
+    fn load_gtfs<T: for<'a> opener::FileOpener<'a>>(
+        mut opener: T,
+        options: &GtfsOptions,
+    ) -> Result<Self, GtfsError> {
+        let agencies = Self::parse_agency(opener.open("agency.txt")?, options.agencies())?;
+        let irw_routes = Self::parse_routes(opener.open("routes.txt")?, agencies, options)?;
         let services = Self::parse_calendar(opener.open("calendar.txt")?)?;
-        let irw_trips = Self::parse_trips(opener.open("trips.txt")?, irw_routes, services)?;
+        let (irw_trips, trip_details) =
+            Self::parse_trips(opener.open("trips.txt")?, irw_routes, services)?;
         let mut result = Self::new();
-        let irw_stops = result.parse_stop_times(opener.open("stop_times.txt")?, irw_trips)?;
+        let platforms = Self::parse_platforms(opener.open("stops.txt")?)?;
+        let irw_stops = result.parse_stop_times(
+            opener.open("stop_times.txt")?,
+            irw_trips,
+            trip_details,
+            &platforms,
+        )?;
+        // frequencies.txt is optional
+        if let Some(reader) = opener::optional(opener.open("frequencies.txt"))? {
+            result.parse_frequencies(reader)?;
+        }
         result.parse_stops(opener.open("stops.txt")?, irw_stops)?;
+        // transfers.txt is optional
+        if let Some(reader) = opener::optional(opener.open("transfers.txt"))? {
+            result.parse_transfers(reader, &platforms)?;
+        }
+        // shapes.txt is optional
+        if let Some(reader) = opener::optional(opener.open("shapes.txt"))? {
+            result.parse_shapes(reader)?;
+        }
+        // Fares are optional, and fare_rules.txt is only meaningful along with fare_attributes.txt
+        let has_fares = match opener::optional(opener.open("fare_attributes.txt"))? {
+            Some(reader) => {
+                result.fares.parse_attributes(reader)?;
+                true
+            }
+            None => false,
+        };
+        if has_fares {
+            if let Some(reader) = opener::optional(opener.open("fare_rules.txt"))? {
+                result.fares.parse_rules(reader)?;
+            }
+        }
+        result.built = Some(Utc::now().naive_utc());
+        info!(
+            stations = result.stations.len(),
+            trains = result.trains.len(),
+            "Loaded GTFS feed"
+        );
         Ok(result)
     }
 
-    /// Loads a GTFS file database from a directory containing GTFS text files.
-    pub fn from_gtfs_directory(root: &Path) -> Result<Self, Box<dyn Error>> {
+    fn load_gtfs_directory(root: &Path, options: &GtfsOptions) -> Result<Self, GtfsError> {
         let opener = opener::PathFileOpener::new(root);
-        Self::load_gtfs(opener)
+        Self::load_gtfs(opener, options)
     }
 
-    /// Loads a GTFS file database from a zip file containing GTFS text files.
-    pub fn from_gtfs_zip(root: &Path) -> Result<Self, Box<dyn Error>> {
-        let file = File::open(root)?;
+    #[cfg(feature = "archives")]
+    fn load_gtfs_zip(root: &Path, options: &GtfsOptions) -> Result<Self, GtfsError> {
+        let file = File::open(root).map_err(|source| GtfsError::Io {
+            file: root.display().to_string(),
+            source,
+        })?;
         let reader = BufReader::new(file);
-        let zip = ZipArchive::new(reader)?;
+        let zip = ZipArchive::new(reader).map_err(|e| GtfsError::Archive {
+            file: root.display().to_string(),
+            message: e.to_string(),
+        })?;
         let opener = opener::ZipFileOpener::new(zip);
-        Self::load_gtfs(opener)
+        Self::load_gtfs(opener, options)
+    }
+
+    #[cfg(feature = "archives")]
+    fn load_gtfs_tar_gz(root: &Path, options: &GtfsOptions) -> Result<Self, GtfsError> {
+        let opener = opener::TarGzFileOpener::new(root);
+        Self::load_gtfs(opener, options)
+    }
+
+    /// Loads a GTFS file database from a directory containing GTFS text files.
+    pub fn from_gtfs_directory(root: &Path) -> Result<Self, GtfsError> {
+        Self::load_gtfs_directory(root, &GtfsOptions::default())
+    }
+
+    /// Loads a GTFS file database from a zip file containing GTFS text files.
+    #[cfg(feature = "archives")]
+    pub fn from_gtfs_zip(root: &Path) -> Result<Self, GtfsError> {
+        Self::load_gtfs_zip(root, &GtfsOptions::default())
+    }
+
+    /// Loads a GTFS file database from a gzip compressed tarball containing GTFS text files.
+    #[cfg(feature = "archives")]
+    pub fn from_gtfs_tar_gz(root: &Path) -> Result<Self, GtfsError> {
+        Self::load_gtfs_tar_gz(root, &GtfsOptions::default())
+    }
+
+    /// Loads a GTFS file database from a directory, zip file or gzip compressed tarball, detecting the format automatically.
+    ///
+    /// Tarballs are recognized by their file extension or by the gzip magic number; any other file is assumed to be a zip file.
+    /// Without the archives feature, only directories can be loaded.
+    pub fn from_gtfs_path(root: &Path) -> Result<Self, GtfsError> {
+        Self::from_gtfs_path_with_options(root, &GtfsOptions::default())
+    }
+
+    /// Loads a GTFS file database like `from_gtfs_path`, with the given settings
+    pub fn from_gtfs_path_with_options(
+        root: &Path,
+        options: &GtfsOptions,
+    ) -> Result<Self, GtfsError> {
+        if root.is_dir() {
+            return Self::load_gtfs_directory(root, options);
+        }
+        Self::load_gtfs_archive(root, options)
+    }
+
+    #[cfg(feature = "archives")]
+    fn load_gtfs_archive(root: &Path, options: &GtfsOptions) -> Result<Self, GtfsError> {
+        let has_tar_gz_extension = root
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.ends_with(".tar.gz") || n.ends_with(".tgz"));
+        let mut magic = [0u8; 2];
+        let has_gzip_magic = File::open(root)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .is_ok_and(|_| magic == [0x1f, 0x8b]);
+        if has_tar_gz_extension || has_gzip_magic {
+            Self::load_gtfs_tar_gz(root, options)
+        } else {
+            Self::load_gtfs_zip(root, options)
+        }
+    }
+
+    #[cfg(not(feature = "archives"))]
+    fn load_gtfs_archive(root: &Path, _options: &GtfsOptions) -> Result<Self, GtfsError> {
+        Err(GtfsError::Archive {
+            file: root.display().to_string(),
+            message: "this build of HaRail loads GTFS feeds from directories only".to_owned(),
+        })
     }
 }
 
@@ -4,16 +4,49 @@
 * License, v. 2.0. If a copy of the MPL was not distributed with this
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::error::Error;
+use crate::GtfsError;
+#[cfg(feature = "archives")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "archives")]
+use std::ffi::OsStr;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::ErrorKind;
+#[cfg(feature = "archives")]
+use std::io::{BufReader, Cursor};
 use std::path::Path;
+#[cfg(feature = "archives")]
+use tar::Archive;
+#[cfg(feature = "archives")]
+use zip::result::ZipError;
+#[cfg(feature = "archives")]
 use zip::{read::ZipFile, ZipArchive};
 
 pub trait FileOpener<'a> {
     type Read: Read;
 
-    fn open(&'a mut self, name: &str) -> Result<Self::Read, Box<dyn Error>>;
+    fn open(&'a mut self, name: &str) -> Result<Self::Read, GtfsError>;
+}
+
+/// Treats a missing file as None, for the files which GTFS makes optional
+pub fn optional<R>(opened: Result<R, GtfsError>) -> Result<Option<R>, GtfsError> {
+    match opened {
+        Ok(reader) => Ok(Some(reader)),
+        Err(GtfsError::MissingFile { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn io_error(name: &str, source: std::io::Error) -> GtfsError {
+    match source.kind() {
+        ErrorKind::NotFound => GtfsError::MissingFile {
+            file: name.to_owned(),
+        },
+        _ => GtfsError::Io {
+            file: name.to_owned(),
+            source,
+        },
+    }
 }
 
 pub struct PathFileOpener<'p> {
@@ -29,25 +62,79 @@ impl<'p> PathFileOpener<'p> {
 impl<'a> FileOpener<'a> for PathFileOpener<'_> {
     type Read = File;
 
-    fn open(&'a mut self, name: &str) -> Result<Self::Read, Box<dyn Error>> {
-        Ok(File::open(self.path.join(name))?)
+    fn open(&'a mut self, name: &str) -> Result<Self::Read, GtfsError> {
+        File::open(self.path.join(name)).map_err(|e| io_error(name, e))
     }
 }
 
+#[cfg(feature = "archives")]
 pub struct ZipFileOpener<R: Read + Seek> {
     zip: ZipArchive<R>,
 }
 
+#[cfg(feature = "archives")]
 impl<R: Read + Seek> ZipFileOpener<R> {
     pub fn new(zip: ZipArchive<R>) -> Self {
         ZipFileOpener { zip }
     }
 }
 
+#[cfg(feature = "archives")]
 impl<'a, R: Read + Seek> FileOpener<'a> for ZipFileOpener<R> {
     type Read = ZipFile<'a>;
 
-    fn open(&'a mut self, name: &str) -> Result<Self::Read, Box<dyn Error>> {
-        Ok(self.zip.by_name(name)?)
+    fn open(&'a mut self, name: &str) -> Result<Self::Read, GtfsError> {
+        self.zip.by_name(name).map_err(|e| match e {
+            ZipError::FileNotFound => GtfsError::MissingFile {
+                file: name.to_owned(),
+            },
+            ZipError::Io(source) => io_error(name, source),
+            e => GtfsError::Archive {
+                file: name.to_owned(),
+                message: e.to_string(),
+            },
+        })
+    }
+}
+
+/// Opens files from a gzip compressed tarball.
+///
+/// Tarballs cannot be accessed randomly, so every open scans the archive from its start and buffers the requested file in memory.
+/// Files are matched by name regardless of the directory they are in, since some feeds wrap their files in a top level directory.
+#[cfg(feature = "archives")]
+pub struct TarGzFileOpener<'p> {
+    path: &'p Path,
+}
+
+#[cfg(feature = "archives")]
+impl<'p> TarGzFileOpener<'p> {
+    pub fn new(path: &'p Path) -> Self {
+        TarGzFileOpener { path }
+    }
+}
+
+#[cfg(feature = "archives")]
+impl<'a> FileOpener<'a> for TarGzFileOpener<'_> {
+    type Read = Cursor<Vec<u8>>;
+
+    fn open(&'a mut self, name: &str) -> Result<Self::Read, GtfsError> {
+        // A failure to read the tarball itself is reported against it rather than the requested file
+        let archive_error = |source: std::io::Error| GtfsError::Io {
+            file: self.path.display().to_string(),
+            source,
+        };
+        let file = BufReader::new(File::open(self.path).map_err(archive_error)?);
+        let mut archive = Archive::new(GzDecoder::new(file));
+        for entry in archive.entries().map_err(archive_error)? {
+            let mut entry = entry.map_err(archive_error)?;
+            if entry.path().map_err(archive_error)?.file_name() == Some(OsStr::new(name)) {
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).map_err(|e| io_error(name, e))?;
+                return Ok(Cursor::new(buf));
+            }
+        }
+        Err(GtfsError::MissingFile {
+            file: name.to_owned(),
+        })
     }
 }
@@ -0,0 +1,168 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::GtfsError;
+use csv::StringRecord;
+use std::io::Read;
+use std::str::FromStr;
+
+/// A column of a GTFS file, found by its name in the header
+#[derive(Copy, Clone)]
+pub struct Column {
+    index: usize,
+    name: &'static str,
+}
+
+/// Reads the records of a GTFS file, reporting problems along with the file and line they were found at
+pub struct GtfsReader<'f, R> {
+    file: &'f str,
+    reader: csv::Reader<R>,
+}
+
+impl<'f, R: Read> GtfsReader<'f, R> {
+    pub fn new(file: &'f str, reader: R) -> Self {
+        GtfsReader {
+            file,
+            reader: csv::Reader::from_reader(reader),
+        }
+    }
+
+    fn find(&mut self, name: &'static str) -> Result<Option<Column>, GtfsError> {
+        let file = self.file;
+        let headers = self.reader.headers().map_err(|source| GtfsError::Csv {
+            file: file.to_owned(),
+            source,
+        })?;
+        Ok(headers
+            .iter()
+            .position(|h| h == name)
+            .map(|index| Column { index, name }))
+    }
+
+    /// Finds the given columns, which the file must have
+    pub fn columns<const N: usize>(
+        &mut self,
+        names: [&'static str; N],
+    ) -> Result<[Column; N], GtfsError> {
+        let columns = self.optional_columns(names)?;
+        if let Some((_, name)) = columns.iter().zip(names).find(|(c, _)| c.is_none()) {
+            return Err(GtfsError::MissingColumn {
+                file: self.file.to_owned(),
+                column: name,
+            });
+        }
+        Ok(columns.map(Option::unwrap))
+    }
+
+    /// Finds the given columns, which the file may lack
+    pub fn optional_columns<const N: usize>(
+        &mut self,
+        names: [&'static str; N],
+    ) -> Result<[Option<Column>; N], GtfsError> {
+        let mut columns = [None; N];
+        for (column, name) in columns.iter_mut().zip(names) {
+            *column = self.find(name)?;
+        }
+        Ok(columns)
+    }
+
+    pub fn records(&mut self) -> impl Iterator<Item = Result<GtfsRecord<'f>, GtfsError>> + '_ {
+        let file = self.file;
+        self.reader.records().map(move |record| {
+            record
+                .map(|record| GtfsRecord { file, record })
+                .map_err(|source| GtfsError::Csv {
+                    file: file.to_owned(),
+                    source,
+                })
+        })
+    }
+
+    /// An empty record, to read the lines of the file into one at a time with `read`
+    pub fn record(&self) -> GtfsRecord<'f> {
+        GtfsRecord {
+            file: self.file,
+            record: StringRecord::new(),
+        }
+    }
+
+    /// Reads the next line into the given record, reusing its memory, and returns whether there was a line to read
+    pub fn read(&mut self, record: &mut GtfsRecord<'f>) -> Result<bool, GtfsError> {
+        self.reader
+            .read_record(&mut record.record)
+            .map_err(|source| GtfsError::Csv {
+                file: self.file.to_owned(),
+                source,
+            })
+    }
+
+    /// A problem with the file as a whole
+    pub fn error(&self, message: String) -> GtfsError {
+        GtfsError::Invalid {
+            file: self.file.to_owned(),
+            message,
+        }
+    }
+}
+
+/// A line of a GTFS file
+pub struct GtfsRecord<'f> {
+    file: &'f str,
+    record: StringRecord,
+}
+
+impl GtfsRecord<'_> {
+    fn line(&self) -> u64 {
+        self.record.position().map_or(0, |p| p.line())
+    }
+
+    /// Gets the value of a column which must have a value
+    pub fn get(&self, column: Column) -> Result<&str, GtfsError> {
+        self.record
+            .get(column.index)
+            .ok_or_else(|| GtfsError::MissingValue {
+                file: self.file.to_owned(),
+                line: self.line(),
+                column: column.name,
+            })
+    }
+
+    /// Gets the value of a column which may be missing or empty
+    pub fn optional(&self, column: Option<Column>) -> Option<&str> {
+        column
+            .and_then(|c| self.record.get(c.index))
+            .filter(|x| !x.is_empty())
+    }
+
+    pub fn parse<T: FromStr>(&self, column: Column) -> Result<T, GtfsError> {
+        let value = self.get(column)?;
+        value.parse().map_err(|_| self.invalid(column, value))
+    }
+
+    /// Parses the value of a column which may be missing or empty
+    pub fn parse_optional<T: FromStr>(
+        &self,
+        column: Option<Column>,
+    ) -> Result<Option<T>, GtfsError> {
+        match (column, self.optional(column)) {
+            (Some(column), Some(value)) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| self.invalid(column, value)),
+            _ => Ok(None),
+        }
+    }
+
+    /// A value which could not be understood
+    pub fn invalid(&self, column: Column, value: &str) -> GtfsError {
+        GtfsError::InvalidValue {
+            file: self.file.to_owned(),
+            line: self.line(),
+            column: column.name,
+            value: value.to_owned(),
+        }
+    }
+}
@@ -0,0 +1,713 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! GTFS-Realtime support, for routing according to the actual state of the trains rather than the static timetable.
+//!
+//! Delays and cancellations are kept in an overlay which is handed to route searches through RoutingOptions, so the database itself never changes.
+
+use crate::{HaError, RailroadData, StationId, Stop, Train, TrainId};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime};
+use prost::Message;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+/// The subset of the GTFS-Realtime protocol buffer messages needed to apply schedule changes.
+///
+/// Field tags match gtfs-realtime.proto, so feeds can be decoded from and encoded to the wire format.
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedMessage {
+        #[prost(message, optional, tag = "1")]
+        pub header: Option<FeedHeader>,
+        #[prost(message, repeated, tag = "2")]
+        pub entity: Vec<FeedEntity>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedHeader {
+        #[prost(string, optional, tag = "1")]
+        pub gtfs_realtime_version: Option<String>,
+        #[prost(uint64, optional, tag = "3")]
+        pub timestamp: Option<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedEntity {
+        #[prost(string, optional, tag = "1")]
+        pub id: Option<String>,
+        #[prost(bool, optional, tag = "2")]
+        pub is_deleted: Option<bool>,
+        #[prost(message, optional, tag = "3")]
+        pub trip_update: Option<TripUpdate>,
+        #[prost(message, optional, tag = "4")]
+        pub vehicle: Option<VehiclePosition>,
+        #[prost(message, optional, tag = "5")]
+        pub alert: Option<Alert>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TripUpdate {
+        #[prost(message, optional, tag = "1")]
+        pub trip: Option<TripDescriptor>,
+        #[prost(message, repeated, tag = "2")]
+        pub stop_time_update: Vec<StopTimeUpdate>,
+        #[prost(int32, optional, tag = "5")]
+        pub delay: Option<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TripDescriptor {
+        #[prost(string, optional, tag = "1")]
+        pub trip_id: Option<String>,
+        #[prost(string, optional, tag = "3")]
+        pub start_date: Option<String>,
+        #[prost(enumeration = "TripScheduleRelationship", optional, tag = "4")]
+        pub schedule_relationship: Option<i32>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum TripScheduleRelationship {
+        Scheduled = 0,
+        Added = 1,
+        Unscheduled = 2,
+        Canceled = 3,
+        Replacement = 5,
+        Duplicated = 6,
+        Deleted = 7,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StopTimeEvent {
+        #[prost(int32, optional, tag = "1")]
+        pub delay: Option<i32>,
+        #[prost(int64, optional, tag = "2")]
+        pub time: Option<i64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StopTimeUpdate {
+        #[prost(uint32, optional, tag = "1")]
+        pub stop_sequence: Option<u32>,
+        #[prost(message, optional, tag = "2")]
+        pub arrival: Option<StopTimeEvent>,
+        #[prost(message, optional, tag = "3")]
+        pub departure: Option<StopTimeEvent>,
+        #[prost(string, optional, tag = "4")]
+        pub stop_id: Option<String>,
+        #[prost(enumeration = "StopScheduleRelationship", optional, tag = "5")]
+        pub schedule_relationship: Option<i32>,
+        #[prost(enumeration = "OccupancyStatus", optional, tag = "7")]
+        pub departure_occupancy_status: Option<i32>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum StopScheduleRelationship {
+        Scheduled = 0,
+        Skipped = 1,
+        NoData = 2,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct VehiclePosition {
+        #[prost(message, optional, tag = "1")]
+        pub trip: Option<TripDescriptor>,
+        #[prost(enumeration = "OccupancyStatus", optional, tag = "9")]
+        pub occupancy_status: Option<i32>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum OccupancyStatus {
+        Empty = 0,
+        ManySeatsAvailable = 1,
+        FewSeatsAvailable = 2,
+        StandingRoomOnly = 3,
+        CrushedStandingRoomOnly = 4,
+        Full = 5,
+        NotAcceptingPassengers = 6,
+        NoDataAvailable = 7,
+        NotBoardable = 8,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Alert {
+        #[prost(message, repeated, tag = "1")]
+        pub active_period: Vec<TimeRange>,
+        #[prost(message, repeated, tag = "5")]
+        pub informed_entity: Vec<EntitySelector>,
+        #[prost(message, optional, tag = "10")]
+        pub header_text: Option<TranslatedString>,
+        #[prost(message, optional, tag = "11")]
+        pub description_text: Option<TranslatedString>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TimeRange {
+        #[prost(uint64, optional, tag = "1")]
+        pub start: Option<u64>,
+        #[prost(uint64, optional, tag = "2")]
+        pub end: Option<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct EntitySelector {
+        #[prost(string, optional, tag = "1")]
+        pub agency_id: Option<String>,
+        #[prost(string, optional, tag = "2")]
+        pub route_id: Option<String>,
+        #[prost(message, optional, tag = "4")]
+        pub trip: Option<TripDescriptor>,
+        #[prost(string, optional, tag = "5")]
+        pub stop_id: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TranslatedString {
+        #[prost(message, repeated, tag = "1")]
+        pub translation: Vec<Translation>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Translation {
+        #[prost(string, optional, tag = "1")]
+        pub text: Option<String>,
+        #[prost(string, optional, tag = "2")]
+        pub language: Option<String>,
+    }
+}
+
+/// A text in several languages, as (language tag, text) pairs
+type Translations = Vec<(Option<String>, String)>;
+
+/// Picks the translation in the given language, falling back to an untagged translation and then to any translation
+fn translate<'a>(translations: &'a Translations, language: &str) -> Option<&'a str> {
+    let primary = |tag: &str| {
+        tag.split('-')
+            .next()
+            .unwrap_or_default()
+            .eq_ignore_ascii_case(language)
+    };
+    translations
+        .iter()
+        .find(|(tag, _)| tag.as_deref().is_some_and(primary))
+        .or_else(|| translations.iter().find(|(tag, _)| tag.is_none()))
+        .or_else(|| translations.first())
+        .map(|(_, text)| text.as_str())
+}
+
+fn translations(text: Option<proto::TranslatedString>) -> Translations {
+    text.into_iter()
+        .flat_map(|t| t.translation)
+        .filter_map(|t| Some((t.language, t.text?)))
+        .collect()
+}
+
+/// A service alert, such as an elevator out of service at a station or a suspended line
+///
+/// Examples:
+/// ```
+/// use harail::gtfs_rt::Alert;
+///
+/// let alert = Alert::new("Elevator out of service").with_station(3700);
+/// assert_eq!("Elevator out of service", alert.header("en"));
+/// assert!(alert.affects_station(3700));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Alert {
+    header: Translations,
+    description: Translations,
+    active_periods: Vec<(Option<NaiveDateTime>, Option<NaiveDateTime>)>,
+    stations: HashSet<StationId>,
+    trains: HashSet<TrainId>,
+    network_wide: bool,
+}
+
+impl Alert {
+    /// Create a new Alert object with the given header, which doesn't affect anything yet
+    pub fn new(header: &str) -> Self {
+        Alert {
+            header: vec![(None, header.to_owned())],
+            description: Vec::new(),
+            active_periods: Vec::new(),
+            stations: HashSet::new(),
+            trains: HashSet::new(),
+            network_wide: false,
+        }
+    }
+
+    /// Sets the detailed description of the alert
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = vec![(None, description.to_owned())];
+        self
+    }
+
+    /// Makes the alert affect boarding and leaving trains at the given station
+    pub fn with_station(mut self, station: StationId) -> Self {
+        self.stations.insert(station);
+        self
+    }
+
+    /// Makes the alert affect the given train
+    pub fn with_train(mut self, train: &str) -> Self {
+        self.trains.insert(train.to_owned());
+        self
+    }
+
+    /// Makes the alert affect every train, e.g. when the whole line is suspended
+    pub fn with_whole_network(mut self) -> Self {
+        self.network_wide = true;
+        self
+    }
+
+    /// Limits the alert to the given period. An alert with several periods is active during any of them.
+    pub fn with_active_period(
+        mut self,
+        start: Option<NaiveDateTime>,
+        end: Option<NaiveDateTime>,
+    ) -> Self {
+        self.active_periods.push((start, end));
+        self
+    }
+
+    /// The short summary of the alert, preferably in the given language
+    pub fn header(&self, language: &str) -> &str {
+        translate(&self.header, language).unwrap_or_default()
+    }
+
+    /// The detailed description of the alert, preferably in the given language
+    pub fn description(&self, language: &str) -> Option<&str> {
+        translate(&self.description, language)
+    }
+
+    /// Whether the alert affects boarding and leaving trains at the given station
+    pub fn affects_station(&self, station: StationId) -> bool {
+        self.network_wide || self.stations.contains(&station)
+    }
+
+    /// Whether the alert affects the given train
+    pub fn affects_train(&self, train: &str) -> bool {
+        self.network_wide || self.trains.contains(train)
+    }
+
+    /// Whether the alert is active at any time between from and to
+    pub fn is_active(&self, from: NaiveDateTime, to: NaiveDateTime) -> bool {
+        self.active_periods.is_empty()
+            || self.active_periods.iter().any(|(start, end)| {
+                start.is_none_or(|start| start <= to) && end.is_none_or(|end| end >= from)
+            })
+    }
+
+    /// Whether the alert is relevant to a train ride between two stations
+    pub(crate) fn affects_ride(
+        &self,
+        train: &str,
+        start: StationId,
+        end: StationId,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> bool {
+        (self.affects_train(train) || self.affects_station(start) || self.affects_station(end))
+            && self.is_active(from, to)
+    }
+
+    fn from_proto(alert: proto::Alert) -> Self {
+        let time = |t: Option<u64>| {
+            t.and_then(|t| DateTime::from_timestamp(t as i64, 0))
+                .map(|t| crate::to_feed_time(&t))
+        };
+        let mut result = Alert {
+            header: translations(alert.header_text),
+            description: translations(alert.description_text),
+            active_periods: alert
+                .active_period
+                .into_iter()
+                .map(|p| (time(p.start), time(p.end)))
+                .collect(),
+            stations: HashSet::new(),
+            trains: HashSet::new(),
+            network_wide: false,
+        };
+        for entity in alert.informed_entity {
+            let station = entity.stop_id.as_deref().map(str::parse::<StationId>);
+            let train = entity.trip.and_then(|t| t.trip_id);
+            match (station, train) {
+                (Some(Ok(station)), _) => {
+                    result.stations.insert(station);
+                }
+                // Stops which are not stations belong to other agencies
+                (Some(Err(_)), _) => {}
+                (None, Some(train)) => {
+                    result.trains.insert(train);
+                }
+                // Only the railway is loaded into the database, so agency and route wide alerts affect everything
+                (None, None) => result.network_wide = true,
+            }
+        }
+        result
+    }
+}
+
+/// How crowded a train is expected to be, from emptiest to fullest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Occupancy {
+    Empty,
+    ManySeatsAvailable,
+    FewSeatsAvailable,
+    StandingRoomOnly,
+    CrushedStandingRoomOnly,
+    Full,
+    NotAcceptingPassengers,
+}
+
+impl Occupancy {
+    /// The name of the occupancy in JSON output, e.g. few_seats_available
+    pub fn name(&self) -> &'static str {
+        match self {
+            Occupancy::Empty => "empty",
+            Occupancy::ManySeatsAvailable => "many_seats_available",
+            Occupancy::FewSeatsAvailable => "few_seats_available",
+            Occupancy::StandingRoomOnly => "standing_room_only",
+            Occupancy::CrushedStandingRoomOnly => "crushed_standing_room_only",
+            Occupancy::Full => "full",
+            Occupancy::NotAcceptingPassengers => "not_accepting_passengers",
+        }
+    }
+
+    /// The occupancy of a feed's occupancy status, or None if the feed has no data about it
+    fn from_proto(status: Option<i32>) -> Option<Self> {
+        let occupancy = match proto::OccupancyStatus::try_from(status?).ok()? {
+            proto::OccupancyStatus::Empty => Occupancy::Empty,
+            proto::OccupancyStatus::ManySeatsAvailable => Occupancy::ManySeatsAvailable,
+            proto::OccupancyStatus::FewSeatsAvailable => Occupancy::FewSeatsAvailable,
+            proto::OccupancyStatus::StandingRoomOnly => Occupancy::StandingRoomOnly,
+            proto::OccupancyStatus::CrushedStandingRoomOnly => Occupancy::CrushedStandingRoomOnly,
+            proto::OccupancyStatus::Full => Occupancy::Full,
+            proto::OccupancyStatus::NotAcceptingPassengers
+            | proto::OccupancyStatus::NotBoardable => Occupancy::NotAcceptingPassengers,
+            proto::OccupancyStatus::NoDataAvailable => return None,
+        };
+        Some(occupancy)
+    }
+}
+
+impl fmt::Display for Occupancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Occupancy::Empty => "empty",
+            Occupancy::ManySeatsAvailable => "many seats available",
+            Occupancy::FewSeatsAvailable => "few seats available",
+            Occupancy::StandingRoomOnly => "standing room only",
+            Occupancy::CrushedStandingRoomOnly => "crowded",
+            Occupancy::Full => "full",
+            Occupancy::NotAcceptingPassengers => "not accepting passengers",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// A real-time change to a train's stop at a single station
+#[derive(Clone, Debug)]
+struct StopChange {
+    station: StationId,
+    arrival_delay: Option<Duration>,
+    departure_delay: Option<Duration>,
+    skipped: bool,
+    /// The expected crowding of the train when leaving the station
+    occupancy: Option<Occupancy>,
+}
+
+/// Real-time changes to a single run of a train
+#[derive(Clone, Debug, Default)]
+struct TripChanges {
+    cancelled: bool,
+    delay: Option<Duration>,
+    stops: Vec<StopChange>,
+    /// The crowding of the train at its latest reported position
+    occupancy: Option<Occupancy>,
+}
+
+/// Real-time changes to the timetable, such as delays and cancellations, applied on top of the static schedule.
+///
+/// Changes apply to a train run on a specific service date. As in GTFS-Realtime, a delay at a stop carries on to the following stops of the train, until another stop gives a different delay.
+///
+/// Examples:
+/// ```
+/// use chrono::{Duration, NaiveDate};
+/// use harail::gtfs_rt::RealtimeUpdates;
+///
+/// let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+/// let mut updates = RealtimeUpdates::new();
+/// updates.cancel("1", date);
+/// updates.delay("2", date, None, Duration::minutes(5));
+/// assert!(updates.is_cancelled("1", date));
+/// assert!(!updates.is_cancelled("2", date));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RealtimeUpdates {
+    trips: HashMap<(TrainId, NaiveDate), TripChanges>,
+    alerts: Vec<Alert>,
+}
+
+impl RealtimeUpdates {
+    /// Create a new RealtimeUpdates object, which doesn't change the timetable
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the run of the train on the given date as cancelled
+    pub fn cancel(&mut self, train: &str, date: NaiveDate) {
+        self.trips
+            .entry((train.to_owned(), date))
+            .or_default()
+            .cancelled = true;
+    }
+
+    /// Delays the run of the train on the given date, starting from the given station, or from its first stop if no station is given
+    pub fn delay(
+        &mut self,
+        train: &str,
+        date: NaiveDate,
+        station: Option<StationId>,
+        delay: Duration,
+    ) {
+        let trip = self.trips.entry((train.to_owned(), date)).or_default();
+        match station {
+            Some(station) => trip.stops.push(StopChange {
+                station,
+                arrival_delay: Some(delay),
+                departure_delay: Some(delay),
+                skipped: false,
+                occupancy: None,
+            }),
+            None => trip.delay = Some(delay),
+        }
+    }
+
+    /// Whether the run of the train on the given date was cancelled
+    pub fn is_cancelled(&self, train: &str, date: NaiveDate) -> bool {
+        self.trips
+            .get(&(train.to_owned(), date))
+            .is_some_and(|t| t.cancelled)
+    }
+
+    /// Sets the expected crowding of the run of the train on the given date when leaving the given station, or of the
+    /// whole run if no station is given
+    pub fn set_occupancy(
+        &mut self,
+        train: &str,
+        date: NaiveDate,
+        station: Option<StationId>,
+        occupancy: Occupancy,
+    ) {
+        let trip = self.trips.entry((train.to_owned(), date)).or_default();
+        let station = match station {
+            Some(station) => station,
+            None => {
+                trip.occupancy = Some(occupancy);
+                return;
+            }
+        };
+        match trip.stops.iter_mut().find(|c| c.station == station) {
+            Some(change) => change.occupancy = Some(occupancy),
+            None => trip.stops.push(StopChange {
+                station,
+                arrival_delay: None,
+                departure_delay: None,
+                skipped: false,
+                occupancy: Some(occupancy),
+            }),
+        }
+    }
+
+    /// The expected crowding of the train when leaving the station at the given time, which may be delayed.
+    ///
+    /// The crowding given for the stop takes precedence over the crowding of the whole run.
+    pub fn occupancy(
+        &self,
+        train: &Train,
+        station: StationId,
+        departure: NaiveDateTime,
+    ) -> Option<Occupancy> {
+        let offset = train
+            .stops()
+            .find(|s| s.station() == station)?
+            .departure_offset()
+            .to_chrono();
+        // Delays move the departure by much less than half a day, so this is the service date of the run
+        let date = (departure - offset + Duration::hours(12)).date();
+        let changes = self.trips.get(&(train.id().to_owned(), date))?;
+        changes
+            .stops
+            .iter()
+            .filter(|c| c.station == station)
+            .find_map(|c| c.occupancy)
+            .or(changes.occupancy)
+    }
+
+    /// Adds a service alert
+    pub fn add_alert(&mut self, alert: Alert) {
+        self.alerts.push(alert);
+    }
+
+    /// Iterates over the service alerts
+    pub fn alerts(&self) -> impl Iterator<Item = &Alert> {
+        self.alerts.iter()
+    }
+
+    /// Whether there are no real-time changes or alerts at all
+    pub fn is_empty(&self) -> bool {
+        self.trips.is_empty() && self.alerts.is_empty()
+    }
+
+    /// Parses a GTFS-Realtime TripUpdates feed in the protocol buffer wire format.
+    ///
+    /// See add_feed for how the feed is interpreted.
+    pub fn from_trip_updates<R: Read>(
+        reader: R,
+        default_date: NaiveDate,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut result = Self::new();
+        result.add_feed(reader, default_date)?;
+        Ok(result)
+    }
+
+    /// Adds the trip updates, vehicle positions and alerts of a GTFS-Realtime feed in the protocol buffer wire format.
+    ///
+    /// Trips without a start date are assumed to run on default_date.
+    /// Stops are matched by stop_id, and only relative delays are applied; absolute event times are ignored.
+    /// Vehicle positions are only used for the occupancy of their trips.
+    pub fn add_feed<R: Read>(
+        &mut self,
+        mut reader: R,
+        default_date: NaiveDate,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        let feed = proto::FeedMessage::decode(buf.as_slice())?;
+        for entity in feed.entity {
+            if entity.is_deleted() {
+                continue;
+            }
+            if let Some(alert) = entity.alert {
+                self.alerts.push(Alert::from_proto(alert));
+            }
+            if let Some(vehicle) = entity.vehicle {
+                let occupancy = Occupancy::from_proto(vehicle.occupancy_status);
+                if let (Some(trip), Some(occupancy)) = (vehicle.trip, occupancy) {
+                    if let Some(trip_id) = trip.trip_id {
+                        let date = match trip.start_date {
+                            Some(ref date) => NaiveDate::parse_from_str(date, "%Y%m%d")?,
+                            None => default_date,
+                        };
+                        self.trips.entry((trip_id, date)).or_default().occupancy = Some(occupancy);
+                    }
+                }
+            }
+            let update = match entity.trip_update {
+                Some(update) => update,
+                None => continue,
+            };
+            let trip = update
+                .trip
+                .ok_or_else(|| HaError::GTFSError("trip".to_owned()))?;
+            let trip_id = match trip.trip_id {
+                Some(ref trip_id) => trip_id.to_owned(),
+                None => continue,
+            };
+            let date = match trip.start_date {
+                Some(ref date) => NaiveDate::parse_from_str(date, "%Y%m%d")?,
+                None => default_date,
+            };
+            let changes = self.trips.entry((trip_id, date)).or_default();
+            if trip.schedule_relationship() == proto::TripScheduleRelationship::Canceled {
+                changes.cancelled = true;
+                continue;
+            }
+            changes.delay = update.delay.map(|d| Duration::seconds(d.into()));
+            for stop_update in update.stop_time_update {
+                let station = match stop_update.stop_id.as_deref().map(str::parse) {
+                    Some(Ok(station)) => station,
+                    _ => continue,
+                };
+                changes.stops.push(StopChange {
+                    station,
+                    arrival_delay: stop_update
+                        .arrival
+                        .as_ref()
+                        .and_then(|e| e.delay)
+                        .map(|d| Duration::seconds(d.into())),
+                    departure_delay: stop_update
+                        .departure
+                        .as_ref()
+                        .and_then(|e| e.delay)
+                        .map(|d| Duration::seconds(d.into())),
+                    skipped: stop_update.schedule_relationship()
+                        == proto::StopScheduleRelationship::Skipped,
+                    occupancy: Occupancy::from_proto(stop_update.departure_occupancy_status),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterates over the train runs, as (train, date) pairs, which have real-time changes
+    pub(crate) fn runs(&self) -> impl Iterator<Item = (&TrainId, NaiveDate)> {
+        self.trips.keys().map(|(train, date)| (train, *date))
+    }
+
+    /// The stops of a train run after applying real-time changes, or None if the run was cancelled.
+    ///
+    /// Skipped stops are left out, and times are kept in order even when the feed's delays are inconsistent.
+    pub(crate) fn stops<'a>(
+        &self,
+        data: &'a RailroadData,
+        train: &'a Train,
+        date: NaiveDate,
+    ) -> Option<Vec<Stop<'a>>> {
+        let scheduled = train
+            .stops()
+            .map(|stop| Stop::from_stop_schedule(data, stop, date));
+        let changes = match self.trips.get(&(train.id().to_owned(), date)) {
+            Some(changes) => changes,
+            None => return Some(scheduled.collect()),
+        };
+        if changes.cancelled {
+            return None;
+        }
+        let mut delay = changes.delay;
+        let mut result: Vec<Stop<'a>> = Vec::new();
+        for mut stop in scheduled {
+            let change = changes
+                .stops
+                .iter()
+                .find(|c| c.station == stop.station.id());
+            if change.is_some_and(|c| c.skipped) {
+                continue;
+            }
+            let arrival_delay = change.and_then(|c| c.arrival_delay).or(delay);
+            let departure_delay = change.and_then(|c| c.departure_delay).or(arrival_delay);
+            if let Some(arrival_delay) = arrival_delay {
+                stop.arrival += arrival_delay;
+                stop.delayed = true;
+            }
+            if let Some(departure_delay) = departure_delay {
+                stop.departure += departure_delay;
+                stop.delayed = true;
+            }
+            if let Some(prev) = result.last() {
+                stop.arrival = stop.arrival.max(prev.departure);
+            }
+            stop.departure = stop.departure.max(stop.arrival);
+            delay = departure_delay;
+            result.push(stop);
+        }
+        Some(result)
+    }
+}
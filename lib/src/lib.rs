@@ -4,20 +4,81 @@
 * License, v. 2.0. If a copy of the MPL was not distributed with this
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+#[cfg(feature = "advanced")]
+pub mod advanced;
+mod arrivals;
+#[cfg(feature = "async")]
+mod async_search;
+mod budget;
+mod database;
+mod departures;
+mod diff;
+mod diversity;
 mod errors;
+mod explanation;
+pub mod fares;
+mod geojson;
 mod graph;
 mod gtfs;
+pub mod gtfs_rt;
+mod options;
+mod owned;
+mod prepared;
+pub mod pricing;
+mod query;
+mod raptor;
+mod reliability;
+mod round_trip;
+mod saved_graph;
+mod station_search;
+mod summary;
+mod timezone;
+mod validation;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod what_if;
 
 #[macro_use(object)]
 extern crate jzon;
 
-use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use budget::Budget;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use gtfs_rt::{Alert, Occupancy, RealtimeUpdates};
 use jzon::JsonValue;
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::time::Instant;
+use tracing::instrument;
 
-pub use errors::HaError;
-pub use gtfs::{HaDuration, RailroadData, Station, StationId, StopSchedule, Train, TrainId};
+pub use arrivals::{get_earliest_arrivals, Arrival};
+#[cfg(feature = "async")]
+pub use async_search::find_routes_async;
+pub use database::DATABASE_VERSION;
+pub use departures::{get_departures, get_departures_in_direction, Departure};
+pub use diff::{diff, Change};
+pub use diversity::rank_by_diversity;
+pub use errors::{GtfsError, HaError};
+pub use explanation::{explain, Explanation};
+pub use gtfs::{
+    HaDuration, Mode, RailroadData, ShapeId, Station, StationId, StopSchedule, Train, TrainId,
+};
+pub use options::{
+    ActionWeights, AgencyFilter, Engine, GtfsOptions, RouteOrder, RoutingOptions, SearchObserver,
+    ISRAEL_RAILWAYS,
+};
+pub use owned::{OwnedRoute, OwnedRoutePart, OwnedStop};
+pub use prepared::PreparedGraph;
+pub use query::RouteQuery;
+pub use reliability::{rate_reliability, DelayHistory, Reliability};
+pub use round_trip::{get_round_trip, RoundTrip, Stay};
+pub use station_search::{nearby_stations, search_stations, station_aliases};
+pub use summary::RouteSummary;
+pub use timezone::{localize, localize_in, to_feed_time, InTimezone, Zoned, FEED_TIMEZONE};
+pub use validation::{validate, Issue};
+pub use what_if::{simulate_delays, DelaySimulation};
 
 /// An object which can be written to JSON.
 ///
@@ -30,37 +91,77 @@ pub trait JSON {
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
 pub struct Stop<'a> {
     station: &'a Station,
+    platform: Option<&'a str>,
     arrival: NaiveDateTime,
     departure: NaiveDateTime,
+    delayed: bool,
 }
 
-impl JSON for Stop<'_> {
-    fn to_json(&self) -> JsonValue {
-        let arrival = DateTime::<Utc>::from_naive_utc_and_offset(self.arrival(), Utc);
-        let departure = DateTime::<Utc>::from_naive_utc_and_offset(self.departure(), Utc);
-        object! {
+impl Zoned for Stop<'_> {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        let arrival = localize_in(self.arrival(), timezone);
+        let departure = localize_in(self.departure(), timezone);
+        let mut result = object! {
             station: self.station.id().to_owned(),
             arrival: arrival.to_rfc3339(),
             departure: departure.to_rfc3339(),
+        };
+        if let Some(platform) = self.platform {
+            result["platform"] = platform.into();
         }
+        result
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        write!(
+            f,
+            "{}{} ({} - {})",
+            self.station.name(),
+            platform_suffix(self.platform),
+            localize_in(self.arrival, timezone).naive_local(),
+            localize_in(self.departure, timezone).naive_local()
+        )
+    }
+}
+
+impl JSON for Stop<'_> {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for Stop<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+/// Serialized as the equivalent OwnedStop, which it can be deserialized as
+impl Serialize for Stop<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        OwnedStop::from(self).serialize(serializer)
     }
 }
 
 impl<'a> Stop<'a> {
     fn inflate_stop_time(date: NaiveDate, offset: HaDuration) -> NaiveDateTime {
-        NaiveDateTime::new(date, NaiveTime::from_hms_opt(0, 0, 0).unwrap()) + offset.to_chrono()
+        timezone::service_time(date, offset.to_chrono())
     }
 
-    /// Construct a Stop object from a StopSchedule and a specific date
+    /// Construct a Stop object from a StopSchedule and a specific date.
+    ///
+    /// The stop's times are local times of the feed, which take daylight saving time changes on the date into account.
     pub fn from_stop_schedule(
         data: &'a RailroadData,
-        stop: &StopSchedule,
+        stop: &'a StopSchedule,
         date: NaiveDate,
     ) -> Self {
         Stop {
             station: data.station(stop.station()).unwrap(),
+            platform: stop.platform(),
             arrival: Self::inflate_stop_time(date, stop.arrival_offset()),
             departure: Self::inflate_stop_time(date, stop.departure_offset()),
+            delayed: false,
         }
     }
 
@@ -68,6 +169,11 @@ impl<'a> Stop<'a> {
         self.station
     }
 
+    /// The platform the train stops at, if known
+    pub fn platform(&self) -> Option<&str> {
+        self.platform
+    }
+
     pub fn arrival(&self) -> NaiveDateTime {
         self.arrival
     }
@@ -75,22 +181,103 @@ impl<'a> Stop<'a> {
     pub fn departure(&self) -> NaiveDateTime {
         self.departure
     }
+
+    /// Whether the stop's times were changed by real-time updates
+    pub fn is_delayed(&self) -> bool {
+        self.delayed
+    }
 }
 
+/// A node of the graph: a train at a station at some time, or the station itself at that time for passengers who are
+/// not on a train
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
-struct Singularity<'a> {
+pub struct Singularity<'a> {
     station: &'a Station,
     time: NaiveDateTime,
     train: Option<&'a Train>,
 }
 
+/// An edge of the graph. Riding, boarding and unboarding carry their penalty in seconds, taken from the action weights
+/// of the routing options the graph was built with.
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
-enum Action<'a> {
+#[non_exhaustive]
+pub enum Action<'a> {
     Wait(Duration),
     TrainWaits(&'a Train, Stop<'a>),
-    Ride(&'a Train, Stop<'a>, Stop<'a>),
-    Board(&'a Train),
-    Unboard,
+    Ride(&'a Train, Stop<'a>, Stop<'a>, i64),
+    Board(&'a Train, i64),
+    Unboard(i64),
+    /// Leaving a train for trains departing before the transfer slack has passed, whose penalty includes the tight
+    /// transfer weight, as boarding edges can't tell how long ago the train they are reached from arrived
+    TightUnboard(i64),
+}
+
+/// Nodes are ordered by time, then by station ID, then by the ID of their train, with the station itself first. Of
+/// equally good routes, searches take the one reaching each node from the least node.
+impl Ord for Singularity<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.station.id(), self.train.map(Train::id)).cmp(&(
+            other.time,
+            other.station.id(),
+            other.train.map(Train::id),
+        ))
+    }
+}
+
+impl PartialOrd for Singularity<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Action<'_> {
+    /// The ID of the edge's train, its kind, its penalty or waiting time in seconds and the stations and times it
+    /// leaves and reaches, by which edges are ordered
+    #[allow(clippy::type_complexity)]
+    fn order_key(
+        &self,
+    ) -> (
+        Option<&TrainId>,
+        u8,
+        i64,
+        Option<(StationId, NaiveDateTime)>,
+        Option<(StationId, NaiveDateTime)>,
+    ) {
+        match self {
+            Action::Wait(time) => (None, 0, time.num_seconds(), None, None),
+            Action::TrainWaits(train, stop) => (
+                Some(train.id()),
+                1,
+                0,
+                Some((stop.station.id(), stop.arrival)),
+                Some((stop.station.id(), stop.departure)),
+            ),
+            Action::Ride(train, start, end, penalty) => (
+                Some(train.id()),
+                2,
+                *penalty,
+                Some((start.station.id(), start.departure)),
+                Some((end.station.id(), end.arrival)),
+            ),
+            Action::Board(train, penalty) => (Some(train.id()), 3, *penalty, None, None),
+            Action::Unboard(penalty) => (None, 4, *penalty, None, None),
+            Action::TightUnboard(penalty) => (None, 5, *penalty, None, None),
+        }
+    }
+}
+
+/// Edges are ordered by the ID of their train, with edges of no train first, so that of equally good routes leaving
+/// a node on different trains, searches take the train whose ID is least
+impl Ord for Action<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order_key().cmp(&other.order_key())
+    }
+}
+
+impl PartialOrd for Action<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl graph::Weight for Action<'_> {
@@ -98,11 +285,12 @@ impl graph::Weight for Action<'_> {
         match self {
             Action::Wait(time) => time.num_seconds(),
             Action::TrainWaits(_, stop) => (stop.departure() - stop.arrival()).num_seconds(),
-            // This minimizes train movements, to prevent e.g. going a->b->c->d->c->b instead of a->b->c->b, if they have the same dest time
-            Action::Ride(_, start, end) => (end.arrival() - start.departure()).num_seconds() + 1,
-            // This minimizes train switches
-            Action::Board(_) => 60,
-            Action::Unboard => 60,
+            Action::Ride(_, start, end, penalty) => {
+                (end.arrival() - start.departure()).num_seconds() + penalty
+            }
+            Action::Board(_, penalty) => *penalty,
+            Action::Unboard(penalty) => *penalty,
+            Action::TightUnboard(penalty) => *penalty,
         }
     }
 }
@@ -110,95 +298,37 @@ impl graph::Weight for Action<'_> {
 type RailroadGraph<'a> = graph::Graph<Singularity<'a>, Action<'a>>;
 
 impl<'a> RailroadGraph<'a> {
+    #[instrument(level = "debug", skip_all, fields(%start_time, %end_time))]
     fn from_data(
         data: &'a RailroadData,
         start_time: NaiveDateTime,
         end_time: NaiveDateTime,
+        options: &RoutingOptions,
+        budget: Option<&Budget>,
     ) -> Self {
         let mut result = Self::new();
         let mut stations_general: HashMap<&Station, HashSet<Singularity>> = HashMap::new();
-        let first_possible_date = start_time.date();
-        let last_possible_date = if end_time.time() == NaiveTime::from_hms_opt(0, 0, 0).unwrap() {
-            end_time.date().pred_opt().unwrap()
-        } else {
-            end_time.date()
-        };
         // Iterate all trains on all dates
         for train in data.trains() {
-            // This is a preliminary filter, using dates only - we will do a fine-tuned filtering that includes time soon
-            for date in train
-                .dates()
-                .filter(|&x| x >= &first_possible_date && x <= &last_possible_date)
-            {
-                let mut prev = None;
-                for stop in train.stops() {
-                    let stop = Stop::from_stop_schedule(data, stop, *date);
-                    // Filter out all irrelevant stops
-                    if stop.arrival > end_time || stop.departure < start_time {
-                        continue;
-                    }
-                    // Make sure we have a singularity set for this station
-                    if !stations_general.contains_key(stop.station) {
-                        stations_general.insert(stop.station, HashSet::new());
-                    }
-                    let station_set = stations_general.get_mut(stop.station).unwrap();
-
-                    // Create nodes for train arrival time and station time, and connect unboarding option
-                    let arrival = Singularity {
-                        station: stop.station,
-                        time: stop.arrival,
-                        train: Some(train),
-                    };
-                    let arrival_station = Singularity {
-                        station: arrival.station,
-                        time: arrival.time,
-                        train: None,
-                    };
-                    result
-                        .get_or_insert(&arrival)
-                        .connect(Action::Unboard, arrival_station);
-                    result.get_or_insert(&arrival_station);
-                    station_set.insert(arrival_station);
-
-                    // Connect previous stop
-                    if let Some((prev_node, prev_stop)) = prev {
-                        result
-                            .get_mut(&prev_node)
-                            .unwrap()
-                            .connect(Action::Ride(train, prev_stop, stop), arrival);
-                    }
-
-                    // Handle waiting on train
-                    // Create nodes for train departure time and station time if train arrival != departure
-                    let (departure, departure_station) = if stop.arrival == stop.departure {
-                        (arrival, arrival_station)
-                    } else {
-                        let departure = Singularity {
-                            station: stop.station,
-                            time: stop.departure(),
-                            train: Some(train),
-                        };
-                        let departure_station = Singularity {
-                            station: departure.station,
-                            time: departure.time,
-                            train: None,
-                        };
-                        result.get_or_insert(&departure);
-                        station_set.insert(departure_station);
-
-                        // Connect waiting on train edge (train waits in station)
-                        result
-                            .get_mut(&arrival)
-                            .unwrap()
-                            .connect(Action::TrainWaits(train, stop), departure);
-                        (departure, departure_station)
-                    };
-
-                    // Connect boarding option
-                    result
-                        .get_or_insert(&departure_station)
-                        .connect(Action::Board(train), departure);
-                    prev = Some((departure, stop));
+            if budget.is_some_and(|b| b.stopped()) {
+                break;
+            }
+            if !uses_train(train, options) {
+                continue;
+            }
+            // This is a preliminary filter, using dates only - we will do a fine-tuned filtering that includes time soon.
+            // Runs of the previous service days are included, as runs crossing midnight stop on the following days.
+            for date in train.service_dates_between(start_time, end_time) {
+                if let Some(stops) = train_run_stops(data, train, date, options) {
+                    result.add_run(
+                        data,
+                        train,
+                        &stops,
+                        start_time,
+                        end_time,
+                        options,
+                        &mut stations_general,
+                    );
                 }
             }
         }
@@ -222,46 +352,228 @@ impl<'a> RailroadGraph<'a> {
         result
     }
 
-    fn ensure(&mut self, s: Singularity<'a>) {
-        if self.get(&s).is_none() {
-            self.get_or_insert(&s);
-            if let Some(next) = self
-                .nodes()
-                .map(|n| n.id())
-                .filter(|n| n.train == s.train && n.station == s.station && n.time > s.time)
-                .min_by_key(|n| n.time)
-                .copied()
-            {
-                self.get_mut(&s)
+    /// Adds the nodes and edges of a train run between start_time and end_time, collecting the station nodes it
+    /// boards and leaves the train at, which still have to be connected by wait edges
+    #[allow(clippy::too_many_arguments)]
+    fn add_run(
+        &mut self,
+        data: &'a RailroadData,
+        train: &'a Train,
+        stops: &[Stop<'a>],
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        options: &RoutingOptions,
+        stations_general: &mut HashMap<&'a Station, HashSet<Singularity<'a>>>,
+    ) {
+        let weights = options.weights();
+        let (board, unboard, ride) = (
+            weights.board().as_secs() as i64,
+            weights.unboard().as_secs() as i64,
+            weights.ride().as_secs() as i64,
+        );
+        let slack = options
+            .transfer_slack()
+            .map(|slack| Duration::seconds(slack.as_secs() as i64));
+        let tight_transfer = weights.tight_transfer().as_secs() as i64;
+        let mut prev = None;
+        for &stop in stops {
+            // Filter out all irrelevant stops
+            if stop.arrival > end_time || stop.departure < start_time {
+                continue;
+            }
+            // Trains pass through avoided stations, but cannot be boarded or left there
+            let avoided = !can_board_at(stop.station, options);
+            // Make sure we have a singularity set for this station
+            let station_set = stations_general.entry(stop.station).or_default();
+
+            // Create nodes for train arrival time and station time, and connect unboarding option.
+            // Unboarding leads to the station only after the minimum transfer time has elapsed.
+            let arrival = Singularity {
+                station: stop.station,
+                time: stop.arrival,
+                train: Some(train),
+            };
+            self.get_or_insert(&arrival);
+            if !avoided {
+                let arrival_station = Singularity {
+                    station: arrival.station,
+                    time: arrival.time + transfer_time(data, stop.station.id(), options),
+                    train: None,
+                };
+                match slack {
+                    // With a transfer slack, unboarding leads to the station once the slack has passed too,
+                    // paying for the time, and only tightly unboarding leads there any earlier
+                    Some(slack) => {
+                        let relaxed_station = Singularity {
+                            time: arrival_station.time + slack,
+                            ..arrival_station
+                        };
+                        let arrival_node = self.get_mut(&arrival).unwrap();
+                        arrival_node.connect(
+                            Action::Unboard(unboard + slack.num_seconds()),
+                            relaxed_station,
+                        );
+                        arrival_node.connect(
+                            Action::TightUnboard(unboard + tight_transfer),
+                            arrival_station,
+                        );
+                        self.get_or_insert(&relaxed_station);
+                        station_set.insert(relaxed_station);
+                    }
+                    None => self
+                        .get_mut(&arrival)
+                        .unwrap()
+                        .connect(Action::Unboard(unboard), arrival_station),
+                }
+                self.get_or_insert(&arrival_station);
+                station_set.insert(arrival_station);
+            }
+
+            // Connect previous stop
+            if let Some((prev_node, prev_stop)) = prev {
+                self.get_mut(&prev_node)
                     .unwrap()
-                    .connect(Action::Wait(next.time - s.time), next);
+                    .connect(Action::Ride(train, prev_stop, stop, ride), arrival);
             }
-            if let Some(prev) = self
-                .nodes()
-                .map(|n| n.id())
-                .filter(|n| n.train == s.train && n.station == s.station && n.time < s.time)
-                .max_by_key(|n| n.time)
-                .copied()
-            {
-                self.get_mut(&prev)
+
+            // Handle waiting on train
+            // Create node for train departure time if train arrival != departure
+            let departure = if stop.arrival == stop.departure {
+                arrival
+            } else {
+                let departure = Singularity {
+                    station: stop.station,
+                    time: stop.departure(),
+                    train: Some(train),
+                };
+                self.get_or_insert(&departure);
+
+                // Connect waiting on train edge (train waits in station)
+                self.get_mut(&arrival)
                     .unwrap()
-                    .connect(Action::Wait(s.time - prev.time), s);
+                    .connect(Action::TrainWaits(train, stop), departure);
+                departure
+            };
+
+            // Connect boarding option
+            if !avoided {
+                let departure_station = Singularity {
+                    station: departure.station,
+                    time: departure.time,
+                    train: None,
+                };
+                station_set.insert(departure_station);
+                self.get_or_insert(&departure_station)
+                    .connect(Action::Board(train, board), departure);
             }
+            prev = Some((departure, stop));
         }
     }
+
+    /// Removes the nodes of a train run added by add_run, along with the boarding edges leading to them.
+    ///
+    /// The station nodes of the run are left in place, as other runs may share them, and stay connected by wait edges.
+    fn remove_run(
+        &mut self,
+        train: &'a Train,
+        stops: &[Stop<'a>],
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+    ) {
+        for stop in stops {
+            if stop.arrival > end_time || stop.departure < start_time {
+                continue;
+            }
+            for time in [stop.arrival, stop.departure] {
+                self.remove(&Singularity {
+                    station: stop.station,
+                    time,
+                    train: Some(train),
+                });
+            }
+            let departure_station = Singularity {
+                station: stop.station,
+                time: stop.departure,
+                train: None,
+            };
+            if let Some(node) = self.get_mut(&departure_station) {
+                node.retain_edges(|_, dest| dest.train != Some(train));
+            }
+        }
+    }
+}
+
+/// Whether found routes may ride the given train
+fn uses_train(train: &Train, options: &RoutingOptions) -> bool {
+    !options.avoids_train(train.id())
+        && options.allows_mode(train.mode())
+        && (!options.wheelchair() || train.wheelchair_accessible() != Some(false))
+        && (!options.bikes() || train.bikes_allowed() == Some(true))
+}
+
+/// Whether found routes may board or leave trains at the given station
+fn can_board_at(station: &Station, options: &RoutingOptions) -> bool {
+    !options.avoids_station(station.id())
+        && (!options.wheelchair() || station.wheelchair_boarding() != Some(false))
+}
+
+/// The time it takes to switch trains at the given station, which is the longer of the station's minimum transfer time
+/// in the feed and the one required by the routing options
+fn transfer_time(data: &RailroadData, station: StationId, options: &RoutingOptions) -> Duration {
+    let station_time = data
+        .min_transfer_time(station)
+        .map_or_else(Duration::zero, |d| d.to_chrono());
+    let required = options
+        .min_transfer_time()
+        .map_or_else(Duration::zero, |d| Duration::seconds(d.as_secs() as i64));
+    station_time.max(required)
+}
+
+/// The stops of a train run on the given date, adjusted by the real-time changes in the routing options.
+///
+/// Returns None if the run was cancelled.
+fn train_run_stops<'a>(
+    data: &'a RailroadData,
+    train: &'a Train,
+    date: NaiveDate,
+    options: &RoutingOptions,
+) -> Option<Vec<Stop<'a>>> {
+    match options.realtime() {
+        Some(realtime) => realtime.stops(data, train, date),
+        None => Some(
+            train
+                .stops()
+                .map(|stop| Stop::from_stop_schedule(data, stop, date))
+                .collect(),
+        ),
+    }
 }
 
 /// Holds information regarding a single train ride
+#[derive(Clone)]
 pub struct RoutePart<'a> {
     train: &'a Train,
     start: Stop<'a>,
     end: Stop<'a>,
+    /// The stops the train makes between start and end
+    intermediate_stops: Vec<Stop<'a>>,
 }
 
 impl<'a> RoutePart<'a> {
-    /// Create a new RoutePart object
+    /// Create a new RoutePart object, without intermediate stops
     pub fn new(train: &'a Train, start: Stop<'a>, end: Stop<'a>) -> Self {
-        RoutePart { train, start, end }
+        RoutePart {
+            train,
+            start,
+            end,
+            intermediate_stops: Vec::new(),
+        }
+    }
+
+    /// Sets the stops the train makes between boarding and unboarding it, in order
+    pub fn with_intermediate_stops(mut self, stops: Vec<Stop<'a>>) -> Self {
+        self.intermediate_stops = stops;
+        self
     }
 
     /// The train associated with the RoutePart object
@@ -274,59 +586,209 @@ impl<'a> RoutePart<'a> {
         self.start
     }
 
+    /// The stops the train makes between boarding and unboarding it, in order
+    pub fn intermediate_stops(&self) -> &[Stop<'a>] {
+        &self.intermediate_stops
+    }
+
     /// The stop at which the train is unboarded
     pub fn end(&self) -> Stop {
         self.end
     }
-}
 
-impl fmt::Display for RoutePart<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{} ({}) -> {} ({})",
-            self.start.station().name().to_owned(),
+    /// The time from boarding the train to unboarding it
+    pub fn duration(&self) -> Duration {
+        self.end.arrival() - self.start.departure()
+    }
+
+    /// Whether real-time updates changed the boarding or unboarding times
+    pub fn is_delayed(&self) -> bool {
+        self.start.is_delayed() || self.end.is_delayed()
+    }
+
+    /// The service alerts relevant to the train ride, i.e. ones about the train or its boarding and unboarding stations
+    pub fn alerts<'r>(&self, realtime: &'r RealtimeUpdates) -> Vec<&'r Alert> {
+        realtime
+            .alerts()
+            .filter(|a| {
+                a.affects_ride(
+                    self.train.id(),
+                    self.start.station().id(),
+                    self.end.station().id(),
+                    self.start.departure(),
+                    self.end.arrival(),
+                )
+            })
+            .collect()
+    }
+
+    /// The expected crowding of the train when boarding it, if the real-time updates give it
+    pub fn occupancy(&self, realtime: &RealtimeUpdates) -> Option<Occupancy> {
+        realtime.occupancy(
+            self.train,
+            self.start.station().id(),
             self.start.departure(),
-            self.end.station().name().to_owned(),
-            self.end.arrival()
         )
     }
 }
 
-impl JSON for RoutePart<'_> {
-    fn to_json(&self) -> JsonValue {
-        let departure = DateTime::<Utc>::from_naive_utc_and_offset(self.start.departure(), Utc);
-        let arrival = DateTime::<Utc>::from_naive_utc_and_offset(self.end.arrival(), Utc);
-        object! {
+/// Describes the platform of a stop after its station's name, or nothing if it is unknown
+fn platform_suffix(platform: Option<&str>) -> String {
+    platform.map_or_else(String::new, |p| format!(" [platform {}]", p))
+}
+
+/// Describes the number of the train after a ride on it, or nothing if the feed gives no train number
+fn train_suffix(number: Option<&str>) -> String {
+    number.map_or_else(String::new, |n| format!(" on train {}", n))
+}
+
+impl Zoned for RoutePart<'_> {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        let departure = localize_in(self.start.departure(), timezone);
+        let arrival = localize_in(self.end.arrival(), timezone);
+        let mut result = object! {
             train: self.train.id().to_owned(),
             start_time: departure.to_rfc3339(),
             start_station: self.start.station().id(),
             end_time: arrival.to_rfc3339(),
-            end_station: self.end.station().id()
+            end_station: self.end.station().id(),
+            duration_seconds: self.duration().num_seconds()
+        };
+        if let Some(platform) = self.start.platform() {
+            result["start_platform"] = platform.into();
         }
+        if let Some(platform) = self.end.platform() {
+            result["end_platform"] = platform.into();
+        }
+        if let Some(number) = self.train.short_name() {
+            result["train_number"] = number.into();
+        }
+        if let Some(bikes_allowed) = self.train.bikes_allowed() {
+            result["bikes_allowed"] = bikes_allowed.into();
+        }
+        if !self.intermediate_stops.is_empty() {
+            result["intermediate_stops"] = JsonValue::Array(
+                self.intermediate_stops
+                    .iter()
+                    .map(|s| s.to_json_in(timezone))
+                    .collect(),
+            );
+        }
+        result
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        write!(
+            f,
+            "{}{} ({}) -> {}{} ({}){}",
+            self.start.station().name(),
+            platform_suffix(self.start.platform()),
+            localize_in(self.start.departure(), timezone).naive_local(),
+            self.end.station().name(),
+            platform_suffix(self.end.platform()),
+            localize_in(self.end.arrival(), timezone).naive_local(),
+            train_suffix(self.train.short_name())
+        )
+    }
+}
+
+impl JSON for RoutePart<'_> {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for RoutePart<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+/// Serialized as the equivalent OwnedRoutePart, which it can be deserialized as
+impl Serialize for RoutePart<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        OwnedRoutePart::from(self).serialize(serializer)
     }
 }
 
 /// Holds details of a route between stations
 pub struct Route<'a> {
     parts: Vec<RoutePart<'a>>,
+    price: Option<pricing::Fare>,
+    explanation: Option<Explanation>,
+    reliability: Option<Reliability>,
 }
 
 impl<'a> Route<'a> {
     /// Create a new Route object
     pub fn new() -> Self {
-        Route { parts: Vec::new() }
+        Route {
+            parts: Vec::new(),
+            price: None,
+            explanation: None,
+            reliability: None,
+        }
     }
 
     /// Create a enw Route object from parts
     pub fn from_parts(parts: Vec<RoutePart<'a>>) -> Self {
-        Route { parts }
+        Route {
+            parts,
+            price: None,
+            explanation: None,
+            reliability: None,
+        }
     }
 
     /// Iterate over the parts of the route. Each RoutePart corresponds to a single train ride.
     pub fn parts(&self) -> impl Iterator<Item = &RoutePart> {
         self.parts.iter()
     }
+
+    /// Whether real-time updates changed the times of any of the route's train rides
+    pub fn is_delayed(&self) -> bool {
+        self.parts.iter().any(|p| p.is_delayed())
+    }
+
+    /// The time waited between arriving on the previous part and boarding the train of the part at the given index,
+    /// or None for the first part
+    pub fn wait_before(&self, index: usize) -> Option<Duration> {
+        let previous = self.parts.get(index.checked_sub(1)?)?;
+        Some(self.parts.get(index)?.start.departure() - previous.end.arrival())
+    }
+
+    /// The ticket price of the route according to the fares of the GTFS feed, if it had fares covering every ride
+    pub fn price(&self) -> Option<pricing::Fare> {
+        self.price
+    }
+
+    /// Why the search found the route, compared to the other routes it found. Routes are explained by the searches
+    /// of find_routes and the get_* functions, or by explain.
+    pub fn explanation(&self) -> Option<&Explanation> {
+        self.explanation.as_ref()
+    }
+
+    /// The chance of the route making all of its train switches, if it was rated by rate_reliability
+    pub fn reliability(&self) -> Option<Reliability> {
+        self.reliability
+    }
+
+    /// The route's total times, train switches and stations passed
+    pub fn summary(&self) -> RouteSummary {
+        RouteSummary::from_rides(self.parts.iter().map(|p| {
+            (
+                p.start.departure(),
+                p.end.arrival(),
+                p.intermediate_stops.len(),
+            )
+        }))
+    }
+
+    /// Prices the route by the given fares
+    pub(crate) fn with_fares(mut self, fares: &fares::FareRules) -> Self {
+        self.price = fares.price(&self);
+        self
+    }
 }
 
 impl Default for Route<'_> {
@@ -335,10 +797,35 @@ impl Default for Route<'_> {
     }
 }
 
-impl fmt::Display for Route<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Zoned for Route<'_> {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        let mut result = JsonValue::new_array();
+        for (i, part) in self.parts.iter().enumerate() {
+            let mut json = part.to_json_in(timezone);
+            if let Some(wait) = self.wait_before(i) {
+                json["wait_seconds"] = wait.num_seconds().into();
+            }
+            result.push(json).unwrap();
+        }
+        let mut json = object! {
+            parts: result
+        };
+        if let Some(price) = self.price {
+            json["price"] = price.to_json();
+        }
+        if let Some(explanation) = &self.explanation {
+            json["explanation"] = explanation.to_json();
+        }
+        if let Some(reliability) = self.reliability {
+            json["reliability"] = reliability.to_json();
+        }
+        json["summary"] = self.summary().to_json();
+        json
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
         for part in self.parts.iter() {
-            writeln!(f, "{}", part)?;
+            writeln!(f, "{}", part.in_timezone(timezone))?;
         }
         Ok(())
     }
@@ -346,26 +833,34 @@ impl fmt::Display for Route<'_> {
 
 impl JSON for Route<'_> {
     fn to_json(&self) -> JsonValue {
-        let mut result = JsonValue::new_array();
-        for part in &self.parts {
-            result.push(part.to_json()).unwrap();
-        }
-        object! {
-            parts: result
-        }
+        self.to_json_in(FEED_TIMEZONE)
     }
 }
 
-fn build_route<'a>(path: Vec<(Action<'a>, Singularity)>) -> Route<'a> {
+impl fmt::Display for Route<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+/// Serialized as the equivalent OwnedRoute, which it can be deserialized as
+impl Serialize for Route<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        OwnedRoute::from(self).serialize(serializer)
+    }
+}
+
+fn build_route<'a>(data: &RailroadData, path: Vec<(Action<'a>, Singularity)>) -> Route<'a> {
     let mut route = Route::new();
     let mut last_train: Option<&Train> = None;
     let mut last_train_start: Option<Stop> = None;
-    let mut last_train_end: Option<Stop> = None;
+    // The stops reached by riding the train, the last of which is where it is left
+    let mut last_train_stops: Vec<Stop> = Vec::new();
     for (action, _) in path {
         match action {
             Action::Wait(_) => {}
             Action::TrainWaits(_, _) => {}
-            Action::Ride(train, start, end) => {
+            Action::Ride(train, start, end, _) => {
                 match last_train {
                     Some(x) => assert!(x == train),
                     None => {
@@ -373,25 +868,443 @@ fn build_route<'a>(path: Vec<(Action<'a>, Singularity)>) -> Route<'a> {
                         last_train_start = Some(start);
                     }
                 }
-                last_train_end = Some(end);
+                last_train_stops.push(end);
             }
-            Action::Board(_) => {}
-            Action::Unboard => {
-                route.parts.push(RoutePart::new(
-                    last_train.take().unwrap(),
-                    last_train_start.take().unwrap(),
-                    last_train_end.take().unwrap(),
-                ));
+            Action::Board(_, _) => {}
+            Action::Unboard(_) | Action::TightUnboard(_) => {
+                let end = last_train_stops.pop().unwrap();
+                route.parts.push(
+                    RoutePart::new(
+                        last_train.take().unwrap(),
+                        last_train_start.take().unwrap(),
+                        end,
+                    )
+                    .with_intermediate_stops(std::mem::take(&mut last_train_stops)),
+                );
+            }
+        }
+    }
+    route.with_fares(data.fares())
+}
+
+/// A ride between two consecutive stops, used to bound the remaining travel time to the destination
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+struct MinRide {
+    from: StationId,
+    to: StationId,
+    seconds: i64,
+}
+
+impl graph::Weight for MinRide {
+    fn weight(&self) -> i64 {
+        self.seconds
+    }
+}
+
+/// Computes a lower bound on the cost of reaching end_station from every station.
+///
+/// The bound is the shortest path over the fastest rides between consecutive stops, ignoring waiting and train switches.
+/// Delayed train runs are taken into account as well, since a delay may shorten a ride.
+/// Stations missing from the result cannot reach end_station at all.
+fn station_lower_bounds(
+    data: &RailroadData,
+    end_station: &Station,
+    options: &RoutingOptions,
+) -> HashMap<StationId, i64> {
+    let penalty = options.weights().ride().as_secs() as i64;
+    let mut fastest: HashMap<(StationId, StationId), i64> = HashMap::new();
+    let mut add_ride = |from: StationId, to: StationId, seconds: i64| {
+        let best = fastest.entry((from, to)).or_insert(seconds);
+        *best = (*best).min(seconds);
+    };
+    for train in data.trains() {
+        for (from, to) in train.stops().zip(train.stops().skip(1)) {
+            // Matches the weight of Action::Ride
+            let seconds = (to.arrival_offset().to_chrono() - from.departure_offset().to_chrono())
+                .num_seconds()
+                + penalty;
+            add_ride(from.station(), to.station(), seconds);
+        }
+    }
+    if let Some(realtime) = options.realtime() {
+        for (train, date) in realtime.runs() {
+            let stops = match data
+                .train(train)
+                .and_then(|t| realtime.stops(data, t, date))
+            {
+                Some(stops) => stops,
+                None => continue,
+            };
+            for (from, to) in stops.iter().zip(stops.iter().skip(1)) {
+                let seconds = (to.arrival - from.departure).num_seconds() + penalty;
+                add_ride(from.station.id(), to.station.id(), seconds);
+            }
+        }
+    }
+    // Rides are connected in reverse, so that distances from end_station are distances to it
+    let mut g: graph::Graph<StationId, MinRide> = graph::Graph::new();
+    for ((from, to), seconds) in fastest {
+        g.get_or_insert(&from);
+        g.get_or_insert(&to)
+            .connect(MinRide { from, to, seconds }, from);
+    }
+    g.shortest_distances(&end_station.id())
+}
+
+fn find_path<'a>(
+    g: &RailroadGraph<'a>,
+    origin: &Singularity<'a>,
+    end_station: &Station,
+    options: &RoutingOptions,
+    bounds: Option<&HashMap<StationId, i64>>,
+    budget: Option<&Budget>,
+) -> Option<Vec<(Action<'a>, Singularity<'a>)>> {
+    let predicate = |s: &Singularity| s.station == end_station && s.train.is_none();
+    if let (Some(bounds), None) = (bounds, options.max_transfers()) {
+        return g.find_shortest_path_astar(
+            origin,
+            predicate,
+            |s| bounds.get(&s.station.id()).copied(),
+            budget,
+        );
+    }
+    match options.max_transfers() {
+        Some(max_transfers) => g.find_shortest_path_limited(
+            origin,
+            predicate,
+            |a| matches!(a, Action::Board(_, _)) as u32,
+            max_transfers as u32 + 1,
+            budget,
+        ),
+        None => g.find_shortest_path(origin, predicate, budget),
+    }
+}
+
+/// Builds the graph or timetable which a search runs on, reporting how long it took to the observer of the routing options
+fn observe_build<T>(options: &RoutingOptions, engine: Engine, build: impl FnOnce() -> T) -> T {
+    // The clock is only read when there is an observer, as it is unavailable on wasm32-unknown-unknown
+    let observer = match options.observer() {
+        Some(observer) => observer,
+        None => return build(),
+    };
+    let start = Instant::now();
+    let result = build();
+    observer.graph_built(engine, start.elapsed());
+    result
+}
+
+/// Searches for the earliest arriving route from varying start times, using the engine selected in the routing options
+enum Searcher<'a> {
+    TimeExpanded(PreparedGraph<'a>, Option<HashMap<StationId, i64>>),
+    Raptor(raptor::Timetable<'a>),
+}
+
+impl<'a> Searcher<'a> {
+    fn new(
+        data: &'a RailroadData,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        end_station: &Station,
+        options: &RoutingOptions,
+        budget: Option<&Budget>,
+    ) -> Self {
+        let engine = options.engine();
+        observe_build(options, engine, || match engine {
+            Engine::TimeExpanded => Searcher::TimeExpanded(
+                PreparedGraph::build(data, start_time, end_time, options, budget),
+                None,
+            ),
+            Engine::AStar => Searcher::TimeExpanded(
+                PreparedGraph::build(data, start_time, end_time, options, budget),
+                Some(station_lower_bounds(data, end_station, options)),
+            ),
+            Engine::Raptor => Searcher::Raptor(raptor::Timetable::from_data(
+                data, start_time, end_time, options, budget,
+            )),
+        })
+    }
+
+    fn route_from(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+        options: &RoutingOptions,
+        budget: Option<&Budget>,
+    ) -> Option<Route<'a>> {
+        match self {
+            Searcher::TimeExpanded(graph, bounds) => graph.route_from(
+                start_time,
+                start_station,
+                end_station,
+                options,
+                bounds.as_ref(),
+                budget,
+            ),
+            Searcher::Raptor(timetable) => {
+                timetable.earliest_route(start_time, start_station, end_station, budget)
             }
         }
     }
-    route
+}
+
+/// Finds the earliest arriving route, then keeps searching from just after its departure for as long as routes arrive
+/// at the same time, returning the one leaving last
+fn latest_good_route_from<'a>(
+    start_time: NaiveDateTime,
+    mut route_from: impl FnMut(NaiveDateTime) -> Option<Route<'a>>,
+) -> Option<Route<'a>> {
+    let mut route = route_from(start_time)?;
+    let best_arrival = match route.parts().last() {
+        Some(x) => x.end.arrival(),
+        None => return Some(route),
+    };
+    while route.parts().last().unwrap().end.arrival() == best_arrival {
+        let time = route.parts().next().unwrap().start.departure() + Duration::seconds(1);
+        route = match route_from(time) {
+            Some(r) => r,
+            None => break,
+        };
+    }
+    Some(route)
+}
+
+/// Finds the earliest arriving route, then again from just after each found route's departure until no route is left
+fn multiple_routes_from<'a>(
+    start_time: NaiveDateTime,
+    mut route_from: impl FnMut(NaiveDateTime) -> Option<Route<'a>>,
+) -> Vec<Route<'a>> {
+    let mut result = Vec::new();
+    let mut route_opt = route_from(start_time);
+    while let Some(route) = route_opt {
+        if route.parts.is_empty() {
+            result.push(route);
+            break;
+        }
+        let time = route.parts().next().unwrap().start.departure() + Duration::seconds(1);
+        result.push(route);
+        route_opt = route_from(time);
+    }
+    result
+}
+
+fn best_single_route<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+    budget: Option<&Budget>,
+) -> Option<Route<'a>> {
+    Searcher::new(data, start_time, end_time, end_station, options, budget).route_from(
+        start_time,
+        start_station,
+        end_station,
+        options,
+        budget,
+    )
+}
+
+fn latest_good_single_route<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+    budget: Option<&Budget>,
+) -> Option<Route<'a>> {
+    let searcher = Searcher::new(data, start_time, end_time, end_station, options, budget);
+    latest_good_route_from(start_time, |time| {
+        searcher.route_from(time, start_station, end_station, options, budget)
+    })
+}
+
+fn multiple_routes<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+    budget: Option<&Budget>,
+) -> Vec<Route<'a>> {
+    let searcher = Searcher::new(data, start_time, end_time, end_station, options, budget);
+    multiple_routes_from(start_time, |time| {
+        searcher.route_from(time, start_station, end_station, options, budget)
+    })
+}
+
+fn pareto_routes<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+    budget: Option<&Budget>,
+) -> Vec<Route<'a>> {
+    observe_build(options, Engine::TimeExpanded, || {
+        PreparedGraph::build(data, start_time, end_time, options, budget)
+    })
+    .pareto(start_time, start_station, end_station, budget)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn alternative_routes<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    count: usize,
+    options: &RoutingOptions,
+    budget: Option<&Budget>,
+) -> Vec<Route<'a>> {
+    observe_build(options, Engine::TimeExpanded, || {
+        PreparedGraph::build(data, start_time, end_time, options, budget)
+    })
+    .alternatives(start_time, start_station, end_station, count, budget)
+}
+
+fn arriving_by_route<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+    budget: Option<&Budget>,
+) -> Option<Route<'a>> {
+    observe_build(options, Engine::TimeExpanded, || {
+        PreparedGraph::build(data, start_time, end_time, options, budget)
+    })
+    .arriving_by(start_station, end_time, end_station, budget)
+}
+
+/// The kind of route search to perform
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum SearchKind {
+    /// Search as in get_best_single_route
+    Best,
+    /// Search as in get_latest_good_single_route
+    Latest,
+    /// Search as in get_multiple_routes
+    Multiple,
+    /// Search as in get_pareto_routes
+    Pareto,
+    /// Search as in get_alternative_routes, for up to the given number of routes
+    Alternatives(usize),
+    /// Search as in get_best_route_arriving_by, treating the end time as the arrival deadline.
+    /// Only the avoided stations and trains, modes, wheelchair accessibility, bicycles and maximum train switches of the routing options are taken into account.
+    ArriveBy,
+}
+
+/// Finds routes as the kind of search of the query does, subject to its routing options.
+///
+/// This is the single entry point for every kind of search, which the get_* functions wrap. Unlike them, searches
+/// running out of the budget set in the routing options fail with HaError::BudgetExceeded instead of returning the
+/// routes found so far.
+pub fn find_routes<'a>(
+    data: &'a RailroadData,
+    query: &RouteQuery<'a>,
+) -> Result<Vec<Route<'a>>, HaError> {
+    let budget = Budget::new(query.options(), None);
+    let routes = query.routes(data, Some(&budget));
+    if budget.exceeded() {
+        return Err(HaError::BudgetExceeded);
+    }
+    Ok(routes)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn search_routes<'a>(
+    data: &'a RailroadData,
+    search: SearchKind,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+    budget: Option<&Budget>,
+) -> Vec<Route<'a>> {
+    let mut routes = match search {
+        SearchKind::Best => best_single_route(
+            data,
+            start_time,
+            start_station,
+            end_time,
+            end_station,
+            options,
+            budget,
+        )
+        .into_iter()
+        .collect(),
+        SearchKind::Latest => latest_good_single_route(
+            data,
+            start_time,
+            start_station,
+            end_time,
+            end_station,
+            options,
+            budget,
+        )
+        .into_iter()
+        .collect(),
+        SearchKind::Multiple => multiple_routes(
+            data,
+            start_time,
+            start_station,
+            end_time,
+            end_station,
+            options,
+            budget,
+        ),
+        SearchKind::Pareto => pareto_routes(
+            data,
+            start_time,
+            start_station,
+            end_time,
+            end_station,
+            options,
+            budget,
+        ),
+        SearchKind::Alternatives(count) => alternative_routes(
+            data,
+            start_time,
+            start_station,
+            end_time,
+            end_station,
+            count,
+            options,
+            budget,
+        ),
+        SearchKind::ArriveBy => arriving_by_route(
+            data,
+            start_time,
+            start_station,
+            end_time,
+            end_station,
+            options,
+            budget,
+        )
+        .into_iter()
+        .collect(),
+    };
+    let ranked = matches!(search, SearchKind::Multiple | SearchKind::Alternatives(_));
+    if ranked && options.order() == RouteOrder::Diverse {
+        rank_by_diversity(&mut routes);
+    }
+    explain(&mut routes);
+    routes
 }
 
 /// Finds the single best route from the source to the destination station at the given time.
 ///
 /// This obtains the route with the fastest arrival time, relative to the given time.
 /// If more than one route is present, routes are prioritized according to least train switches, and least stations passed through in general.
+/// Remaining ties are broken by a fixed order rather than by chance, preferring trains whose IDs are least, so the same query on the same database always finds the same route.
 /// The supplied end time is the latest possible arrival time that will be considered. This is used for optimization purposes.
 pub fn get_best_single_route<'a>(
     data: &'a RailroadData,
@@ -400,15 +1313,75 @@ pub fn get_best_single_route<'a>(
     end_time: NaiveDateTime,
     end_station: &'a Station,
 ) -> Option<Route<'a>> {
-    let mut g = RailroadGraph::from_data(data, start_time, end_time);
-    let origin = Singularity {
-        station: start_station,
-        time: start_time,
-        train: None,
-    };
-    g.ensure(origin);
-    let path = g.find_shortest_path(&origin, |s| s.station == end_station && s.train.is_none())?;
-    Some(build_route(path))
+    get_best_single_route_with_options(
+        data,
+        start_time,
+        start_station,
+        end_time,
+        end_station,
+        &RoutingOptions::default(),
+    )
+}
+
+/// Finds the single best route from the source to the destination station at the given time, subject to the given routing options.
+///
+/// See get_best_single_route for the route selection criteria.
+pub fn get_best_single_route_with_options<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+) -> Option<Route<'a>> {
+    RouteQuery::new(start_station, end_station, start_time, end_time)
+        .with_search(SearchKind::Best)
+        .with_options(options.clone())
+        .routes(data, Some(&Budget::new(options, None)))
+        .into_iter()
+        .next()
+}
+
+/// Finds the single best route from the source station to each of the destination stations at the given time.
+///
+/// Routes are selected as in get_best_single_route, but the graph is built and searched once for all destinations,
+/// which is much faster than searching for each destination separately.
+/// Routes are returned in the order of the destinations, with None for destinations which cannot be reached.
+pub fn get_best_routes_to_many<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_stations: &[&'a Station],
+) -> Vec<Option<Route<'a>>> {
+    get_best_routes_to_many_with_options(
+        data,
+        start_time,
+        start_station,
+        end_time,
+        end_stations,
+        &RoutingOptions::default(),
+    )
+}
+
+/// Finds the single best route from the source station to each of the destination stations, subject to the given
+/// routing options.
+///
+/// See get_best_routes_to_many for the route selection criteria. The A* and RAPTOR engines, which search towards a
+/// single destination, are searched as the time-expanded engine.
+pub fn get_best_routes_to_many_with_options<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_stations: &[&'a Station],
+    options: &RoutingOptions,
+) -> Vec<Option<Route<'a>>> {
+    let budget = Budget::new(options, None);
+    observe_build(options, Engine::TimeExpanded, || {
+        PreparedGraph::build(data, start_time, end_time, options, Some(&budget))
+    })
+    .routes_to_many(start_time, start_station, end_stations, Some(&budget))
 }
 
 /// Finds a route that arrives no later than the best route, but leaves as late as possible.
@@ -424,34 +1397,33 @@ pub fn get_latest_good_single_route<'a>(
     end_time: NaiveDateTime,
     end_station: &'a Station,
 ) -> Option<Route<'a>> {
-    let mut g = RailroadGraph::from_data(data, start_time, end_time);
-    let origin = Singularity {
-        station: start_station,
-        time: start_time,
-        train: None,
-    };
-    g.ensure(origin);
-    let path = g.find_shortest_path(&origin, |s| s.station == end_station && s.train.is_none())?;
-    let mut route = build_route(path);
-    let best_arrival = match route.parts().last() {
-        Some(x) => x.end.arrival(),
-        None => return Some(route),
-    };
-    while route.parts().last().unwrap().end.arrival() == best_arrival {
-        let origin = Singularity {
-            station: start_station,
-            time: route.parts().next().unwrap().start.departure() + Duration::seconds(1),
-            train: None,
-        };
-        g.ensure(origin);
-        let path_opt =
-            g.find_shortest_path(&origin, |s| s.station == end_station && s.train.is_none());
-        route = match path_opt {
-            Some(p) => build_route(p),
-            None => break,
-        };
-    }
-    Some(route)
+    get_latest_good_single_route_with_options(
+        data,
+        start_time,
+        start_station,
+        end_time,
+        end_station,
+        &RoutingOptions::default(),
+    )
+}
+
+/// Finds a route that arrives no later than the best route, but leaves as late as possible, subject to the given routing options.
+///
+/// See get_latest_good_single_route for the route selection criteria.
+pub fn get_latest_good_single_route_with_options<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+) -> Option<Route<'a>> {
+    RouteQuery::new(start_station, end_station, start_time, end_time)
+        .with_search(SearchKind::Latest)
+        .with_options(options.clone())
+        .routes(data, Some(&Budget::new(options, None)))
+        .into_iter()
+        .next()
 }
 
 /// Finds all good routes to the destination
@@ -465,31 +1437,153 @@ pub fn get_multiple_routes<'a>(
     end_time: NaiveDateTime,
     end_station: &'a Station,
 ) -> Vec<Route<'a>> {
-    let mut g = RailroadGraph::from_data(data, start_time, end_time);
-    let mut result = Vec::new();
+    get_multiple_routes_with_options(
+        data,
+        start_time,
+        start_station,
+        end_time,
+        end_station,
+        &RoutingOptions::default(),
+    )
+}
 
-    let origin = Singularity {
-        station: start_station,
-        time: start_time,
-        train: None,
-    };
-    g.ensure(origin);
-    let mut path_opt =
-        g.find_shortest_path(&origin, |s| s.station == end_station && s.train.is_none());
-    while let Some(path) = path_opt {
-        let route = build_route(path);
-        if route.parts.is_empty() {
-            result.push(route);
-            break;
-        }
-        let origin = Singularity {
-            station: start_station,
-            time: route.parts().next().unwrap().start.departure() + Duration::seconds(1),
-            train: None,
-        };
-        result.push(route);
-        g.ensure(origin);
-        path_opt = g.find_shortest_path(&origin, |s| s.station == end_station && s.train.is_none());
-    }
-    result
+/// Finds all good routes to the destination, subject to the given routing options.
+///
+/// See get_multiple_routes for the route selection criteria.
+pub fn get_multiple_routes_with_options<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+) -> Vec<Route<'a>> {
+    RouteQuery::new(start_station, end_station, start_time, end_time)
+        .with_search(SearchKind::Multiple)
+        .with_options(options.clone())
+        .routes(data, Some(&Budget::new(options, None)))
+}
+
+/// Finds the routes that trade off arrival time against the number of train switches.
+///
+/// This obtains the Pareto frontier of routes: no returned route both arrives later and has at least as many train switches as another returned route.
+/// Routes are ordered by increasing number of train switches, and therefore by decreasing arrival time. The last route is the fastest one.
+/// The supplied end time is the latest possible arrival time that will be considered. This is used for optimization purposes.
+pub fn get_pareto_routes<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+) -> Vec<Route<'a>> {
+    get_pareto_routes_with_options(
+        data,
+        start_time,
+        start_station,
+        end_time,
+        end_station,
+        &RoutingOptions::default(),
+    )
+}
+
+/// Finds the routes that trade off arrival time against the number of train switches, subject to the given routing options.
+///
+/// See get_pareto_routes for the route selection criteria.
+pub fn get_pareto_routes_with_options<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+) -> Vec<Route<'a>> {
+    RouteQuery::new(start_station, end_station, start_time, end_time)
+        .with_search(SearchKind::Pareto)
+        .with_options(options.clone())
+        .routes(data, Some(&Budget::new(options, None)))
+}
+
+/// Finds the best, second best, third best and so on routes to the destination, leaving at or after the given time.
+///
+/// Unlike get_multiple_routes, which finds the best route leaving after each found route, this obtains up to count
+/// distinct itineraries for the same departure, such as riding other trains or switching trains elsewhere, ordered as
+/// get_best_single_route would prefer them. Routes are distinct when they ride different trains. They are found by Yen's
+/// algorithm on the time-expanded graph, whichever engine the routing options select.
+/// The supplied end time is the latest possible arrival time that will be considered. This is used for optimization purposes.
+pub fn get_alternative_routes<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    count: usize,
+) -> Vec<Route<'a>> {
+    get_alternative_routes_with_options(
+        data,
+        start_time,
+        start_station,
+        end_time,
+        end_station,
+        count,
+        &RoutingOptions::default(),
+    )
+}
+
+/// Finds up to count distinct routes to the destination, best first, subject to the given routing options.
+///
+/// See get_alternative_routes for the route selection criteria.
+pub fn get_alternative_routes_with_options<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    count: usize,
+    options: &RoutingOptions,
+) -> Vec<Route<'a>> {
+    RouteQuery::new(start_station, end_station, start_time, end_time)
+        .with_search(SearchKind::Alternatives(count))
+        .with_options(options.clone())
+        .routes(data, Some(&Budget::new(options, None)))
+}
+
+/// Finds the route that leaves the source station as late as possible while reaching the destination station by the given arrival time.
+///
+/// The search runs backwards from the destination, starting at end_time.
+/// If more than one route leaves at the same time, routes are prioritized according to least train switches, and least stations passed through in general.
+/// The supplied start time is the earliest possible departure time that will be considered. This is used for optimization purposes.
+pub fn get_best_route_arriving_by<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+) -> Option<Route<'a>> {
+    get_best_route_arriving_by_with_options(
+        data,
+        start_time,
+        start_station,
+        end_time,
+        end_station,
+        &RoutingOptions::default(),
+    )
+}
+
+/// Finds the route that leaves the source station as late as possible while reaching the destination station by the given arrival time, subject to the given routing options.
+///
+/// See get_best_route_arriving_by for the route selection criteria.
+pub fn get_best_route_arriving_by_with_options<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    start_station: &'a Station,
+    end_time: NaiveDateTime,
+    end_station: &'a Station,
+    options: &RoutingOptions,
+) -> Option<Route<'a>> {
+    RouteQuery::new(start_station, end_station, start_time, end_time)
+        .with_search(SearchKind::ArriveBy)
+        .with_options(options.clone())
+        .routes(data, Some(&Budget::new(options, None)))
+        .into_iter()
+        .next()
 }
@@ -0,0 +1,459 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::gtfs_rt::RealtimeUpdates;
+use crate::{Mode, StationId, TrainId};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The algorithm used to search for routes
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum Engine {
+    /// Dijkstra's algorithm over a time-expanded graph of the timetable
+    #[default]
+    TimeExpanded,
+    /// A* search over the time-expanded graph, guided by a lower bound on the remaining travel time to the destination
+    AStar,
+    /// Round-based search (RAPTOR) directly over the train schedules, which is faster for long search windows
+    Raptor,
+}
+
+/// The order in which searches finding several routes return them
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum RouteOrder {
+    /// The order the search finds them in, e.g. by departure for get_multiple_routes
+    #[default]
+    Found,
+    /// Meaningfully different routes first and near-duplicates last, as rank_by_diversity orders them
+    Diverse,
+}
+
+/// Receives measurements taken while searching, e.g. to export them as metrics
+pub trait SearchObserver: fmt::Debug + Send + Sync {
+    /// Called after building the graph, or the RAPTOR timetable, which a search runs on
+    fn graph_built(&self, engine: Engine, elapsed: Duration);
+}
+
+/// The penalties which the time-expanded and A* engines add to the travel time of a route for each of its actions, which
+/// trade off arrival time against train switches.
+///
+/// Example:
+/// ```
+/// use harail::ActionWeights;
+/// use std::time::Duration;
+///
+/// // Arriving up to half an hour later is worth saving a train switch
+/// let weights = ActionWeights::new().with_board(Duration::from_secs(30 * 60));
+/// assert_eq!(Duration::from_secs(30 * 60), weights.board());
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ActionWeights {
+    board: Duration,
+    unboard: Duration,
+    ride: Duration,
+    tight_transfer: Duration,
+}
+
+impl ActionWeights {
+    /// The default weights, which only break ties between routes arriving at the same time
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the penalty for boarding a train
+    pub fn with_board(mut self, board: Duration) -> Self {
+        self.board = board;
+        self
+    }
+
+    /// The penalty for boarding a train, one minute by default
+    pub fn board(&self) -> Duration {
+        self.board
+    }
+
+    /// Sets the penalty for leaving a train
+    pub fn with_unboard(mut self, unboard: Duration) -> Self {
+        self.unboard = unboard;
+        self
+    }
+
+    /// The penalty for leaving a train, one minute by default
+    pub fn unboard(&self) -> Duration {
+        self.unboard
+    }
+
+    /// Sets the penalty for riding a train from one stop to the next
+    pub fn with_ride(mut self, ride: Duration) -> Self {
+        self.ride = ride;
+        self
+    }
+
+    /// The penalty for riding a train from one stop to the next, one second by default so that routes passing through
+    /// fewer stations are preferred, e.g. a->b->c->b over a->b->c->d->c->b when both arrive at the same time
+    pub fn ride(&self) -> Duration {
+        self.ride
+    }
+
+    /// Sets the penalty for switching trains with less than the transfer slack of the routing options to spare
+    pub fn with_tight_transfer(mut self, tight_transfer: Duration) -> Self {
+        self.tight_transfer = tight_transfer;
+        self
+    }
+
+    /// The penalty for switching trains with less than the transfer slack of the routing options to spare, ten
+    /// minutes by default
+    pub fn tight_transfer(&self) -> Duration {
+        self.tight_transfer
+    }
+}
+
+impl Default for ActionWeights {
+    fn default() -> Self {
+        ActionWeights {
+            board: Duration::from_secs(60),
+            unboard: Duration::from_secs(60),
+            ride: Duration::from_secs(1),
+            tight_transfer: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// Constraints and preferences which affect the routes found by a search
+///
+/// Examples:
+/// ```
+/// use harail::RoutingOptions;
+///
+/// let options = RoutingOptions::new().with_max_transfers(Some(1));
+/// assert_eq!(Some(1), options.max_transfers());
+///
+/// let options = RoutingOptions::new().with_avoided_stations([3700]);
+/// assert!(options.avoids_station(3700));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RoutingOptions {
+    max_transfers: Option<usize>,
+    avoided_stations: HashSet<StationId>,
+    avoided_trains: HashSet<TrainId>,
+    modes: Option<HashSet<Mode>>,
+    wheelchair: bool,
+    bikes: bool,
+    engine: Engine,
+    contraction: bool,
+    weights: ActionWeights,
+    realtime: Option<Arc<RealtimeUpdates>>,
+    observer: Option<Arc<dyn SearchObserver>>,
+    max_expanded_nodes: Option<usize>,
+    time_limit: Option<Duration>,
+    min_transfer_time: Option<Duration>,
+    transfer_slack: Option<Duration>,
+    order: RouteOrder,
+}
+
+impl RoutingOptions {
+    /// Create a new RoutingOptions object, which doesn't constrain the search in any way
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the number of train switches on found routes.
+    ///
+    /// Routes may be slower than the fastest possible route in order to respect this limit.
+    pub fn with_max_transfers(mut self, max_transfers: Option<usize>) -> Self {
+        self.max_transfers = max_transfers;
+        self
+    }
+
+    /// The maximum number of train switches allowed on found routes, if limited
+    pub fn max_transfers(&self) -> Option<usize> {
+        self.max_transfers
+    }
+
+    /// Excludes the given stations from found routes, e.g. due to a station being closed for maintenance.
+    ///
+    /// Trains may still pass through avoided stations, but routes will never board or leave a train at them.
+    pub fn with_avoided_stations<I: IntoIterator<Item = StationId>>(mut self, stations: I) -> Self {
+        self.avoided_stations.extend(stations);
+        self
+    }
+
+    /// Excludes the given trains from found routes
+    pub fn with_avoided_trains<I: IntoIterator<Item = TrainId>>(mut self, trains: I) -> Self {
+        self.avoided_trains.extend(trains);
+        self
+    }
+
+    /// Whether found routes may not board or leave trains at the given station
+    pub fn avoids_station(&self, station: StationId) -> bool {
+        self.avoided_stations.contains(&station)
+    }
+
+    /// Whether found routes may not use the given train
+    pub fn avoids_train(&self, train: &str) -> bool {
+        self.avoided_trains.contains(train)
+    }
+
+    /// Restricts found routes to trains of the given modes, e.g. to route by rail only in a database which also has buses
+    pub fn with_modes<I: IntoIterator<Item = Mode>>(mut self, modes: I) -> Self {
+        self.modes = Some(modes.into_iter().collect());
+        self
+    }
+
+    /// The stations at which found routes may not board or leave trains
+    pub(crate) fn avoided_stations(&self) -> &HashSet<StationId> {
+        &self.avoided_stations
+    }
+
+    /// The trains which found routes may not use
+    pub(crate) fn avoided_trains(&self) -> &HashSet<TrainId> {
+        &self.avoided_trains
+    }
+
+    /// The modes which found routes are restricted to, if any
+    pub(crate) fn modes(&self) -> Option<&HashSet<Mode>> {
+        self.modes.as_ref()
+    }
+
+    /// Whether found routes may use trains of the given mode
+    pub fn allows_mode(&self, mode: Mode) -> bool {
+        self.modes
+            .as_ref()
+            .is_none_or(|modes| modes.contains(&mode))
+    }
+
+    /// Restricts found routes to ones usable by wheelchair users.
+    ///
+    /// Trains and stations which the feed marks as inaccessible are avoided, while ones of unknown accessibility are
+    /// still used.
+    pub fn with_wheelchair(mut self, wheelchair: bool) -> Self {
+        self.wheelchair = wheelchair;
+        self
+    }
+
+    /// Whether found routes must be usable by wheelchair users
+    pub fn wheelchair(&self) -> bool {
+        self.wheelchair
+    }
+
+    /// Restricts found routes to trains which the feed marks as allowing bicycles, for riders taking a bicycle along
+    pub fn with_bikes(mut self, bikes: bool) -> Self {
+        self.bikes = bikes;
+        self
+    }
+
+    /// Whether found routes must only use trains which allow bicycles
+    pub fn bikes(&self) -> bool {
+        self.bikes
+    }
+
+    /// Selects the search algorithm used by get_best_single_route, get_latest_good_single_route and get_multiple_routes.
+    ///
+    /// With the default action weights, all engines find routes with the same arrival times, but may pick different routes among equally good ones.
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// The search algorithm to use
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Contracts the graph searched by the time-expanded and A* engines before searching it, leaving only the nodes of
+    /// stations, which shortcuts along each train run connect.
+    ///
+    /// Routes are found with the same costs, but searches expand far fewer nodes in return for a slower build, so this
+    /// pays off for long windows and for prepared graphs searched many times.
+    pub fn with_contraction(mut self, contraction: bool) -> Self {
+        self.contraction = contraction;
+        self
+    }
+
+    /// Whether the searched graph is contracted
+    pub fn contraction(&self) -> bool {
+        self.contraction
+    }
+
+    /// Sets the penalties of the actions making up a route, such as a high boarding penalty for users who strongly
+    /// prefer fewer train switches even if they arrive later.
+    ///
+    /// Only the time-expanded and A* engines weigh actions, as RAPTOR always finds the earliest arrival.
+    pub fn with_weights(mut self, weights: ActionWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    /// The penalties of the actions making up a route
+    pub fn weights(&self) -> ActionWeights {
+        self.weights
+    }
+
+    /// Applies real-time delays and cancellations on top of the static timetable.
+    ///
+    /// Cancelled train runs are never used, and delayed ones are used at their adjusted times.
+    pub fn with_realtime(mut self, realtime: Arc<RealtimeUpdates>) -> Self {
+        self.realtime = Some(realtime);
+        self
+    }
+
+    /// The real-time changes taken into account by the search, if any
+    pub fn realtime(&self) -> Option<&RealtimeUpdates> {
+        self.realtime.as_deref()
+    }
+
+    /// Splits the real-time changes off the options, for graphs built from the timetable and updated afterwards
+    pub(crate) fn take_realtime(mut self) -> (Self, Option<Arc<RealtimeUpdates>>) {
+        let realtime = self.realtime.take();
+        (self, realtime)
+    }
+
+    /// Reports measurements of the search to the given observer
+    pub fn with_observer(mut self, observer: Arc<dyn SearchObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// The observer which measurements of the search are reported to, if any
+    pub fn observer(&self) -> Option<&dyn SearchObserver> {
+        self.observer.as_deref()
+    }
+
+    /// Limits the number of nodes a search may expand, counting every station reached in a round of RAPTOR as a node.
+    ///
+    /// Searches which reach the limit stop without finding a route: find_routes and find_routes_async then fail with
+    /// HaError::BudgetExceeded, while the get_* functions find no route, or only the routes found so far.
+    pub fn with_max_expanded_nodes(mut self, max_expanded_nodes: Option<usize>) -> Self {
+        self.max_expanded_nodes = max_expanded_nodes;
+        self
+    }
+
+    /// The maximum number of nodes a search may expand, if limited
+    pub fn max_expanded_nodes(&self) -> Option<usize> {
+        self.max_expanded_nodes
+    }
+
+    /// Limits the time a search may run for, including building the graph it runs on.
+    ///
+    /// Searches which run out of time stop like searches expanding too many nodes do. Time limits cannot be used on
+    /// wasm32-unknown-unknown, which has no clock to read.
+    pub fn with_time_limit(mut self, time_limit: Option<Duration>) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    /// The time a search may run for, if limited
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.time_limit
+    }
+
+    /// Requires at least the given time between leaving a train and boarding another, as switching trains within
+    /// moments of arriving is rarely possible. Stations whose feed sets a longer minimum transfer time keep theirs.
+    pub fn with_min_transfer_time(mut self, min_transfer_time: Option<Duration>) -> Self {
+        self.min_transfer_time = min_transfer_time;
+        self
+    }
+
+    /// The minimum time required for switching trains at any station, if set
+    pub fn min_transfer_time(&self) -> Option<Duration> {
+        self.min_transfer_time
+    }
+
+    /// Prefers switching trains with at least the given time to spare beyond the minimum transfer time, so that a
+    /// delayed train doesn't make the next one missed. Tighter train switches are still made, but are penalized by
+    /// the tight transfer weight of the action weights, so routes arrive later rather than switch trains tightly as
+    /// long as they arrive no more than that much later.
+    ///
+    /// Only the time-expanded and A* engines weigh train switches, as RAPTOR always finds the earliest arrival.
+    pub fn with_transfer_slack(mut self, transfer_slack: Option<Duration>) -> Self {
+        self.transfer_slack = transfer_slack;
+        self
+    }
+
+    /// The time to spare which train switches should leave beyond the minimum transfer time, if set
+    pub fn transfer_slack(&self) -> Option<Duration> {
+        self.transfer_slack
+    }
+
+    /// Sets the order in which get_multiple_routes and get_alternative_routes return the routes they find
+    pub fn with_order(mut self, order: RouteOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// The order in which searches finding several routes return them
+    pub fn order(&self) -> RouteOrder {
+        self.order
+    }
+}
+
+/// The name of Israel Railways in the Israeli GTFS feed, whose trips are loaded by default
+pub const ISRAEL_RAILWAYS: &str = "רכבת ישראל";
+
+/// Which agencies' trips are loaded from a GTFS feed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AgencyFilter {
+    /// Agencies whose name or ID is one of the given strings
+    Matching(Vec<String>),
+    /// Every agency in the feed
+    All,
+}
+
+impl Default for AgencyFilter {
+    fn default() -> Self {
+        AgencyFilter::Matching(vec![ISRAEL_RAILWAYS.to_owned()])
+    }
+}
+
+/// Settings for loading a GTFS feed
+///
+/// Examples:
+/// ```
+/// use harail::{AgencyFilter, GtfsOptions, Mode};
+///
+/// let options = GtfsOptions::new().with_agencies(AgencyFilter::All);
+/// assert_eq!(&AgencyFilter::All, options.agencies());
+///
+/// let options = GtfsOptions::new().with_modes([Mode::Rail, Mode::LightRail]);
+/// assert!(!options.loads_mode(Mode::Bus));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GtfsOptions {
+    agencies: AgencyFilter,
+    modes: Option<HashSet<Mode>>,
+}
+
+impl GtfsOptions {
+    /// Create a new GtfsOptions object, which loads Israel Railways trips of every mode
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the agencies whose trips are loaded
+    pub fn with_agencies(mut self, agencies: AgencyFilter) -> Self {
+        self.agencies = agencies;
+        self
+    }
+
+    /// The agencies whose trips are loaded
+    pub fn agencies(&self) -> &AgencyFilter {
+        &self.agencies
+    }
+
+    /// Only loads trips whose route is of one of the given modes
+    pub fn with_modes<I: IntoIterator<Item = Mode>>(mut self, modes: I) -> Self {
+        self.modes = Some(modes.into_iter().collect());
+        self
+    }
+
+    /// Whether trips of the given mode are loaded
+    pub fn loads_mode(&self, mode: Mode) -> bool {
+        self.modes
+            .as_ref()
+            .is_none_or(|modes| modes.contains(&mode))
+    }
+}
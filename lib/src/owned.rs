@@ -0,0 +1,446 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::gtfs_rt::{Alert, Occupancy, RealtimeUpdates};
+use crate::pricing::Fare;
+use crate::reliability::Transfer;
+use crate::{
+    localize_in, DelayHistory, Explanation, RailroadData, Reliability, Route, RoutePart,
+    RouteSummary, StationId, Stop, TrainId, Zoned, FEED_TIMEZONE, JSON,
+};
+use chrono::{Duration, NaiveDateTime};
+use chrono_tz::Tz;
+use jzon::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A Stop which does not borrow from the database.
+///
+/// With serde, times are given in RFC 3339 in the timezone of the feed, as in JSON.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub struct OwnedStop {
+    station: StationId,
+    station_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    #[serde(with = "crate::timezone::zoned")]
+    arrival: NaiveDateTime,
+    #[serde(with = "crate::timezone::zoned")]
+    departure: NaiveDateTime,
+    #[serde(default)]
+    delayed: bool,
+}
+
+impl OwnedStop {
+    /// The station at which the train stops
+    pub fn station(&self) -> StationId {
+        self.station
+    }
+
+    /// The name of the station at which the train stops
+    pub fn station_name(&self) -> &String {
+        &self.station_name
+    }
+
+    /// The platform the train stops at, if known
+    pub fn platform(&self) -> Option<&str> {
+        self.platform.as_deref()
+    }
+
+    pub fn arrival(&self) -> NaiveDateTime {
+        self.arrival
+    }
+
+    pub fn departure(&self) -> NaiveDateTime {
+        self.departure
+    }
+
+    /// Whether the stop's times were changed by real-time updates
+    pub fn is_delayed(&self) -> bool {
+        self.delayed
+    }
+}
+
+impl From<&Stop<'_>> for OwnedStop {
+    fn from(stop: &Stop<'_>) -> Self {
+        OwnedStop {
+            station: stop.station().id(),
+            station_name: stop.station().name().to_owned(),
+            platform: stop.platform().map(str::to_owned),
+            arrival: stop.arrival(),
+            departure: stop.departure(),
+            delayed: stop.is_delayed(),
+        }
+    }
+}
+
+impl Zoned for OwnedStop {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        let mut result = object! {
+            station: self.station,
+            arrival: localize_in(self.arrival, timezone).to_rfc3339(),
+            departure: localize_in(self.departure, timezone).to_rfc3339(),
+        };
+        if let Some(platform) = self.platform() {
+            result["platform"] = platform.into();
+        }
+        result
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        write!(
+            f,
+            "{}{} ({} - {})",
+            self.station_name,
+            crate::platform_suffix(self.platform()),
+            localize_in(self.arrival, timezone).naive_local(),
+            localize_in(self.departure, timezone).naive_local()
+        )
+    }
+}
+
+impl JSON for OwnedStop {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for OwnedStop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+/// A RoutePart which does not borrow from the database
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub struct OwnedRoutePart {
+    train: TrainId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    train_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bikes_allowed: Option<bool>,
+    start: OwnedStop,
+    end: OwnedStop,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    intermediate_stops: Vec<OwnedStop>,
+}
+
+impl OwnedRoutePart {
+    /// Finds the ride on a train from start_station to end_station, with the latest real-time changes applied.
+    ///
+    /// The train run used is the one scheduled to leave start_station closest to the given departure time,
+    /// so that a ride which was found before can be looked up again after it was delayed.
+    /// Returns None if the train does not run, was cancelled, or no longer stops at either station.
+    pub fn from_ride(
+        data: &RailroadData,
+        realtime: &RealtimeUpdates,
+        train: &str,
+        start_station: StationId,
+        departure: NaiveDateTime,
+        end_station: StationId,
+    ) -> Option<Self> {
+        let train = data.train(train)?;
+        let scheduled = train.stops().find(|s| s.station() == start_station)?;
+        let date = *train.dates().min_by_key(|&&date| {
+            (Stop::from_stop_schedule(data, scheduled, date).departure() - departure)
+                .num_seconds()
+                .abs()
+        })?;
+        let stops = realtime.stops(data, train, date)?;
+        let start = stops
+            .iter()
+            .position(|s| s.station().id() == start_station)?;
+        let end = start
+            + stops[start..]
+                .iter()
+                .position(|s| s.station().id() == end_station)?;
+        let part = RoutePart::new(train, stops[start], stops[end])
+            .with_intermediate_stops(stops[start + 1..end].to_vec());
+        Some((&part).into())
+    }
+
+    /// The train associated with the route part
+    pub fn train(&self) -> &TrainId {
+        &self.train
+    }
+
+    /// The stop at which the train is boarded
+    pub fn start(&self) -> &OwnedStop {
+        &self.start
+    }
+
+    /// The stop at which the train is unboarded
+    pub fn end(&self) -> &OwnedStop {
+        &self.end
+    }
+
+    /// The time from boarding the train to unboarding it
+    pub fn duration(&self) -> Duration {
+        self.end.arrival - self.start.departure
+    }
+
+    /// The stops the train makes between boarding and unboarding it, in order
+    pub fn intermediate_stops(&self) -> &[OwnedStop] {
+        &self.intermediate_stops
+    }
+
+    /// The number passengers know the train by, if the feed gives one
+    pub fn train_number(&self) -> Option<&str> {
+        self.train_number.as_deref()
+    }
+
+    /// Whether bicycles may be taken on the train, if known
+    pub fn bikes_allowed(&self) -> Option<bool> {
+        self.bikes_allowed
+    }
+
+    /// Whether real-time updates changed the boarding or unboarding times
+    pub fn is_delayed(&self) -> bool {
+        self.start.delayed || self.end.delayed
+    }
+
+    /// The service alerts relevant to the train ride, i.e. ones about the train or its boarding and unboarding stations
+    pub fn alerts<'r>(&self, realtime: &'r RealtimeUpdates) -> Vec<&'r Alert> {
+        realtime
+            .alerts()
+            .filter(|a| {
+                a.affects_ride(
+                    &self.train,
+                    self.start.station,
+                    self.end.station,
+                    self.start.departure,
+                    self.end.arrival,
+                )
+            })
+            .collect()
+    }
+
+    /// The expected crowding of the train when boarding it, if the real-time updates give it
+    pub fn occupancy(&self, data: &RailroadData, realtime: &RealtimeUpdates) -> Option<Occupancy> {
+        realtime.occupancy(
+            data.train(&self.train)?,
+            self.start.station,
+            self.start.departure,
+        )
+    }
+}
+
+impl From<&RoutePart<'_>> for OwnedRoutePart {
+    fn from(part: &RoutePart<'_>) -> Self {
+        OwnedRoutePart {
+            train: part.train().id().to_owned(),
+            train_number: part.train().short_name().map(str::to_owned),
+            bikes_allowed: part.train().bikes_allowed(),
+            start: (&part.start()).into(),
+            end: (&part.end()).into(),
+            intermediate_stops: part
+                .intermediate_stops()
+                .iter()
+                .map(OwnedStop::from)
+                .collect(),
+        }
+    }
+}
+
+impl Zoned for OwnedRoutePart {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        let departure = localize_in(self.start.departure, timezone);
+        let arrival = localize_in(self.end.arrival, timezone);
+        let mut result = object! {
+            train: self.train.to_owned(),
+            start_time: departure.to_rfc3339(),
+            start_station: self.start.station,
+            end_time: arrival.to_rfc3339(),
+            end_station: self.end.station,
+            duration_seconds: self.duration().num_seconds()
+        };
+        if let Some(platform) = self.start.platform() {
+            result["start_platform"] = platform.into();
+        }
+        if let Some(platform) = self.end.platform() {
+            result["end_platform"] = platform.into();
+        }
+        if let Some(number) = self.train_number() {
+            result["train_number"] = number.into();
+        }
+        if let Some(bikes_allowed) = self.bikes_allowed {
+            result["bikes_allowed"] = bikes_allowed.into();
+        }
+        if !self.intermediate_stops.is_empty() {
+            result["intermediate_stops"] = JsonValue::Array(
+                self.intermediate_stops
+                    .iter()
+                    .map(|s| s.to_json_in(timezone))
+                    .collect(),
+            );
+        }
+        result
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        write!(
+            f,
+            "{}{} ({}) -> {}{} ({}){}",
+            self.start.station_name,
+            crate::platform_suffix(self.start.platform()),
+            localize_in(self.start.departure, timezone).naive_local(),
+            self.end.station_name,
+            crate::platform_suffix(self.end.platform()),
+            localize_in(self.end.arrival, timezone).naive_local(),
+            crate::train_suffix(self.train_number())
+        )
+    }
+}
+
+impl JSON for OwnedRoutePart {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for OwnedRoutePart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+/// A Route which does not borrow from the database.
+///
+/// This can be sent between threads and outlive the database it was found in, which is needed e.g. when searching on a thread pool.
+/// Routes serialized with serde, whether borrowing or owned, are deserialized as OwnedRoute.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct OwnedRoute {
+    parts: Vec<OwnedRoutePart>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    price: Option<Fare>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reliability: Option<Reliability>,
+}
+
+impl OwnedRoute {
+    /// Create a new OwnedRoute from its train rides
+    pub fn from_parts(parts: Vec<OwnedRoutePart>) -> Self {
+        OwnedRoute {
+            parts,
+            price: None,
+            explanation: None,
+            reliability: None,
+        }
+    }
+
+    /// Iterate over the parts of the route. Each part corresponds to a single train ride.
+    pub fn parts(&self) -> impl Iterator<Item = &OwnedRoutePart> {
+        self.parts.iter()
+    }
+
+    /// Whether real-time updates changed the times of any of the route's train rides
+    pub fn is_delayed(&self) -> bool {
+        self.parts.iter().any(|p| p.is_delayed())
+    }
+
+    /// The time waited between arriving on the previous part and boarding the train of the part at the given index,
+    /// or None for the first part
+    pub fn wait_before(&self, index: usize) -> Option<Duration> {
+        let previous = self.parts.get(index.checked_sub(1)?)?;
+        Some(self.parts.get(index)?.start.departure - previous.end.arrival)
+    }
+
+    /// The ticket price of the route, if the database had fares covering every ride
+    pub fn price(&self) -> Option<Fare> {
+        self.price
+    }
+
+    /// Why the search found the route, compared to the other routes it found, if it was explained
+    pub fn explanation(&self) -> Option<&Explanation> {
+        self.explanation.as_ref()
+    }
+
+    /// The chance of the route making all of its train switches, if it was rated
+    pub fn reliability(&self) -> Option<Reliability> {
+        self.reliability
+    }
+
+    /// Rates the route by its chance of making all of its train switches, according to the delays in the history
+    pub fn with_reliability(mut self, data: &RailroadData, history: &DelayHistory) -> Self {
+        let transfers = self.parts.windows(2).map(|w| Transfer {
+            arriving: &w[0].train,
+            departing: &w[1].train,
+            station: w[0].end.station,
+            arrival: w[0].end.arrival,
+            departure: w[1].start.departure,
+        });
+        self.reliability = Some(history.reliability(data, transfers));
+        self
+    }
+
+    /// The route's total times, train switches and stations passed
+    pub fn summary(&self) -> RouteSummary {
+        RouteSummary::from_rides(
+            self.parts
+                .iter()
+                .map(|p| (p.start.departure, p.end.arrival, p.intermediate_stops.len())),
+        )
+    }
+}
+
+impl From<&Route<'_>> for OwnedRoute {
+    fn from(route: &Route<'_>) -> Self {
+        OwnedRoute {
+            parts: route.parts().map(OwnedRoutePart::from).collect(),
+            price: route.price(),
+            explanation: route.explanation().cloned(),
+            reliability: route.reliability(),
+        }
+    }
+}
+
+impl Zoned for OwnedRoute {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        let mut result = JsonValue::new_array();
+        for (i, part) in self.parts.iter().enumerate() {
+            let mut json = part.to_json_in(timezone);
+            if let Some(wait) = self.wait_before(i) {
+                json["wait_seconds"] = wait.num_seconds().into();
+            }
+            result.push(json).unwrap();
+        }
+        let mut json = object! {
+            parts: result
+        };
+        if let Some(price) = self.price {
+            json["price"] = price.to_json();
+        }
+        if let Some(explanation) = &self.explanation {
+            json["explanation"] = explanation.to_json();
+        }
+        if let Some(reliability) = self.reliability {
+            json["reliability"] = reliability.to_json();
+        }
+        json["summary"] = self.summary().to_json();
+        json
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        for part in self.parts.iter() {
+            writeln!(f, "{}", part.in_timezone(timezone))?;
+        }
+        Ok(())
+    }
+}
+
+impl JSON for OwnedRoute {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for OwnedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
@@ -0,0 +1,653 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::budget::Budget;
+use crate::gtfs_rt::RealtimeUpdates;
+use crate::saved_graph;
+use crate::{
+    build_route, find_path, latest_good_route_from, multiple_routes_from, observe_build,
+    station_lower_bounds, train_run_stops, transfer_time, uses_train, Action, Arrival, Engine,
+    HaError, RailroadData, RailroadGraph, Route, RoutingOptions, Singularity, Station, StationId,
+    Train,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// The most paths through the graph looked at per alternative route asked for, as paths which only switch between
+/// the same trains at other stations are skipped
+const PATHS_PER_ALTERNATIVE: usize = 20;
+
+/// A routing graph built once for a time window, which any number of route searches can then run on.
+///
+/// Building the graph is most of the work of a search, so servers and batch jobs answering many queries within the same
+/// window should prepare a graph once instead of calling the get_* functions for every query. Searches take `&self`, so a
+/// prepared graph can be shared between threads.
+///
+/// Limits on the work of searches in the routing options apply to building the graph and to each search separately.
+///
+/// Only routes within the window the graph was prepared for are found. The graph is the one searched by the
+/// time-expanded and A* engines; searches with the A* engine compute their lower bounds per destination, and the RAPTOR
+/// engine, which has no graph to prepare, is searched as the time-expanded engine.
+///
+/// Examples:
+/// ```
+/// use chrono::NaiveDate;
+/// use harail::{HaDuration, PreparedGraph, RailroadData, RoutingOptions, Station, StopSchedule, Train};
+///
+/// let date = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+/// let stops = vec![
+///     StopSchedule::new(100, HaDuration::from_hms(10, 0, 0), None),
+///     StopSchedule::new(200, HaDuration::from_hms(10, 30, 0), None),
+/// ];
+/// let data = RailroadData::from_stations_trains(
+///     vec![Station::new(100, "A"), Station::new(200, "B")],
+///     vec![Train::from_stops_date("1", stops, date)],
+/// );
+/// let start = date.and_hms_opt(0, 0, 0).unwrap();
+/// let graph = PreparedGraph::new(&data, start, start + chrono::Duration::days(1), &RoutingOptions::new());
+/// let route = graph.best_route(start, data.station(100).unwrap(), data.station(200).unwrap());
+/// assert_eq!(route.unwrap().parts().count(), 1);
+/// ```
+pub struct PreparedGraph<'a> {
+    data: &'a RailroadData,
+    graph: RailroadGraph<'a>,
+    options: RoutingOptions,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    /// The times of the nodes at which trains may be boarded or left at each station, in order
+    station_times: HashMap<StationId, Vec<NaiveDateTime>>,
+}
+
+impl<'a> PreparedGraph<'a> {
+    /// Builds the graph of the train runs between start_time and end_time, subject to the given routing options
+    pub fn new(
+        data: &'a RailroadData,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        options: &RoutingOptions,
+    ) -> Self {
+        let budget = Budget::new(options, None);
+        observe_build(options, Engine::TimeExpanded, || {
+            Self::build(data, start_time, end_time, options, Some(&budget))
+        })
+    }
+
+    pub(crate) fn build(
+        data: &'a RailroadData,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        options: &RoutingOptions,
+        budget: Option<&Budget>,
+    ) -> Self {
+        let graph = RailroadGraph::from_data(data, start_time, end_time, options, budget);
+        Self::from_graph(data, graph, start_time, end_time, options)
+    }
+
+    fn from_graph(
+        data: &'a RailroadData,
+        mut graph: RailroadGraph<'a>,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        options: &RoutingOptions,
+    ) -> Self {
+        if options.contraction() {
+            graph.contract(|s| s.train.is_none());
+        }
+        let mut station_times: HashMap<StationId, Vec<NaiveDateTime>> = HashMap::new();
+        for node in graph.nodes().map(|n| n.id()).filter(|n| n.train.is_none()) {
+            station_times
+                .entry(node.station.id())
+                .or_default()
+                .push(node.time);
+        }
+        for times in station_times.values_mut() {
+            times.sort_unstable();
+        }
+        PreparedGraph {
+            data,
+            graph,
+            options: options.clone(),
+            start_time,
+            end_time,
+            station_times,
+        }
+    }
+
+    /// Writes the graph to a file, which load reads back much faster than the graph can be prepared again.
+    ///
+    /// The file refers to stations and trains by their identifiers, so it can only be loaded along with the database the
+    /// graph was prepared from. Graphs prepared with contraction or real-time updates can't be saved, as loading
+    /// contracts the graph and applies the updates of the time.
+    pub fn save<W: Write>(&self, writer: W) -> Result<(), HaError> {
+        if self.options.contraction() {
+            return Err(HaError::UsageError(
+                "graphs prepared with contraction can't be saved".to_owned(),
+            ));
+        }
+        if self.options.realtime().is_some() {
+            return Err(HaError::UsageError(
+                "graphs prepared with real-time updates can't be saved".to_owned(),
+            ));
+        }
+        saved_graph::write(
+            writer,
+            self.data,
+            &self.graph,
+            self.start_time,
+            self.end_time,
+            &self.options,
+        )
+    }
+
+    /// Reads a graph written by save, for the time window it was prepared for.
+    ///
+    /// The routing options must shape the graph as the ones it was prepared with did, while options which only affect
+    /// searches, such as the engine and the limits on their work, may differ. Contraction and real-time updates in the
+    /// options are applied to the loaded graph.
+    pub fn load<R: Read>(
+        data: &'a RailroadData,
+        reader: R,
+        options: &RoutingOptions,
+    ) -> Result<Self, HaError> {
+        let (options, realtime) = options.clone().take_realtime();
+        let (graph, start_time, end_time) = saved_graph::read(reader, data, &options)?;
+        let mut graph = Self::from_graph(data, graph, start_time, end_time, &options);
+        if let Some(realtime) = realtime {
+            graph.update_realtime(realtime);
+        }
+        Ok(graph)
+    }
+
+    /// The start of the time window the graph was prepared for
+    pub fn start_time(&self) -> NaiveDateTime {
+        self.start_time
+    }
+
+    /// The end of the time window the graph was prepared for
+    pub fn end_time(&self) -> NaiveDateTime {
+        self.end_time
+    }
+
+    /// The routing options the graph was prepared with
+    pub fn options(&self) -> &RoutingOptions {
+        &self.options
+    }
+
+    /// Applies new real-time updates to the graph in place of the ones it was prepared with, which is much faster than
+    /// preparing the graph again when the updates change only a few of the train runs, e.g. when a real-time feed is
+    /// downloaded every minute. Returns the number of train runs whose times changed.
+    ///
+    /// Only the runs whose stops differ between the old and new updates are removed from the graph and added back at
+    /// their new times. Graphs prepared with contraction are prepared again instead, as their shortcuts would have to be
+    /// found again anyway.
+    pub fn update_realtime(&mut self, realtime: Arc<RealtimeUpdates>) -> usize {
+        let old_options = self.options.clone();
+        let options = self.options.clone().with_realtime(realtime);
+        // The runs changed by either set of updates are the only ones whose stops may differ
+        let mut runs: Vec<(&'a Train, NaiveDate)> = old_options
+            .realtime()
+            .into_iter()
+            .chain(options.realtime())
+            .flat_map(|r| r.runs())
+            .filter_map(|(train, date)| Some((self.data.train(train)?, date)))
+            .filter(|(train, _)| uses_train(train, &options))
+            .collect();
+        runs.sort_unstable_by(|(a, a_date), (b, b_date)| {
+            a.id().cmp(b.id()).then(a_date.cmp(b_date))
+        });
+        runs.dedup_by(|(a, a_date), (b, b_date)| a.id() == b.id() && a_date == b_date);
+        let changed: Vec<_> = runs
+            .into_iter()
+            .filter_map(|(train, date)| {
+                let old = train_run_stops(self.data, train, date, &old_options);
+                let new = train_run_stops(self.data, train, date, &options);
+                (old != new).then_some((train, old, new))
+            })
+            .collect();
+        if options.contraction() && !changed.is_empty() {
+            *self = Self::build(self.data, self.start_time, self.end_time, &options, None);
+            return changed.len();
+        }
+        let mut stations_general: HashMap<&'a Station, HashSet<Singularity<'a>>> = HashMap::new();
+        for &(train, ref old, ref new) in &changed {
+            if let Some(old) = old {
+                self.graph
+                    .remove_run(train, old, self.start_time, self.end_time);
+            }
+            if let Some(new) = new {
+                self.graph.add_run(
+                    self.data,
+                    train,
+                    new,
+                    self.start_time,
+                    self.end_time,
+                    &options,
+                    &mut stations_general,
+                );
+            }
+        }
+        // New station nodes are put on their station's chain of wait edges, between the nodes before and after them
+        for (station, nodes) in stations_general {
+            let times = self.station_times.entry(station.id()).or_default();
+            for node in nodes {
+                let index = match times.binary_search(&node.time) {
+                    Ok(_) => continue,
+                    Err(index) => index,
+                };
+                let at = |time| Singularity {
+                    station,
+                    time,
+                    train: None,
+                };
+                let prev = index.checked_sub(1).map(|i| at(times[i]));
+                let next = times.get(index).map(|&time| at(time));
+                if let Some(prev) = prev {
+                    let prev_node = self.graph.get_mut(&prev).unwrap();
+                    if let Some(next) = next {
+                        prev_node.retain_edges(|_, dest| *dest != next);
+                    }
+                    prev_node.connect(Action::Wait(node.time - prev.time), node);
+                }
+                if let Some(next) = next {
+                    self.graph
+                        .get_mut(&node)
+                        .unwrap()
+                        .connect(Action::Wait(next.time - node.time), next);
+                }
+                times.insert(index, node.time);
+            }
+        }
+        self.options = options;
+        changed.len()
+    }
+
+    /// The first node of the station at or after the given time, at which routes leaving then start
+    fn first_node_after(
+        &self,
+        station: &'a Station,
+        time: NaiveDateTime,
+    ) -> Option<Singularity<'a>> {
+        let times = self.station_times.get(&station.id())?;
+        let time = *times.get(times.partition_point(|t| *t < time))?;
+        Some(Singularity {
+            station,
+            time,
+            train: None,
+        })
+    }
+
+    /// The last node of the station at or before the given time, at which routes arriving by then end
+    fn last_node_before(
+        &self,
+        station: &'a Station,
+        time: NaiveDateTime,
+    ) -> Option<Singularity<'a>> {
+        let times = self.station_times.get(&station.id())?;
+        let time = times[times.partition_point(|t| *t <= time).checked_sub(1)?];
+        Some(Singularity {
+            station,
+            time,
+            train: None,
+        })
+    }
+
+    pub(crate) fn route_from(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+        options: &RoutingOptions,
+        bounds: Option<&HashMap<StationId, i64>>,
+        budget: Option<&Budget>,
+    ) -> Option<Route<'a>> {
+        if start_station == end_station {
+            return Some(Route::new().with_fares(self.data.fares()));
+        }
+        let origin = self.first_node_after(start_station, start_time)?;
+        let path = find_path(&self.graph, &origin, end_station, options, bounds, budget)?;
+        Some(build_route(self.data, path))
+    }
+
+    /// The lower bounds guiding searches to the given destination, if the routing options select the A* engine
+    fn bounds(&self, end_station: &Station) -> Option<HashMap<StationId, i64>> {
+        (self.options.engine() == Engine::AStar)
+            .then(|| station_lower_bounds(self.data, end_station, &self.options))
+    }
+
+    pub(crate) fn pareto(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+        budget: Option<&Budget>,
+    ) -> Vec<Route<'a>> {
+        let arrival = |route: &Route| route.parts().last().map(|p| p.end.arrival());
+        // The fastest route bounds the number of transfers worth considering
+        let fastest = match self.route_from(
+            start_time,
+            start_station,
+            end_station,
+            &self.options,
+            None,
+            budget,
+        ) {
+            Some(route) => route,
+            None => return Vec::new(),
+        };
+        let best_arrival = arrival(&fastest);
+        let max_transfers = fastest.parts().count().saturating_sub(1);
+
+        // Each additional allowed transfer is only worth it if it improves the arrival time
+        let mut result: Vec<Route> = Vec::new();
+        for transfers in 0..=max_transfers {
+            let limited = self.options.clone().with_max_transfers(Some(transfers));
+            let route = match self.route_from(
+                start_time,
+                start_station,
+                end_station,
+                &limited,
+                None,
+                budget,
+            ) {
+                Some(route) => route,
+                None => continue,
+            };
+            if result
+                .last()
+                .is_some_and(|prev| arrival(prev) <= arrival(&route))
+            {
+                continue;
+            }
+            let done = arrival(&route) == best_arrival;
+            result.push(route);
+            if done {
+                break;
+            }
+        }
+        result
+    }
+
+    pub(crate) fn alternatives(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+        count: usize,
+        budget: Option<&Budget>,
+    ) -> Vec<Route<'a>> {
+        if start_station == end_station {
+            return vec![Route::new().with_fares(self.data.fares())];
+        }
+        let origin = match self.first_node_after(start_station, start_time) {
+            Some(origin) => origin,
+            None => return Vec::new(),
+        };
+        let max_parts = self.options.max_transfers().map(|m| m + 1);
+        // Routes riding the same trains are the same itinerary to passengers, wherever they switch between them
+        let mut seen: HashSet<Vec<&'a str>> = HashSet::new();
+        self.graph
+            .shortest_paths(
+                &origin,
+                |s| s.station == end_station && s.train.is_none(),
+                budget,
+            )
+            .take(count.saturating_mul(PATHS_PER_ALTERNATIVE))
+            .map(|path| build_route(self.data, path))
+            .filter(|route| max_parts.is_none_or(|max| route.parts.len() <= max))
+            .filter(|route| {
+                seen.insert(route.parts.iter().map(|p| p.train.id().as_str()).collect())
+            })
+            .take(count)
+            .collect()
+    }
+
+    pub(crate) fn arriving_by(
+        &self,
+        start_station: &'a Station,
+        end_time: NaiveDateTime,
+        end_station: &'a Station,
+        budget: Option<&Budget>,
+    ) -> Option<Route<'a>> {
+        if start_station == end_station {
+            return Some(Route::new().with_fares(self.data.fares()));
+        }
+        // Unboarding at the destination leads to a station node only after the minimum transfer time,
+        // so the target is shifted by the same amount to accept trains arriving exactly at end_time
+        let transfer_time = transfer_time(self.data, end_station.id(), &self.options);
+        let target = self.last_node_before(end_station, end_time + transfer_time)?;
+        let predicate = |s: &Singularity| s.station == start_station && s.train.is_none();
+        let (_, path) = match self.options.max_transfers() {
+            Some(max_transfers) => self.graph.find_shortest_path_reverse_limited(
+                &target,
+                predicate,
+                |a| matches!(a, Action::Board(_, _)) as u32,
+                max_transfers as u32 + 1,
+                budget,
+            ),
+            None => self
+                .graph
+                .find_shortest_path_reverse(&target, predicate, budget),
+        }?;
+        Some(build_route(self.data, path))
+    }
+
+    pub(crate) fn arrivals(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        budget: Option<&Budget>,
+    ) -> Vec<Arrival<'a>> {
+        let origin = match self.first_node_after(start_station, start_time) {
+            Some(origin) => origin,
+            None => return Vec::new(),
+        };
+        let tree = match self.graph.shortest_path_tree(&origin, budget) {
+            Some(tree) => tree,
+            None => return Vec::new(),
+        };
+        // Routes to a station end at its earliest reached node, which can only be reached by leaving a train there
+        let mut earliest: HashMap<StationId, Singularity<'a>> = HashMap::new();
+        for node in tree.reached().filter(|n| n.train.is_none()) {
+            let entry = earliest.entry(node.station.id()).or_insert(*node);
+            if node.time < entry.time {
+                *entry = *node;
+            }
+        }
+        let mut result: Vec<Arrival<'a>> = earliest
+            .into_values()
+            .filter_map(|node| {
+                if node.station == start_station {
+                    let route = Route::new().with_fares(self.data.fares());
+                    return Some(Arrival::new(start_station, start_time, route));
+                }
+                let route = build_route(self.data, tree.path_to(&node)?);
+                let time = route.parts().last()?.end().arrival();
+                Some(Arrival::new(node.station, time, route))
+            })
+            .collect();
+        result.sort_by(|a, b| {
+            a.time()
+                .cmp(&b.time())
+                .then_with(|| a.station().id().cmp(&b.station().id()))
+        });
+        result
+    }
+
+    pub(crate) fn routes_to_many(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_stations: &[&'a Station],
+        budget: Option<&Budget>,
+    ) -> Vec<Option<Route<'a>>> {
+        // Limiting train switches needs a search per destination, which still share the graph
+        if self.options.max_transfers().is_some() {
+            return end_stations
+                .iter()
+                .map(|&end| {
+                    self.route_from(start_time, start_station, end, &self.options, None, budget)
+                })
+                .collect();
+        }
+        let wanted: HashSet<StationId> = end_stations
+            .iter()
+            .filter(|&&s| s != start_station)
+            .map(|s| s.id())
+            .collect();
+        // The first node settled at each destination ends the best route to it, as in route_from
+        let found: RefCell<HashMap<StationId, Singularity<'a>>> = RefCell::new(HashMap::new());
+        let tree = self
+            .first_node_after(start_station, start_time)
+            .filter(|_| !wanted.is_empty())
+            .and_then(|origin| {
+                self.graph.shortest_path_tree_until(
+                    &origin,
+                    |n| {
+                        if n.train.is_some() || !wanted.contains(&n.station.id()) {
+                            return false;
+                        }
+                        let mut found = found.borrow_mut();
+                        found.entry(n.station.id()).or_insert(*n);
+                        found.len() == wanted.len()
+                    },
+                    budget,
+                )
+            });
+        let found = found.into_inner();
+        end_stations
+            .iter()
+            .map(|&end| {
+                if end == start_station {
+                    return Some(Route::new().with_fares(self.data.fares()));
+                }
+                let path = tree.as_ref()?.path_to(found.get(&end.id())?)?;
+                Some(build_route(self.data, path))
+            })
+            .collect()
+    }
+
+    /// Finds the single best route to each of the destination stations, as get_best_routes_to_many does
+    pub fn best_routes_to_many(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_stations: &[&'a Station],
+    ) -> Vec<Option<Route<'a>>> {
+        let budget = Budget::new(&self.options, None);
+        self.routes_to_many(start_time, start_station, end_stations, Some(&budget))
+    }
+
+    /// Finds the earliest arrival at every station reachable from the source station, as get_earliest_arrivals does
+    pub fn earliest_arrivals(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+    ) -> Vec<Arrival<'a>> {
+        let budget = Budget::new(&self.options, None);
+        self.arrivals(start_time, start_station, Some(&budget))
+    }
+
+    /// Finds the single best route leaving the source station at or after start_time, as get_best_single_route does
+    pub fn best_route(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+    ) -> Option<Route<'a>> {
+        let bounds = self.bounds(end_station);
+        let budget = Budget::new(&self.options, None);
+        self.route_from(
+            start_time,
+            start_station,
+            end_station,
+            &self.options,
+            bounds.as_ref(),
+            Some(&budget),
+        )
+    }
+
+    /// Finds a route arriving no later than the best route but leaving as late as possible, as
+    /// get_latest_good_single_route does
+    pub fn latest_good_route(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+    ) -> Option<Route<'a>> {
+        let bounds = self.bounds(end_station);
+        let budget = Budget::new(&self.options, None);
+        latest_good_route_from(start_time, |time| {
+            self.route_from(
+                time,
+                start_station,
+                end_station,
+                &self.options,
+                bounds.as_ref(),
+                Some(&budget),
+            )
+        })
+    }
+
+    /// Finds all good routes leaving the source station at or after start_time, as get_multiple_routes does
+    pub fn multiple_routes(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+    ) -> Vec<Route<'a>> {
+        let bounds = self.bounds(end_station);
+        let budget = Budget::new(&self.options, None);
+        multiple_routes_from(start_time, |time| {
+            self.route_from(
+                time,
+                start_station,
+                end_station,
+                &self.options,
+                bounds.as_ref(),
+                Some(&budget),
+            )
+        })
+    }
+
+    /// Finds the routes trading off arrival time against train switches, as get_pareto_routes does
+    pub fn pareto_routes(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+    ) -> Vec<Route<'a>> {
+        let budget = Budget::new(&self.options, None);
+        self.pareto(start_time, start_station, end_station, Some(&budget))
+    }
+
+    /// Finds up to count distinct routes leaving the source station at or after start_time, best first, as
+    /// get_alternative_routes does
+    pub fn alternative_routes(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &'a Station,
+        end_station: &'a Station,
+        count: usize,
+    ) -> Vec<Route<'a>> {
+        let budget = Budget::new(&self.options, None);
+        self.alternatives(start_time, start_station, end_station, count, Some(&budget))
+    }
+
+    /// Finds the route leaving the source station as late as possible while arriving by end_time, as
+    /// get_best_route_arriving_by does
+    pub fn route_arriving_by(
+        &self,
+        start_station: &'a Station,
+        end_time: NaiveDateTime,
+        end_station: &'a Station,
+    ) -> Option<Route<'a>> {
+        let budget = Budget::new(&self.options, None);
+        self.arriving_by(start_station, end_time, end_station, Some(&budget))
+    }
+}
@@ -0,0 +1,203 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Rav-Kav fare estimation based on the Israeli fare zone model.
+//!
+//! Each station belongs to a fare zone, and the price of a journey is determined by the number of distinct zones it touches.
+//! This allows estimating a fare even when the GTFS feed does not contain fare files.
+
+use crate::gtfs::reader::GtfsReader;
+use crate::{GtfsError, Route, StationId, JSON};
+use jzon::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io::prelude::*;
+
+/// A unique identifier type for fare zones
+pub type ZoneId = u32;
+
+/// Represents a ticket price, in agorot
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Fare {
+    agorot: u32,
+}
+
+impl Fare {
+    /// Create a new Fare object from a price in agorot
+    pub fn from_agorot(agorot: u32) -> Self {
+        Fare { agorot }
+    }
+
+    /// Gets the price in agorot
+    pub fn agorot(&self) -> u32 {
+        self.agorot
+    }
+}
+
+impl fmt::Display for Fare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "₪{}.{:02}", self.agorot / 100, self.agorot % 100)
+    }
+}
+
+impl JSON for Fare {
+    fn to_json(&self) -> JsonValue {
+        object! {
+            agorot: self.agorot,
+            display: self.to_string()
+        }
+    }
+}
+
+/// A table assigning each station to a fare zone
+#[derive(Default, Serialize, Deserialize)]
+pub struct ZoneTable {
+    zones: HashMap<StationId, ZoneId>,
+}
+
+impl ZoneTable {
+    /// Create a new, empty ZoneTable object
+    pub fn new() -> Self {
+        ZoneTable {
+            zones: HashMap::new(),
+        }
+    }
+
+    /// Create a new ZoneTable object from (station, zone) pairs
+    pub fn from_pairs(pairs: Vec<(StationId, ZoneId)>) -> Self {
+        ZoneTable {
+            zones: pairs.into_iter().collect(),
+        }
+    }
+
+    /// Loads a zone table from a CSV file with `station_id` and `zone_id` columns, where file is the name by which
+    /// errors refer to it
+    pub fn from_csv<R: Read>(file: &str, reader: R) -> Result<Self, GtfsError> {
+        let mut reader = GtfsReader::new(file, reader);
+        let [station_id, zone_id] = reader.columns(["station_id", "zone_id"])?;
+        let mut result = Self::new();
+        for record in reader.records() {
+            let record = record?;
+            result
+                .zones
+                .insert(record.parse(station_id)?, record.parse(zone_id)?);
+        }
+        Ok(result)
+    }
+
+    /// Assigns a station to a zone, replacing any previous assignment
+    pub fn insert(&mut self, station: StationId, zone: ZoneId) {
+        self.zones.insert(station, zone);
+    }
+
+    /// Gets the zone of the given station
+    pub fn zone(&self, station: StationId) -> Option<ZoneId> {
+        self.zones.get(&station).copied()
+    }
+}
+
+/// A table of prices by the number of zones a journey touches
+///
+/// Journeys touching more zones than the table covers are charged the price of the last entry.
+#[derive(Default, Serialize, Deserialize)]
+pub struct FareTable {
+    prices: Vec<Fare>,
+}
+
+impl FareTable {
+    /// Create a new FareTable object, where the n-th price is the price of a journey touching n+1 zones
+    pub fn from_prices(prices: Vec<Fare>) -> Self {
+        FareTable { prices }
+    }
+
+    /// Loads a fare table from a CSV file with `zones` and `price` columns, with prices given in agorot, where file is
+    /// the name by which errors refer to it
+    pub fn from_csv<R: Read>(file: &str, reader: R) -> Result<Self, GtfsError> {
+        let mut reader = GtfsReader::new(file, reader);
+        let [zones, price] = reader.columns(["zones", "price"])?;
+        let mut prices = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let zone_count: usize = record.parse(zones)?;
+            if zone_count == 0 {
+                return Err(record.invalid(zones, record.get(zones)?));
+            }
+            prices.push((zone_count, Fare::from_agorot(record.parse(price)?)));
+        }
+        prices.sort_unstable_by_key(|(zones, _)| *zones);
+        if prices
+            .iter()
+            .enumerate()
+            .any(|(i, (zones, _))| *zones != i + 1)
+        {
+            return Err(reader.error("fare table must cover every zone count from 1".to_owned()));
+        }
+        Ok(Self::from_prices(
+            prices.into_iter().map(|(_, price)| price).collect(),
+        ))
+    }
+
+    /// Gets the price of a journey touching the given number of zones
+    pub fn price(&self, zones: usize) -> Option<Fare> {
+        if zones == 0 {
+            return Some(Fare::from_agorot(0));
+        }
+        self.prices
+            .get(zones - 1)
+            .or_else(|| self.prices.last())
+            .copied()
+    }
+}
+
+/// Estimates Rav-Kav fares for routes using a zone table and a fare table
+#[derive(Default, Serialize, Deserialize)]
+pub struct PricingModel {
+    zones: ZoneTable,
+    fares: FareTable,
+}
+
+impl PricingModel {
+    /// Create a new PricingModel object
+    pub fn new(zones: ZoneTable, fares: FareTable) -> Self {
+        PricingModel { zones, fares }
+    }
+
+    /// The zone table used by this model
+    pub fn zones(&self) -> &ZoneTable {
+        &self.zones
+    }
+
+    /// The fare table used by this model
+    pub fn fares(&self) -> &FareTable {
+        &self.fares
+    }
+
+    /// Estimates the fare of the given route.
+    ///
+    /// The journey is charged according to the number of distinct zones of the stations at which trains are boarded or unboarded.
+    /// Returns None if one of these stations has no zone assigned, or if the fare table is empty.
+    ///
+    /// Examples:
+    /// ```
+    /// use harail::pricing::{Fare, FareTable, PricingModel, ZoneTable};
+    /// use harail::Route;
+    ///
+    /// let model = PricingModel::new(
+    ///     ZoneTable::new(),
+    ///     FareTable::from_prices(vec![Fare::from_agorot(550)]),
+    /// );
+    /// assert_eq!(Some(Fare::from_agorot(0)), model.price(&Route::new()));
+    /// ```
+    pub fn price(&self, route: &Route) -> Option<Fare> {
+        let mut zones = BTreeSet::new();
+        for part in route.parts() {
+            zones.insert(self.zones.zone(part.start().station().id())?);
+            zones.insert(self.zones.zone(part.end().station().id())?);
+        }
+        self.fares.price(zones.len())
+    }
+}
@@ -0,0 +1,112 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::budget::Budget;
+use crate::{RailroadData, Route, RoutingOptions, SearchKind, Station};
+use chrono::NaiveDateTime;
+
+/// A route search, made of the stations to route between, the time window to search in, the kind of search and the
+/// routing options constraining it. Queries are run by find_routes.
+///
+/// Example:
+/// ```
+/// use chrono::NaiveDate;
+/// use harail::{HaDuration, RailroadData, RouteQuery, RoutingOptions, SearchKind, Station, StopSchedule, Train};
+///
+/// let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+/// let stations = vec![Station::new(100, "Tel Aviv"), Station::new(200, "Haifa")];
+/// let trains = vec![Train::from_stops_date("1", vec![
+///     StopSchedule::new(100, HaDuration::from_hms(10, 0, 0), None),
+///     StopSchedule::new(200, HaDuration::from_hms(11, 0, 0), None),
+/// ], date)];
+/// let data = RailroadData::from_stations_trains(stations, trains);
+///
+/// let (start, end) = (date.and_hms_opt(9, 0, 0).unwrap(), date.and_hms_opt(12, 0, 0).unwrap());
+/// let query = RouteQuery::new(data.station(100).unwrap(), data.station(200).unwrap(), start, end)
+///     .with_search(SearchKind::Multiple)
+///     .with_options(RoutingOptions::new().with_max_transfers(Some(0)));
+/// let routes = harail::find_routes(&data, &query).unwrap();
+/// assert_eq!(1, routes.len());
+/// ```
+#[derive(Clone)]
+pub struct RouteQuery<'a> {
+    start_station: &'a Station,
+    end_station: &'a Station,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    search: SearchKind,
+    options: RoutingOptions,
+}
+
+impl<'a> RouteQuery<'a> {
+    /// A search for the single best route from the source to the destination station, leaving at or after start_time
+    /// and arriving by end_time, which the routing options don't constrain in any way
+    pub fn new(
+        start_station: &'a Station,
+        end_station: &'a Station,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+    ) -> Self {
+        RouteQuery {
+            start_station,
+            end_station,
+            start_time,
+            end_time,
+            search: SearchKind::Best,
+            options: RoutingOptions::default(),
+        }
+    }
+
+    /// Sets the kind of search to perform
+    pub fn with_search(mut self, search: SearchKind) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// Sets the routing options constraining the search
+    pub fn with_options(mut self, options: RoutingOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn start_station(&self) -> &'a Station {
+        self.start_station
+    }
+
+    pub fn end_station(&self) -> &'a Station {
+        self.end_station
+    }
+
+    pub fn start_time(&self) -> NaiveDateTime {
+        self.start_time
+    }
+
+    pub fn end_time(&self) -> NaiveDateTime {
+        self.end_time
+    }
+
+    pub fn search(&self) -> SearchKind {
+        self.search
+    }
+
+    pub fn options(&self) -> &RoutingOptions {
+        &self.options
+    }
+
+    /// Runs the search, returning the routes found before it stopped if it ran out of budget
+    pub(crate) fn routes(&self, data: &'a RailroadData, budget: Option<&Budget>) -> Vec<Route<'a>> {
+        crate::search_routes(
+            data,
+            self.search,
+            self.start_time,
+            self.start_station,
+            self.end_time,
+            self.end_station,
+            &self.options,
+            budget,
+        )
+    }
+}
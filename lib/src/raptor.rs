@@ -0,0 +1,192 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::budget::Budget;
+use crate::{RailroadData, Route, RoutePart, RoutingOptions, Station, StationId, Stop, Train};
+use chrono::{Duration, NaiveDateTime};
+use std::collections::{HashMap, HashSet};
+use tracing::instrument;
+
+/// A single run of a train on a specific date, restricted to the search window
+struct Trip<'a> {
+    train: &'a Train,
+    stops: Vec<Stop<'a>>,
+}
+
+/// The trip taken to reach a station in some round, as (trip, boarding stop index, alighting stop index)
+type Parent = (usize, usize, usize);
+
+/// Round-based public transit routing (RAPTOR), operating directly on the train schedules.
+///
+/// Round k finds the earliest arrival at every station using at most k trains, so the search
+/// never needs to materialize the time-expanded graph.
+pub struct Timetable<'a> {
+    data: &'a RailroadData,
+    /// Ordered by the ID of their train, then by departure, so that of trips arriving at the same time, rounds keep the
+    /// one whose train ID is least
+    trips: Vec<Trip<'a>>,
+    trips_by_station: HashMap<StationId, Vec<usize>>,
+    options: RoutingOptions,
+}
+
+impl<'a> Timetable<'a> {
+    #[instrument(level = "debug", skip_all, fields(%start_time, %end_time))]
+    pub fn from_data(
+        data: &'a RailroadData,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        options: &RoutingOptions,
+        budget: Option<&Budget>,
+    ) -> Self {
+        let mut trips = Vec::new();
+        for train in data.trains() {
+            if budget.is_some_and(|b| b.stopped()) {
+                break;
+            }
+            if !crate::uses_train(train, options) {
+                continue;
+            }
+            for date in train.service_dates_between(start_time, end_time) {
+                let stops: Vec<Stop> = match crate::train_run_stops(data, train, date, options) {
+                    Some(stops) => stops
+                        .into_iter()
+                        .filter(|stop| stop.arrival <= end_time && stop.departure >= start_time)
+                        .collect(),
+                    None => continue,
+                };
+                if stops.len() < 2 {
+                    continue;
+                }
+                trips.push(Trip { train, stops });
+            }
+        }
+        trips.sort_by(|a: &Trip, b: &Trip| {
+            a.train
+                .id()
+                .cmp(b.train.id())
+                .then(a.stops[0].departure.cmp(&b.stops[0].departure))
+        });
+        let mut trips_by_station: HashMap<StationId, Vec<usize>> = HashMap::new();
+        for (index, trip) in trips.iter().enumerate() {
+            for station in trip
+                .stops
+                .iter()
+                .map(|s| s.station.id())
+                .collect::<HashSet<_>>()
+            {
+                trips_by_station.entry(station).or_default().push(index);
+            }
+        }
+        Timetable {
+            data,
+            trips,
+            trips_by_station,
+            options: options.clone(),
+        }
+    }
+
+    fn transfer_time(&self, station: StationId) -> Duration {
+        crate::transfer_time(self.data, station, &self.options)
+    }
+
+    /// Finds the route with the earliest arrival time, preferring fewer train switches among equally fast routes
+    pub fn earliest_route(
+        &self,
+        start_time: NaiveDateTime,
+        start_station: &Station,
+        end_station: &Station,
+        budget: Option<&Budget>,
+    ) -> Option<Route<'a>> {
+        let origin = start_station.id();
+        let target = end_station.id();
+        if origin == target {
+            return Some(Route::new().with_fares(self.data.fares()));
+        }
+        let max_rounds = self.options.max_transfers().map_or(usize::MAX, |x| x + 1);
+
+        // arrivals[k] holds the earliest arrival at each station using at most k trains
+        let mut arrivals: Vec<HashMap<StationId, NaiveDateTime>> =
+            vec![HashMap::from([(origin, start_time)])];
+        let mut parents: Vec<HashMap<StationId, Parent>> = vec![HashMap::new()];
+        let mut marked = HashSet::from([origin]);
+        let mut best_round = None;
+        let mut round = 0;
+        while !marked.is_empty() && round < max_rounds {
+            if budget.is_some_and(|b| b.expand(marked.len())) {
+                return None;
+            }
+            round += 1;
+            let prev = &arrivals[round - 1];
+            let mut curr = prev.clone();
+            let mut curr_parents = HashMap::new();
+            let mut improved = HashSet::new();
+
+            let mut trip_indices: Vec<usize> = marked
+                .iter()
+                .filter_map(|s| self.trips_by_station.get(s))
+                .flatten()
+                .copied()
+                .collect();
+            trip_indices.sort_unstable();
+            trip_indices.dedup();
+            for trip_index in trip_indices {
+                let trip = &self.trips[trip_index];
+                let mut boarded = None;
+                for (i, stop) in trip.stops.iter().enumerate() {
+                    let station = stop.station.id();
+                    if !crate::can_board_at(stop.station, &self.options) {
+                        continue;
+                    }
+                    if let Some(board) = boarded {
+                        let target_arrival = curr.get(&target).copied();
+                        let improves = curr.get(&station).is_none_or(|&t| stop.arrival < t)
+                            && target_arrival.is_none_or(|t| stop.arrival < t);
+                        if improves {
+                            curr.insert(station, stop.arrival);
+                            curr_parents.insert(station, (trip_index, board, i));
+                            improved.insert(station);
+                        }
+                    } else if let Some(&arrival) = prev.get(&station) {
+                        // Only the origin can be left without changing trains
+                        let ready = if round == 1 {
+                            arrival
+                        } else {
+                            arrival + self.transfer_time(station)
+                        };
+                        if ready <= stop.departure {
+                            boarded = Some(i);
+                        }
+                    }
+                }
+            }
+            if improved.contains(&target) {
+                best_round = Some(round);
+            }
+            arrivals.push(curr);
+            parents.push(curr_parents);
+            marked = improved;
+        }
+
+        // Walk back through the rounds to rebuild the journey
+        let mut round = best_round?;
+        let mut station = target;
+        let mut parts = Vec::new();
+        while station != origin {
+            // Stations without a parent in this round carried their label over from the previous round
+            if let Some(&(trip_index, board, alight)) = parents[round].get(&station) {
+                let trip = &self.trips[trip_index];
+                parts.push(
+                    RoutePart::new(trip.train, trip.stops[board], trip.stops[alight])
+                        .with_intermediate_stops(trip.stops[board + 1..alight].to_vec()),
+                );
+                station = trip.stops[board].station.id();
+            }
+            round -= 1;
+        }
+        parts.reverse();
+        Some(Route::from_parts(parts).with_fares(self.data.fares()))
+    }
+}
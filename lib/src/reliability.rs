@@ -0,0 +1,214 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Route reliability estimation from the delays observed in real-time updates over time.
+//!
+//! Each train switch is made when the delay of the arriving train, less the delay of the departing train, fits in the
+//! time to spare beyond the station's minimum transfer time. Delays of different trains are taken to be independent,
+//! so the chance of a route making all of its train switches is the product of the chances of each.
+
+use crate::gtfs_rt::RealtimeUpdates;
+use crate::{HaError, RailroadData, Route, StationId, Stop, TrainId, JSON};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use jzon::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{Read, Write};
+
+/// The chance of a route making all of its train switches
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Reliability {
+    /// The chance in hundredths of a percent, so that routes can be compared and hashed
+    basis_points: u16,
+}
+
+impl Reliability {
+    fn from_probability(probability: f64) -> Self {
+        Reliability {
+            basis_points: (probability.clamp(0.0, 1.0) * 10000.0).round() as u16,
+        }
+    }
+
+    /// The chance of making all train switches, from 0 to 1
+    pub fn probability(&self) -> f64 {
+        self.basis_points as f64 / 10000.0
+    }
+}
+
+impl fmt::Display for Reliability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.basis_points / 100)
+    }
+}
+
+impl JSON for Reliability {
+    fn to_json(&self) -> JsonValue {
+        self.probability().into()
+    }
+}
+
+/// A train switch of a route, as the arriving and departing trains and their times at the station
+pub(crate) struct Transfer<'r> {
+    pub arriving: &'r str,
+    pub departing: &'r str,
+    pub station: StationId,
+    pub arrival: NaiveDateTime,
+    pub departure: NaiveDateTime,
+}
+
+/// The arrival and departure delays in seconds of a train at each station, by the service day of the run
+type StationDelays = HashMap<StationId, BTreeMap<NaiveDate, (i64, i64)>>;
+
+/// A store of the delays observed in real-time updates, from which the reliability of routes is estimated.
+///
+/// Examples:
+/// ```
+/// use chrono::{Duration, NaiveDate};
+/// use harail::DelayHistory;
+///
+/// let mut history = DelayHistory::new();
+/// let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+/// history.record("1", 200, date, Duration::minutes(5), Duration::minutes(5));
+/// assert!(!history.is_empty());
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DelayHistory {
+    /// The delays of each train at each station
+    delays: HashMap<TrainId, StationDelays>,
+}
+
+impl DelayHistory {
+    /// Create a new DelayHistory object, without any observations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether no delays were observed yet
+    pub fn is_empty(&self) -> bool {
+        self.delays.is_empty()
+    }
+
+    /// Records the delays of a train at a station, on the run of the given service day. Recording the same run again
+    /// replaces its earlier observation, so that repeated real-time updates of a run are counted once.
+    pub fn record(
+        &mut self,
+        train: &str,
+        station: StationId,
+        date: NaiveDate,
+        arrival_delay: Duration,
+        departure_delay: Duration,
+    ) {
+        self.delays
+            .entry(train.to_owned())
+            .or_default()
+            .entry(station)
+            .or_default()
+            .insert(
+                date,
+                (arrival_delay.num_seconds(), departure_delay.num_seconds()),
+            );
+    }
+
+    /// Records the delays of every train run which the real-time updates changed. Cancelled runs are skipped, as are
+    /// runs of trains missing from the database.
+    pub fn record_updates(&mut self, data: &RailroadData, updates: &RealtimeUpdates) {
+        for (train, date) in updates.runs() {
+            let train = match data.train(train) {
+                Some(train) => train,
+                None => continue,
+            };
+            let stops = match updates.stops(data, train, date) {
+                Some(stops) => stops,
+                None => continue,
+            };
+            for scheduled in train.stops() {
+                let scheduled = Stop::from_stop_schedule(data, scheduled, date);
+                // Skipped stops are left out of the updated stops
+                if let Some(stop) = stops
+                    .iter()
+                    .find(|s| s.station.id() == scheduled.station.id())
+                {
+                    self.record(
+                        train.id(),
+                        stop.station.id(),
+                        date,
+                        stop.arrival - scheduled.arrival,
+                        stop.departure - scheduled.departure,
+                    );
+                }
+            }
+        }
+    }
+
+    /// The observed arrival or departure delays of a train at a station, in seconds, or a single delay of zero if
+    /// there are no observations
+    fn observed(&self, train: &str, station: StationId, arrival: bool) -> Vec<i64> {
+        let observed: Vec<i64> = self
+            .delays
+            .get(train)
+            .and_then(|stations| stations.get(&station))
+            .into_iter()
+            .flat_map(|runs| runs.values())
+            .map(|&(a, d)| if arrival { a } else { d })
+            .collect();
+        if observed.is_empty() {
+            vec![0]
+        } else {
+            observed
+        }
+    }
+
+    /// The chance of making the given train switches, each leaving the given time to spare beyond the station's
+    /// minimum transfer time
+    pub(crate) fn reliability<'r>(
+        &self,
+        data: &RailroadData,
+        transfers: impl Iterator<Item = Transfer<'r>>,
+    ) -> Reliability {
+        let mut probability = 1.0;
+        for transfer in transfers {
+            let min_transfer = data
+                .min_transfer_time(transfer.station)
+                .map_or_else(Duration::zero, |d| d.to_chrono());
+            let spare = (transfer.departure - transfer.arrival - min_transfer).num_seconds();
+            let arrivals = self.observed(transfer.arriving, transfer.station, true);
+            let mut departures = self.observed(transfer.departing, transfer.station, false);
+            departures.sort_unstable();
+            // The switch is made when the arrival delay, less the departure delay, is at most the time to spare
+            let made: usize = arrivals
+                .iter()
+                .map(|a| departures.len() - departures.partition_point(|d| a - d > spare))
+                .sum();
+            probability *= made as f64 / (arrivals.len() * departures.len()) as f64;
+        }
+        Reliability::from_probability(probability)
+    }
+
+    /// Loads a history saved by save
+    pub fn load<R: Read>(reader: R) -> Result<Self, HaError> {
+        bincode::deserialize_from(reader).map_err(|e| HaError::DatabaseError(e.to_string()))
+    }
+
+    /// Saves the history, to be loaded again by load
+    pub fn save<W: Write>(&self, writer: W) -> Result<(), HaError> {
+        bincode::serialize_into(writer, self).map_err(|e| HaError::DatabaseError(e.to_string()))
+    }
+}
+
+/// Rates each of the routes by its chance of making all of its train switches, according to the delays in the history
+pub fn rate_reliability(routes: &mut [Route], data: &RailroadData, history: &DelayHistory) {
+    for route in routes {
+        let transfers = route.parts.windows(2).map(|w| Transfer {
+            arriving: w[0].train.id(),
+            departing: w[1].train.id(),
+            station: w[0].end.station.id(),
+            arrival: w[0].end.arrival,
+            departure: w[1].start.departure,
+        });
+        route.reliability = Some(history.reliability(data, transfers));
+    }
+}
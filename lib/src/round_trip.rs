@@ -0,0 +1,138 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{
+    observe_build, Engine, PreparedGraph, RailroadData, Route, RoutingOptions, Station, Zoned,
+    FEED_TIMEZONE, JSON,
+};
+use chrono::{Duration, NaiveDateTime};
+use chrono_tz::Tz;
+use jzon::JsonValue;
+use std::fmt;
+
+/// How long a round trip stays at its destination
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stay {
+    /// Stay for at least the given duration, and be back home as early as possible after
+    AtLeast(Duration),
+    /// Stay for as long as possible, and be back home by the end time
+    Longest,
+}
+
+/// An outbound journey to a destination together with the journey back home
+pub struct RoundTrip<'a> {
+    outbound: Route<'a>,
+    inbound: Route<'a>,
+}
+
+impl<'a> RoundTrip<'a> {
+    /// The route from home to the destination
+    pub fn outbound(&self) -> &Route<'a> {
+        &self.outbound
+    }
+
+    /// The route from the destination back home
+    pub fn inbound(&self) -> &Route<'a> {
+        &self.inbound
+    }
+
+    /// The time spent at the destination, from the arrival of the outbound route until the departure of the inbound
+    pub fn stay(&self) -> Duration {
+        departure(&self.inbound) - arrival(&self.outbound)
+    }
+}
+
+impl Zoned for RoundTrip<'_> {
+    fn to_json_in(&self, timezone: Tz) -> JsonValue {
+        object! {
+            outbound: self.outbound.to_json_in(timezone),
+            inbound: self.inbound.to_json_in(timezone),
+            stay_minutes: self.stay().num_minutes(),
+        }
+    }
+
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result {
+        let stay = self.stay();
+        writeln!(f, "Outbound:")?;
+        write!(f, "{}", self.outbound.in_timezone(timezone))?;
+        writeln!(
+            f,
+            "Stay: {}:{:02}",
+            stay.num_hours(),
+            stay.num_minutes() % 60
+        )?;
+        writeln!(f, "Return:")?;
+        write!(f, "{}", self.inbound.in_timezone(timezone))
+    }
+}
+
+impl JSON for RoundTrip<'_> {
+    fn to_json(&self) -> JsonValue {
+        self.to_json_in(FEED_TIMEZONE)
+    }
+}
+
+impl fmt::Display for RoundTrip<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_in(f, FEED_TIMEZONE)
+    }
+}
+
+fn departure(route: &Route) -> NaiveDateTime {
+    route.parts().next().unwrap().start().departure()
+}
+
+fn arrival(route: &Route) -> NaiveDateTime {
+    route.parts().last().unwrap().end().arrival()
+}
+
+/// Plans a round trip from the home station to the destination station and back, leaving home at or after start_time
+/// and returning by end_time.
+///
+/// Both legs are optimized together on a single graph. With Stay::AtLeast, the trip is back home as early as possible
+/// while staying at the destination for at least the given duration. With Stay::Longest, the stay at the destination is
+/// as long as possible. Either way, both legs then leave as late as possible without changing the trip's arrival
+/// times, so that no time is wasted waiting at stations.
+///
+/// Returns None if no such trip exists, or if the home and destination stations are the same. The maximum number of
+/// train switches of the routing options is only honored when looking for the earliest arrivals, as searches for the
+/// latest departures ignore it.
+pub fn get_round_trip<'a>(
+    data: &'a RailroadData,
+    start_time: NaiveDateTime,
+    home_station: &'a Station,
+    destination: &'a Station,
+    end_time: NaiveDateTime,
+    stay: Stay,
+    options: &RoutingOptions,
+) -> Option<RoundTrip<'a>> {
+    if home_station == destination {
+        return None;
+    }
+    let graph = observe_build(options, Engine::TimeExpanded, || {
+        PreparedGraph::new(data, start_time, end_time, options)
+    });
+    let earliest = graph.best_route(start_time, home_station, destination)?;
+    let (outbound_by, inbound) = match stay {
+        Stay::AtLeast(duration) => {
+            let earliest_back =
+                graph.best_route(arrival(&earliest) + duration, destination, home_station)?;
+            // Leaving the destination later may still make it back home as early
+            let inbound =
+                graph.route_arriving_by(destination, arrival(&earliest_back), home_station)?;
+            (departure(&inbound) - duration, inbound)
+        }
+        Stay::Longest => {
+            let inbound = graph.route_arriving_by(destination, end_time, home_station)?;
+            if departure(&inbound) < arrival(&earliest) {
+                return None;
+            }
+            (arrival(&earliest), inbound)
+        }
+    };
+    let outbound = graph.route_arriving_by(home_station, outbound_by, destination)?;
+    Some(RoundTrip { outbound, inbound })
+}
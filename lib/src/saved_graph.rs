@@ -0,0 +1,282 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The file format of prepared graphs, which refer to the stations and trains of the database they were prepared from
+//! by their identifiers.
+
+use crate::{
+    Action, HaError, Mode, RailroadData, RailroadGraph, RoutingOptions, Singularity, StationId,
+    Stop, Train, TrainId,
+};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+/// The first bytes of every HaRail graph file
+const MAGIC: &[u8; 8] = b"HARAILGR";
+
+/// The version of the graph file format written by this version of HaRail.
+///
+/// Graphs are quick to prepare again from the database, so files of other versions are rejected rather than converted.
+const GRAPH_VERSION: u32 = 1;
+
+fn io_error(e: impl ToString) -> HaError {
+    HaError::DatabaseError(e.to_string())
+}
+
+/// What a graph was prepared from, to tell whether a database is the same one
+#[derive(Serialize, Deserialize, PartialEq)]
+struct DatabaseIdentity {
+    built: Option<NaiveDateTime>,
+    stations: usize,
+    trains: usize,
+}
+
+impl DatabaseIdentity {
+    fn of(data: &RailroadData) -> Self {
+        DatabaseIdentity {
+            built: data.build_time(),
+            stations: data.stations().count(),
+            trains: data.trains().count(),
+        }
+    }
+}
+
+/// The routing options which shape the graph, as opposed to the ones which only affect searches on it
+#[derive(Serialize, Deserialize, PartialEq)]
+struct GraphOptions {
+    board: u64,
+    unboard: u64,
+    ride: u64,
+    tight_transfer: u64,
+    min_transfer_time: Option<u64>,
+    transfer_slack: Option<u64>,
+    avoided_stations: HashSet<StationId>,
+    avoided_trains: HashSet<TrainId>,
+    modes: Option<HashSet<Mode>>,
+    wheelchair: bool,
+    bikes: bool,
+}
+
+impl GraphOptions {
+    fn of(options: &RoutingOptions) -> Self {
+        let weights = options.weights();
+        GraphOptions {
+            board: weights.board().as_secs(),
+            unboard: weights.unboard().as_secs(),
+            ride: weights.ride().as_secs(),
+            tight_transfer: weights.tight_transfer().as_secs(),
+            min_transfer_time: options.min_transfer_time().map(|d| d.as_secs()),
+            transfer_slack: options.transfer_slack().map(|d| d.as_secs()),
+            avoided_stations: options.avoided_stations().clone(),
+            avoided_trains: options.avoided_trains().clone(),
+            modes: options.modes().cloned(),
+            wheelchair: options.wheelchair(),
+            bikes: options.bikes(),
+        }
+    }
+}
+
+/// A node, with its train given by its index in the file's list of trains
+#[derive(Serialize, Deserialize)]
+struct SavedNode {
+    station: StationId,
+    time: NaiveDateTime,
+    train: Option<u32>,
+}
+
+/// A stop of a train, whose platform is taken from the train's schedule when loaded
+#[derive(Serialize, Deserialize)]
+struct SavedStop {
+    station: StationId,
+    arrival: NaiveDateTime,
+    departure: NaiveDateTime,
+    delayed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SavedAction {
+    Wait(i64),
+    TrainWaits(u32, SavedStop),
+    Ride(u32, SavedStop, SavedStop, i64),
+    Board(u32, i64),
+    Unboard(i64),
+    TightUnboard(i64),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedGraph {
+    database: DatabaseIdentity,
+    options: GraphOptions,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    trains: Vec<TrainId>,
+    /// Each node along with the edges leaving it and the nodes they lead to
+    nodes: Vec<(SavedNode, Vec<(SavedAction, SavedNode)>)>,
+}
+
+/// Assigns the trains of a graph being saved their indices in the file
+#[derive(Default)]
+struct TrainIndex<'a> {
+    trains: Vec<TrainId>,
+    indices: HashMap<&'a str, u32>,
+}
+
+impl<'a> TrainIndex<'a> {
+    fn index(&mut self, train: &'a Train) -> u32 {
+        *self.indices.entry(train.id().as_str()).or_insert_with(|| {
+            self.trains.push(train.id().to_owned());
+            self.trains.len() as u32 - 1
+        })
+    }
+
+    fn node(&mut self, node: &Singularity<'a>) -> SavedNode {
+        SavedNode {
+            station: node.station.id(),
+            time: node.time,
+            train: node.train.map(|t| self.index(t)),
+        }
+    }
+
+    fn action(&mut self, action: &Action<'a>) -> SavedAction {
+        let stop = |stop: &Stop| SavedStop {
+            station: stop.station.id(),
+            arrival: stop.arrival,
+            departure: stop.departure,
+            delayed: stop.delayed,
+        };
+        match *action {
+            Action::Wait(time) => SavedAction::Wait(time.num_seconds()),
+            Action::TrainWaits(train, s) => SavedAction::TrainWaits(self.index(train), stop(&s)),
+            Action::Ride(train, start, end, penalty) => {
+                SavedAction::Ride(self.index(train), stop(&start), stop(&end), penalty)
+            }
+            Action::Board(train, penalty) => SavedAction::Board(self.index(train), penalty),
+            Action::Unboard(penalty) => SavedAction::Unboard(penalty),
+            Action::TightUnboard(penalty) => SavedAction::TightUnboard(penalty),
+        }
+    }
+}
+
+/// Writes a graph prepared from the given database and options for the window between start_time and end_time
+pub(crate) fn write<W: Write>(
+    mut writer: W,
+    data: &RailroadData,
+    graph: &RailroadGraph,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    options: &RoutingOptions,
+) -> Result<(), HaError> {
+    let mut trains = TrainIndex::default();
+    let nodes = graph
+        .nodes()
+        .map(|node| {
+            let edges = node
+                .edges()
+                .map(|(action, dest)| (trains.action(action), trains.node(dest)))
+                .collect();
+            (trains.node(node.id()), edges)
+        })
+        .collect();
+    let saved = SavedGraph {
+        database: DatabaseIdentity::of(data),
+        options: GraphOptions::of(options),
+        start_time,
+        end_time,
+        trains: trains.trains,
+        nodes,
+    };
+    writer.write_all(MAGIC).map_err(io_error)?;
+    writer
+        .write_all(&GRAPH_VERSION.to_le_bytes())
+        .map_err(io_error)?;
+    bincode::serialize_into(writer, &saved).map_err(io_error)
+}
+
+/// Reads a graph written by write, returning it along with the start and end of its window.
+///
+/// Fails if the graph was prepared from another database or with routing options which shape the graph differently.
+pub(crate) fn read<'a, R: Read>(
+    mut reader: R,
+    data: &'a RailroadData,
+    options: &RoutingOptions,
+) -> Result<(RailroadGraph<'a>, NaiveDateTime, NaiveDateTime), HaError> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header).map_err(io_error)?;
+    if &header[..8] != MAGIC {
+        return Err(HaError::DatabaseError("not a HaRail graph file".to_owned()));
+    }
+    let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+    if version != GRAPH_VERSION {
+        return Err(HaError::DatabaseError(format!(
+            "graph file format version {} is not supported, prepare the graph again",
+            version
+        )));
+    }
+    let saved: SavedGraph = bincode::deserialize_from(reader).map_err(io_error)?;
+    if saved.database != DatabaseIdentity::of(data) {
+        return Err(HaError::DatabaseError(
+            "the graph was prepared from another database".to_owned(),
+        ));
+    }
+    if saved.options != GraphOptions::of(options) {
+        return Err(HaError::UsageError(
+            "the graph was prepared with other routing options".to_owned(),
+        ));
+    }
+    let mismatch = || HaError::DatabaseError("the graph doesn't match the database".to_owned());
+    let trains = saved
+        .trains
+        .iter()
+        .map(|id| data.train(id))
+        .collect::<Option<Vec<&Train>>>()
+        .ok_or_else(mismatch)?;
+    let train = |index: u32| trains.get(index as usize).copied().ok_or_else(mismatch);
+    let node = |node: &SavedNode| -> Result<Singularity<'a>, HaError> {
+        Ok(Singularity {
+            station: data.station(node.station).ok_or_else(mismatch)?,
+            time: node.time,
+            train: node.train.map(train).transpose()?,
+        })
+    };
+    let stop = |train: &'a Train, stop: &SavedStop| -> Result<Stop<'a>, HaError> {
+        Ok(Stop {
+            station: data.station(stop.station).ok_or_else(mismatch)?,
+            platform: train
+                .stops()
+                .find(|s| s.station() == stop.station)
+                .and_then(|s| s.platform()),
+            arrival: stop.arrival,
+            departure: stop.departure,
+            delayed: stop.delayed,
+        })
+    };
+    let mut graph = RailroadGraph::new();
+    for (saved_node, edges) in &saved.nodes {
+        let id = node(saved_node)?;
+        graph.get_or_insert(&id);
+        for (action, dest) in edges {
+            let action = match action {
+                SavedAction::Wait(seconds) => Action::Wait(Duration::seconds(*seconds)),
+                SavedAction::TrainWaits(t, s) => {
+                    let t = train(*t)?;
+                    Action::TrainWaits(t, stop(t, s)?)
+                }
+                SavedAction::Ride(t, start, end, penalty) => {
+                    let t = train(*t)?;
+                    Action::Ride(t, stop(t, start)?, stop(t, end)?, *penalty)
+                }
+                SavedAction::Board(t, penalty) => Action::Board(train(*t)?, *penalty),
+                SavedAction::Unboard(penalty) => Action::Unboard(*penalty),
+                SavedAction::TightUnboard(penalty) => Action::TightUnboard(*penalty),
+            };
+            let dest = node(dest)?;
+            graph.get_or_insert(&id).connect(action, dest);
+        }
+    }
+    Ok((graph, saved.start_time, saved.end_time))
+}
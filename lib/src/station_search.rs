@@ -0,0 +1,330 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{RailroadData, Station};
+
+/// How well a station name matches a query, from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Match {
+    Exact,
+    Prefix,
+    WordPrefix,
+    Transliterated,
+    Fuzzy(usize),
+}
+
+/// Other names which Israel Railways stations are commonly known by, along with the names the feed gives them.
+///
+/// Names are compared normalized, so aliases need not list variations in case, punctuation or Hebrew final letters.
+const ALIASES: &[(&str, &[&str])] = &[
+    (
+        "תל אביב - סבידור מרכז",
+        &[
+            "Tel Aviv Savidor",
+            "Tel Aviv Center",
+            "Tel Aviv Merkaz",
+            "Savidor",
+            "ת\"א מרכז",
+            "ת\"א סבידור",
+            "תל אביב מרכז",
+            "סבידור",
+        ],
+    ),
+    (
+        "תל אביב - השלום",
+        &[
+            "Tel Aviv HaShalom",
+            "Tel Aviv Azrieli",
+            "ת\"א השלום",
+            "עזריאלי",
+        ],
+    ),
+    ("תל אביב - ההגנה", &["Tel Aviv HaHagana", "ת\"א ההגנה"]),
+    (
+        "תל אביב - אוניברסיטה",
+        &["Tel Aviv University", "ת\"א אוניברסיטה"],
+    ),
+    ("חיפה - חוף הכרמל", &["Haifa Hof HaCarmel", "חוף הכרמל"]),
+    (
+        "חיפה מרכז - השמונה",
+        &[
+            "Haifa Center",
+            "Haifa Merkaz",
+            "Haifa HaShmona",
+            "חיפה מרכז",
+        ],
+    ),
+    (
+        "באר שבע - מרכז",
+        &["Beer Sheva Center", "Be'er Sheva Merkaz", "ב\"ש מרכז"],
+    ),
+    (
+        "ירושלים - יצחק נבון",
+        &["Jerusalem Yitzhak Navon", "Jerusalem Navon", "ירושלים נבון"],
+    ),
+    (
+        "נתב\"ג",
+        &["Ben Gurion Airport", "Ben Gurion", "נמל התעופה בן גוריון"],
+    ),
+];
+
+/// A station name or query, prepared for matching
+struct Normalized {
+    /// The lowercase words, without punctuation and with Hebrew final letters replaced by their regular forms
+    words: Vec<Vec<char>>,
+    /// The consonants of each word, with Hebrew and Latin letters mapped to the same sounds
+    skeleton: Vec<char>,
+}
+
+impl Normalized {
+    fn new(text: &str) -> Self {
+        let words: Vec<Vec<char>> = text
+            .split(|c: char| !c.is_alphanumeric() && !is_hebrew_point(c) && !is_quote(c))
+            .map(|w| {
+                w.chars()
+                    .filter(|&c| !is_hebrew_point(c) && !is_quote(c))
+                    .map(unfinal)
+                    .flat_map(char::to_lowercase)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|w| !w.is_empty())
+            .collect();
+        let skeleton = words.iter().flat_map(|w| skeleton(w)).collect();
+        Normalized { words, skeleton }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// The text from each word onwards, with the words joined by spaces
+    fn suffixes(&self) -> impl Iterator<Item = Vec<char>> + '_ {
+        (0..self.words.len()).map(|i| self.words[i..].join(&' '))
+    }
+
+    fn joined(&self) -> Vec<char> {
+        self.words.join(&' ')
+    }
+}
+
+/// Vowel signs and cantillation marks, which station names sometimes carry
+fn is_hebrew_point(c: char) -> bool {
+    ('\u{0591}'..='\u{05C7}').contains(&c)
+}
+
+/// Quotation marks, which abbreviations (e.g. "ת\"א") and transliterations (e.g. "Be'er") carry within words
+fn is_quote(c: char) -> bool {
+    matches!(c, '"' | '\'' | '`' | '\u{2019}' | '\u{05F3}' | '\u{05F4}')
+}
+
+/// The regular form of a Hebrew final letter
+fn unfinal(c: char) -> char {
+    match c {
+        'ך' => 'כ',
+        'ם' => 'מ',
+        'ן' => 'נ',
+        'ף' => 'פ',
+        'ץ' => 'צ',
+        c => c,
+    }
+}
+
+/// Reduces a word to its consonant sounds, so that e.g. "tel aviv" and "תל אביב" look alike
+fn skeleton(word: &[char]) -> Vec<char> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < word.len() {
+        let next = word.get(i + 1).copied();
+        let (consonant, len) = match (word[i], next) {
+            ('s', Some('h')) => (Some('s'), 2),
+            ('c' | 'k', Some('h')) => (Some('h'), 2),
+            ('t', Some('z' | 's')) => (Some('z'), 2),
+            ('p', Some('h')) => (Some('p'), 2),
+            // A final He is silent
+            ('ה', None) => (None, 1),
+            (c, _) => (sound(c), 1),
+        };
+        // Doubled letters in transliterations (e.g. "akko") are a single sound
+        if consonant.is_some() && result.last() != consonant.as_ref() {
+            result.extend(consonant);
+        }
+        i += len;
+    }
+    result
+}
+
+fn sound(c: char) -> Option<char> {
+    let sound = match c {
+        'b' | 'v' | 'ב' => 'b',
+        'g' | 'j' | 'ג' => 'g',
+        'd' | 'ד' => 'd',
+        'h' | 'ה' | 'ח' => 'h',
+        'z' | 'ז' | 'צ' | 'ץ' => 'z',
+        't' | 'ט' | 'ת' => 't',
+        'k' | 'c' | 'q' | 'כ' | 'ך' | 'ק' => 'k',
+        'l' | 'ל' => 'l',
+        'm' | 'מ' | 'ם' => 'm',
+        'n' | 'נ' | 'ן' => 'n',
+        's' | 'x' | 'ס' | 'ש' => 's',
+        'p' | 'f' | 'פ' | 'ף' => 'p',
+        'r' | 'ר' => 'r',
+        c if c.is_ascii_digit() => c,
+        // Vowels, and the letters which usually stand for them
+        _ => return None,
+    };
+    Some(sound)
+}
+
+/// The edit distance between the query and the closest prefix of the text
+fn prefix_distance(query: &[char], text: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=text.len()).collect();
+    for (i, &q) in query.iter().enumerate() {
+        let mut next = vec![i + 1; text.len() + 1];
+        for (j, &t) in text.iter().enumerate() {
+            let substitute = row[j] + usize::from(q != t);
+            next[j + 1] = substitute.min(row[j + 1] + 1).min(next[j] + 1);
+        }
+        row = next;
+    }
+    // The query may match any prefix of the text, so the text's remainder costs nothing
+    *row.iter().min().unwrap()
+}
+
+/// The number of typos tolerated in a query of the given length
+fn max_typos(len: usize) -> usize {
+    match len {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+fn rank(query: &Normalized, name: &Normalized) -> Option<Match> {
+    let query_text = query.joined();
+    let name_text = name.joined();
+    if query_text == name_text {
+        return Some(Match::Exact);
+    }
+    if name_text.starts_with(&query_text) {
+        return Some(Match::Prefix);
+    }
+    if name.suffixes().any(|s| s.starts_with(&query_text)) {
+        return Some(Match::WordPrefix);
+    }
+    if !query.skeleton.is_empty() && name.skeleton.starts_with(&query.skeleton) {
+        return Some(Match::Transliterated);
+    }
+    let spelled = name
+        .suffixes()
+        .map(|s| prefix_distance(&query_text, &s))
+        .min()
+        .filter(|&typos| typos <= max_typos(query_text.len()));
+    let sounded = (!query.skeleton.is_empty())
+        .then(|| prefix_distance(&query.skeleton, &name.skeleton))
+        .filter(|&typos| typos <= max_typos(query.skeleton.len()));
+    spelled.into_iter().chain(sounded).min().map(Match::Fuzzy)
+}
+
+/// Finds the stations whose names best match a partially typed query, for autocompletion.
+///
+/// Names match when they start with the query, have a word starting with it, sound like it when
+/// transliterated between Hebrew and Latin letters, or are within a few typos of it. Stations also
+/// match by their built-in aliases. Better matches come first, and at most limit stations are returned.
+pub fn search_stations<'a>(data: &'a RailroadData, query: &str, limit: usize) -> Vec<&'a Station> {
+    let query = Normalized::new(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let aliases = aliases();
+    let mut result: Vec<_> = data
+        .stations()
+        .filter_map(|station| {
+            let name = Normalized::new(station.name());
+            let joined = name.joined();
+            let best = aliases
+                .iter()
+                .filter(|(canonical, _)| *canonical == joined)
+                .filter_map(|(_, alias)| rank(&query, alias))
+                .chain(rank(&query, &name))
+                .min()?;
+            Some((best, station))
+        })
+        .collect();
+    result.sort_by(|(a_rank, a), (b_rank, b)| {
+        a_rank
+            .cmp(b_rank)
+            .then_with(|| a.name().chars().count().cmp(&b.name().chars().count()))
+            .then_with(|| a.name().cmp(b.name()))
+            .then_with(|| a.id().cmp(&b.id()))
+    });
+    result.truncate(limit);
+    result.into_iter().map(|(_, station)| station).collect()
+}
+
+/// The built-in aliases, each along with the normalized name of the station it stands for
+fn aliases() -> Vec<(Vec<char>, Normalized)> {
+    ALIASES
+        .iter()
+        .flat_map(|(canonical, aliases)| {
+            let canonical = Normalized::new(canonical).joined();
+            aliases
+                .iter()
+                .map(move |alias| (canonical.clone(), Normalized::new(alias)))
+        })
+        .collect()
+}
+
+/// Finds the station with the given name, ignoring differences in case, punctuation, vowel signs and Hebrew final
+/// letters, or else the station known by it as a built-in alias
+pub(crate) fn find_station<'a>(data: &'a RailroadData, name: &str) -> Option<&'a Station> {
+    let name = Normalized::new(name).joined();
+    if name.is_empty() {
+        return None;
+    }
+    let named = |target: &[char]| {
+        data.stations()
+            .filter(|station| Normalized::new(station.name()).joined() == target)
+            .min_by_key(|station| station.id())
+    };
+    named(&name).or_else(|| {
+        let (canonical, _) = aliases()
+            .into_iter()
+            .find(|(_, alias)| alias.joined() == name)?;
+        named(&canonical)
+    })
+}
+
+/// The built-in aliases by which the station is also known, such as Savidor for Tel Aviv Savidor Center
+pub fn station_aliases(station: &Station) -> Vec<&'static str> {
+    let name = Normalized::new(station.name()).joined();
+    ALIASES
+        .iter()
+        .filter(|(canonical, _)| Normalized::new(canonical).joined() == name)
+        .flat_map(|(_, aliases)| aliases.iter().copied())
+        .collect()
+}
+
+/// Finds the stations within radius meters of the given WGS84 coordinates, nearest first.
+///
+/// Returns each station along with its distance in meters. Stations without coordinates are never found.
+pub fn nearby_stations(
+    data: &RailroadData,
+    lat: f64,
+    lon: f64,
+    radius: f64,
+) -> Vec<(&Station, f64)> {
+    let mut result: Vec<_> = data
+        .stations()
+        .filter_map(|station| Some((station, station.distance_to(lat, lon)?)))
+        .filter(|&(_, distance)| distance <= radius)
+        .collect();
+    result.sort_by(|(a, a_distance), (b, b_distance)| {
+        a_distance
+            .total_cmp(b_distance)
+            .then_with(|| a.id().cmp(&b.id()))
+    });
+    result
+}
@@ -0,0 +1,92 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::JSON;
+use chrono::{Duration, NaiveDateTime};
+use jzon::JsonValue;
+use serde::{Deserialize, Serialize};
+
+/// Totals of a route which would otherwise have to be derived from its parts
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct RouteSummary {
+    duration_seconds: i64,
+    ride_seconds: i64,
+    wait_seconds: i64,
+    transfers: usize,
+    stations_passed: usize,
+}
+
+impl RouteSummary {
+    /// Sums up the train rides of a route, each given as its departure, its arrival and the number of stops the train
+    /// makes in between
+    pub(crate) fn from_rides(
+        rides: impl Iterator<Item = (NaiveDateTime, NaiveDateTime, usize)>,
+    ) -> Self {
+        let mut summary = RouteSummary {
+            duration_seconds: 0,
+            ride_seconds: 0,
+            wait_seconds: 0,
+            transfers: 0,
+            stations_passed: 0,
+        };
+        let mut first_departure = None;
+        let mut last_arrival: Option<NaiveDateTime> = None;
+        for (departure, arrival, stops) in rides {
+            first_departure.get_or_insert(departure);
+            if let Some(previous) = last_arrival {
+                summary.wait_seconds += (departure - previous).num_seconds();
+                summary.transfers += 1;
+                // The station the trains are switched at is passed too
+                summary.stations_passed += 1;
+            }
+            summary.ride_seconds += (arrival - departure).num_seconds();
+            summary.stations_passed += stops;
+            last_arrival = Some(arrival);
+        }
+        if let (Some(departure), Some(arrival)) = (first_departure, last_arrival) {
+            summary.duration_seconds = (arrival - departure).num_seconds();
+        }
+        summary
+    }
+
+    /// The time from departing the first station to arriving at the last one
+    pub fn duration(&self) -> Duration {
+        Duration::seconds(self.duration_seconds)
+    }
+
+    /// The time spent on trains, including the time trains wait at their stops
+    pub fn ride_time(&self) -> Duration {
+        Duration::seconds(self.ride_seconds)
+    }
+
+    /// The time spent waiting at stations to switch trains
+    pub fn wait_time(&self) -> Duration {
+        Duration::seconds(self.wait_seconds)
+    }
+
+    /// The number of train switches
+    pub fn transfers(&self) -> usize {
+        self.transfers
+    }
+
+    /// The number of stations between the first and last one, whether the train only stops at them or they are
+    /// switched trains at
+    pub fn stations_passed(&self) -> usize {
+        self.stations_passed
+    }
+}
+
+impl JSON for RouteSummary {
+    fn to_json(&self) -> JsonValue {
+        object! {
+            duration_seconds: self.duration_seconds,
+            ride_seconds: self.ride_seconds,
+            wait_seconds: self.wait_seconds,
+            transfers: self.transfers,
+            stations_passed: self.stations_passed,
+        }
+    }
+}
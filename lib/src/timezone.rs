@@ -0,0 +1,120 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Conversion between the local times of the GTFS feed and points in time.
+//!
+//! Times in the database are naive, as GTFS gives them in the local time of the feed. They are serialized and displayed
+//! in Asia/Jerusalem, or in the timezone passed to `Zoned::in_timezone` for objects with times.
+//!
+//! Stop times are offsets from the start of their service day, which `service_time` turns into local times of the feed
+//! according to the daylight saving time rules of Asia/Jerusalem.
+
+use crate::JSON;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use jzon::JsonValue;
+use std::fmt;
+
+/// The timezone in which the times of the feed are given
+pub const FEED_TIMEZONE: Tz = chrono_tz::Asia::Jerusalem;
+
+/// An object with times, whose Display and JSON give them in the timezone of the feed, and which can be shown in
+/// any other timezone through `in_timezone`
+pub trait Zoned {
+    /// Writes the object to JSON, with its times in the given timezone
+    fn to_json_in(&self, timezone: Tz) -> JsonValue;
+
+    /// Writes the object in human readable form, with its times in the given timezone
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, timezone: Tz) -> fmt::Result;
+
+    /// The object shown with its times in the given timezone, by both Display and JSON
+    fn in_timezone(&self, timezone: Tz) -> InTimezone<'_, Self> {
+        InTimezone {
+            value: self,
+            timezone,
+        }
+    }
+}
+
+/// An object shown with its times in a given timezone, as returned by `Zoned::in_timezone`
+pub struct InTimezone<'a, T: ?Sized> {
+    value: &'a T,
+    timezone: Tz,
+}
+
+impl<T: Zoned + ?Sized> fmt::Display for InTimezone<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt_in(f, self.timezone)
+    }
+}
+
+impl<T: Zoned + ?Sized> JSON for InTimezone<'_, T> {
+    fn to_json(&self) -> JsonValue {
+        self.value.to_json_in(self.timezone)
+    }
+}
+
+/// Converts a local time of the feed to a point in time in the timezone of the feed.
+///
+/// Times repeated when the clocks go back are taken as the earlier one, and times skipped when the clocks go forward
+/// are taken as an hour later.
+pub fn localize(time: NaiveDateTime) -> DateTime<Tz> {
+    FEED_TIMEZONE
+        .from_local_datetime(&time)
+        .earliest()
+        .or_else(|| {
+            FEED_TIMEZONE
+                .from_local_datetime(&(time + Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| FEED_TIMEZONE.from_utc_datetime(&time))
+}
+
+/// Converts a local time of the feed to a point in time in the given timezone, like `localize`
+pub fn localize_in(time: NaiveDateTime, timezone: Tz) -> DateTime<Tz> {
+    localize(time).with_timezone(&timezone)
+}
+
+/// The local time of the feed at which a stop time of the given service day takes place.
+///
+/// GTFS measures stop times from noon minus 12 hours of their service day. This is midnight, except on the days the
+/// clocks change: on the day they go forward it is 23:00 of the previous day, and on the day they go back it is 01:00,
+/// so stop times before the change are an hour off from the local time they would read as. Times after the change read
+/// as local times, as on any other day.
+pub(crate) fn service_time(date: NaiveDate, offset: Duration) -> NaiveDateTime {
+    let noon = date.and_hms_opt(12, 0, 0).unwrap();
+    match FEED_TIMEZONE.from_local_datetime(&noon).earliest() {
+        Some(noon) => (noon - Duration::hours(12) + offset).naive_local(),
+        None => date.and_hms_opt(0, 0, 0).unwrap() + offset,
+    }
+}
+
+/// Converts a point in time to the local time of the feed, as used for searching the database
+pub fn to_feed_time<T: TimeZone>(time: &DateTime<T>) -> NaiveDateTime {
+    time.with_timezone(&FEED_TIMEZONE).naive_local()
+}
+
+/// Serializes local times of the feed as RFC 3339 times in the timezone of the feed, like the JSON of routes gives
+/// them, and deserializes them back, for use with `#[serde(with = "...")]`
+pub(crate) mod zoned {
+    use super::{localize, to_feed_time};
+    use chrono::{DateTime, FixedOffset, NaiveDateTime};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        time: &NaiveDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&localize(*time).to_rfc3339())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let time = DateTime::<FixedOffset>::deserialize(deserializer)?;
+        Ok(to_feed_time(&time))
+    }
+}
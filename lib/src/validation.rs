@@ -0,0 +1,111 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{RailroadData, StationId, TrainId, JSON};
+use jzon::JsonValue;
+use std::fmt;
+
+/// A problem found in a database, which would otherwise only show up as odd routes
+#[derive(PartialEq, Eq, Debug)]
+pub enum Issue {
+    /// A train stops at a station which isn't in the database
+    UnknownStation { train: TrainId, station: StationId },
+    /// A train has no stops
+    NoStops { train: TrainId },
+    /// A train doesn't run on any date
+    NoDates { train: TrainId },
+    /// A train leaves a stop before arriving at it, or arrives at a stop before leaving the previous one.
+    ///
+    /// The stop is given by its index in the train's stops.
+    TimeTravel { train: TrainId, stop: usize },
+}
+
+impl Issue {
+    /// A short name of the kind of problem, as used in JSON
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Issue::UnknownStation { .. } => "unknown_station",
+            Issue::NoStops { .. } => "no_stops",
+            Issue::NoDates { .. } => "no_dates",
+            Issue::TimeTravel { .. } => "time_travel",
+        }
+    }
+
+    /// The train the problem was found in
+    pub fn train(&self) -> &TrainId {
+        match self {
+            Issue::UnknownStation { train, .. }
+            | Issue::NoStops { train }
+            | Issue::NoDates { train }
+            | Issue::TimeTravel { train, .. } => train,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::UnknownStation { train, station } => {
+                write!(f, "train {} stops at unknown station {}", train, station)
+            }
+            Issue::NoStops { train } => write!(f, "train {} has no stops", train),
+            Issue::NoDates { train } => write!(f, "train {} never runs", train),
+            Issue::TimeTravel { train, stop } => {
+                write!(f, "train {} goes back in time at stop {}", train, stop)
+            }
+        }
+    }
+}
+
+impl JSON for Issue {
+    fn to_json(&self) -> JsonValue {
+        let mut json = object! {
+            kind: self.kind(),
+            train: self.train().as_str(),
+        };
+        match self {
+            Issue::UnknownStation { station, .. } => json["station"] = (*station).into(),
+            Issue::TimeTravel { stop, .. } => json["stop"] = (*stop).into(),
+            Issue::NoStops { .. } | Issue::NoDates { .. } => {}
+        }
+        json
+    }
+}
+
+/// Checks the database for problems, returning them ordered by train
+pub fn validate(data: &RailroadData) -> Vec<Issue> {
+    let mut trains: Vec<_> = data.trains().collect();
+    trains.sort_by_key(|t| t.id());
+    let mut issues = Vec::new();
+    for train in trains {
+        let id = train.id();
+        if train.stops().next().is_none() {
+            issues.push(Issue::NoStops { train: id.clone() });
+        }
+        if train.dates().next().is_none() {
+            issues.push(Issue::NoDates { train: id.clone() });
+        }
+        let mut last_departure = None;
+        for (i, stop) in train.stops().enumerate() {
+            if data.station(stop.station()).is_none() {
+                issues.push(Issue::UnknownStation {
+                    train: id.clone(),
+                    station: stop.station(),
+                });
+            }
+            let arrival = stop.arrival_offset().to_chrono();
+            let departure = stop.departure_offset().to_chrono();
+            if departure < arrival || last_departure.is_some_and(|last| arrival < last) {
+                issues.push(Issue::TimeTravel {
+                    train: id.clone(),
+                    stop: i,
+                });
+            }
+            last_departure = Some(departure);
+        }
+    }
+    issues
+}
@@ -0,0 +1,105 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Route search from JavaScript, for running HaRail in the browser without a server.
+//!
+//! Build the library for wasm32-unknown-unknown with the wasm feature and without the default features, e.g. with
+//! `wasm-pack build lib -- --no-default-features --features wasm`. The page fetches a database written by
+//! `parse-gtfs --compression-level 0` and hands its bytes to a `Planner`; results are returned as JSON strings, in the
+//! same shape as the JSON trait gives routes and stations in.
+
+use crate::{
+    search_stations, HaError, RailroadData, RouteQuery, RoutingOptions, SearchKind, StationId, JSON,
+};
+use chrono::{DateTime, NaiveDateTime};
+use jzon::JsonValue;
+use wasm_bindgen::prelude::*;
+
+/// A database loaded into memory, which routes are searched in
+#[wasm_bindgen]
+pub struct Planner {
+    data: RailroadData,
+}
+
+fn parse_search(search: &str) -> Result<SearchKind, HaError> {
+    match search {
+        "best" => Ok(SearchKind::Best),
+        "latest" => Ok(SearchKind::Latest),
+        "multi" => Ok(SearchKind::Multiple),
+        "pareto" => Ok(SearchKind::Pareto),
+        "arriveby" => Ok(SearchKind::ArriveBy),
+        _ => Err(HaError::UsageError(format!("Unknown search {}", search))),
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveDateTime, HaError> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| crate::to_feed_time(&time))
+        .map_err(|e| HaError::UsageError(format!("Invalid time {}: {}", time, e)))
+}
+
+#[wasm_bindgen]
+impl Planner {
+    /// Loads a database from the contents of its file. Databases compressed with zstd can only be loaded if the
+    /// library was built with the zstd feature.
+    #[wasm_bindgen(constructor)]
+    pub fn new(database: &[u8]) -> Result<Planner, JsError> {
+        Ok(Planner {
+            data: RailroadData::load(database)?,
+        })
+    }
+
+    /// The stations of the database, as a JSON array
+    pub fn stations(&self) -> String {
+        JsonValue::Array(self.data.stations().map(|s| s.to_json()).collect()).dump()
+    }
+
+    /// The stations best matching a query, as search-station finds them, as a JSON array
+    #[wasm_bindgen(js_name = searchStations)]
+    pub fn search_stations(&self, query: &str, limit: usize) -> String {
+        JsonValue::Array(
+            search_stations(&self.data, query, limit)
+                .into_iter()
+                .map(|s| s.to_json())
+                .collect(),
+        )
+        .dump()
+    }
+
+    /// Finds routes between stations, as a JSON array of routes.
+    ///
+    /// The search is named as in the server's API: best, latest, multi, pareto or arriveby. Times are given in RFC 3339. Searches
+    /// expanding more than max_expanded_nodes nodes, if given, fail rather than keep the page busy.
+    #[wasm_bindgen(js_name = findRoutes)]
+    pub fn find_routes(
+        &self,
+        search: &str,
+        start_time: &str,
+        start_station: StationId,
+        end_time: &str,
+        end_station: StationId,
+        max_expanded_nodes: Option<usize>,
+    ) -> Result<String, JsError> {
+        let start_station = self
+            .data
+            .station(start_station)
+            .ok_or_else(|| HaError::UsageError("Could not find source station".to_owned()))?;
+        let end_station = self
+            .data
+            .station(end_station)
+            .ok_or_else(|| HaError::UsageError("Could not find dest station".to_owned()))?;
+        let query = RouteQuery::new(
+            start_station,
+            end_station,
+            parse_time(start_time)?,
+            parse_time(end_time)?,
+        )
+        .with_search(parse_search(search)?)
+        .with_options(RoutingOptions::new().with_max_expanded_nodes(max_expanded_nodes));
+        let routes = crate::find_routes(&self.data, &query)?;
+        Ok(JsonValue::Array(routes.iter().map(|r| r.to_json()).collect()).dump())
+    }
+}
@@ -0,0 +1,143 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{RailroadData, Route, RoutePart, RoutingOptions, Stop, JSON};
+use chrono::{Duration, NaiveDateTime};
+use jzon::JsonValue;
+use std::sync::Arc;
+
+/// What becomes of a route when some of the trains run late, as found by simulate_delays
+pub struct DelaySimulation<'a> {
+    delayed: Route<'a>,
+    missed_part: Option<usize>,
+    recovery: Option<Route<'a>>,
+}
+
+impl<'a> DelaySimulation<'a> {
+    /// The route with the times of its late trains pushed back, whether or not its train switches still work
+    pub fn delayed_route(&self) -> &Route<'a> {
+        &self.delayed
+    }
+
+    /// Whether every train switch of the route still leaves enough time to make the next train
+    pub fn is_feasible(&self) -> bool {
+        self.missed_part.is_none()
+    }
+
+    /// The index of the first part whose train is missed because of the delays, if any
+    pub fn missed_part(&self) -> Option<usize> {
+        self.missed_part
+    }
+
+    /// The best way to the destination when a train is missed: the parts ridden up to the missed train switch,
+    /// followed by the best route from there. None if the route is feasible, or if the destination can't be reached in
+    /// time anymore.
+    pub fn recovery(&self) -> Option<&Route<'a>> {
+        self.recovery.as_ref()
+    }
+}
+
+impl JSON for DelaySimulation<'_> {
+    fn to_json(&self) -> JsonValue {
+        let mut result = object! {
+            feasible: self.is_feasible(),
+            delayed: self.delayed.to_json(),
+        };
+        if let Some(missed_part) = self.missed_part {
+            result["missed_part"] = missed_part.into();
+        }
+        if let Some(recovery) = &self.recovery {
+            result["recovery"] = recovery.to_json();
+        }
+        result
+    }
+}
+
+fn delay_stop<'a>(stop: Stop<'a>, delay: Duration) -> Stop<'a> {
+    Stop {
+        arrival: stop.arrival + delay,
+        departure: stop.departure + delay,
+        delayed: true,
+        ..stop
+    }
+}
+
+/// Simulates the given trains running late by the given durations, e.g. ("1", 12 minutes) for train 1 running 12
+/// minutes behind schedule, to tell whether the route still works and how to recover if it doesn't.
+///
+/// Each train switch needs the station's minimum transfer time, or the one in the routing options if longer. When a
+/// train is missed, the recovery route is searched from the station where it was missed, once the transfer time has
+/// passed, to arrive by end_time. The search takes the delays into account along with any real-time updates of the
+/// routing options, and the delays apply to every run of their trains between the route's departure and end_time.
+pub fn simulate_delays<'a>(
+    data: &'a RailroadData,
+    route: &Route<'a>,
+    delays: &[(&str, Duration)],
+    end_time: NaiveDateTime,
+    options: &RoutingOptions,
+) -> DelaySimulation<'a> {
+    let delay_of = |part: &RoutePart| {
+        delays
+            .iter()
+            .find(|(train, _)| *train == part.train.id())
+            .map(|&(_, delay)| delay)
+    };
+    let delayed = Route::from_parts(
+        route
+            .parts
+            .iter()
+            .map(|part| match delay_of(part) {
+                Some(delay) => RoutePart::new(
+                    part.train,
+                    delay_stop(part.start, delay),
+                    delay_stop(part.end, delay),
+                )
+                .with_intermediate_stops(
+                    part.intermediate_stops
+                        .iter()
+                        .map(|&s| delay_stop(s, delay))
+                        .collect(),
+                ),
+                None => part.clone(),
+            })
+            .collect(),
+    );
+    let missed_part = delayed.parts.windows(2).position(|w| {
+        w[1].start.departure - w[0].end.arrival
+            < crate::transfer_time(data, w[0].end.station.id(), options)
+    });
+    let recovery = missed_part.and_then(|i| {
+        let stranded = delayed.parts[i].end;
+        let start_time =
+            stranded.arrival + crate::transfer_time(data, stranded.station.id(), options);
+        let mut updates = options.realtime().cloned().unwrap_or_default();
+        let departure = delayed.parts[0].start.departure;
+        for &(train, delay) in delays {
+            if let Some(train) = data.train(train) {
+                for date in train.service_dates_between(departure, end_time) {
+                    updates.delay(train.id(), date, None, delay);
+                }
+            }
+        }
+        let options = options.clone().with_realtime(Arc::new(updates));
+        let rest = crate::get_best_single_route_with_options(
+            data,
+            start_time,
+            stranded.station,
+            end_time,
+            route.parts.last()?.end.station,
+            &options,
+        )?;
+        let mut parts: Vec<RoutePart<'a>> = delayed.parts[..=i].to_vec();
+        parts.extend(rest.parts);
+        Some(Route::from_parts(parts))
+    });
+    DelaySimulation {
+        delayed,
+        missed_part: missed_part.map(|i| i + 1),
+        recovery,
+    }
+}
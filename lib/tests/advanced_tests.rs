@@ -0,0 +1,58 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![cfg(feature = "advanced")]
+
+mod test_data;
+use chrono::{NaiveDateTime, NaiveTime};
+use harail::advanced::{Action, RoutingGraph};
+use harail::{HaDuration, RailroadData, RoutingOptions, StopSchedule, Train};
+use test_data::test_date;
+
+#[test]
+fn custom_searches() {
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 15, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 45, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 15, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let graph = RoutingGraph::new(&data, at(0, 00), at(23, 59), &RoutingOptions::new());
+    let origin = graph.station_nodes(data.station(100).unwrap())[0];
+    assert_eq!(origin.time(), at(10, 00));
+    let at_end = |n: &harail::advanced::Singularity| n.station().id() == 300 && n.train().is_none();
+
+    let path = graph.find_path(&origin, at_end).unwrap();
+    let route = graph.route(path.clone());
+    assert_eq!(route.parts().next().unwrap().train().id(), "1");
+    let cost: i64 = path.iter().map(|(action, _)| action.cost()).sum();
+    assert_eq!(graph.distances(&origin)[&path.last().unwrap().1], cost);
+
+    // A route through 200 only rides train 2
+    let through = graph.subgraph(|n| n.train().is_none_or(|t| t.id() != "1"));
+    let path = through.find_path(&origin, at_end).unwrap();
+    assert!(path
+        .iter()
+        .any(|(action, node)| matches!(action, Action::Ride(..)) && node.station().id() == 200));
+    let route = through.route(path);
+    assert_eq!(route.parts().next().unwrap().train().id(), "2");
+    assert_eq!(route.parts().last().unwrap().end().arrival(), at(11, 15));
+}
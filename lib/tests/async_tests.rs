@@ -0,0 +1,87 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![cfg(feature = "async")]
+
+mod test_data;
+use chrono::{NaiveDateTime, NaiveTime};
+use harail::{HaDuration, HaError, RailroadData, RoutingOptions, SearchKind, StopSchedule, Train};
+use std::sync::Arc;
+use test_data::test_date;
+
+#[tokio::test]
+async fn async_search() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(11, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = Arc::new(RailroadData::from_stations_trains(
+        test_data::stations(),
+        trains,
+    ));
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap());
+
+    let routes = harail::find_routes_async(
+        Arc::clone(&data),
+        SearchKind::Best,
+        start_time,
+        100,
+        end_time,
+        300,
+        RoutingOptions::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(1, routes.len());
+    let parts: Vec<_> = routes[0].parts().collect();
+    assert_eq!(1, parts.len());
+    assert_eq!("1", parts[0].train());
+    assert_eq!(100, parts[0].start().station());
+    assert_eq!(300, parts[0].end().station());
+
+    let routes = harail::find_routes_async(
+        Arc::clone(&data),
+        SearchKind::Multiple,
+        start_time,
+        100,
+        end_time,
+        300,
+        RoutingOptions::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(2, routes.len());
+    assert_eq!("2", routes[1].parts().next().unwrap().train());
+
+    let result = harail::find_routes_async(
+        data,
+        SearchKind::Best,
+        start_time,
+        999,
+        end_time,
+        300,
+        RoutingOptions::new(),
+    )
+    .await;
+    assert!(matches!(result, Err(HaError::UsageError(_))));
+}
@@ -0,0 +1,139 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+mod test_data;
+use harail::{HaDuration, HaError, Mode, RailroadData, StopSchedule, Train};
+use serde::Serialize;
+use std::collections::HashMap;
+use test_data::test_date;
+
+fn data() -> RailroadData {
+    let trains = vec![Train::from_stops_dates(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ],
+        vec![test_date()],
+    )];
+    RailroadData::from_stations_trains(test_data::stations(), trains)
+}
+
+#[test]
+fn save_and_load() {
+    let mut content = Vec::new();
+    data().save(&mut content).unwrap();
+    assert!(content.starts_with(b"HARAILDB"));
+    let loaded = RailroadData::load(content.as_slice()).unwrap();
+    assert_eq!(loaded.stations().count(), 6);
+    assert_eq!(loaded.train("1").unwrap().stops().count(), 2);
+}
+
+#[test]
+fn save_and_load_compressed() {
+    let mut content = Vec::new();
+    data().save_compressed(&mut content, 3).unwrap();
+    assert_eq!(content[12..16], 1u32.to_le_bytes());
+    let loaded = RailroadData::load(content.as_slice()).unwrap();
+    assert_eq!(loaded.stations().count(), 6);
+    assert_eq!(loaded.train("1").unwrap().stops().count(), 2);
+}
+
+/// The layout of a database of version 3 or 4 with the test data, with the given fields appended to each train
+fn legacy_payload<T: Serialize>(train_fields: T) -> Vec<u8> {
+    // Stations had no wheelchair accessibility up to version 5, and stops had no platform up to version 4
+    let stations: HashMap<_, _> = test_data::stations()
+        .into_iter()
+        .map(|s| (s.id(), (s.id(), s.name().clone(), s.lat(), s.lon())))
+        .collect();
+    let stops = vec![
+        (
+            100u64,
+            HaDuration::from_hms(10, 00, 00),
+            HaDuration::from_hms(10, 00, 00),
+        ),
+        (
+            200u64,
+            HaDuration::from_hms(10, 30, 00),
+            HaDuration::from_hms(10, 30, 00),
+        ),
+    ];
+    let trains = HashMap::from([(
+        "1".to_owned(),
+        (
+            "1".to_owned(),
+            stops,
+            vec![test_date()],
+            None::<String>,
+            train_fields,
+        ),
+    )]);
+    let data = (
+        stations,
+        trains,
+        HashMap::<u64, HaDuration>::new(),
+        HashMap::<String, Vec<(f64, f64)>>::new(),
+        None::<()>,
+    );
+    bincode::serialize(&data).unwrap()
+}
+
+#[test]
+fn load_older_versions() {
+    // Trains had no mode up to version 3
+    let payload = legacy_payload(());
+    let loaded = RailroadData::load(payload.as_slice()).unwrap();
+    assert_eq!(loaded.stations().count(), 6);
+    assert_eq!(loaded.trains().count(), 1);
+
+    let mut content = b"HARAILDB".to_vec();
+    content.extend_from_slice(&2u32.to_le_bytes());
+    content.extend_from_slice(&payload);
+    let loaded = RailroadData::load(content.as_slice()).unwrap();
+    assert_eq!(loaded.stations().count(), 6);
+    assert_eq!(loaded.trains().count(), 1);
+
+    let mut content = b"HARAILDB".to_vec();
+    content.extend_from_slice(&3u32.to_le_bytes());
+    content.extend_from_slice(&0u32.to_le_bytes());
+    content.extend_from_slice(&payload);
+    let loaded = RailroadData::load(content.as_slice()).unwrap();
+    assert_eq!(loaded.train("1").unwrap().mode(), Mode::Rail);
+
+    let mut content = b"HARAILDB".to_vec();
+    content.extend_from_slice(&4u32.to_le_bytes());
+    content.extend_from_slice(&0u32.to_le_bytes());
+    content.extend_from_slice(&legacy_payload(Mode::Bus));
+    let loaded = RailroadData::load(content.as_slice()).unwrap();
+    let train = loaded.train("1").unwrap();
+    assert_eq!(train.mode(), Mode::Bus);
+    assert_eq!(train.stops().count(), 2);
+    assert!(train.stops().all(|s| s.platform().is_none()));
+}
+
+#[test]
+fn load_unsupported() {
+    // A database from before the format was versioned, which only had (empty) maps of stations and trains
+    let content = bincode::serialize(&(0u64, 0u64)).unwrap();
+    assert!(matches!(
+        RailroadData::load(content.as_slice()),
+        Err(HaError::DatabaseTooOld)
+    ));
+
+    let mut content = Vec::new();
+    data().save(&mut content).unwrap();
+    content[8..12].copy_from_slice(&(harail::DATABASE_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        RailroadData::load(content.as_slice()),
+        Err(HaError::DatabaseTooNew(version)) if version == harail::DATABASE_VERSION + 1
+    ));
+
+    content[8..12].copy_from_slice(&0u32.to_le_bytes());
+    assert!(matches!(
+        RailroadData::load(content.as_slice()),
+        Err(HaError::DatabaseTooOld)
+    ));
+}
@@ -0,0 +1,82 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+mod test_data;
+use harail::{Change, HaDuration, RailroadData, StopSchedule, Train, JSON};
+use test_data::test_date;
+
+fn stops(arrival: HaDuration) -> Vec<StopSchedule> {
+    vec![
+        StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+        StopSchedule::new(200, arrival, None),
+        StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+    ]
+}
+
+#[test]
+fn schedule_diff() {
+    let next_day = test_date().succ_opt().unwrap();
+    let old = RailroadData::from_stations_trains(
+        test_data::stations(),
+        vec![
+            Train::from_stops_dates(
+                "1",
+                stops(HaDuration::from_hms(10, 30, 00)),
+                vec![test_date()],
+            ),
+            Train::from_stops_dates(
+                "2",
+                stops(HaDuration::from_hms(10, 30, 00)),
+                vec![test_date()],
+            ),
+            Train::from_stops_dates(
+                "3",
+                stops(HaDuration::from_hms(10, 30, 00)),
+                vec![test_date()],
+            ),
+        ],
+    );
+    let new = RailroadData::from_stations_trains(
+        test_data::stations(),
+        vec![
+            Train::from_stops_dates(
+                "1",
+                stops(HaDuration::from_hms(10, 30, 00)),
+                vec![test_date()],
+            ),
+            Train::from_stops_dates("2", stops(HaDuration::from_hms(10, 35, 00)), vec![next_day]),
+            Train::from_stops_dates(
+                "4",
+                stops(HaDuration::from_hms(10, 30, 00)),
+                vec![test_date()],
+            ),
+        ],
+    );
+    let changes = harail::diff(&old, &new);
+    assert_eq!(
+        changes,
+        vec![
+            Change::StopsChanged {
+                train: "2".to_owned(),
+                stop: 1,
+            },
+            Change::DatesChanged {
+                train: "2".to_owned(),
+                added: vec![next_day],
+                removed: vec![test_date()],
+            },
+            Change::TrainRemoved {
+                train: "3".to_owned(),
+            },
+            Change::TrainAdded {
+                train: "4".to_owned(),
+            },
+        ]
+    );
+    assert_eq!(changes[1].to_json()["added"][0], "2000-01-02");
+    assert_eq!(changes[2].to_string(), "train 3 was removed");
+    assert!(harail::diff(&old, &old).is_empty());
+}
@@ -6,8 +6,13 @@
 
 mod test_data;
 use chrono::{NaiveDateTime, NaiveTime};
-use harail::RoutePart;
-use harail::{HaDuration, RailroadData, StopSchedule, Train};
+use harail::{
+    ActionWeights, Engine, HaDuration, HaError, Mode, PreparedGraph, RailroadData, RouteOrder,
+    RouteQuery, RoutingOptions, SearchObserver, Station, Stay, StopSchedule, Train,
+};
+use harail::{RoutePart, JSON};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use test_data::test_date;
 
 #[test]
@@ -351,6 +356,141 @@ fn multiple_routes() {
     assert_eq!(300, trains[0].end().station().id());
 }
 
+#[test]
+fn alternative_routes() {
+    // Train 1 is the best route, switching from train 2 to train 3 the second best and train 4 the third
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 20, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "4",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 5, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap());
+    let train_ids = |routes: &[harail::Route]| -> Vec<Vec<String>> {
+        routes
+            .iter()
+            .map(|r| r.parts().map(|p| p.train().id().to_owned()).collect())
+            .collect()
+    };
+    let routes = harail::get_alternative_routes(
+        &data,
+        start_time,
+        data.station(100).unwrap(),
+        end_time,
+        data.station(300).unwrap(),
+        5,
+    );
+    assert_eq!(
+        vec![vec!["1"], vec!["2", "3"], vec!["4"]],
+        train_ids(&routes)
+    );
+
+    let graph = PreparedGraph::new(&data, start_time, end_time, &RoutingOptions::new());
+    let routes = graph.alternative_routes(
+        start_time,
+        data.station(100).unwrap(),
+        data.station(300).unwrap(),
+        2,
+    );
+    assert_eq!(vec![vec!["1"], vec!["2", "3"]], train_ids(&routes));
+
+    let options = RoutingOptions::new().with_max_transfers(Some(0));
+    let routes = harail::get_alternative_routes_with_options(
+        &data,
+        start_time,
+        data.station(100).unwrap(),
+        end_time,
+        data.station(300).unwrap(),
+        5,
+        &options,
+    );
+    assert_eq!(vec![vec!["1"], vec!["4"]], train_ids(&routes));
+}
+
+#[test]
+fn diverse_multiple_routes() {
+    // Switching from train 2 to train 1 is fastest, after which train 3 differs more from it than train 1 alone does
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 10, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 20, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 15, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |order| {
+        harail::get_multiple_routes_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(300).unwrap(),
+            &RoutingOptions::new().with_order(order),
+        )
+        .iter()
+        .map(|r| r.parts().map(|p| p.train().id().to_owned()).collect())
+        .collect::<Vec<Vec<String>>>()
+    };
+    assert_eq!(
+        vec![vec!["1"], vec!["2", "1"], vec!["3"]],
+        find(RouteOrder::Found)
+    );
+    assert_eq!(
+        vec![vec!["2", "1"], vec!["3"], vec!["1"]],
+        find(RouteOrder::Diverse)
+    );
+}
+
 #[test]
 fn wait_on_train_multiple_routes() {
     let trains = vec![
@@ -399,3 +539,1681 @@ fn wait_on_train_multiple_routes() {
     assert_eq!(200, trains[1].start().station().id());
     assert_eq!(300, trains[1].end().station().id());
 }
+
+#[test]
+fn min_transfer_time() {
+    // Train 1 arrives at 200 at 10:30, and train 2 leaves it at 10:32. With a 5 minute minimum transfer time at 200,
+    // the connection is infeasible and the later train 3 must be used instead.
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 32, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let mut data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |data: &RailroadData| {
+        let route = harail::get_best_single_route(
+            data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(300).unwrap(),
+        )
+        .unwrap();
+        route
+            .parts()
+            .map(|p| p.train().id().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec!["1", "2"], find(&data));
+    data.set_min_transfer_time(200, HaDuration::from_hms(0, 5, 0));
+    assert_eq!(vec!["1", "3"], find(&data));
+}
+
+#[test]
+fn max_transfers() {
+    // The fastest route rides train 2 and then switches to train 3, but with no switches allowed
+    // the slower direct train 1 must be used.
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 40, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |options: &RoutingOptions| {
+        let route = harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(300).unwrap(),
+            options,
+        )
+        .unwrap();
+        route
+            .parts()
+            .map(|p| p.train().id().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec!["2", "3"], find(&RoutingOptions::new()));
+    assert_eq!(
+        vec!["2", "3"],
+        find(&RoutingOptions::new().with_max_transfers(Some(1)))
+    );
+    assert_eq!(
+        vec!["1"],
+        find(&RoutingOptions::new().with_max_transfers(Some(0)))
+    );
+
+    let routes = harail::get_multiple_routes_with_options(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+        data.station(300).unwrap(),
+        &RoutingOptions::new().with_max_transfers(Some(0)),
+    );
+    assert!(routes.iter().all(|r| r.parts().count() == 1));
+}
+
+#[test]
+fn arrive_by() {
+    // Trains 1 and 2 both reach 300 by 11:30, but train 2 leaves later. Train 3 arrives too late.
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(9, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 45, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |deadline: NaiveTime| {
+        harail::get_best_route_arriving_by(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(0, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), deadline),
+            data.station(300).unwrap(),
+        )
+        .map(|route| {
+            route
+                .parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(
+        Some(vec!["2".to_owned()]),
+        find(NaiveTime::from_hms_opt(11, 30, 00).unwrap())
+    );
+    assert_eq!(
+        Some(vec!["1".to_owned()]),
+        find(NaiveTime::from_hms_opt(11, 29, 59).unwrap())
+    );
+    assert_eq!(None, find(NaiveTime::from_hms_opt(9, 59, 59).unwrap()));
+}
+
+#[test]
+fn arrive_by_max_transfers() {
+    // Train 1 goes directly, trains 2 and 3 leave later and switch at station 400
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(9, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 20, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 50, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(0, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(11, 00, 00).unwrap());
+    let train_ids = |route: Option<harail::Route>| {
+        route.map(|r| {
+            r.parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    let find = |options: &RoutingOptions| {
+        train_ids(harail::get_best_route_arriving_by_with_options(
+            &data,
+            start_time,
+            data.station(100).unwrap(),
+            end_time,
+            data.station(300).unwrap(),
+            options,
+        ))
+    };
+    assert_eq!(
+        Some(vec!["2".to_owned(), "3".to_owned()]),
+        find(&RoutingOptions::new())
+    );
+    assert_eq!(
+        Some(vec!["2".to_owned(), "3".to_owned()]),
+        find(&RoutingOptions::new().with_max_transfers(Some(1)))
+    );
+    assert_eq!(
+        Some(vec!["1".to_owned()]),
+        find(&RoutingOptions::new().with_max_transfers(Some(0)))
+    );
+
+    let options = RoutingOptions::new().with_max_transfers(Some(0));
+    let graph = PreparedGraph::new(&data, start_time, end_time, &options);
+    assert_eq!(
+        Some(vec!["1".to_owned()]),
+        train_ids(graph.route_arriving_by(
+            data.station(100).unwrap(),
+            end_time,
+            data.station(300).unwrap()
+        ))
+    );
+}
+
+#[test]
+fn avoid_stations_and_trains() {
+    // Train 1 goes directly, trains 2 and 3 switch at station 400.
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 10, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |options: &RoutingOptions| {
+        harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(300).unwrap(),
+            options,
+        )
+        .map(|route| {
+            route
+                .parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(Some(vec!["1".to_owned()]), find(&RoutingOptions::new()));
+    // Passing through an avoided station is allowed
+    assert_eq!(
+        Some(vec!["1".to_owned()]),
+        find(&RoutingOptions::new().with_avoided_stations([200]))
+    );
+    assert_eq!(
+        Some(vec!["2".to_owned(), "3".to_owned()]),
+        find(&RoutingOptions::new().with_avoided_trains(["1".to_owned()]))
+    );
+    assert_eq!(
+        None,
+        find(
+            &RoutingOptions::new()
+                .with_avoided_trains(["1".to_owned()])
+                .with_avoided_stations([400])
+        )
+    );
+    assert_eq!(
+        None,
+        find(&RoutingOptions::new().with_avoided_stations([300]))
+    );
+}
+
+#[test]
+fn filter_modes() {
+    // Bus 1 goes directly, trains 2 and 3 switch at station 400.
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        )
+        .with_mode(Mode::Bus),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 10, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |options: &RoutingOptions| {
+        harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(300).unwrap(),
+            options,
+        )
+        .map(|route| {
+            route
+                .parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(Some(vec!["1".to_owned()]), find(&RoutingOptions::new()));
+    for engine in [Engine::TimeExpanded, Engine::Raptor] {
+        assert_eq!(
+            Some(vec!["2".to_owned(), "3".to_owned()]),
+            find(
+                &RoutingOptions::new()
+                    .with_engine(engine)
+                    .with_modes([Mode::Rail])
+            )
+        );
+    }
+    assert_eq!(
+        None,
+        find(&RoutingOptions::new().with_modes([Mode::LightRail]))
+    );
+}
+
+#[test]
+fn wheelchair_routes() {
+    // Train 1 goes directly but is inaccessible, trains 2 and 3 switch at station 400.
+    let trains = || {
+        vec![
+            Train::from_stops_date(
+                "1",
+                vec![
+                    StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                    StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+                ],
+                test_date(),
+            )
+            .with_wheelchair_accessible(false),
+            Train::from_stops_date(
+                "2",
+                vec![
+                    StopSchedule::new(100, HaDuration::from_hms(10, 10, 00), None),
+                    StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+                ],
+                test_date(),
+            )
+            .with_wheelchair_accessible(true),
+            Train::from_stops_date(
+                "3",
+                vec![
+                    StopSchedule::new(400, HaDuration::from_hms(10, 40, 00), None),
+                    StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+                ],
+                test_date(),
+            ),
+        ]
+    };
+    let find = |stations: Vec<Station>, options: &RoutingOptions| {
+        let data = RailroadData::from_stations_trains(stations, trains());
+        harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(300).unwrap(),
+            options,
+        )
+        .map(|route| {
+            route
+                .parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    let wheelchair = RoutingOptions::new().with_wheelchair(true);
+    assert_eq!(
+        Some(vec!["1".to_owned()]),
+        find(test_data::stations(), &RoutingOptions::new())
+    );
+    // Train 3's accessibility is unknown, so it is still used
+    assert_eq!(
+        Some(vec!["2".to_owned(), "3".to_owned()]),
+        find(test_data::stations(), &wheelchair)
+    );
+    let stations = test_data::stations()
+        .into_iter()
+        .map(|s| match s.id() {
+            400 => Station::new(400, "stat_d").with_wheelchair_boarding(false),
+            _ => s,
+        })
+        .collect();
+    assert_eq!(None, find(stations, &wheelchair));
+}
+
+#[test]
+fn bikes_routes() {
+    // Train 1 goes directly but doesn't allow bicycles, trains 2 and 3 switch at station 400.
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        )
+        .with_bikes_allowed(false),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 10, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        )
+        .with_bikes_allowed(true),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |end_station: u64, options: &RoutingOptions| {
+        harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(end_station).unwrap(),
+            options,
+        )
+    };
+    let bikes = RoutingOptions::new().with_bikes(true);
+    let route = find(400, &bikes).unwrap();
+    let part = route.parts().next().unwrap();
+    assert_eq!(part.train().id(), "2");
+    assert_eq!(part.to_json()["bikes_allowed"], true);
+    assert!(find(300, &RoutingOptions::new()).is_some());
+    // Train 3 doesn't say whether it allows bicycles, so it isn't used
+    assert!(find(300, &bikes).is_none());
+}
+
+#[test]
+fn pareto_routes() {
+    // Train 1 is direct but slow, trains 2+3 switch once and are faster, trains 4+5+6 switch twice and are fastest.
+    // Train 7 switches once but is slower than trains 2+3, so it is not on the frontier.
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(12, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 20, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "4",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 10, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "5",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 15, 00), None),
+                StopSchedule::new(500, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "6",
+            vec![
+                StopSchedule::new(500, HaDuration::from_hms(10, 35, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "7",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 20, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 45, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let routes = harail::get_pareto_routes(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(13, 00, 00).unwrap()),
+        data.station(300).unwrap(),
+    );
+    let trains: Vec<Vec<&str>> = routes
+        .iter()
+        .map(|r| r.parts().map(|p| p.train().id().as_str()).collect())
+        .collect();
+    assert_eq!(vec![vec!["1"], vec!["2", "3"], vec!["4", "5", "6"]], trains);
+}
+
+#[test]
+fn raptor_engine() {
+    // Same network as wait_on_train_multiple_routes, plus a late train 3 used when transfer time at 200 is too short
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(
+                    200,
+                    HaDuration::from_hms(10, 20, 00),
+                    Some(HaDuration::from_hms(10, 30, 00)),
+                ),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 10, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 20, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 45, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 15, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let mut data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |data: &RailroadData, engine: Engine| {
+        harail::get_multiple_routes_with_options(
+            data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(300).unwrap(),
+            &RoutingOptions::new().with_engine(engine),
+        )
+        .iter()
+        .map(|route| {
+            route
+                .parts()
+                .map(|p| {
+                    (
+                        p.train().id().to_owned(),
+                        p.start().station().id(),
+                        p.end().station().id(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+    };
+    let expected = vec![
+        vec![("1".to_owned(), 100, 300)],
+        vec![("2".to_owned(), 100, 200), ("1".to_owned(), 200, 300)],
+    ];
+    assert_eq!(expected, find(&data, Engine::TimeExpanded));
+    assert_eq!(expected, find(&data, Engine::Raptor));
+
+    data.set_min_transfer_time(200, HaDuration::from_hms(0, 15, 0));
+    let expected = vec![
+        vec![("1".to_owned(), 100, 300)],
+        vec![("2".to_owned(), 100, 200), ("3".to_owned(), 200, 300)],
+    ];
+    assert_eq!(expected, find(&data, Engine::TimeExpanded));
+    assert_eq!(expected, find(&data, Engine::Raptor));
+}
+
+#[test]
+fn intermediate_stops() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(
+                200,
+                HaDuration::from_hms(10, 20, 00),
+                Some(HaDuration::from_hms(10, 30, 00)),
+            ),
+            StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            StopSchedule::new(400, HaDuration::from_hms(11, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    for engine in [Engine::TimeExpanded, Engine::Raptor] {
+        let route = harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(400).unwrap(),
+            &RoutingOptions::new().with_engine(engine),
+        )
+        .unwrap();
+        let part = route.parts().next().unwrap();
+        let stops = part
+            .intermediate_stops()
+            .iter()
+            .map(|s| (s.station().id(), s.arrival().time(), s.departure().time()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stops,
+            vec![
+                (
+                    200,
+                    NaiveTime::from_hms_opt(10, 20, 00).unwrap(),
+                    NaiveTime::from_hms_opt(10, 30, 00).unwrap()
+                ),
+                (
+                    300,
+                    NaiveTime::from_hms_opt(11, 00, 00).unwrap(),
+                    NaiveTime::from_hms_opt(11, 00, 00).unwrap()
+                ),
+            ]
+        );
+        assert_eq!(part.to_json()["intermediate_stops"][1]["station"], 300);
+    }
+}
+
+#[test]
+fn astar_engine() {
+    // Same network as shortest_path
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 40, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let options = RoutingOptions::new().with_engine(Engine::AStar);
+    let find = |end_station| {
+        harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(end_station).unwrap(),
+            &options,
+        )
+        .map(|route| {
+            route
+                .parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(Some(vec!["2".to_owned(), "3".to_owned()]), find(300));
+    assert_eq!(Some(vec!["1".to_owned()]), find(200));
+    // No train reaches station 500
+    assert_eq!(None, find(500));
+}
+
+#[derive(Debug, Default)]
+struct BuildRecorder(Mutex<Vec<Engine>>);
+
+impl SearchObserver for BuildRecorder {
+    fn graph_built(&self, engine: Engine, _elapsed: Duration) {
+        self.0.lock().unwrap().push(engine);
+    }
+}
+
+#[test]
+fn observe_graph_builds() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let recorder = Arc::new(BuildRecorder::default());
+    for engine in [Engine::TimeExpanded, Engine::Raptor] {
+        let options = RoutingOptions::new()
+            .with_engine(engine)
+            .with_observer(recorder.clone());
+        let route = harail::get_best_single_route_with_options(
+            &data,
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(9, 00, 00).unwrap()),
+            data.station(100).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+            data.station(200).unwrap(),
+            &options,
+        );
+        assert!(route.is_some());
+    }
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        vec![Engine::TimeExpanded, Engine::Raptor]
+    );
+}
+
+#[test]
+fn prepared_graph() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(11, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let time = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let recorder = Arc::new(BuildRecorder::default());
+    let options = RoutingOptions::new().with_observer(recorder.clone());
+    let graph = PreparedGraph::new(&data, time(0, 00), time(23, 59), &options);
+    fn assert_sync<T: Sync>(_: &T) {}
+    assert_sync(&graph);
+    let train_ids = |route: Option<harail::Route>| {
+        route.map(|r| {
+            r.parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    let station = |id| data.station(id).unwrap();
+    assert_eq!(
+        Some(vec!["1".to_owned()]),
+        train_ids(graph.best_route(time(9, 00), station(100), station(300)))
+    );
+    assert_eq!(
+        Some(vec!["2".to_owned()]),
+        train_ids(graph.best_route(time(10, 15), station(100), station(300)))
+    );
+    assert_eq!(
+        Some(vec!["2".to_owned()]),
+        train_ids(graph.best_route(time(10, 45), station(200), station(300)))
+    );
+    assert_eq!(
+        None,
+        train_ids(graph.best_route(time(10, 45), station(100), station(300)))
+    );
+    assert_eq!(
+        Some(vec!["1".to_owned()]),
+        train_ids(graph.route_arriving_by(station(100), time(11, 15), station(300)))
+    );
+    assert_eq!(
+        2,
+        graph
+            .multiple_routes(time(9, 00), station(100), station(300))
+            .len()
+    );
+    // The graph was only built once for all of the searches
+    assert_eq!(*recorder.0.lock().unwrap(), vec![Engine::TimeExpanded]);
+}
+
+#[test]
+fn search_budget() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let find = |options: &RoutingOptions| {
+        let query = RouteQuery::new(
+            data.station(100).unwrap(),
+            data.station(300).unwrap(),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+            NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+        )
+        .with_options(options.clone());
+        harail::find_routes(&data, &query)
+    };
+    let routes = find(&RoutingOptions::new().with_max_expanded_nodes(Some(1000))).unwrap();
+    assert_eq!(1, routes.len());
+    assert!(matches!(
+        find(&RoutingOptions::new().with_max_expanded_nodes(Some(1))),
+        Err(HaError::BudgetExceeded)
+    ));
+    assert!(matches!(
+        find(&RoutingOptions::new().with_time_limit(Some(Duration::ZERO))),
+        Err(HaError::BudgetExceeded)
+    ));
+
+    // The get_* functions give up on the search without failing
+    let route = harail::get_best_single_route_with_options(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+        data.station(300).unwrap(),
+        &RoutingOptions::new().with_max_expanded_nodes(Some(1)),
+    );
+    assert!(route.is_none());
+}
+
+#[test]
+fn earliest_arrivals() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(500, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(600, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let arrivals = harail::get_earliest_arrivals(
+        &data,
+        start_time,
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+        &RoutingOptions::new(),
+    );
+    let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    assert_eq!(
+        vec![
+            (100, at(10, 00), 0),
+            (200, at(10, 30), 1),
+            (300, at(11, 00), 1),
+            (400, at(11, 00), 2)
+        ],
+        arrivals
+            .iter()
+            .map(|a| (a.station().id(), a.time().time(), a.route().parts().count()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec!["1", "2"],
+        arrivals[3]
+            .route()
+            .parts()
+            .map(|p| p.train().id().to_owned())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(start_time, arrivals[0].time());
+}
+
+#[test]
+fn routes_to_many() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap());
+    let destinations: Vec<&Station> = [400, 500, 100, 300]
+        .iter()
+        .map(|&id| data.station(id).unwrap())
+        .collect();
+    let trains_of = |route: &Option<harail::Route>| {
+        route.as_ref().map(|r| {
+            r.parts()
+                .map(|p| p.train().id().to_owned())
+                .collect::<Vec<_>>()
+        })
+    };
+    let routes = harail::get_best_routes_to_many(
+        &data,
+        start_time,
+        data.station(100).unwrap(),
+        end_time,
+        &destinations,
+    );
+    assert_eq!(
+        vec![
+            Some(vec!["1".to_owned(), "2".to_owned()]),
+            None,
+            Some(vec![]),
+            Some(vec!["1".to_owned()]),
+        ],
+        routes.iter().map(trains_of).collect::<Vec<_>>()
+    );
+    for (destination, route) in destinations.iter().zip(&routes) {
+        let single = harail::get_best_single_route(
+            &data,
+            start_time,
+            data.station(100).unwrap(),
+            end_time,
+            destination,
+        );
+        assert_eq!(trains_of(&single), trains_of(route));
+    }
+
+    // Limiting train switches searches each destination separately
+    let routes = harail::get_best_routes_to_many_with_options(
+        &data,
+        start_time,
+        data.station(100).unwrap(),
+        end_time,
+        &destinations,
+        &RoutingOptions::new().with_max_transfers(Some(0)),
+    );
+    assert!(routes[0].is_none());
+    assert!(routes[3].is_some());
+}
+
+#[test]
+fn contracted_graph() {
+    // Train 2 waits at 200, and train 3 passes through the avoided station 500
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 10, 00), None),
+                StopSchedule::new(
+                    200,
+                    HaDuration::from_hms(10, 35, 00),
+                    Some(HaDuration::from_hms(10, 40, 00)),
+                ),
+                StopSchedule::new(400, HaDuration::from_hms(10, 50, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 55, 00), None),
+                StopSchedule::new(500, HaDuration::from_hms(11, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap());
+    let trains_of = |route: &harail::Route| {
+        route
+            .parts()
+            .map(|p| p.train().id().to_owned())
+            .collect::<Vec<_>>()
+    };
+    let options = RoutingOptions::new().with_avoided_stations([500]);
+    let graph = PreparedGraph::new(&data, start_time, end_time, &options);
+    let contracted = PreparedGraph::new(
+        &data,
+        start_time,
+        end_time,
+        &options.clone().with_contraction(true),
+    );
+    for (start, end) in [(100, 300), (100, 400), (200, 300), (400, 300)] {
+        let (start, end) = (data.station(start).unwrap(), data.station(end).unwrap());
+        assert_eq!(
+            graph
+                .best_route(start_time, start, end)
+                .map(|r| trains_of(&r)),
+            contracted
+                .best_route(start_time, start, end)
+                .map(|r| trains_of(&r))
+        );
+        assert_eq!(
+            graph
+                .multiple_routes(start_time, start, end)
+                .iter()
+                .map(trains_of)
+                .collect::<Vec<_>>(),
+            contracted
+                .multiple_routes(start_time, start, end)
+                .iter()
+                .map(trains_of)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            graph
+                .route_arriving_by(start, end_time, end)
+                .map(|r| trains_of(&r)),
+            contracted
+                .route_arriving_by(start, end_time, end)
+                .map(|r| trains_of(&r))
+        );
+    }
+    let route = contracted
+        .best_route(
+            start_time,
+            data.station(100).unwrap(),
+            data.station(300).unwrap(),
+        )
+        .unwrap();
+    assert_eq!(vec!["2", "3"], trains_of(&route));
+
+    // Train switches are still counted along shortcuts
+    let route = harail::get_best_single_route_with_options(
+        &data,
+        start_time,
+        data.station(100).unwrap(),
+        end_time,
+        data.station(300).unwrap(),
+        &options.with_contraction(true).with_max_transfers(Some(0)),
+    )
+    .unwrap();
+    assert_eq!(vec!["1"], trains_of(&route));
+}
+
+#[test]
+fn round_trip() {
+    let trip = |id: &str, from, departure, to, arrival| {
+        Train::from_stops_date(
+            id,
+            vec![
+                StopSchedule::new(from, departure, None),
+                StopSchedule::new(to, arrival, None),
+            ],
+            test_date(),
+        )
+    };
+    let trains = vec![
+        trip(
+            "1",
+            100,
+            HaDuration::from_hms(9, 00, 00),
+            200,
+            HaDuration::from_hms(9, 30, 00),
+        ),
+        trip(
+            "2",
+            100,
+            HaDuration::from_hms(10, 00, 00),
+            200,
+            HaDuration::from_hms(10, 30, 00),
+        ),
+        trip(
+            "3",
+            200,
+            HaDuration::from_hms(12, 00, 00),
+            100,
+            HaDuration::from_hms(12, 30, 00),
+        ),
+        trip(
+            "4",
+            200,
+            HaDuration::from_hms(18, 00, 00),
+            100,
+            HaDuration::from_hms(18, 30, 00),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 0).unwrap());
+    let plan = |stay, end_time| {
+        harail::get_round_trip(
+            &data,
+            at(8, 00),
+            data.station(100).unwrap(),
+            data.station(200).unwrap(),
+            end_time,
+            stay,
+            &RoutingOptions::new(),
+        )
+        .map(|trip| {
+            let train =
+                |route: &harail::Route| route.parts().next().unwrap().train().id().to_owned();
+            (
+                train(trip.outbound()),
+                train(trip.inbound()),
+                trip.stay().num_minutes(),
+            )
+        })
+    };
+
+    // Train 2 still makes it in time for the earliest train back, so the trip leaves home with it
+    assert_eq!(
+        Some(("2".to_owned(), "3".to_owned(), 90)),
+        plan(Stay::AtLeast(chrono::Duration::minutes(90)), at(20, 00))
+    );
+    assert_eq!(
+        Some(("1".to_owned(), "4".to_owned(), 510)),
+        plan(Stay::Longest, at(20, 00))
+    );
+    assert_eq!(
+        Some(("1".to_owned(), "3".to_owned(), 150)),
+        plan(Stay::Longest, at(13, 00))
+    );
+    assert_eq!(None, plan(Stay::Longest, at(12, 00)));
+    assert_eq!(
+        None,
+        plan(Stay::AtLeast(chrono::Duration::hours(10)), at(20, 00))
+    );
+}
+
+#[test]
+fn min_transfer_time_option() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 32, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap());
+    for engine in [Engine::TimeExpanded, Engine::Raptor] {
+        let trains_with = |min_transfer_time| {
+            harail::get_best_single_route_with_options(
+                &data,
+                start_time,
+                data.station(100).unwrap(),
+                end_time,
+                data.station(300).unwrap(),
+                &RoutingOptions::new()
+                    .with_engine(engine)
+                    .with_min_transfer_time(min_transfer_time),
+            )
+            .unwrap()
+            .parts()
+            .map(|p| p.train().id().to_owned())
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["1", "2"], trains_with(None));
+        assert_eq!(
+            vec!["1", "3"],
+            trains_with(Some(Duration::from_secs(4 * 60)))
+        );
+    }
+}
+
+#[test]
+fn action_weights() {
+    // Switching trains arrives half an hour earlier than the direct train 1
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 35, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap());
+    for engine in [Engine::TimeExpanded, Engine::AStar] {
+        let trains_with = |weights| {
+            harail::get_best_single_route_with_options(
+                &data,
+                start_time,
+                data.station(100).unwrap(),
+                end_time,
+                data.station(300).unwrap(),
+                &RoutingOptions::new()
+                    .with_engine(engine)
+                    .with_weights(weights),
+            )
+            .unwrap()
+            .parts()
+            .map(|p| p.train().id().to_owned())
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["2", "3"], trains_with(ActionWeights::new()));
+        assert_eq!(
+            vec!["1"],
+            trains_with(ActionWeights::new().with_board(Duration::from_secs(40 * 60)))
+        );
+    }
+}
+
+#[test]
+fn transfer_slack() {
+    // Train 2 leaves 200 three minutes after train 1 arrives there, and train 3 a quarter of an hour after
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 33, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 45, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 5, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let start_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap());
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap());
+    for engine in [Engine::TimeExpanded, Engine::AStar] {
+        let trains_with = |options: RoutingOptions| {
+            harail::get_best_single_route_with_options(
+                &data,
+                start_time,
+                data.station(100).unwrap(),
+                end_time,
+                data.station(300).unwrap(),
+                &options.with_engine(engine),
+            )
+            .unwrap()
+            .parts()
+            .map(|p| p.train().id().to_owned())
+            .collect::<Vec<_>>()
+        };
+        let slack = RoutingOptions::new().with_transfer_slack(Some(Duration::from_secs(10 * 60)));
+        assert_eq!(vec!["1", "2"], trains_with(RoutingOptions::new()));
+        // Arriving 5 minutes later is worth the default penalty of a tight train switch
+        assert_eq!(vec!["1", "3"], trains_with(slack.clone()));
+        assert_eq!(
+            vec!["1", "2"],
+            trains_with(slack.with_weights(
+                ActionWeights::new().with_tight_transfer(Duration::from_secs(2 * 60))
+            ))
+        );
+    }
+}
+
+#[test]
+fn route_explanations() {
+    // Train 1 goes directly, while switching from train 2 to train 3 arrives half an hour earlier
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 35, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let routes = harail::get_pareto_routes(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+        data.station(300).unwrap(),
+    );
+    assert_eq!(2, routes.len());
+    let direct = routes[0].explanation().unwrap();
+    assert_eq!(0, direct.transfers());
+    assert!(direct.has_fewest_transfers() && !direct.is_fastest() && !direct.is_tied());
+    let switching = routes[1].explanation().unwrap();
+    assert_eq!(1, switching.transfers());
+    assert!(switching.is_fastest() && !switching.has_fewest_transfers());
+    assert_eq!(chrono::Duration::minutes(55), switching.ride_time());
+    assert_eq!(
+        vec![chrono::Duration::minutes(5)],
+        switching.waits().collect::<Vec<_>>()
+    );
+    assert_eq!(routes[1].to_json()["explanation"]["wait_seconds"][0], 300);
+}
+
+#[test]
+fn route_summary() {
+    // Train 1 stops at 200 on its way to 300, where train 2 is switched to after a 10 minute wait
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 20, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 40, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(300, HaDuration::from_hms(10, 50, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(11, 20, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let route = harail::get_best_single_route(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(10, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(12, 00, 00).unwrap()),
+        data.station(400).unwrap(),
+    )
+    .unwrap();
+    let summary = route.summary();
+    assert_eq!(chrono::Duration::minutes(80), summary.duration());
+    assert_eq!(chrono::Duration::minutes(70), summary.ride_time());
+    assert_eq!(chrono::Duration::minutes(10), summary.wait_time());
+    assert_eq!(1, summary.transfers());
+    assert_eq!(2, summary.stations_passed());
+    assert_eq!(summary, harail::OwnedRoute::from(&route).summary());
+    assert_eq!(route.to_json()["summary"]["duration_seconds"], 4800);
+}
+
+#[test]
+fn past_midnight_trips() {
+    // Train 1 runs on the night of test_date, leaving 100 before midnight and reaching 200 and 300 after it. Its times
+    // past 24:00:00 belong to test_date's service day, though they are on the day after.
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(23, 30, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(24, 30, 00), None),
+            StopSchedule::new(300, HaDuration::from_hms(25, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let next_day = test_date().succ_opt().unwrap();
+    let at = |date, h, m| NaiveDateTime::new(date, NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    for engine in [Engine::TimeExpanded, Engine::AStar, Engine::Raptor] {
+        let options = RoutingOptions::new().with_engine(engine);
+        let ride = |start_time, start_station, end_station| {
+            harail::get_best_single_route_with_options(
+                &data,
+                start_time,
+                data.station(start_station).unwrap(),
+                at(next_day, 6, 00),
+                data.station(end_station).unwrap(),
+                &options,
+            )
+            .map(|route| {
+                let part = route.parts().next().unwrap();
+                (part.start().departure(), part.end().arrival())
+            })
+        };
+        // Searching on the previous day rides through midnight
+        assert_eq!(
+            Some((at(test_date(), 23, 30), at(next_day, 1, 30))),
+            ride(at(test_date(), 22, 00), 100, 300)
+        );
+        // Searching after midnight still finds the run of the previous service day
+        assert_eq!(
+            Some((at(next_day, 0, 30), at(next_day, 1, 30))),
+            ride(at(next_day, 0, 00), 200, 300)
+        );
+        assert_eq!(None, ride(at(next_day, 0, 45), 200, 300));
+    }
+}
+
+#[test]
+fn save_and_load_prepared_graph() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None).with_platform("2"),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None).with_platform("1"),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let graph = PreparedGraph::new(&data, at(0, 00), at(23, 59), &RoutingOptions::new());
+    let mut file = Vec::new();
+    graph.save(&mut file).unwrap();
+    let route = |graph: &PreparedGraph| {
+        graph
+            .best_route(
+                at(9, 00),
+                data.station(100).unwrap(),
+                data.station(300).unwrap(),
+            )
+            .unwrap()
+            .to_json()
+    };
+
+    // Options which only affect searches may differ, and contraction is applied when loading
+    for options in [
+        RoutingOptions::new(),
+        RoutingOptions::new().with_engine(Engine::AStar),
+        RoutingOptions::new().with_contraction(true),
+    ] {
+        let loaded = PreparedGraph::load(&data, file.as_slice(), &options).unwrap();
+        assert_eq!(at(0, 00), loaded.start_time());
+        assert_eq!(at(23, 59), loaded.end_time());
+        assert_eq!(route(&graph), route(&loaded));
+    }
+
+    // The graph must be loaded with options shaping it the same way, along with the database it was prepared from
+    let options = RoutingOptions::new().with_avoided_stations([200]);
+    assert!(matches!(
+        PreparedGraph::load(&data, file.as_slice(), &options),
+        Err(HaError::UsageError(_))
+    ));
+    let other = RailroadData::from_stations_trains(test_data::stations(), Vec::new());
+    assert!(matches!(
+        PreparedGraph::load(&other, file.as_slice(), &RoutingOptions::new()),
+        Err(HaError::DatabaseError(_))
+    ));
+    assert!(matches!(
+        PreparedGraph::load(&data, &b"HARAILDB"[..], &RoutingOptions::new()),
+        Err(HaError::DatabaseError(_))
+    ));
+
+    let contracted = PreparedGraph::new(
+        &data,
+        at(0, 00),
+        at(23, 59),
+        &RoutingOptions::new().with_contraction(true),
+    );
+    assert!(matches!(
+        contracted.save(Vec::new()),
+        Err(HaError::UsageError(_))
+    ));
+}
+
+#[test]
+fn deterministic_ties() {
+    // The trains make the same ride, so every engine takes the one whose ID is least, however the hash maps of the
+    // database and the graph happen to be ordered
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    for _ in 0..10 {
+        let trains = ["3", "1", "4", "2"]
+            .into_iter()
+            .map(|id| {
+                Train::from_stops_date(
+                    id,
+                    vec![
+                        StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                        StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                    ],
+                    test_date(),
+                )
+            })
+            .collect();
+        let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+        for engine in [Engine::TimeExpanded, Engine::AStar, Engine::Raptor] {
+            let route = harail::get_best_single_route_with_options(
+                &data,
+                at(9, 00),
+                data.station(100).unwrap(),
+                at(12, 00),
+                data.station(200).unwrap(),
+                &RoutingOptions::new().with_engine(engine),
+            )
+            .unwrap();
+            assert_eq!("1", route.parts().next().unwrap().train().id().as_str());
+        }
+    }
+}
@@ -4,8 +4,10 @@
 * License, v. 2.0. If a copy of the MPL was not distributed with this
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use harail::RailroadData;
+use chrono::NaiveDate;
+use harail::{AgencyFilter, GtfsError, GtfsOptions, HaDuration, Mode, RailroadData, JSON};
 use std::path::Path;
+use tempfile::TempDir;
 
 #[test]
 fn load_zipped_gtfs() {
@@ -15,3 +17,385 @@ fn load_zipped_gtfs() {
     assert_eq!(data.stations().count(), 66);
     assert_eq!(data.trains().count(), 4119);
 }
+
+const MINIMAL_FEED: [(&str, &str); 6] = [
+    (
+        "agency.txt",
+        "agency_id,agency_name\n2,רכבת ישראל\n3,Other\n",
+    ),
+    ("routes.txt", "route_id,agency_id\n10,2\n11,3\n"),
+    (
+        "calendar.txt",
+        "service_id,sunday,monday,tuesday,wednesday,thursday,friday,saturday,start_date,end_date\n1,1,1,1,1,1,1,1,20200101,20200110\n",
+    ),
+    ("trips.txt", "route_id,service_id,trip_id\n10,1,T1\n11,1,B1\n"),
+    (
+        "stop_times.txt",
+        "trip_id,arrival_time,departure_time,stop_id,stop_sequence\nT1,10:00:00,10:00:00,100,1\nT1,10:30:00,10:32:00,200,2\nB1,10:00:00,10:00:00,900,1\nB1,10:10:00,10:10:00,100,2\n",
+    ),
+    (
+        "stops.txt",
+        "stop_id,stop_name,stop_lat,stop_lon\n100,Tel Aviv Center,32.0836,34.7982\n200,Herzliya,,\n900,Bus stop,32.0,34.7\n",
+    ),
+];
+
+#[test]
+fn load_tar_gz_gtfs() {
+    let path = tempfile::Builder::new()
+        .prefix("harail-test-")
+        .suffix(".tar.gz")
+        .tempfile()
+        .unwrap()
+        .into_temp_path();
+    {
+        let file = std::fs::File::create(&path).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, contents) in MINIMAL_FEED {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, format!("feed/{}", name), contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+    let data = RailroadData::from_gtfs_path(&path).unwrap();
+    assert_eq!(data.stations().count(), 2);
+    assert_eq!(data.trains().count(), 1);
+    assert_eq!(data.train("T1").unwrap().stops().count(), 2);
+    assert_eq!(Some(32.0836), data.station(100).unwrap().lat());
+    assert_eq!(Some(34.7982), data.station(100).unwrap().lon());
+    assert_eq!(None, data.station(200).unwrap().lat());
+    assert!(data.build_time().is_some());
+    assert_eq!(
+        Some((
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2020, 1, 10).unwrap()
+        )),
+        data.date_range()
+    );
+}
+
+/// Writes MINIMAL_FEED into a temporary directory, with the given files replacing or adding to its own.
+///
+/// The directory is removed when the returned TempDir is dropped, even if the test panics.
+fn write_feed(overrides: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::Builder::new()
+        .prefix("harail-test-")
+        .tempdir()
+        .unwrap();
+    for (name, contents) in MINIMAL_FEED.iter().chain(overrides) {
+        std::fs::write(dir.path().join(name), contents).unwrap();
+    }
+    dir
+}
+
+/// Loads MINIMAL_FEED with the given files replacing or adding to its own
+fn load_feed(overrides: &[(&str, &str)]) -> Result<RailroadData, GtfsError> {
+    load_feed_with_options(overrides, &GtfsOptions::new())
+}
+
+fn load_feed_with_options(
+    overrides: &[(&str, &str)],
+    options: &GtfsOptions,
+) -> Result<RailroadData, GtfsError> {
+    let dir = write_feed(overrides);
+    RailroadData::from_gtfs_path_with_options(dir.path(), options)
+}
+
+#[test]
+fn filter_agencies() {
+    let load = |agencies| load_feed_with_options(&[], &GtfsOptions::new().with_agencies(agencies));
+    let all = load(AgencyFilter::All).unwrap();
+    let other = load(AgencyFilter::Matching(vec!["Other".to_owned()])).unwrap();
+    let by_id = load(AgencyFilter::Matching(vec!["3".to_owned()])).unwrap();
+    let missing = load(AgencyFilter::Matching(vec!["Egged".to_owned()]));
+    assert_eq!(all.trains().count(), 2);
+    assert_eq!(all.stations().count(), 3);
+    assert!(other.train("B1").is_some());
+    assert!(other.train("T1").is_none());
+    assert_eq!(by_id.trains().count(), 1);
+    assert!(missing.is_err());
+}
+
+#[test]
+fn filter_modes() {
+    let routes = [(
+        "routes.txt",
+        "route_id,agency_id,route_type\n10,2,2\n11,3,3\n",
+    )];
+    let options = GtfsOptions::new().with_agencies(AgencyFilter::All);
+    let all = load_feed_with_options(&routes, &options).unwrap();
+    let buses = load_feed_with_options(&routes, &options.with_modes([Mode::Bus])).unwrap();
+    assert_eq!(all.train("T1").unwrap().mode(), Mode::Rail);
+    assert_eq!(all.train("B1").unwrap().mode(), Mode::Bus);
+    assert!(buses.train("T1").is_none());
+    assert_eq!(buses.stations().count(), 2);
+}
+
+#[test]
+fn load_platforms() {
+    let data = load_feed(&[
+        (
+            "stop_times.txt",
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence\nT1,10:00:00,10:00:00,101,1\nT1,10:30:00,10:32:00,200,2\n",
+        ),
+        (
+            "stops.txt",
+            "stop_id,stop_name,location_type,parent_station,platform_code\n100,Tel Aviv Center,1,,\n101,Tel Aviv Center Platform 3,0,100,3\n200,Herzliya,0,,\n",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(data.stations().count(), 2);
+    assert!(data.station(101).is_none());
+    let stops: Vec<_> = data.train("T1").unwrap().stops().collect();
+    assert_eq!(stops[0].station(), 100);
+    assert_eq!(stops[0].platform(), Some("3"));
+    assert_eq!(stops[1].platform(), None);
+}
+
+#[test]
+fn load_station_hierarchy() {
+    let data = load_feed(&[
+        (
+            "stop_times.txt",
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence\nT1,10:00:00,10:00:00,102,1\nT1,10:30:00,10:32:00,200,2\n",
+        ),
+        // Boarding area 102 is within platform 101, and entrance 103 leads into station 100
+        (
+            "stops.txt",
+            "stop_id,stop_name,location_type,parent_station,platform_code\n100,Tel Aviv Center,1,,\n101,Tel Aviv Center Platform 3,0,100,3\n102,Platform 3 North,4,101,\n103,North Entrance,2,100,\n104,Tel Aviv Center Platform 4,0,100,4\n200,Herzliya,0,,\n",
+        ),
+        // Transfers between the station's platforms take the longest of their times
+        (
+            "transfers.txt",
+            "from_stop_id,to_stop_id,transfer_type,min_transfer_time\n101,104,2,180\n104,102,2,240\n101,200,2,600\n",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(data.stations().count(), 2);
+    assert!(data.station(102).is_none());
+    let stops: Vec<_> = data.train("T1").unwrap().stops().collect();
+    assert_eq!(stops[0].station(), 100);
+    assert_eq!(stops[0].platform(), Some("3"));
+    assert_eq!(
+        Some(HaDuration::from_seconds(240).to_chrono()),
+        data.min_transfer_time(100).map(|d| d.to_chrono())
+    );
+    assert!(data.min_transfer_time(200).is_none());
+}
+
+#[test]
+fn load_wheelchair_accessibility() {
+    let data = load_feed(&[
+        (
+            "trips.txt",
+            "route_id,service_id,trip_id,wheelchair_accessible\n10,1,T1,1\n11,1,B1,\n",
+        ),
+        (
+            "stops.txt",
+            "stop_id,stop_name,wheelchair_boarding\n100,Tel Aviv Center,1\n200,Herzliya,2\n900,Bus stop,0\n",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(
+        data.train("T1").unwrap().wheelchair_accessible(),
+        Some(true)
+    );
+    assert_eq!(data.station(100).unwrap().wheelchair_boarding(), Some(true));
+    assert_eq!(
+        data.station(200).unwrap().wheelchair_boarding(),
+        Some(false)
+    );
+}
+
+#[test]
+fn load_trip_short_names() {
+    // B1 belongs to another agency, which is only loaded on request
+    let data = load_feed_with_options(
+        &[(
+            "trips.txt",
+            "route_id,service_id,trip_id,trip_short_name\n10,1,T1,115\n11,1,B1,\n",
+        )],
+        &GtfsOptions::new().with_agencies(AgencyFilter::All),
+    )
+    .unwrap();
+    let train = data.train("T1").unwrap();
+    assert_eq!(train.short_name(), Some("115"));
+    assert_eq!(train.number(), "115");
+    let ids: Vec<_> = data.find_trains("115").iter().map(|t| t.id()).collect();
+    assert_eq!(ids, vec!["T1"]);
+    assert!(data
+        .find_train("115", NaiveDate::from_ymd_opt(2020, 1, 5).unwrap())
+        .is_some());
+    assert!(data
+        .find_train("115", NaiveDate::from_ymd_opt(2020, 1, 11).unwrap())
+        .is_none());
+    assert_eq!(data.train("B1").unwrap().number(), "B1");
+}
+
+#[test]
+fn load_trip_directions() {
+    let data = load_feed(&[(
+        "trips.txt",
+        "route_id,service_id,trip_id,direction_id\n10,1,T1,1\n11,1,B1,\n",
+    )]);
+    let invalid = load_feed(&[(
+        "trips.txt",
+        "route_id,service_id,trip_id,direction_id\n10,1,T1,2\n11,1,B1,\n",
+    )]);
+    assert_eq!(data.unwrap().train("T1").unwrap().direction_id(), Some(1));
+    assert!(invalid.is_err());
+}
+
+#[test]
+fn expand_frequencies() {
+    let data = load_feed(&[(
+        "frequencies.txt",
+        "trip_id,start_time,end_time,headway_secs\nT1,06:00:00,07:00:00,1200\nB1,06:00:00,07:00:00,600\n",
+    )])
+    .unwrap();
+    assert!(data.train("T1").is_none());
+    let mut ids: Vec<_> = data.trains().map(|t| t.id().as_str()).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["T1_06:00:00", "T1_06:20:00", "T1_06:40:00"]);
+    let stops: Vec<_> = data.train("T1_06:20:00").unwrap().stops().collect();
+    assert_eq!(
+        HaDuration::from_hms(6, 20, 0).to_chrono(),
+        stops[0].departure_offset().to_chrono()
+    );
+    assert_eq!(
+        HaDuration::from_hms(6, 50, 0).to_chrono(),
+        stops[1].arrival_offset().to_chrono()
+    );
+    assert_eq!(
+        HaDuration::from_hms(6, 52, 0).to_chrono(),
+        stops[1].departure_offset().to_chrono()
+    );
+}
+
+#[test]
+fn load_transfers() {
+    let data = load_feed(&[(
+        "transfers.txt",
+        "from_stop_id,to_stop_id,transfer_type,min_transfer_time\n100,100,2,180\n200,100,2,600\n200,200,0,\n",
+    )])
+    .unwrap();
+    assert_eq!(
+        Some(HaDuration::from_seconds(180).to_chrono()),
+        data.min_transfer_time(100).map(|d| d.to_chrono())
+    );
+    assert!(data.min_transfer_time(200).is_none());
+}
+
+#[test]
+fn load_shapes() {
+    let data = load_feed(&[
+        (
+            "trips.txt",
+            "route_id,service_id,trip_id,shape_id\n10,1,T1,S1\n11,1,B1,S2\n",
+        ),
+        (
+            "shapes.txt",
+            "shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence\nS1,32.2,34.8,3\nS1,32.0836,34.7982,1\nS1,32.1,34.8,2\nS2,32.0,34.7,1\n",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(
+        Some("S1"),
+        data.train("T1").unwrap().shape().map(|s| s.as_str())
+    );
+    assert_eq!(
+        Some(&[(32.0836, 34.7982), (32.1, 34.8), (32.2, 34.8)][..]),
+        data.shape("S1")
+    );
+    assert!(data.shape("S2").is_none());
+}
+
+#[test]
+fn load_fares() {
+    let data = load_feed(&[
+        (
+            "stops.txt",
+            "stop_id,stop_name,zone_id\n100,Tel Aviv Center,A\n200,Herzliya,B\n900,Bus stop,A\n",
+        ),
+        (
+            "fare_attributes.txt",
+            "fare_id,price,currency_type,payment_method,transfers\nF1,5.5,ILS,0,0\nF2,13,ILS,0,0\nF3,2,ILS,0,0\n",
+        ),
+        // F3 is cheaper, but only applies to rides within zone A
+        (
+            "fare_rules.txt",
+            "fare_id,route_id,origin_id,destination_id\nF1,10,A,B\nF3,,A,A\n",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(data.station(100).unwrap().fare_zone(), Some("A"));
+    let start_time = NaiveDate::from_ymd_opt(2020, 1, 1)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap();
+    let route = harail::get_best_single_route(
+        &data,
+        start_time,
+        data.station(100).unwrap(),
+        start_time + chrono::Duration::hours(3),
+        data.station(200).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(route.price().map(|p| p.agorot()), Some(550));
+    assert_eq!(route.to_json()["price"]["agorot"], 550);
+}
+
+#[test]
+fn report_error_location() {
+    let dir = write_feed(&[(
+        "stops.txt",
+        "stop_id,stop_name,stop_lat,stop_lon\n100,Tel Aviv Center,32.0836,34.7982\n200,Herzliya,north,34.8\n",
+    )]);
+    let invalid = RailroadData::from_gtfs_directory(dir.path()).err().unwrap();
+    std::fs::write(dir.path().join("trips.txt"), "route_id,trip_id\n10,T1\n").unwrap();
+    let missing_column = RailroadData::from_gtfs_directory(dir.path()).err().unwrap();
+    std::fs::remove_file(dir.path().join("calendar.txt")).unwrap();
+    let missing_file = RailroadData::from_gtfs_directory(dir.path()).err().unwrap();
+    assert!(matches!(invalid, GtfsError::InvalidValue { .. }));
+    assert_eq!(invalid.file(), "stops.txt");
+    assert_eq!(invalid.line(), Some(3));
+    assert_eq!(invalid.column(), Some("stop_lat"));
+    assert_eq!(
+        invalid.to_string(),
+        "stops.txt, line 3: invalid stop_lat \"north\""
+    );
+    assert_eq!(missing_column.file(), "trips.txt");
+    assert_eq!(missing_column.column(), Some("service_id"));
+    assert!(matches!(missing_file, GtfsError::MissingFile { .. }));
+    assert_eq!(missing_file.file(), "calendar.txt");
+}
+
+#[test]
+fn load_scattered_stop_times() {
+    let data = load_feed(&[
+        (
+            "trips.txt",
+            "route_id,service_id,trip_id\n10,1,T1\n10,1,T2\n11,1,B1\n",
+        ),
+        // The stops of each trip are interleaved with those of other trips, and out of order
+        (
+            "stop_times.txt",
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence\nT1,10:30:00,10:32:00,200,2\nB1,10:00:00,10:00:00,900,1\nT2,11:00:00,11:00:00,100,1\nT1,10:00:00,10:00:00,100,1\nT2,11:30:00,11:30:00,200,2\nT1,11:00:00,11:00:00,900,3\n",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(data.trains().count(), 2);
+    let stations: Vec<_> = data
+        .train("T1")
+        .unwrap()
+        .stops()
+        .map(|s| s.station())
+        .collect();
+    assert_eq!(stations, vec![100, 200, 900]);
+    assert_eq!(data.train("T2").unwrap().stops().count(), 2);
+}
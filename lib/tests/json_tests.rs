@@ -6,7 +6,8 @@
 
 mod test_data;
 use harail::{
-    HaDuration, RailroadData, Route, RoutePart, Station, Stop, StopSchedule, Train, JSON,
+    HaDuration, OwnedRoute, OwnedStop, RailroadData, Route, RoutePart, Station, Stop, StopSchedule,
+    Train, Zoned, JSON,
 };
 use test_data::test_date;
 
@@ -14,6 +15,16 @@ use test_data::test_date;
 fn stations_save() {
     let x = Station::new(100, "stationary");
     assert_eq!(x.to_json().dump(), r#"{"id":100,"name":"stationary"}"#);
+    let x = Station::with_coordinates(100, "stationary", 32.5, 34.75);
+    assert_eq!(
+        x.to_json().dump(),
+        r#"{"id":100,"name":"stationary","lat":32.5,"lon":34.75}"#
+    );
+    let x = Station::new(100, "stationary").with_fare_zone("7");
+    assert_eq!(
+        x.to_json().dump(),
+        r#"{"id":100,"name":"stationary","fare_zone":"7"}"#
+    );
 }
 
 #[test]
@@ -35,6 +46,264 @@ fn route_save() {
     let route = Route::from_parts(vec![RoutePart::new(train, stops[0], stops[1])]);
     assert_eq!(
         route.to_json().dump(),
-        r#"{"parts":[{"train":"1","start_time":"2000-01-01T10:00:00+00:00","start_station":100,"end_time":"2000-01-01T10:30:00+00:00","end_station":200}]}"#
+        r#"{"parts":[{"train":"1","start_time":"2000-01-01T10:00:00+02:00","start_station":100,"end_time":"2000-01-01T10:30:00+02:00","end_station":200,"duration_seconds":1800}],"summary":{"duration_seconds":1800,"ride_seconds":1800,"wait_seconds":0,"transfers":0,"stations_passed":0}}"#
+    );
+}
+
+#[test]
+fn route_save_platforms() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None).with_platform("3"),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let train = data.train("1").unwrap();
+    let stops = train
+        .stops()
+        .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        stops[0].to_json().dump(),
+        r#"{"station":100,"arrival":"2000-01-01T10:00:00+02:00","departure":"2000-01-01T10:00:00+02:00","platform":"3"}"#
+    );
+    let part = RoutePart::new(train, stops[0], stops[1]);
+    assert_eq!(
+        part.to_json().dump(),
+        r#"{"train":"1","start_time":"2000-01-01T10:00:00+02:00","start_station":100,"end_time":"2000-01-01T10:30:00+02:00","end_station":200,"duration_seconds":1800,"start_platform":"3"}"#
+    );
+}
+
+#[test]
+fn route_save_intermediate_stops() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None).with_platform("1"),
+            StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let train = data.train("1").unwrap();
+    let stops = train
+        .stops()
+        .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+        .collect::<Vec<_>>();
+    let part = RoutePart::new(train, stops[0], stops[2]).with_intermediate_stops(vec![stops[1]]);
+    assert_eq!(
+        part.to_json().dump(),
+        r#"{"train":"1","start_time":"2000-01-01T10:00:00+02:00","start_station":100,"end_time":"2000-01-01T11:00:00+02:00","end_station":300,"duration_seconds":3600,"intermediate_stops":[{"station":200,"arrival":"2000-01-01T10:30:00+02:00","departure":"2000-01-01T10:30:00+02:00","platform":"1"}]}"#
+    );
+    let route = Route::from_parts(vec![part]);
+    let json = serde_json::to_string(&route).unwrap();
+    assert_eq!(
+        serde_json::from_str::<OwnedRoute>(&json).unwrap(),
+        OwnedRoute::from(&route)
+    );
+    assert_eq!(
+        route.to_json().dump(),
+        OwnedRoute::from(&route).to_json().dump()
+    );
+}
+
+#[test]
+fn owned_stop_save() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None).with_platform("1"),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let train = data.train("1").unwrap();
+    let stops = train
+        .stops()
+        .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        OwnedStop::from(&stops[1]).to_json().dump(),
+        r#"{"station":200,"arrival":"2000-01-01T10:30:00+02:00","departure":"2000-01-01T10:30:00+02:00","platform":"1"}"#
     );
+    for stop in &stops {
+        assert_eq!(
+            OwnedStop::from(stop).to_json().dump(),
+            stop.to_json().dump()
+        );
+    }
+}
+
+#[test]
+fn route_save_waits_past_midnight() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(23, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(23, 50, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(24, 10, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(24, 40, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let part = |id: &str| {
+        let train = data.train(id).unwrap();
+        let stops = train
+            .stops()
+            .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+            .collect::<Vec<_>>();
+        RoutePart::new(train, stops[0], stops[1])
+    };
+    let route = Route::from_parts(vec![part("1"), part("2")]);
+    let json = route.to_json();
+    assert_eq!(json["parts"][0]["duration_seconds"], 3000);
+    assert!(json["parts"][0]["wait_seconds"].is_null());
+    assert_eq!(json["parts"][1]["duration_seconds"], 1800);
+    assert_eq!(json["parts"][1]["wait_seconds"], 1200);
+    assert_eq!(json.dump(), OwnedRoute::from(&route).to_json().dump());
+}
+
+#[test]
+fn route_serde() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None).with_platform("3"),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let train = data.train("1").unwrap();
+    let stops = train
+        .stops()
+        .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+        .collect::<Vec<_>>();
+    let route = Route::from_parts(vec![RoutePart::new(train, stops[0], stops[1])]);
+    let json = serde_json::to_string(&route).unwrap();
+    assert_eq!(
+        json,
+        r#"{"parts":[{"train":"1","start":{"station":100,"station_name":"stat_a","platform":"3","arrival":"2000-01-01T10:00:00+02:00","departure":"2000-01-01T10:00:00+02:00","delayed":false},"end":{"station":200,"station_name":"stat_b","arrival":"2000-01-01T10:30:00+02:00","departure":"2000-01-01T10:30:00+02:00","delayed":false}}]}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<OwnedRoute>(&json).unwrap(),
+        OwnedRoute::from(&route)
+    );
+}
+
+#[test]
+fn stop_save_summer_time() {
+    let summer = chrono::NaiveDate::from_ymd_opt(2020, 7, 1).unwrap();
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![StopSchedule::new(
+            100,
+            HaDuration::from_hms(25, 00, 00),
+            None,
+        )],
+        summer,
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let stop = Stop::from_stop_schedule(
+        &data,
+        data.train("1").unwrap().stops().next().unwrap(),
+        summer,
+    );
+    assert_eq!(
+        stop.to_json().dump(),
+        r#"{"station":100,"arrival":"2020-07-02T01:00:00+03:00","departure":"2020-07-02T01:00:00+03:00"}"#
+    );
+    assert_eq!(
+        harail::to_feed_time(&harail::localize(stop.departure())),
+        stop.departure()
+    );
+}
+
+#[test]
+fn route_save_in_timezone() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let train = data.train("1").unwrap();
+    let stops = train
+        .stops()
+        .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+        .collect::<Vec<_>>();
+    let route = Route::from_parts(vec![RoutePart::new(train, stops[0], stops[1])]);
+    let utc = route.in_timezone(chrono_tz::UTC);
+    let json = utc.to_json();
+    assert_eq!(
+        json["parts"][0]["start_time"].as_str(),
+        Some("2000-01-01T08:00:00+00:00")
+    );
+    assert_eq!(
+        json["parts"][0]["end_time"].as_str(),
+        Some("2000-01-01T08:30:00+00:00")
+    );
+    assert!(utc.to_string().contains("(2000-01-01 08:00:00) -> "));
+    // Other objects are still shown in the timezone of the feed
+    assert_eq!(
+        route.to_json()["parts"][0]["start_time"].as_str(),
+        Some("2000-01-01T10:00:00+02:00")
+    );
+}
+
+#[test]
+fn route_geojson() {
+    let stations = vec![
+        Station::with_coordinates(100, "stat_a", 32.5, 34.5),
+        Station::with_coordinates(200, "stat_b", 33.0, 35.0),
+        Station::new(300, "stat_c"),
+    ];
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+        ],
+        test_date(),
+    )
+    .with_shape("S1")];
+    let mut data = RailroadData::from_stations_trains(stations, trains);
+    data.set_shape(
+        "S1",
+        vec![(32.0, 34.0), (32.5, 34.5), (33.0, 35.0), (33.5, 35.5)],
+    );
+    let train = data.train("1").unwrap();
+    let stops = train
+        .stops()
+        .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+        .collect::<Vec<_>>();
+    let route = Route::from_parts(vec![RoutePart::new(train, stops[0], stops[1])]);
+    let json = route.to_geojson(&data);
+    assert_eq!(json["type"], "FeatureCollection");
+    let feature = &json["features"][0];
+    assert_eq!(feature["geometry"]["type"], "LineString");
+    assert_eq!(feature["properties"]["train"], "1");
+    let coordinates: Vec<(f64, f64)> = feature["geometry"]["coordinates"]
+        .members()
+        .map(|p| (p[0].as_f64().unwrap(), p[1].as_f64().unwrap()))
+        .collect();
+    assert_eq!(coordinates, vec![(34.5, 32.5), (35.0, 33.0)]);
 }
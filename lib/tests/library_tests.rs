@@ -5,8 +5,8 @@
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 mod test_data;
-use chrono::{NaiveDateTime, NaiveTime};
-use harail::{HaDuration, RailroadData, StopSchedule, Train};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use harail::{HaDuration, RailroadData, Stop, StopSchedule, Train};
 use test_data::test_date;
 
 #[test]
@@ -83,3 +83,92 @@ fn graph_time_cutoff() {
     );
     assert!(route.is_some());
 }
+
+#[test]
+fn departures_in_direction() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        )
+        .with_direction_id(0),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(300, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 15, 00), None),
+                StopSchedule::new(100, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        )
+        .with_direction_id(1),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let station = data.station(200).unwrap();
+    let from = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(9, 00, 00).unwrap());
+    let ids = |direction| {
+        harail::get_departures_in_direction(&data, station, from, 10, direction)
+            .iter()
+            .map(|d| d.train().id().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(None), vec!["2", "1"]);
+    assert_eq!(ids(Some(0)), vec!["1"]);
+    assert_eq!(ids(Some(1)), vec!["2"]);
+}
+
+#[test]
+fn daylight_saving_time_changes() {
+    // Israel's clocks went forward at 02:00 on 2024-03-29, and back at 02:00 on 2024-10-27
+    let data = RailroadData::from_stations_trains(test_data::stations(), vec![]);
+    let times = |date: NaiveDate| {
+        [(0, 30), (1, 30), (10, 00), (25, 00)].map(|(h, m)| {
+            let stop = StopSchedule::new(100, HaDuration::from_hms(h, m, 00), None);
+            Stop::from_stop_schedule(&data, &stop, date).departure()
+        })
+    };
+    let at = |date: NaiveDate, h, m| date.and_hms_opt(h, m, 00).unwrap();
+
+    let spring = NaiveDate::from_ymd_opt(2024, 3, 29).unwrap();
+    let day_before = spring.pred_opt().unwrap();
+    let day_after = spring.succ_opt().unwrap();
+    assert_eq!(
+        times(spring),
+        [
+            at(day_before, 23, 30),
+            at(spring, 0, 30),
+            at(spring, 10, 00),
+            at(day_after, 1, 00)
+        ]
+    );
+
+    let autumn = NaiveDate::from_ymd_opt(2024, 10, 27).unwrap();
+    let day_after = autumn.succ_opt().unwrap();
+    assert_eq!(
+        times(autumn),
+        [
+            at(autumn, 1, 30),
+            at(autumn, 1, 30),
+            at(autumn, 10, 00),
+            at(day_after, 1, 00)
+        ]
+    );
+
+    // Service days without a change start at midnight
+    let day = test_date();
+    let next_day = day.succ_opt().unwrap();
+    assert_eq!(
+        times(day),
+        [
+            at(day, 0, 30),
+            at(day, 1, 30),
+            at(day, 10, 00),
+            at(next_day, 1, 00)
+        ]
+    );
+}
@@ -0,0 +1,95 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+mod test_data;
+use harail::pricing::{Fare, FareTable, PricingModel, ZoneTable};
+use harail::{HaDuration, RailroadData, Route, RoutePart, Stop, StopSchedule, Train};
+use test_data::test_date;
+
+#[test]
+fn zone_pricing() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let zones = ZoneTable::from_csv(
+        "zones.csv",
+        "station_id,zone_id\n100,1\n200,1\n300,2\n".as_bytes(),
+    )
+    .unwrap();
+    let fares =
+        FareTable::from_csv("fares.csv", "zones,price\n2,1200\n1,550\n".as_bytes()).unwrap();
+    let model = PricingModel::new(zones, fares);
+
+    let part = |id: &str| {
+        let train = data.train(id).unwrap();
+        let stops = train
+            .stops()
+            .map(|s| Stop::from_stop_schedule(&data, s, test_date()))
+            .collect::<Vec<_>>();
+        RoutePart::new(train, stops[0], stops[1])
+    };
+    let route = Route::from_parts(vec![part("1")]);
+    assert_eq!(Some(Fare::from_agorot(550)), model.price(&route));
+    let route = Route::from_parts(vec![part("1"), part("2")]);
+    assert_eq!(Some(Fare::from_agorot(1200)), model.price(&route));
+    assert_eq!("₪12.00", model.price(&route).unwrap().to_string());
+
+    let model = PricingModel::new(
+        ZoneTable::from_pairs(vec![(100, 1)]),
+        FareTable::from_prices(vec![Fare::from_agorot(550)]),
+    );
+    assert_eq!(None, model.price(&route));
+}
+
+#[test]
+fn fare_table_capping() {
+    let fares = FareTable::from_prices(vec![Fare::from_agorot(550), Fare::from_agorot(1200)]);
+    assert_eq!(Some(Fare::from_agorot(550)), fares.price(1));
+    assert_eq!(Some(Fare::from_agorot(1200)), fares.price(5));
+    assert!(FareTable::from_csv("fares.csv", "zones,price\n2,1200\n".as_bytes()).is_err());
+}
+
+#[test]
+fn pricing_error_location() {
+    let error = ZoneTable::from_csv(
+        "zones.csv",
+        "station_id,zone_id\n100,1\nnorth,2\n".as_bytes(),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(error.file(), "zones.csv");
+    assert_eq!(error.line(), Some(3));
+    assert_eq!(error.column(), Some("station_id"));
+    assert_eq!(
+        error.to_string(),
+        "zones.csv, line 3: invalid station_id \"north\""
+    );
+    let error = FareTable::from_csv("fares.csv", "zones,price\n0,550\n".as_bytes())
+        .err()
+        .unwrap();
+    assert_eq!(error.line(), Some(2));
+    assert_eq!(error.column(), Some("zones"));
+    let error = FareTable::from_csv("fares.csv", "zones\n1\n".as_bytes())
+        .err()
+        .unwrap();
+    assert_eq!(error.column(), Some("price"));
+}
@@ -0,0 +1,497 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+mod test_data;
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use harail::gtfs_rt::{proto, Alert, Occupancy, RealtimeUpdates};
+use harail::{
+    DelayHistory, Engine, HaDuration, OwnedRoute, OwnedRoutePart, PreparedGraph, RailroadData,
+    RoutingOptions, StopSchedule, Train, JSON,
+};
+use prost::Message;
+use std::sync::Arc;
+use test_data::test_date;
+
+fn trains() -> Vec<Train> {
+    vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 15, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+    ]
+}
+
+fn best_route(data: &RailroadData, options: &RoutingOptions) -> Option<(String, NaiveTime)> {
+    harail::get_best_single_route_with_options(
+        data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(9, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(13, 00, 00).unwrap()),
+        data.station(300).unwrap(),
+        options,
+    )
+    .map(|route| {
+        let part = route.parts().last().unwrap();
+        (part.train().id().to_owned(), part.end().arrival().time())
+    })
+}
+
+#[test]
+fn delayed_routes() {
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), Some(200), Duration::minutes(15));
+    let updates = Arc::new(updates);
+    for engine in [Engine::TimeExpanded, Engine::AStar, Engine::Raptor] {
+        let options = RoutingOptions::new().with_engine(engine);
+        assert_eq!(
+            Some(("1".to_owned(), NaiveTime::from_hms_opt(11, 00, 00).unwrap())),
+            best_route(&data, &options)
+        );
+        // The delay carries on from 200 to 300, making train 2 the faster option
+        let options = options.with_realtime(Arc::clone(&updates));
+        assert_eq!(
+            Some(("2".to_owned(), NaiveTime::from_hms_opt(11, 10, 00).unwrap())),
+            best_route(&data, &options)
+        );
+    }
+}
+
+#[test]
+fn update_prepared_graph() {
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let best = |graph: &PreparedGraph| {
+        graph
+            .best_route(
+                at(9, 00),
+                data.station(100).unwrap(),
+                data.station(300).unwrap(),
+            )
+            .map(|route| {
+                let part = route.parts().last().unwrap();
+                (part.train().id().to_owned(), part.end().arrival().time())
+            })
+    };
+    for contraction in [false, true] {
+        let options = RoutingOptions::new().with_contraction(contraction);
+        let mut graph = PreparedGraph::new(&data, at(0, 00), at(23, 59), &options);
+        assert_eq!(Some(("1".to_owned(), at(11, 00).time())), best(&graph));
+
+        // The delay carries on from 200 to 300, making train 2 the faster option
+        let mut updates = RealtimeUpdates::new();
+        updates.delay("1", test_date(), Some(200), Duration::minutes(15));
+        updates.set_occupancy("2", test_date(), None, Occupancy::Full);
+        assert_eq!(1, graph.update_realtime(Arc::new(updates)));
+        assert_eq!(Some(("2".to_owned(), at(11, 10).time())), best(&graph));
+        let route = graph
+            .best_route(
+                at(10, 20),
+                data.station(200).unwrap(),
+                data.station(300).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            route.parts().next().unwrap().start().departure(),
+            at(10, 45)
+        );
+
+        // Both runs change back, and the cancelled train is no longer found
+        let mut updates = RealtimeUpdates::new();
+        updates.cancel("2", test_date());
+        let updates = Arc::new(updates);
+        assert_eq!(2, graph.update_realtime(Arc::clone(&updates)));
+        assert_eq!(Some(("1".to_owned(), at(11, 00).time())), best(&graph));
+        assert_eq!(0, graph.update_realtime(updates));
+    }
+}
+
+#[test]
+fn delayed_route_flag() {
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("2", test_date(), None, Duration::minutes(1));
+    let options = RoutingOptions::new().with_realtime(Arc::new(updates));
+    let routes = harail::get_multiple_routes_with_options(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(9, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(13, 00, 00).unwrap()),
+        data.station(300).unwrap(),
+        &options,
+    );
+    let delayed: Vec<_> = routes.iter().map(|r| r.is_delayed()).collect();
+    assert_eq!(delayed, vec![false, true]);
+}
+
+#[test]
+fn parse_trip_updates() {
+    let feed = proto::FeedMessage {
+        header: Some(proto::FeedHeader {
+            gtfs_realtime_version: Some("2.0".to_owned()),
+            timestamp: None,
+        }),
+        entity: vec![
+            proto::FeedEntity {
+                id: Some("a".to_owned()),
+                is_deleted: None,
+                trip_update: Some(proto::TripUpdate {
+                    trip: Some(proto::TripDescriptor {
+                        trip_id: Some("1".to_owned()),
+                        start_date: Some("20000101".to_owned()),
+                        schedule_relationship: Some(
+                            proto::TripScheduleRelationship::Canceled as i32,
+                        ),
+                    }),
+                    stop_time_update: vec![],
+                    delay: None,
+                }),
+                vehicle: None,
+                alert: None,
+            },
+            proto::FeedEntity {
+                id: Some("b".to_owned()),
+                is_deleted: None,
+                trip_update: Some(proto::TripUpdate {
+                    trip: Some(proto::TripDescriptor {
+                        trip_id: Some("2".to_owned()),
+                        start_date: None,
+                        schedule_relationship: None,
+                    }),
+                    stop_time_update: vec![proto::StopTimeUpdate {
+                        stop_sequence: Some(2),
+                        arrival: Some(proto::StopTimeEvent {
+                            delay: Some(-300),
+                            time: None,
+                        }),
+                        departure: None,
+                        stop_id: Some("300".to_owned()),
+                        schedule_relationship: None,
+                        departure_occupancy_status: None,
+                    }],
+                    delay: None,
+                }),
+                vehicle: None,
+                alert: None,
+            },
+            proto::FeedEntity {
+                id: Some("c".to_owned()),
+                is_deleted: None,
+                trip_update: None,
+                vehicle: None,
+                alert: Some(proto::Alert {
+                    active_period: vec![],
+                    informed_entity: vec![proto::EntitySelector {
+                        agency_id: Some("2".to_owned()),
+                        route_id: None,
+                        trip: None,
+                        stop_id: Some("300".to_owned()),
+                    }],
+                    header_text: Some(proto::TranslatedString {
+                        translation: vec![
+                            proto::Translation {
+                                text: Some("Elevator out of service".to_owned()),
+                                language: Some("en".to_owned()),
+                            },
+                            proto::Translation {
+                                text: Some("המעלית מושבתת".to_owned()),
+                                language: Some("he".to_owned()),
+                            },
+                        ],
+                    }),
+                    description_text: None,
+                }),
+            },
+        ],
+    };
+    let updates =
+        RealtimeUpdates::from_trip_updates(feed.encode_to_vec().as_slice(), test_date()).unwrap();
+    assert!(updates.is_cancelled("1", test_date()));
+    assert!(!updates.is_cancelled("2", test_date()));
+    let alert = updates.alerts().next().unwrap();
+    assert_eq!("המעלית מושבתת", alert.header("he"));
+    assert_eq!("Elevator out of service", alert.header("fr"));
+    assert!(alert.affects_station(300));
+    assert!(!alert.affects_station(100));
+
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let options = RoutingOptions::new().with_realtime(Arc::new(updates));
+    assert_eq!(
+        Some(("2".to_owned(), NaiveTime::from_hms_opt(11, 5, 00).unwrap())),
+        best_route(&data, &options)
+    );
+}
+
+#[test]
+fn route_alerts() {
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let mut updates = RealtimeUpdates::new();
+    updates.add_alert(Alert::new("Train 2 is crowded").with_train("2"));
+    updates.add_alert(Alert::new("Station 200 closed").with_station(200));
+    updates.add_alert(
+        Alert::new("Line suspended")
+            .with_whole_network()
+            .with_active_period(Some(at(12, 00)), None),
+    );
+    let route = harail::get_best_single_route(
+        &data,
+        at(10, 10),
+        data.station(100).unwrap(),
+        at(13, 00),
+        data.station(300).unwrap(),
+    )
+    .unwrap();
+    let part = route.parts().next().unwrap();
+    let headers: Vec<_> = part
+        .alerts(&updates)
+        .iter()
+        .map(|a| a.header("en"))
+        .collect();
+    assert_eq!(headers, vec!["Train 2 is crowded"]);
+}
+
+#[test]
+fn refresh_ride() {
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), Some(200), Duration::minutes(15));
+    let part = OwnedRoutePart::from_ride(&data, &updates, "1", 100, at(10, 00), 300).unwrap();
+    assert_eq!(part.end().arrival(), at(11, 15));
+    assert!(part.is_delayed());
+    // Looking the ride up by its delayed departure finds the same run
+    let part = OwnedRoutePart::from_ride(&data, &updates, "1", 200, at(10, 45), 300).unwrap();
+    assert_eq!(part.start().departure(), at(10, 45));
+
+    assert!(OwnedRoutePart::from_ride(&data, &updates, "1", 300, at(11, 00), 100).is_none());
+    updates.cancel("1", test_date());
+    assert!(OwnedRoutePart::from_ride(&data, &updates, "1", 100, at(10, 00), 300).is_none());
+}
+
+#[test]
+fn occupancy() {
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let feed = proto::FeedMessage {
+        header: Some(proto::FeedHeader {
+            gtfs_realtime_version: Some("2.0".to_owned()),
+            timestamp: None,
+        }),
+        entity: vec![proto::FeedEntity {
+            id: Some("a".to_owned()),
+            is_deleted: None,
+            trip_update: None,
+            vehicle: Some(proto::VehiclePosition {
+                trip: Some(proto::TripDescriptor {
+                    trip_id: Some("1".to_owned()),
+                    start_date: None,
+                    schedule_relationship: None,
+                }),
+                occupancy_status: Some(proto::OccupancyStatus::StandingRoomOnly as i32),
+            }),
+            alert: None,
+        }],
+    };
+    let mut updates =
+        RealtimeUpdates::from_trip_updates(feed.encode_to_vec().as_slice(), test_date()).unwrap();
+    updates.set_occupancy("1", test_date(), Some(200), Occupancy::ManySeatsAvailable);
+
+    let departures = harail::get_departures(&data, data.station(100).unwrap(), at(9, 00), 2);
+    let occupancies: Vec<_> = departures.iter().map(|d| d.occupancy(&updates)).collect();
+    assert_eq!(occupancies, vec![Some(Occupancy::StandingRoomOnly), None]);
+
+    // The crowding given for the stop takes precedence over that of the whole run
+    let part = OwnedRoutePart::from_ride(&data, &updates, "1", 200, at(10, 30), 300).unwrap();
+    assert_eq!(
+        part.occupancy(&data, &updates),
+        Some(Occupancy::ManySeatsAvailable)
+    );
+    assert_eq!(Occupancy::ManySeatsAvailable.name(), "many_seats_available");
+    assert_eq!(
+        updates.occupancy(
+            data.train("1").unwrap(),
+            100,
+            at(10, 00) + Duration::days(1)
+        ),
+        None
+    );
+}
+
+#[test]
+fn expected_departures() {
+    let at = |h, m| NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(h, m, 00).unwrap());
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains());
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), None, Duration::minutes(5));
+    updates.cancel("2", test_date());
+
+    let departures = harail::get_departures(&data, data.station(100).unwrap(), at(9, 00), 2);
+    let expected: Vec<_> = departures
+        .iter()
+        .map(|d| d.expected_departure(&data, &updates))
+        .collect();
+    assert_eq!(expected, vec![Some(at(10, 5)), None]);
+    assert_eq!(
+        departures[0].expected_departure(&data, &RealtimeUpdates::new()),
+        Some(at(10, 00))
+    );
+}
+
+#[test]
+fn simulate_delays() {
+    // Switching from train 1 to train 2 at 200 leaves 10 minutes, and train 3 leaves 200 later
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "3",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(11, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(11, 40, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let end_time = NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(13, 00, 00).unwrap());
+    let route = harail::get_best_single_route(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(9, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        end_time,
+        data.station(400).unwrap(),
+    )
+    .unwrap();
+    let options = RoutingOptions::new();
+
+    let simulation = harail::simulate_delays(
+        &data,
+        &route,
+        &[("1", Duration::minutes(5))],
+        end_time,
+        &options,
+    );
+    assert!(simulation.is_feasible());
+    assert!(simulation.recovery().is_none());
+    let part = simulation.delayed_route().parts().next().unwrap();
+    assert_eq!(
+        part.end().arrival().time(),
+        NaiveTime::from_hms_opt(10, 35, 00).unwrap()
+    );
+    assert!(part.is_delayed());
+
+    let simulation = harail::simulate_delays(
+        &data,
+        &route,
+        &[("1", Duration::minutes(12))],
+        end_time,
+        &options,
+    );
+    assert!(!simulation.is_feasible());
+    assert_eq!(simulation.missed_part(), Some(1));
+    let recovery: Vec<_> = simulation
+        .recovery()
+        .unwrap()
+        .parts()
+        .map(|p| (p.train().id().to_owned(), p.end().arrival().time()))
+        .collect();
+    assert_eq!(
+        recovery,
+        vec![
+            ("1".to_owned(), NaiveTime::from_hms_opt(10, 42, 00).unwrap()),
+            ("3".to_owned(), NaiveTime::from_hms_opt(11, 40, 00).unwrap()),
+        ]
+    );
+    assert_eq!(simulation.to_json()["missed_part"], 1);
+}
+
+#[test]
+fn route_reliability() {
+    // Switching from train 1 to train 2 at 200 leaves 10 minutes to spare
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(200, HaDuration::from_hms(10, 40, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 10, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let mut routes = harail::get_multiple_routes(
+        &data,
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(9, 00, 00).unwrap()),
+        data.station(100).unwrap(),
+        NaiveDateTime::new(test_date(), NaiveTime::from_hms_opt(13, 00, 00).unwrap()),
+        data.station(300).unwrap(),
+    );
+    assert!(routes[0].reliability().is_none());
+
+    // Train 1 was late by 15 minutes on one of the two days observed
+    let mut history = DelayHistory::new();
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), None, Duration::minutes(15));
+    history.record_updates(&data, &updates);
+    history.record(
+        "1",
+        200,
+        test_date().pred_opt().unwrap(),
+        Duration::minutes(5),
+        Duration::minutes(5),
+    );
+    harail::rate_reliability(&mut routes, &data, &history);
+    let reliability = routes[0].reliability().unwrap();
+    assert_eq!(reliability.probability(), 0.5);
+    assert_eq!(routes[0].to_json()["reliability"], 0.5);
+    assert_eq!(
+        OwnedRoute::from(&routes[0]),
+        OwnedRoute::from(&routes[0]).with_reliability(&data, &history)
+    );
+
+    // Saved histories load with the same observations
+    let mut saved = Vec::new();
+    history.save(&mut saved).unwrap();
+    let loaded = DelayHistory::load(saved.as_slice()).unwrap();
+    harail::rate_reliability(&mut routes, &data, &loaded);
+    assert_eq!(routes[0].reliability(), Some(reliability));
+}
@@ -0,0 +1,107 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use harail::{RailroadData, Station};
+
+fn data() -> RailroadData {
+    RailroadData::from_stations_trains(
+        vec![
+            Station::new(3700, "תל אביב - סבידור מרכז"),
+            Station::new(4600, "תל אביב - השלום"),
+            Station::new(2300, "חיפה - חוף הכרמל"),
+            Station::new(1300, "חיפה מרכז - השמונה"),
+            Station::new(7000, "באר שבע - מרכז"),
+            Station::new(3400, "Herzliya"),
+        ],
+        vec![],
+    )
+}
+
+fn search(data: &RailroadData, query: &str) -> Vec<u64> {
+    harail::search_stations(data, query, 10)
+        .iter()
+        .map(|s| s.id())
+        .collect()
+}
+
+#[test]
+fn prefix_matches() {
+    let data = data();
+    assert_eq!(search(&data, "תל"), vec![4600, 3700]);
+    assert_eq!(search(&data, "השלום"), vec![4600]);
+    assert_eq!(search(&data, "herz"), vec![3400]);
+    assert_eq!(search(&data, "HERZLIYA"), vec![3400]);
+    assert!(search(&data, "").is_empty());
+    assert_eq!(harail::search_stations(&data, "תל", 1).len(), 1);
+}
+
+#[test]
+fn transliterated_matches() {
+    let data = data();
+    assert_eq!(search(&data, "tel aviv"), vec![4600, 3700]);
+    assert_eq!(search(&data, "haifa"), vec![2300, 1300]);
+    assert_eq!(search(&data, "beer sheva"), vec![7000]);
+}
+
+#[test]
+fn fuzzy_matches() {
+    let data = data();
+    assert_eq!(search(&data, "herzilya"), vec![3400]);
+    assert_eq!(search(&data, "באר שבא"), vec![7000]);
+    assert!(search(&data, "jerusalem").is_empty());
+}
+
+#[test]
+fn nearby() {
+    let data = RailroadData::from_stations_trains(
+        vec![
+            Station::with_coordinates(3700, "תל אביב - סבידור מרכז", 32.0837, 34.7982),
+            Station::with_coordinates(4600, "תל אביב - השלום", 32.0733, 34.7931),
+            Station::with_coordinates(2300, "חיפה - חוף הכרמל", 32.7935, 34.9571),
+            Station::new(9999, "unknown"),
+        ],
+        vec![],
+    );
+    let found: Vec<_> = harail::nearby_stations(&data, 32.0750, 34.7900, 2000.0)
+        .into_iter()
+        .map(|(s, distance)| (s.id(), distance.round()))
+        .collect();
+    assert_eq!(found, vec![(4600, 348.0), (3700, 1238.0)]);
+    assert_eq!(
+        harail::nearby_stations(&data, 32.0750, 34.7900, 100_000.0).len(),
+        3
+    );
+}
+
+#[test]
+fn find_by_alias() {
+    let data = data();
+    let find = |name| data.find_station(name).map(|s| s.id());
+    assert_eq!(find("תל אביב - סבידור מרכז"), Some(3700));
+    assert_eq!(find("תל אביב סבידור מרכז"), Some(3700));
+    assert_eq!(find("Tel Aviv Savidor"), Some(3700));
+    assert_eq!(find("tel aviv center"), Some(3700));
+    assert_eq!(find("ת\"א מרכז"), Some(3700));
+    assert_eq!(find("תא מרכז"), Some(3700));
+    assert_eq!(find("herzliya"), Some(3400));
+    // Jerusalem has an alias, but no station in this database
+    assert_eq!(find("Jerusalem Navon"), None);
+    assert_eq!(find("tel aviv"), None);
+    assert_eq!(find(""), None);
+    assert_eq!(search(&data, "savidor"), vec![3700]);
+}
+
+#[test]
+fn final_letters() {
+    let data = RailroadData::from_stations_trains(
+        vec![Station::new(200, "ראשון לציון - משה דיין")],
+        vec![],
+    );
+    assert_eq!(
+        data.find_station("ראשונ לציונ משה דיינ").map(|s| s.id()),
+        Some(200)
+    );
+}
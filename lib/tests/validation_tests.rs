@@ -0,0 +1,78 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+mod test_data;
+use harail::{HaDuration, Issue, RailroadData, StopSchedule, Train, JSON};
+use test_data::test_date;
+
+#[test]
+fn valid_database() {
+    let trains = vec![Train::from_stops_dates(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(
+                200,
+                HaDuration::from_hms(10, 30, 00),
+                Some(HaDuration::from_hms(10, 32, 00)),
+            ),
+            StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+        ],
+        vec![test_date()],
+    )];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    assert!(harail::validate(&data).is_empty());
+}
+
+#[test]
+fn invalid_database() {
+    let trains = vec![
+        Train::from_stops_dates(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(999, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(10, 20, 00), None),
+            ],
+            vec![test_date()],
+        ),
+        Train::from_stops_dates(
+            "2",
+            vec![StopSchedule::new(
+                100,
+                HaDuration::from_hms(10, 00, 00),
+                None,
+            )],
+            vec![],
+        ),
+        Train::from_stops_dates("3", vec![], vec![test_date()]),
+    ];
+    let data = RailroadData::from_stations_trains(test_data::stations(), trains);
+    let issues = harail::validate(&data);
+    assert_eq!(
+        issues,
+        vec![
+            Issue::UnknownStation {
+                train: "1".to_owned(),
+                station: 999
+            },
+            Issue::TimeTravel {
+                train: "1".to_owned(),
+                stop: 2
+            },
+            Issue::NoDates {
+                train: "2".to_owned()
+            },
+            Issue::NoStops {
+                train: "3".to_owned()
+            },
+        ]
+    );
+    assert_eq!(
+        issues[0].to_json().dump(),
+        r#"{"kind":"unknown_station","train":"1","station":999}"#
+    );
+}
@@ -0,0 +1,65 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The API keys allowed to run route searches.
+///
+/// When the server manages ApiKeys, route searches require one of them, while the rest of the API stays open to anyone.
+pub struct ApiKeys(HashSet<String>);
+
+impl ApiKeys {
+    /// Loads API keys from a file with one key per line. Empty lines and lines starting with # are ignored.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let keys = fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect();
+        Ok(ApiKeys(keys))
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.0.contains(key)
+    }
+}
+
+/// The API key sent with a request, either as a bearer token in the Authorization header or in the X-Api-Key header
+fn request_key<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    let headers = req.headers();
+    headers
+        .get_one("Authorization")
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+        .or_else(|| headers.get_one("X-Api-Key"))
+        .map(str::trim)
+}
+
+/// A request guard which fails with 401 Unauthorized unless the request carries a valid API key.
+///
+/// Every request is authorized when the server doesn't manage ApiKeys, in which case no key is held.
+pub struct Authorized(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authorized {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let keys = match req.rocket().state::<ApiKeys>() {
+            Some(keys) => keys,
+            None => return Outcome::Success(Authorized(None)),
+        };
+        match request_key(req) {
+            Some(key) if keys.contains(key) => Outcome::Success(Authorized(Some(key.to_owned()))),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
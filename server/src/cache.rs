@@ -0,0 +1,66 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::database::Database;
+use crate::metrics::Metrics;
+use chrono::{DateTime, Utc};
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+
+/// A response which depends only on the database, letting clients revalidate it with ETag and Last-Modified.
+///
+/// Requests whose If-None-Match (or, lacking that, If-Modified-Since) shows the client already has the
+/// response get an empty 304 Not Modified instead.
+pub struct Cached<R> {
+    etag: String,
+    last_modified: DateTime<Utc>,
+    inner: R,
+}
+
+impl<R> Cached<R> {
+    pub fn new(database: &Database, inner: R) -> Self {
+        Cached {
+            etag: database.etag(),
+            last_modified: database.last_modified(),
+            inner,
+        }
+    }
+
+    fn is_fresh(&self, req: &Request<'_>) -> bool {
+        if let Some(tags) = req.headers().get_one("If-None-Match") {
+            return tags
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag);
+        }
+        req.headers()
+            .get_one("If-Modified-Since")
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| since.timestamp() >= self.last_modified.timestamp())
+    }
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Cached<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let last_modified = self
+            .last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let fresh = self.is_fresh(req);
+        if let Some(metrics) = req.rocket().state::<Metrics>() {
+            metrics.observe_cache(fresh);
+        }
+        let mut response = if fresh {
+            Response::build().status(Status::NotModified).finalize()
+        } else {
+            self.inner.respond_to(req)?
+        };
+        response.set_header(Header::new("ETag", self.etag));
+        response.set_header(Header::new("Last-Modified", last_modified));
+        Ok(response)
+    }
+}
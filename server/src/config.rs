@@ -0,0 +1,186 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use clap::ArgMatches;
+use harail::{AgencyFilter, GtfsOptions, Mode};
+use rocket::figment::providers::{Format, Toml};
+use rocket::figment::{self, Figment};
+use rocket::serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// How often real-time feeds are downloaded when not configured otherwise
+const DEFAULT_REALTIME_INTERVAL: u64 = 60;
+
+/// How often the GTFS feed is downloaded when not configured otherwise, once a day
+const DEFAULT_GTFS_INTERVAL: u64 = 24 * 60 * 60;
+
+/// How long route searches may run when not configured otherwise
+const DEFAULT_SEARCH_TIMEOUT: u64 = 30;
+
+/// How many graphs are cached for route searches when not configured otherwise, e.g. for a week of single day searches
+const DEFAULT_GRAPH_CACHE: usize = 8;
+
+/// The server's settings, read from a TOML file and overridden by command line options.
+///
+/// Keys are named like the command line options, e.g.:
+/// ```toml
+/// database = "/var/lib/harail/harail.db"
+/// port = 8080
+/// cors-origins = ["https://example.com"]
+/// realtime-urls = ["https://example.com/gtfs-rt/tripupdates"]
+/// gtfs-url = "https://gtfs.mot.gov.il/gtfsfiles/israel-public-transportation.zip"
+/// gtfs-modes = ["rail", "light_rail"]
+///
+/// [feeds]
+/// north = "/var/lib/harail/north.db"
+/// ```
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(
+    crate = "rocket::serde",
+    default,
+    rename_all = "kebab-case",
+    deny_unknown_fields
+)]
+pub struct Config {
+    pub database: Option<PathBuf>,
+    pub feeds: BTreeMap<String, PathBuf>,
+    #[serde(rename = "static")]
+    pub static_path: Option<PathBuf>,
+    pub address: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub cors_origins: Vec<String>,
+    pub rate_limit: Option<u32>,
+    pub graph_cache: Option<usize>,
+    pub search_timeout: Option<u64>,
+    pub api_keys: Option<PathBuf>,
+    pub realtime_urls: Vec<String>,
+    pub realtime_interval: Option<u64>,
+    pub delay_history: Option<PathBuf>,
+    pub gtfs_url: Option<String>,
+    pub gtfs_interval: Option<u64>,
+    pub gtfs_agencies: Vec<String>,
+    pub gtfs_all_agencies: bool,
+    pub gtfs_modes: Vec<String>,
+}
+
+impl Config {
+    /// Loads the configuration from a TOML file
+    pub fn load(path: &Path) -> Result<Self, figment::Error> {
+        Figment::from(Toml::file_exact(path)).extract()
+    }
+
+    /// Replaces settings with the ones given as command line options
+    pub fn apply_args(&mut self, matches: &ArgMatches) {
+        if let Some(database) = matches.get_one::<String>("DATABASE") {
+            self.database = Some(database.into());
+        }
+        if let Some(feeds) = matches.get_many::<(String, PathBuf)>("feed") {
+            self.feeds.extend(feeds.cloned());
+        }
+        if let Some(static_path) = matches.get_one::<String>("static") {
+            self.static_path = Some(static_path.into());
+        }
+        if let Some(address) = matches.get_one::<IpAddr>("address") {
+            self.address = Some(*address);
+        }
+        if let Some(port) = matches.get_one::<u16>("port") {
+            self.port = Some(*port);
+        }
+        if let Some(origins) = matches.get_many::<String>("cors-origin") {
+            self.cors_origins = origins.cloned().collect();
+        }
+        if let Some(per_minute) = matches.get_one::<u32>("rate-limit") {
+            self.rate_limit = Some(*per_minute);
+        }
+        if let Some(capacity) = matches.get_one::<usize>("graph-cache") {
+            self.graph_cache = Some(*capacity);
+        }
+        if let Some(timeout) = matches.get_one::<u64>("search-timeout") {
+            self.search_timeout = Some(*timeout);
+        }
+        if let Some(keys) = matches.get_one::<String>("api-keys") {
+            self.api_keys = Some(keys.into());
+        }
+        if let Some(urls) = matches.get_many::<String>("realtime-url") {
+            self.realtime_urls = urls.cloned().collect();
+        }
+        if let Some(interval) = matches.get_one::<u64>("realtime-interval") {
+            self.realtime_interval = Some(*interval);
+        }
+        if let Some(history) = matches.get_one::<String>("delay-history") {
+            self.delay_history = Some(history.into());
+        }
+        if let Some(url) = matches.get_one::<String>("gtfs-url") {
+            self.gtfs_url = Some(url.clone());
+        }
+        if let Some(interval) = matches.get_one::<u64>("gtfs-interval") {
+            self.gtfs_interval = Some(*interval);
+        }
+        if let Some(agencies) = matches.get_many::<String>("gtfs-agency") {
+            self.gtfs_agencies = agencies.cloned().collect();
+            self.gtfs_all_agencies = false;
+        }
+        if matches.get_flag("gtfs-all-agencies") {
+            self.gtfs_all_agencies = true;
+        }
+        if let Some(modes) = matches.get_many::<String>("gtfs-mode") {
+            self.gtfs_modes = modes.cloned().collect();
+        }
+    }
+
+    /// The number of graphs cached for route searches
+    pub fn graph_cache(&self) -> usize {
+        self.graph_cache.unwrap_or(DEFAULT_GRAPH_CACHE)
+    }
+
+    /// The time after which route searches are abandoned, in seconds
+    pub fn search_timeout(&self) -> u64 {
+        self.search_timeout.unwrap_or(DEFAULT_SEARCH_TIMEOUT)
+    }
+
+    /// The interval between downloads of the real-time feeds, in seconds
+    pub fn realtime_interval(&self) -> u64 {
+        self.realtime_interval.unwrap_or(DEFAULT_REALTIME_INTERVAL)
+    }
+
+    /// The interval between downloads of the GTFS feed, in seconds
+    pub fn gtfs_interval(&self) -> u64 {
+        self.gtfs_interval.unwrap_or(DEFAULT_GTFS_INTERVAL)
+    }
+
+    /// The agencies and modes whose trips are loaded from the GTFS feed, as parse-gtfs would load them
+    pub fn gtfs_options(&self) -> Result<GtfsOptions, String> {
+        let agencies = if self.gtfs_all_agencies {
+            AgencyFilter::All
+        } else if !self.gtfs_agencies.is_empty() {
+            AgencyFilter::Matching(self.gtfs_agencies.clone())
+        } else {
+            AgencyFilter::default()
+        };
+        let mut options = GtfsOptions::new().with_agencies(agencies);
+        if !self.gtfs_modes.is_empty() {
+            let modes = self
+                .gtfs_modes
+                .iter()
+                .map(|m| Mode::from_name(m).ok_or_else(|| format!("unknown mode {}", m)))
+                .collect::<Result<Vec<_>, _>>()?;
+            options = options.with_modes(modes);
+        }
+        Ok(options)
+    }
+}
+
+/// Parses a --feed option given as NAME=FILE
+pub fn parse_feed(arg: &str) -> Result<(String, PathBuf), String> {
+    match arg.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !name.contains('/') && !path.is_empty() => {
+            Ok((name.to_owned(), path.into()))
+        }
+        _ => Err(format!("expected NAME=FILE, got {}", arg)),
+    }
+}
@@ -0,0 +1,66 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::{Build, Request, Response, Rocket};
+
+/// Allows browsers to call the API from pages served by other origins.
+///
+/// Only origins in the allow-list get CORS headers, and "*" allows any origin.
+/// Preflight requests are answered for every path.
+pub struct Cors {
+    origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(origins: Vec<String>) -> Self {
+        Cors { origins }
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.origins.iter().any(|o| o == "*" || o == origin)
+    }
+}
+
+#[options("/<_..>")]
+fn preflight() -> Status {
+    Status::NoContent
+}
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.mount("/", routes![preflight]))
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let origin = match req.headers().get_one("Origin") {
+            Some(origin) if self.allows(origin) => origin.to_owned(),
+            _ => return,
+        };
+        res.set_header(Header::new("Access-Control-Allow-Origin", origin));
+        res.set_header(Header::new("Vary", "Origin"));
+        if req.method() == Method::Options {
+            res.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                "GET, POST, OPTIONS",
+            ));
+            res.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                "Accept, Accept-Language, Authorization, Content-Type, X-Api-Key",
+            ));
+            res.set_header(Header::new("Access-Control-Max-Age", "86400"));
+        }
+    }
+}
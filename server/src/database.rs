@@ -0,0 +1,124 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::live::Live;
+use chrono::{DateTime, Utc};
+use harail::RailroadData;
+use memmap2::Mmap;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// A database being served, along with the validators clients can use to cache responses derived from it
+pub struct Database {
+    data: Arc<RailroadData>,
+    fingerprint: u64,
+    last_modified: DateTime<Utc>,
+}
+
+impl Database {
+    fn new(data: RailroadData, content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let last_modified = data
+            .build_time()
+            .map_or_else(Utc::now, |t| DateTime::from_naive_utc_and_offset(t, Utc));
+        Database {
+            data: Arc::new(data),
+            fingerprint: hasher.finish(),
+            last_modified,
+        }
+    }
+
+    /// The database itself, shared so that searches can outlive the request which started them
+    pub fn data(&self) -> &Arc<RailroadData> {
+        &self.data
+    }
+
+    /// An entity tag which changes whenever the database does
+    pub fn etag(&self) -> String {
+        format!("\"{:016x}\"", self.fingerprint)
+    }
+
+    /// The time at which the database was built, or loaded if the build time is unknown
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        self.last_modified
+    }
+}
+
+impl From<RailroadData> for Database {
+    /// Wraps a database which was not loaded from a file, fingerprinting its serialized contents
+    fn from(data: RailroadData) -> Self {
+        let mut content = Vec::new();
+        data.save(&mut content).unwrap();
+        Database::new(data, &content)
+    }
+}
+
+impl Deref for Database {
+    type Target = RailroadData;
+
+    fn deref(&self) -> &RailroadData {
+        &self.data
+    }
+}
+
+/// The database currently being served, which can be swapped for a newer one without a restart
+pub type LiveData = Live<Database>;
+
+/// Loads a HaRail database file, fingerprinting the file's contents.
+///
+/// The file is mapped into memory rather than read into a buffer, so that large databases aren't held in memory twice
+/// while they are being deserialized. The whole database is still deserialized up front: HaRail's file format is a
+/// serialized `RailroadData`, zstd compressed by default, rather than a layout which could be read in place and paged
+/// in on demand.
+pub fn load(path: &Path) -> Result<Database, Box<dyn Error + Send + Sync>> {
+    let file = File::open(path)?;
+    // Safety: the mapping only lives while the database is loaded. Databases are expected to be replaced by renaming a
+    // new file over them, which leaves the mapped file intact, rather than by being rewritten in place.
+    let content = unsafe { Mmap::map(&file)? };
+    let data = RailroadData::load(&content[..])?;
+    Ok(Database::new(data, &content))
+}
+
+/// Loads the database file again and publishes it.
+///
+/// A database which fails to load is reported and the current one stays in place.
+pub async fn reload(data: &LiveData, path: PathBuf) {
+    let loaded = rocket::tokio::task::spawn_blocking(move || load(&path)).await;
+    match loaded {
+        Ok(Ok(loaded)) => {
+            data.publish(loaded);
+            info!("Reloaded database");
+        }
+        Ok(Err(e)) => error!("Failed to reload database: {}", e),
+        Err(e) => error!("Failed to reload database: {}", e),
+    }
+}
+
+/// Reloads the database files whenever the server receives SIGHUP, e.g. after a nightly GTFS update
+#[cfg(unix)]
+pub async fn reload_on_hangup(databases: Vec<(LiveData, PathBuf)>) {
+    use rocket::tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        for (data, path) in &databases {
+            reload(data, path.clone()).await;
+        }
+    }
+}
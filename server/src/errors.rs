@@ -0,0 +1,70 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::messages::{ErrorCode, Language};
+use crate::responses::{CandidateResponse, ErrorResponse};
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+
+/// A structured error response.
+///
+/// The message is localized according to the request's Accept-Language header.
+#[derive(Debug)]
+pub struct ApiError {
+    code: ErrorCode,
+    /// The stations an ambiguous station name could refer to
+    candidates: Vec<CandidateResponse>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode) -> Self {
+        ApiError {
+            code,
+            candidates: Vec::new(),
+        }
+    }
+
+    /// An error listing the stations a station name could refer to, so that the client can pick one
+    pub fn with_candidates(mut self, candidates: Vec<CandidateResponse>) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    fn status(&self) -> Status {
+        match self.code {
+            ErrorCode::StartStationNotFound
+            | ErrorCode::EndStationNotFound
+            | ErrorCode::NoRouteFound
+            | ErrorCode::TrainNotFound
+            | ErrorCode::StationNotFound => Status::NotFound,
+            ErrorCode::AmbiguousStartStation | ErrorCode::AmbiguousEndStation => {
+                Status::UnprocessableEntity
+            }
+            ErrorCode::SearchFailed => Status::InternalServerError,
+            ErrorCode::SearchTimedOut => Status::ServiceUnavailable,
+            ErrorCode::RateLimited => Status::TooManyRequests,
+            ErrorCode::Unauthorized => Status::Unauthorized,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let language = Language::from_headers(req.headers());
+        let body = Json(ErrorResponse::new(self.code, language).with_candidates(self.candidates))
+            .respond_to(req)?;
+        Response::build_from(body)
+            .status(self.status())
+            .header(Header::new("Content-Language", language.tag()))
+            .ok()
+    }
+}
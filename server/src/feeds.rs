@@ -0,0 +1,90 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::database::LiveData;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{Data, State};
+use std::collections::HashMap;
+use std::ops::Deref;
+
+/// Where the API is mounted, and where feeds are selected
+const PREFIX: &str = "/harail/";
+
+/// Additional databases served next to the default one, e.g. for other regions or older snapshots of the GTFS feed.
+///
+/// Attached as a fairing, it serves each feed under /harail/<name>/ by routing its requests as if the name wasn't in
+/// the path, and remembering which feed the handlers should use.
+pub struct Feeds(HashMap<String, LiveData>);
+
+impl Feeds {
+    pub fn new(feeds: HashMap<String, LiveData>) -> Self {
+        Feeds(feeds)
+    }
+}
+
+/// The feed selected by a request's path, or None for the default database
+struct SelectedFeed(Option<LiveData>);
+
+#[rocket::async_trait]
+impl Fairing for Feeds {
+    fn info(&self) -> Info {
+        Info {
+            name: "Feeds",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let selected = req
+            .uri()
+            .path()
+            .as_str()
+            .strip_prefix(PREFIX)
+            .and_then(|path| {
+                let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+                let data = self.0.get(name)?;
+                let mut uri = format!("{}{}", PREFIX, rest);
+                if let Some(query) = req.uri().query() {
+                    uri.push('?');
+                    uri.push_str(query.as_str());
+                }
+                Some((data.clone(), Origin::parse_owned(uri).ok()?))
+            });
+        if let Some((data, uri)) = selected {
+            req.set_uri(uri);
+            req.local_cache(|| SelectedFeed(Some(data)));
+        }
+    }
+}
+
+/// A request guard giving the database selected by the request's path, which is the default one unless the path starts
+/// with the name of a feed
+pub struct FeedData(LiveData);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for FeedData {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match &req.local_cache(|| SelectedFeed(None)).0 {
+            Some(data) => Outcome::Success(FeedData(data.clone())),
+            None => req
+                .guard::<&State<LiveData>>()
+                .await
+                .map(|data| FeedData(LiveData::clone(data))),
+        }
+    }
+}
+
+impl Deref for FeedData {
+    type Target = LiveData;
+
+    fn deref(&self) -> &LiveData {
+        &self.0
+    }
+}
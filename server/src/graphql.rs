@@ -0,0 +1,444 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::auth::Authorized;
+use crate::database::{Database, LiveData};
+use crate::feeds::FeedData;
+use crate::graphs::GraphCache;
+use crate::history::LiveHistory;
+use crate::limits::{RateLimited, SearchDeadline};
+use crate::messages::{ErrorCode, Language};
+use crate::metrics::Metrics;
+use crate::realtime::LiveUpdates;
+use crate::{search_routes, FindOptions, HaDateTime, SearchType, SearchWeights, StationRef};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Error, ErrorExtensions, Object, Result, Schema,
+    SimpleObject,
+};
+use async_graphql_rocket::{GraphQLQuery, GraphQLRequest, GraphQLResponse};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use harail::gtfs_rt::RealtimeUpdates;
+use harail::{DelayHistory, OwnedRoute, OwnedRoutePart, OwnedStop, StationId, TrainId};
+use rocket::State;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The GraphQL schema, exposing the same data as the REST endpoints
+pub type HaSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+pub fn schema() -> HaSchema {
+    Schema::new(Query, EmptyMutation, EmptySubscription)
+}
+
+/// What the resolvers of a single request work with, captured when the request arrives
+struct RequestData {
+    database: Arc<Database>,
+    realtime: Arc<RealtimeUpdates>,
+    history: Arc<DelayHistory>,
+    metrics: Metrics,
+    graphs: Option<GraphCache>,
+    timeout: Option<Duration>,
+    language: Language,
+    authorized: bool,
+}
+
+fn request_data<'a>(ctx: &Context<'a>) -> &'a RequestData {
+    ctx.data_unchecked()
+}
+
+/// A GraphQL error with the same message and code as the REST API's error responses
+fn api_error(code: ErrorCode, language: Language) -> Error {
+    Error::new(code.message(language)).extend_with(|_, ext| ext.set("code", code.code()))
+}
+
+/// A time of the feed, in the timezone of the feed
+fn zoned(time: NaiveDateTime) -> DateTime<FixedOffset> {
+    harail::localize(time).fixed_offset()
+}
+
+#[derive(SimpleObject)]
+struct Station {
+    id: StationId,
+    name: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    /// The GTFS fare zone (zone_id) of the station, if it has one
+    fare_zone: Option<String>,
+}
+
+impl From<&harail::Station> for Station {
+    fn from(station: &harail::Station) -> Self {
+        Station {
+            id: station.id(),
+            name: station.name().to_owned(),
+            lat: station.lat(),
+            lon: station.lon(),
+            fare_zone: station.fare_zone().map(str::to_owned),
+        }
+    }
+}
+
+struct Train(TrainId);
+
+#[Object]
+impl Train {
+    async fn id(&self) -> &str {
+        &self.0
+    }
+
+    /// The number passengers know the train by, if the feed gives one
+    async fn number(&self, ctx: &Context<'_>) -> Option<String> {
+        request_data(ctx)
+            .database
+            .train(&self.0)
+            .and_then(|train| train.short_name())
+            .map(str::to_owned)
+    }
+
+    /// The train's stops when it runs on the given date
+    async fn stops(&self, ctx: &Context<'_>, date: NaiveDate) -> Vec<Stop> {
+        let data = &request_data(ctx).database;
+        data.train(&self.0).map_or_else(Vec::new, |train| {
+            train
+                .stops()
+                .map(|s| Stop((&harail::Stop::from_stop_schedule(data, s, date)).into()))
+                .collect()
+        })
+    }
+}
+
+struct Stop(OwnedStop);
+
+#[Object]
+impl Stop {
+    async fn station(&self, ctx: &Context<'_>) -> Option<Station> {
+        request_data(ctx)
+            .database
+            .station(self.0.station())
+            .map(Station::from)
+    }
+
+    async fn arrival(&self) -> DateTime<FixedOffset> {
+        zoned(self.0.arrival())
+    }
+
+    async fn departure(&self) -> DateTime<FixedOffset> {
+        zoned(self.0.departure())
+    }
+
+    /// The platform the train stops at, if the feed specifies one
+    async fn platform(&self) -> Option<&str> {
+        self.0.platform()
+    }
+
+    /// Whether real-time updates changed the stop's times
+    async fn delayed(&self) -> bool {
+        self.0.is_delayed()
+    }
+}
+
+/// A service alert, in the language requested by the Accept-Language header
+#[derive(SimpleObject)]
+struct Alert {
+    header: String,
+    description: Option<String>,
+}
+
+struct RoutePart(OwnedRoutePart);
+
+#[Object]
+impl RoutePart {
+    async fn train(&self) -> Train {
+        Train(self.0.train().to_owned())
+    }
+
+    async fn start(&self) -> Stop {
+        Stop(self.0.start().clone())
+    }
+
+    async fn end(&self) -> Stop {
+        Stop(self.0.end().clone())
+    }
+
+    /// The stops the train makes between boarding and unboarding it
+    async fn intermediate_stops(&self) -> Vec<Stop> {
+        self.0
+            .intermediate_stops()
+            .iter()
+            .cloned()
+            .map(Stop)
+            .collect()
+    }
+
+    /// Whether bicycles may be taken on the train, if the feed specifies it
+    async fn bikes_allowed(&self) -> Option<bool> {
+        self.0.bikes_allowed()
+    }
+
+    async fn delayed(&self) -> bool {
+        self.0.is_delayed()
+    }
+
+    /// The expected crowding of the train when boarding it, e.g. few_seats_available, if the real-time feed gives it
+    async fn occupancy(&self, ctx: &Context<'_>) -> Option<&'static str> {
+        let request = request_data(ctx);
+        self.0
+            .occupancy(&request.database, &request.realtime)
+            .map(|o| o.name())
+    }
+
+    async fn alerts(&self, ctx: &Context<'_>) -> Vec<Alert> {
+        let request = request_data(ctx);
+        let language = request.language.tag();
+        self.0
+            .alerts(&request.realtime)
+            .into_iter()
+            .map(|alert| Alert {
+                header: alert.header(language).to_owned(),
+                description: alert.description(language).map(str::to_owned),
+            })
+            .collect()
+    }
+}
+
+/// The totals of a route, with times in seconds
+#[derive(SimpleObject)]
+struct RouteSummary {
+    duration_seconds: i64,
+    ride_seconds: i64,
+    wait_seconds: i64,
+    transfers: usize,
+    stations_passed: usize,
+}
+
+impl From<harail::RouteSummary> for RouteSummary {
+    fn from(summary: harail::RouteSummary) -> Self {
+        RouteSummary {
+            duration_seconds: summary.duration().num_seconds(),
+            ride_seconds: summary.ride_time().num_seconds(),
+            wait_seconds: summary.wait_time().num_seconds(),
+            transfers: summary.transfers(),
+            stations_passed: summary.stations_passed(),
+        }
+    }
+}
+
+struct Route(OwnedRoute);
+
+#[Object]
+impl Route {
+    async fn parts(&self) -> Vec<RoutePart> {
+        self.0.parts().cloned().map(RoutePart).collect()
+    }
+
+    async fn delayed(&self) -> bool {
+        self.0.is_delayed()
+    }
+
+    /// The ticket price of the route in agorot, if the feed had fares covering every ride
+    async fn price(&self) -> Option<u32> {
+        self.0.price().map(|p| p.agorot())
+    }
+
+    /// The chance of making all of the route's train switches, if the server keeps a delay history
+    async fn reliability(&self) -> Option<f64> {
+        self.0.reliability().map(|r| r.probability())
+    }
+
+    async fn summary(&self) -> RouteSummary {
+        self.0.summary().into()
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn station(&self, ctx: &Context<'_>, id: StationId) -> Option<Station> {
+        request_data(ctx).database.station(id).map(Station::from)
+    }
+
+    async fn stations(&self, ctx: &Context<'_>) -> Vec<Station> {
+        request_data(ctx)
+            .database
+            .stations()
+            .map(Station::from)
+            .collect()
+    }
+
+    /// Stations best matching a partially typed name, as in /stations/search
+    async fn search_stations(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        #[graphql(default = 10)] limit: usize,
+    ) -> Vec<Station> {
+        harail::search_stations(&request_data(ctx).database, &query, limit)
+            .into_iter()
+            .map(Station::from)
+            .collect()
+    }
+
+    async fn train(&self, ctx: &Context<'_>, id: String) -> Option<Train> {
+        request_data(ctx)
+            .database
+            .train(&id)
+            .map(|train| Train(train.id().to_owned()))
+    }
+
+    /// The trains passengers know by the given number, or the train with the given identifier
+    async fn trains(&self, ctx: &Context<'_>, number: String) -> Vec<Train> {
+        request_data(ctx)
+            .database
+            .find_trains(&number)
+            .into_iter()
+            .map(|train| Train(train.id().to_owned()))
+            .collect()
+    }
+
+    /// Searches for routes, as in /routes/find. Single route searches find at most one route.
+    #[allow(clippy::too_many_arguments)]
+    async fn routes(
+        &self,
+        ctx: &Context<'_>,
+        search: SearchType,
+        start_station: StationId,
+        start_time: DateTime<Utc>,
+        end_station: StationId,
+        end_time: DateTime<Utc>,
+        max_transfers: Option<usize>,
+        min_transfer: Option<u64>,
+        slack: Option<u64>,
+        #[graphql(default)] avoid_stations: Vec<StationId>,
+        #[graphql(default)] avoid_trains: Vec<TrainId>,
+        #[graphql(default)] wheelchair: bool,
+        #[graphql(default)] bikes: bool,
+        #[graphql(default)] diverse: bool,
+    ) -> Result<Vec<Route>> {
+        let request = request_data(ctx);
+        if !request.authorized {
+            return Err(api_error(ErrorCode::Unauthorized, request.language));
+        }
+        let options = FindOptions {
+            search,
+            start_station: StationRef::Id(start_station),
+            start_time: HaDateTime(harail::to_feed_time(&start_time)),
+            end_station: StationRef::Id(end_station),
+            end_time: HaDateTime(harail::to_feed_time(&end_time)),
+            max_transfers,
+            min_transfer,
+            slack,
+            avoid_stations,
+            avoid_trains,
+            wheelchair,
+            bikes,
+            diverse,
+            weights: SearchWeights::default(),
+        };
+        let (_, routes) = search_routes(
+            Arc::clone(request.database.data()),
+            Arc::clone(&request.realtime),
+            Arc::clone(&request.history),
+            &request.metrics,
+            request.graphs.as_ref(),
+            request.timeout,
+            options,
+        )
+        .await
+        .map_err(|e| api_error(e.code(), request.language))?;
+        Ok(routes.into_iter().map(Route).collect())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute(
+    schema: &HaSchema,
+    data: &LiveData,
+    live: &LiveUpdates,
+    history: &LiveHistory,
+    metrics: &Metrics,
+    graphs: Option<&GraphCache>,
+    timeout: Option<Duration>,
+    language: Language,
+    authorized: bool,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    request
+        .data(RequestData {
+            database: data.current(),
+            realtime: live.current(),
+            history: history.current(),
+            metrics: metrics.clone(),
+            graphs: graphs.cloned(),
+            timeout,
+            language,
+            authorized,
+        })
+        .execute(schema)
+        .await
+}
+
+/// Runs a GraphQL query given in the query string.
+///
+/// Queries may search for routes, so they are rate limited like /routes/find, and searching requires an API key if those are configured.
+#[get("/graphql?<query..>")]
+pub async fn get_graphql(
+    schema: &State<HaSchema>,
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    history: &State<LiveHistory>,
+    metrics: &State<Metrics>,
+    graphs: Option<&State<GraphCache>>,
+    deadline: SearchDeadline,
+    language: Language,
+    key: Option<Authorized>,
+    _limit: RateLimited,
+    query: GraphQLQuery,
+) -> GraphQLResponse {
+    execute(
+        schema,
+        &data,
+        live,
+        history,
+        metrics,
+        graphs.map(|g| g.inner()),
+        deadline.0,
+        language,
+        key.is_some(),
+        query.into(),
+    )
+    .await
+}
+
+/// Runs a GraphQL query given as a JSON body
+#[post("/graphql", data = "<request>", format = "json")]
+pub async fn post_graphql(
+    schema: &State<HaSchema>,
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    history: &State<LiveHistory>,
+    metrics: &State<Metrics>,
+    graphs: Option<&State<GraphCache>>,
+    deadline: SearchDeadline,
+    language: Language,
+    key: Option<Authorized>,
+    _limit: RateLimited,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    execute(
+        schema,
+        &data,
+        live,
+        history,
+        metrics,
+        graphs.map(|g| g.inner()),
+        deadline.0,
+        language,
+        key.is_some(),
+        request,
+    )
+    .await
+}
@@ -0,0 +1,281 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::metrics::Metrics;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use harail::gtfs_rt::RealtimeUpdates;
+use harail::{
+    OwnedRoute, PreparedGraph, RailroadData, Route, RoutingOptions, SearchKind, StationId,
+};
+use memmap2::Mmap;
+use rocket::tokio::task::{self, JoinError};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use tracing::{error, info};
+
+/// The days a cached graph covers, from the start of the first to the end of the last
+#[derive(PartialEq, Eq, Copy, Clone)]
+struct Window {
+    first: NaiveDate,
+    last: NaiveDate,
+}
+
+impl Window {
+    /// The whole days around a search window, so that searches on the same days share a graph
+    fn around(start_time: NaiveDateTime, end_time: NaiveDateTime) -> Self {
+        Window {
+            first: start_time.date(),
+            last: end_time.date().max(start_time.date()),
+        }
+    }
+
+    fn start_time(&self) -> NaiveDateTime {
+        self.first.and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    fn end_time(&self) -> NaiveDateTime {
+        self.last.and_hms_opt(0, 0, 0).unwrap() + Duration::days(1)
+    }
+}
+
+/// A graph prepared from a database, kept together with the database it borrows from
+struct CachedGraph {
+    // Declared before the database so that it is dropped first
+    graph: RwLock<PreparedGraph<'static>>,
+    data: Arc<RailroadData>,
+    window: Window,
+}
+
+impl CachedGraph {
+    fn build(data: Arc<RailroadData>, window: Window, options: &RoutingOptions) -> Self {
+        // Safety: the database lives behind an Arc, so it stays in place for as long as the Arc stored next to the
+        // graph keeps it alive, and the graph is only ever handed out borrowed for no longer than the CachedGraph itself
+        let borrowed: &'static RailroadData = unsafe { &*Arc::as_ptr(&data) };
+        let graph = PreparedGraph::new(borrowed, window.start_time(), window.end_time(), options);
+        CachedGraph {
+            graph: RwLock::new(graph),
+            data,
+            window,
+        }
+    }
+
+    /// Loads a graph saved by PreparedGraph::save from the given database, which must span whole days
+    fn load(data: Arc<RailroadData>, path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let file = File::open(path)?;
+        // Safety: the mapping is only read while the graph is loaded, and graph files are replaced by renaming like
+        // databases are
+        let content = unsafe { Mmap::map(&file)? };
+        // Safety: as in build
+        let borrowed: &'static RailroadData = unsafe { &*Arc::as_ptr(&data) };
+        let graph = PreparedGraph::load(borrowed, &content[..], &RoutingOptions::new())?;
+        let window = Window {
+            first: graph.start_time().date(),
+            last: (graph.end_time() - Duration::days(1)).date(),
+        };
+        if window.start_time() != graph.start_time() || window.end_time() != graph.end_time() {
+            return Err("the graph doesn't span whole days".into());
+        }
+        Ok(CachedGraph {
+            graph: RwLock::new(graph),
+            data,
+            window,
+        })
+    }
+
+    fn graph(&self) -> RwLockReadGuard<'_, PreparedGraph<'_>> {
+        self.graph.read().unwrap()
+    }
+
+    /// Whether the graph was built for the given database, window and options, though maybe with other real-time
+    /// updates, which refresh patches into it
+    fn serves(&self, data: &Arc<RailroadData>, window: Window, options: &RoutingOptions) -> bool {
+        let graph = self.graph();
+        Arc::ptr_eq(&self.data, data)
+            && self.window == window
+            && graph.options().max_transfers() == options.max_transfers()
+            && graph.options().weights() == options.weights()
+    }
+
+    /// Patches the given real-time updates into the graph, unless it has them already.
+    ///
+    /// Real-time updates are compared by identity, as a new set of updates is published whenever the feed is downloaded.
+    /// Searches on the graph wait for the patch, which only touches the train runs the updates changed.
+    fn refresh(&self, realtime: &Arc<RealtimeUpdates>) {
+        let current = |graph: &PreparedGraph| {
+            graph
+                .options()
+                .realtime()
+                .is_some_and(|r| std::ptr::eq(r, Arc::as_ptr(realtime)))
+        };
+        if current(&*self.graph()) {
+            return;
+        }
+        let mut graph = self.graph.write().unwrap();
+        // Another search may have patched the graph while waiting for the lock
+        if !current(&*graph) {
+            graph.update_realtime(Arc::clone(realtime));
+        }
+    }
+
+    fn search(
+        &self,
+        search: SearchKind,
+        start_time: NaiveDateTime,
+        start_station: StationId,
+        end_time: NaiveDateTime,
+        end_station: StationId,
+    ) -> Vec<OwnedRoute> {
+        let graph = self.graph();
+        let (start, end) = match (
+            self.data.station(start_station),
+            self.data.station(end_station),
+        ) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Vec::new(),
+        };
+        let mut routes: Vec<Route> = match search {
+            SearchKind::Best => graph
+                .best_route(start_time, start, end)
+                .into_iter()
+                .collect(),
+            SearchKind::Latest => graph
+                .latest_good_route(start_time, start, end)
+                .into_iter()
+                .collect(),
+            SearchKind::Multiple => graph.multiple_routes(start_time, start, end),
+            SearchKind::Pareto => graph.pareto_routes(start_time, start, end),
+            SearchKind::Alternatives(count) => {
+                graph.alternative_routes(start_time, start, end, count)
+            }
+            SearchKind::ArriveBy => graph
+                .route_arriving_by(start, end_time, end)
+                .into_iter()
+                .collect(),
+        };
+        // The graph spans whole days, so routes outside of the searched window are dropped
+        routes.retain(|route| {
+            route
+                .parts()
+                .next()
+                .is_none_or(|p| p.start().departure() >= start_time)
+                && route
+                    .parts()
+                    .last()
+                    .is_none_or(|p| p.end().arrival() <= end_time)
+        });
+        harail::explain(&mut routes);
+        routes.iter().map(OwnedRoute::from).collect()
+    }
+}
+
+/// The graphs built for recent route searches, so that searches on the same days don't build the graph again.
+///
+/// Graphs span whole days and are built lazily by the first search on those days. Once more than the capacity are
+/// cached, the least recently searched graph is dropped. A graph is only used with the database it was built from, so
+/// reloading the database makes searches build graphs afresh, while newly downloaded real-time updates are patched into
+/// the cached graphs by the first search using them.
+#[derive(Clone)]
+pub struct GraphCache {
+    capacity: usize,
+    /// The cached graphs, most recently searched first
+    graphs: Arc<Mutex<VecDeque<Arc<CachedGraph>>>>,
+}
+
+impl GraphCache {
+    pub fn new(capacity: usize) -> Self {
+        GraphCache {
+            capacity,
+            graphs: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// The cached graph for the given database, window and options, marking it as the most recently searched
+    fn get(
+        &self,
+        data: &Arc<RailroadData>,
+        window: Window,
+        options: &RoutingOptions,
+    ) -> Option<Arc<CachedGraph>> {
+        let mut graphs = self.graphs.lock().unwrap();
+        let index = graphs
+            .iter()
+            .position(|g| g.serves(data, window, options))?;
+        let graph = graphs.remove(index)?;
+        graphs.push_front(Arc::clone(&graph));
+        Some(graph)
+    }
+
+    /// Caches a newly built graph, replacing any built concurrently for the same searches and evicting the least
+    /// recently searched graphs beyond the capacity
+    fn insert(&self, graph: Arc<CachedGraph>, options: &RoutingOptions) {
+        let mut graphs = self.graphs.lock().unwrap();
+        graphs.retain(|g| !g.serves(&graph.data, graph.window, options));
+        graphs.push_front(graph);
+        graphs.truncate(self.capacity);
+    }
+
+    /// Caches the graph saved next to the database file with a .graph extension, e.g. by the CLI's save-graph command,
+    /// so that the first searches on its days don't have to build it.
+    ///
+    /// A graph which fails to load, e.g. as it was saved from an older version of the database, is reported and left
+    /// to be built by the first search on its days.
+    pub fn preload(&self, data: &Arc<RailroadData>, database_path: &Path) {
+        let path = database_path.with_extension("graph");
+        if !path.exists() {
+            return;
+        }
+        match CachedGraph::load(Arc::clone(data), &path) {
+            Ok(graph) => {
+                self.insert(Arc::new(graph), &RoutingOptions::new());
+                info!("Loaded graph {}", path.display());
+            }
+            Err(e) => error!("Failed to load graph {}: {}", path.display(), e),
+        }
+    }
+
+    /// Finds routes like harail::find_routes_async does, on a cached graph spanning the days of the search.
+    ///
+    /// The graph is built first if it isn't cached, in which case concurrent searches on the same days may each build
+    /// it, and the given real-time updates are patched into it if it was cached with others. Searches run on the
+    /// blocking thread pool.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search(
+        &self,
+        data: Arc<RailroadData>,
+        realtime: Arc<RealtimeUpdates>,
+        metrics: &Metrics,
+        search: SearchKind,
+        start_time: NaiveDateTime,
+        start_station: StationId,
+        end_time: NaiveDateTime,
+        end_station: StationId,
+        options: RoutingOptions,
+    ) -> Result<Vec<OwnedRoute>, JoinError> {
+        let cache = self.clone();
+        let metrics = metrics.clone();
+        task::spawn_blocking(move || {
+            let window = Window::around(start_time, end_time);
+            let graph = match cache.get(&data, window, &options) {
+                Some(graph) => {
+                    metrics.observe_graph_cache(true);
+                    graph.refresh(&realtime);
+                    graph
+                }
+                None => {
+                    metrics.observe_graph_cache(false);
+                    let graph = Arc::new(CachedGraph::build(data, window, &options));
+                    cache.insert(Arc::clone(&graph), &options);
+                    graph
+                }
+            };
+            graph.search(search, start_time, start_station, end_time, end_station)
+        })
+        .await
+    }
+}
@@ -0,0 +1,65 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::database::LiveData;
+use crate::live::Live;
+use crate::realtime::LiveUpdates;
+use harail::DelayHistory;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+/// The delays observed in the real-time feeds, which found routes are rated by
+pub type LiveHistory = Live<DelayHistory>;
+
+/// Loads the delay history saved at the given path, or starts an empty one if the file doesn't exist yet
+pub fn load(path: &Path) -> Result<DelayHistory, Box<dyn Error + Send + Sync>> {
+    match File::open(path) {
+        Ok(file) => Ok(DelayHistory::load(BufReader::new(file))?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(DelayHistory::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the delay history next to its file and renames it over the file once complete
+fn save(history: &DelayHistory, path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let partial = path.with_extension("partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    history.save(&mut writer)?;
+    writer.flush()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Records the delays of every real-time update published into the history, publishing it and saving it to its file.
+///
+/// A history which fails to save is reported, and is still used for rating routes.
+pub async fn record(history: LiveHistory, updates: LiveUpdates, data: LiveData, path: PathBuf) {
+    let mut published = updates.subscribe();
+    while published.changed().await.is_ok() {
+        let updates = published.borrow_and_update().clone();
+        let mut recorded = DelayHistory::clone(&history.current());
+        let data = data.current();
+        let path = path.clone();
+        let saved = rocket::tokio::task::spawn_blocking(move || {
+            recorded.record_updates(&data, &updates);
+            let saved = save(&recorded, &path);
+            (recorded, saved)
+        })
+        .await;
+        match saved {
+            Ok((recorded, saved)) => {
+                if let Err(e) = saved {
+                    error!("Failed to save the delay history: {}", e);
+                }
+                history.publish(recorded);
+            }
+            Err(e) => error!("Failed to record delays: {}", e),
+        }
+    }
+}
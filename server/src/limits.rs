@@ -0,0 +1,105 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::auth::Authorized;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets are forgotten once there are this many, keeping only clients which are still being limited
+const MAX_TRACKED_CLIENTS: usize = 10000;
+
+/// Who a token bucket belongs to
+#[derive(PartialEq, Eq, Hash)]
+enum Client {
+    Address(Option<IpAddr>),
+    ApiKey(String),
+}
+
+/// Limits how often each client may run expensive requests, using a token bucket per client.
+///
+/// Clients are told apart by their API key when they authenticate with one, and by their IP address otherwise.
+/// Clients may burst up to the per-minute limit, and then regain requests gradually.
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<Client, (f64, Instant)>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a request from the client's bucket, returning whether the client may proceed
+    fn acquire(&self, client: Client) -> bool {
+        let capacity = self.per_minute as f64;
+        let now = Instant::now();
+        let refill = |(tokens, last): (f64, Instant)| {
+            (tokens + now.duration_since(last).as_secs_f64() * capacity / 60.0).min(capacity)
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| refill(*bucket) < capacity);
+        }
+        let bucket = buckets.entry(client).or_insert((capacity, now));
+        let tokens = refill(*bucket);
+        if tokens < 1.0 {
+            *bucket = (tokens, now);
+            return false;
+        }
+        *bucket = (tokens - 1.0, now);
+        true
+    }
+}
+
+/// A request guard which fails with 429 Too Many Requests when the client exceeds the rate limit.
+///
+/// Requests are not limited unless a RateLimiter is managed by the server.
+pub struct RateLimited;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RateLimited {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let limiter = match req.rocket().state::<RateLimiter>() {
+            Some(limiter) => limiter,
+            None => return Outcome::Success(RateLimited),
+        };
+        let client = match req.guard::<Authorized>().await {
+            Outcome::Success(Authorized(Some(key))) => Client::ApiKey(key),
+            _ => Client::Address(req.client_ip()),
+        };
+        if limiter.acquire(client) {
+            Outcome::Success(RateLimited)
+        } else {
+            Outcome::Error((Status::TooManyRequests, ()))
+        }
+    }
+}
+
+/// How long route searches may run before they are abandoned and answered with 503 Service Unavailable
+pub struct SearchTimeout(pub Duration);
+
+/// A request guard giving the time limit of route searches, if a SearchTimeout is managed by the server
+pub struct SearchDeadline(pub Option<Duration>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SearchDeadline {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(SearchDeadline(
+            req.rocket().state::<SearchTimeout>().map(|t| t.0),
+        ))
+    }
+}
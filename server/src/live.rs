@@ -0,0 +1,46 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use rocket::tokio::sync::watch;
+use std::sync::Arc;
+
+/// A value shared with the request handlers, which can be replaced while the server is running.
+///
+/// Requests keep using the value they started with, even if a newer one is published meanwhile.
+pub struct Live<T>(Arc<watch::Sender<Arc<T>>>);
+
+impl<T> Live<T> {
+    pub fn new(value: T) -> Self {
+        Live(Arc::new(watch::Sender::new(Arc::new(value))))
+    }
+
+    /// The most recently published value
+    pub fn current(&self) -> Arc<T> {
+        Arc::clone(&self.0.borrow())
+    }
+
+    /// Replaces the published value
+    pub fn publish(&self, value: T) {
+        self.0.send_replace(Arc::new(value));
+    }
+
+    /// A receiver which is notified whenever a new value is published
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.0.subscribe()
+    }
+}
+
+impl<T> Clone for Live<T> {
+    fn clone(&self) -> Self {
+        Live(Arc::clone(&self.0))
+    }
+}
+
+impl<T: Default> Default for Live<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
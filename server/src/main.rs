@@ -10,29 +10,152 @@
 #[macro_use]
 extern crate rocket;
 
-use bincode::deserialize_from;
-use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
-use clap::{Arg, Command};
-use harail::{RailroadData, StationId, Stop, JSON};
-use jzon::JsonValue;
+use auth::{ApiKeys, Authorized};
+use cache::Cached;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use clap::{Arg, ArgAction, Command};
+use config::Config;
+use cors::Cors;
+use database::{Database, LiveData};
+use errors::ApiError;
+use feeds::{FeedData, Feeds};
+use graphs::GraphCache;
+use harail::gtfs_rt::RealtimeUpdates;
+use harail::{
+    ActionWeights, DelayHistory, OwnedRoute, OwnedRoutePart, RailroadData, RouteOrder,
+    RoutingOptions, SearchKind, Station, StationId, Stop, TrainId, JSON,
+};
+use history::LiveHistory;
+use jzon::{object, JsonValue};
+use limits::{RateLimited, RateLimiter, SearchDeadline, SearchTimeout};
+use messages::{ErrorCode, Language};
+use metrics::Metrics;
+use realtime::LiveUpdates;
+use responses::{
+    CandidateResponse, FoundRoutes, RouteResponse, StationResponse, StopResponse, TrainsResponse,
+};
 use rocket::form::{self, FromFormField, ValueField};
 use rocket::fs::FileServer;
 use rocket::http::RawStr;
 use rocket::request::FromParam;
 use rocket::response::content::RawJson;
-use rocket::response::status;
-use rocket::State;
-use std::path::PathBuf;
-use std::{fs::File, io::BufReader, path::Path};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::{self, Json};
+use rocket::serde::{de, Deserialize, Deserializer};
+use rocket::tokio::{select, time};
+use rocket::{Shutdown, State};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing_subscriber::EnvFilter;
 
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
+mod auth;
+mod cache;
+mod config;
+mod cors;
+mod database;
+mod errors;
+mod feeds;
+mod graphql;
+mod graphs;
+mod history;
+mod limits;
+mod live;
+mod messages;
+mod metrics;
+mod realtime;
+mod refresh;
+mod responses;
 #[cfg(test)]
 mod tests;
 
 #[get("/stations")]
-fn list_stations(data: &State<RailroadData>) -> RawJson<String> {
+fn list_stations(data: FeedData) -> Cached<RawJson<String>> {
+    let data = data.current();
     let json = JsonValue::Array(data.stations().map(|s| s.to_json()).collect());
+    Cached::new(&data, RawJson(json.dump()))
+}
+
+#[get("/stations/search?<q>&<limit>")]
+fn search_stations(data: FeedData, q: &str, limit: Option<usize>) -> Cached<RawJson<String>> {
+    let data = data.current();
+    let json = JsonValue::Array(
+        harail::search_stations(&data, q, limit.unwrap_or(10))
+            .into_iter()
+            .map(|s| s.to_json())
+            .collect(),
+    );
+    Cached::new(&data, RawJson(json.dump()))
+}
+
+#[get("/stations/nearby?<lat>&<lon>&<radius>&<limit>")]
+fn nearby_stations(
+    data: FeedData,
+    lat: f64,
+    lon: f64,
+    radius: Option<f64>,
+    limit: Option<usize>,
+) -> Cached<RawJson<String>> {
+    let data = data.current();
+    let json = JsonValue::Array(
+        harail::nearby_stations(&data, lat, lon, radius.unwrap_or(2000.0))
+            .into_iter()
+            .take(limit.unwrap_or(10))
+            .map(|(station, distance)| {
+                let mut json = station.to_json();
+                json["distance"] = distance.round().into();
+                json
+            })
+            .collect(),
+    );
+    Cached::new(&data, RawJson(json.dump()))
+}
+
+/// A station's full record, along with the trains stopping at it on the given date, today by default
+#[get("/stations/<id>?<date>")]
+fn get_station(
+    data: FeedData,
+    id: StationId,
+    date: Option<HaDateTime>,
+) -> Result<Json<StationResponse>, ApiError> {
+    let data = data.current();
+    let station = data
+        .station(id)
+        .ok_or(ApiError::new(ErrorCode::StationNotFound))?;
+    let date = date.map_or_else(|| harail::to_feed_time(&Utc::now()), |date| date.0);
+    Ok(Json(StationResponse::new(&data, station, date.date())))
+}
+
+#[get("/health")]
+fn health(data: FeedData) -> RawJson<String> {
+    let data = data.current();
+    let (first_date, last_date) = data.date_range().map_or((None, None), |(first, last)| {
+        (Some(first.to_string()), Some(last.to_string()))
+    });
+    let json = object! {
+        status: "ok",
+        stations: data.stations().count(),
+        trains: data.trains().count(),
+        first_date: first_date,
+        last_date: last_date,
+    };
+    RawJson(json.dump())
+}
+
+#[get("/version")]
+fn version(data: FeedData) -> RawJson<String> {
+    let built = data
+        .current()
+        .build_time()
+        .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc).to_rfc3339());
+    let json = object! {
+        version: VERSION.unwrap_or_default(),
+        database_built: built,
+    };
     RawJson(json.dump())
 }
 
@@ -42,28 +165,69 @@ impl<'v> FromParam<'v> for HaDate {
     type Error = &'v RawStr;
 
     fn from_param(param: &'v str) -> Result<Self, Self::Error> {
-        let dt = param.parse::<DateTime<Utc>>().map_err(|_| param)?;
-        Ok(HaDate(dt.naive_utc().date()))
+        let dt = param.parse::<DateTime<FixedOffset>>().map_err(|_| param)?;
+        Ok(HaDate(harail::to_feed_time(&dt).date()))
     }
 }
 
+/// The trains running on the given date, today by default, or only those stopping at the given station.
+///
+/// Trains are paged by offset, listing at most limit trains, 100 by default.
+#[get("/trains?<date>&<station>&<offset>&<limit>")]
+fn list_trains(
+    data: FeedData,
+    date: Option<HaDateTime>,
+    station: Option<StationId>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Json<TrainsResponse>, ApiError> {
+    let data = data.current();
+    let date = date
+        .map_or_else(|| harail::to_feed_time(&Utc::now()), |date| date.0)
+        .date();
+    let trains = match station {
+        Some(station) => {
+            data.station(station)
+                .ok_or(ApiError::new(ErrorCode::StationNotFound))?;
+            data.trains_stopping_at(station, date)
+        }
+        None => data.trains().filter(|t| t.runs_on(date)).collect(),
+    };
+    Ok(Json(TrainsResponse::new(
+        &data,
+        trains,
+        date,
+        offset.unwrap_or(0),
+        limit.unwrap_or(100),
+    )))
+}
+
+/// The stops of a train on the given date. The train is given by its identifier, or by the number passengers know it by.
 #[get("/trains/<id>/stops/<date>")]
-fn get_train(data: &State<RailroadData>, id: &str, date: HaDate) -> Option<RawJson<String>> {
-    let train = data.train(id)?;
-    let json = JsonValue::Array(
-        train
-            .stops()
-            .map(|s| Stop::from_stop_schedule(data, s, date.0).to_json())
-            .collect(),
-    );
-    Some(RawJson(json.dump()))
+fn get_train(
+    data: FeedData,
+    id: &str,
+    date: HaDate,
+) -> Result<Cached<Json<Vec<StopResponse>>>, ApiError> {
+    let data = data.current();
+    let train = data
+        .find_train(id, date.0)
+        .ok_or(ApiError::new(ErrorCode::TrainNotFound))?;
+    let stops = train
+        .stops()
+        .map(|s| StopResponse::from(&Stop::from_stop_schedule(&data, s, date.0)))
+        .collect();
+    Ok(Cached::new(&data, Json(stops)))
 }
 
-#[derive(FromFormField)]
+#[derive(FromFormField, Deserialize, async_graphql::Enum, Copy, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
 enum SearchType {
     Best,
     Latest,
     Multi,
+    Pareto,
+    ArriveBy,
 }
 
 struct HaDateTime(NaiveDateTime);
@@ -71,66 +235,591 @@ struct HaDateTime(NaiveDateTime);
 #[rocket::async_trait]
 impl<'v> FromFormField<'v> for HaDateTime {
     fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
-        let dt = field.value.parse::<DateTime<Utc>>().map_err(|_| {
+        let dt = field.value.parse::<DateTime<FixedOffset>>().map_err(|_| {
             form::Error::validation(format! {"Cannot parse {} as date", field.value})
         })?;
-        Ok(HaDateTime(dt.naive_utc()))
+        Ok(HaDateTime(harail::to_feed_time(&dt)))
     }
 }
 
-#[derive(FromForm)]
+impl<'de> Deserialize<'de> for HaDateTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        let dt = value
+            .parse::<DateTime<FixedOffset>>()
+            .map_err(|_| de::Error::custom(format!("Cannot parse {} as date", value)))?;
+        Ok(HaDateTime(harail::to_feed_time(&dt)))
+    }
+}
+
+/// The most stations listed when a station name is ambiguous
+const MAX_CANDIDATES: usize = 5;
+
+/// A station given by its identifier or by its name
+enum StationRef {
+    Id(StationId),
+    Name(String),
+}
+
+impl StationRef {
+    /// Finds the station, by the name's spelling or one of the station's aliases, or else by the fuzzy matcher if
+    /// it matches a single station. Names matching several stations fail with the ambiguous error, listing them.
+    fn resolve(
+        &self,
+        data: &RailroadData,
+        not_found: ErrorCode,
+        ambiguous: ErrorCode,
+    ) -> Result<StationId, ApiError> {
+        let name = match self {
+            StationRef::Id(id) => {
+                return data
+                    .station(*id)
+                    .map(|s| s.id())
+                    .ok_or(ApiError::new(not_found))
+            }
+            StationRef::Name(name) => name,
+        };
+        if let Some(station) = data.find_station(name) {
+            return Ok(station.id());
+        }
+        let candidates = harail::search_stations(data, name, MAX_CANDIDATES);
+        match candidates[..] {
+            [] => Err(ApiError::new(not_found)),
+            [station] => Ok(station.id()),
+            _ => Err(ApiError::new(ambiguous).with_candidates(
+                candidates
+                    .into_iter()
+                    .map(CandidateResponse::from)
+                    .collect(),
+            )),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for StationRef {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        if field.value.trim().is_empty() {
+            return Err(form::Error::validation("Station must not be empty").into());
+        }
+        Ok(match field.value.parse() {
+            Ok(id) => StationRef::Id(id),
+            Err(_) => StationRef::Name(field.value.to_owned()),
+        })
+    }
+}
+
+/// Stations are given in JSON bodies by their identifiers as numbers, or by their names or identifiers as strings
+impl<'de> Deserialize<'de> for StationRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(crate = "rocket::serde", untagged)]
+        enum Value {
+            Id(StationId),
+            Name(String),
+        }
+        match Value::deserialize(deserializer)? {
+            Value::Id(id) => Ok(StationRef::Id(id)),
+            Value::Name(name) if name.trim().is_empty() => {
+                Err(de::Error::custom("Station must not be empty"))
+            }
+            Value::Name(name) => Ok(match name.parse() {
+                Ok(id) => StationRef::Id(id),
+                Err(_) => StationRef::Name(name),
+            }),
+        }
+    }
+}
+
+/// The departures of a station as JSON, with the crowding and expected departure times given by the real-time updates.
+///
+/// Departures get an expected_departure when their train runs late or early, and are marked cancelled when their run
+/// was cancelled or skips the station.
+fn departure_board(
+    data: &RailroadData,
+    station: &Station,
+    from: NaiveDateTime,
+    limit: usize,
+    direction: Option<u8>,
+    realtime: &RealtimeUpdates,
+) -> JsonValue {
+    let departures = harail::get_departures_in_direction(data, station, from, limit, direction);
+    JsonValue::Array(
+        departures
+            .iter()
+            .map(|d| {
+                let mut json = d.to_json();
+                if let Some(occupancy) = d.occupancy(realtime) {
+                    json["occupancy"] = occupancy.name().into();
+                }
+                match d.expected_departure(data, realtime) {
+                    Some(expected) if expected != d.stop().departure() => {
+                        json["expected_departure"] = harail::localize(expected).to_rfc3339().into();
+                    }
+                    Some(_) => {}
+                    None => json["cancelled"] = true.into(),
+                }
+                json
+            })
+            .collect(),
+    )
+}
+
+/// The next trains leaving a station, only listing the trains with the given GTFS direction_id if one is given
+#[get("/stations/<id>/departures?<from>&<limit>&<direction>")]
+fn get_departures(
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    id: StationId,
+    from: Option<HaDateTime>,
+    limit: Option<usize>,
+    direction: Option<u8>,
+) -> Result<RawJson<String>, ApiError> {
+    let data = data.current();
+    let station = data
+        .station(id)
+        .ok_or(ApiError::new(ErrorCode::StationNotFound))?;
+    let from = from.map_or_else(|| harail::to_feed_time(&Utc::now()), |from| from.0);
+    let json = departure_board(
+        &data,
+        station,
+        from,
+        limit.unwrap_or(10),
+        direction,
+        &live.current(),
+    );
+    Ok(RawJson(json.dump()))
+}
+
+/// How often a streamed departure board is sent again even if no real-time updates arrived, as trains leave it
+const BOARD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Streams a station's departure board as server-sent events, each holding the departures as listed by
+/// /stations/<id>/departures. The board is sent again whenever real-time updates change it, and at least every minute
+/// while time moves on.
+///
+/// The board starts at from, now by default, and moves on with the time passed since the stream started.
+#[get("/stations/<id>/departures/live?<from>&<limit>&<direction>")]
+fn live_departures(
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    id: StationId,
+    from: Option<HaDateTime>,
+    limit: Option<usize>,
+    direction: Option<u8>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], ApiError> {
+    data.current()
+        .station(id)
+        .ok_or(ApiError::new(ErrorCode::StationNotFound))?;
+    let mut updates = live.subscribe();
+    let from = from.map_or_else(|| harail::to_feed_time(&Utc::now()), |from| from.0);
+    let started = Instant::now();
+    let limit = limit.unwrap_or(10);
+    Ok(EventStream! {
+        let mut last = None;
+        loop {
+            let realtime = Arc::clone(&updates.borrow_and_update());
+            let data = data.current();
+            // The station may be gone from a reloaded database
+            let station = match data.station(id) {
+                Some(station) => station,
+                None => break,
+            };
+            let now = from + chrono::Duration::from_std(started.elapsed()).unwrap_or_default();
+            let json = departure_board(&data, station, now, limit, direction, &realtime).dump();
+            if last.as_ref() != Some(&json) {
+                yield Event::data(json.clone());
+                last = Some(json);
+            }
+            select! {
+                changed = updates.changed() => if changed.is_err() { break },
+                _ = time::sleep(BOARD_INTERVAL) => {},
+                _ = &mut shutdown => break,
+            }
+        }
+    })
+}
+
+/// The penalties added to the travel time of a route for each of its actions, in seconds, each left at the default of
+/// the routing options if not given
+#[derive(FromForm, Deserialize, Default, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+struct SearchWeights {
+    board: Option<u64>,
+    unboard: Option<u64>,
+    ride: Option<u64>,
+    tight_transfer: Option<u64>,
+}
+
+impl SearchWeights {
+    fn to_action_weights(&self) -> ActionWeights {
+        let mut weights = ActionWeights::new();
+        if let Some(board) = self.board {
+            weights = weights.with_board(Duration::from_secs(board));
+        }
+        if let Some(unboard) = self.unboard {
+            weights = weights.with_unboard(Duration::from_secs(unboard));
+        }
+        if let Some(ride) = self.ride {
+            weights = weights.with_ride(Duration::from_secs(ride));
+        }
+        if let Some(tight_transfer) = self.tight_transfer {
+            weights = weights.with_tight_transfer(Duration::from_secs(tight_transfer));
+        }
+        weights
+    }
+}
+
+/// The options of a route search, given in the query string of GET /routes/find or as the JSON body of POST
+/// /routes/find
+#[derive(FromForm, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
 struct FindOptions {
     search: SearchType,
-    start_station: StationId,
+    start_station: StationRef,
     start_time: HaDateTime,
-    end_station: StationId,
+    end_station: StationRef,
     end_time: HaDateTime,
+    max_transfers: Option<usize>,
+    /// The minimum time between leaving a train and boarding another, in minutes
+    min_transfer: Option<u64>,
+    /// The time to spare which train switches should preferably leave beyond the minimum, in minutes
+    slack: Option<u64>,
+    #[serde(default)]
+    avoid_stations: Vec<StationId>,
+    #[serde(default)]
+    avoid_trains: Vec<TrainId>,
+    #[serde(default)]
+    wheelchair: bool,
+    #[serde(default)]
+    bikes: bool,
+    /// Whether to order multiple routes with meaningfully different ones first
+    #[serde(default)]
+    diverse: bool,
+    #[serde(default)]
+    weights: SearchWeights,
+}
+
+/// Searches for routes, on a cached graph if there is a graph cache and the options allow it.
+///
+/// Minimum transfer times and slacks, avoided stations and trains, accessibility requirements and weights vary between
+/// searches, and would leave a graph per search in the cache, so searches using them build their own graphs.
+///
+/// Searches run on the blocking thread pool and are abandoned once the timeout, if any, passes. Searches building their
+/// own graphs are cancelled then, as they are when the client disconnects, while cached graphs are left to finish
+/// building for later searches.
+async fn search_routes(
+    data: Arc<RailroadData>,
+    realtime: Arc<RealtimeUpdates>,
+    history: Arc<DelayHistory>,
+    metrics: &Metrics,
+    graphs: Option<&GraphCache>,
+    timeout: Option<Duration>,
+    options: FindOptions,
+) -> Result<(SearchKind, Vec<OwnedRoute>), ApiError> {
+    let start_station = options.start_station.resolve(
+        &data,
+        ErrorCode::StartStationNotFound,
+        ErrorCode::AmbiguousStartStation,
+    )?;
+    let end_station = options.end_station.resolve(
+        &data,
+        ErrorCode::EndStationNotFound,
+        ErrorCode::AmbiguousEndStation,
+    )?;
+    let search = match options.search {
+        SearchType::Best => SearchKind::Best,
+        SearchType::Latest => SearchKind::Latest,
+        SearchType::Multi => SearchKind::Multiple,
+        SearchType::Pareto => SearchKind::Pareto,
+        SearchType::ArriveBy => SearchKind::ArriveBy,
+    };
+    let cacheable = options.min_transfer.is_none()
+        && options.slack.is_none()
+        && options.avoid_stations.is_empty()
+        && options.avoid_trains.is_empty()
+        && !options.wheelchair
+        && !options.bikes
+        && !options.diverse
+        && options.weights == SearchWeights::default();
+    let routing = RoutingOptions::new()
+        .with_max_transfers(options.max_transfers)
+        .with_min_transfer_time(
+            options
+                .min_transfer
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+        )
+        .with_transfer_slack(
+            options
+                .slack
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+        )
+        .with_avoided_stations(options.avoid_stations)
+        .with_avoided_trains(options.avoid_trains)
+        .with_wheelchair(options.wheelchair)
+        .with_bikes(options.bikes)
+        .with_order(if options.diverse {
+            RouteOrder::Diverse
+        } else {
+            RouteOrder::Found
+        })
+        .with_weights(options.weights.to_action_weights())
+        .with_realtime(Arc::clone(&realtime))
+        .with_observer(Arc::new(metrics.clone()));
+    let start = Instant::now();
+    let routes = async {
+        match graphs.filter(|_| cacheable) {
+            Some(graphs) => graphs
+                .search(
+                    Arc::clone(&data),
+                    Arc::clone(&realtime),
+                    metrics,
+                    search,
+                    options.start_time.0,
+                    start_station,
+                    options.end_time.0,
+                    end_station,
+                    routing,
+                )
+                .await
+                .map_err(|_| ApiError::new(ErrorCode::SearchFailed)),
+            None => harail::find_routes_async(
+                Arc::clone(&data),
+                search,
+                options.start_time.0,
+                start_station,
+                options.end_time.0,
+                end_station,
+                routing,
+            )
+            .await
+            .map_err(|_| ApiError::new(ErrorCode::SearchFailed)),
+        }
+    };
+    let routes = match timeout {
+        Some(timeout) => time::timeout(timeout, routes)
+            .await
+            .map_err(|_| ApiError::new(ErrorCode::SearchTimedOut))??,
+        None => routes.await?,
+    };
+    metrics.observe_search(search, start.elapsed());
+    if history.is_empty() {
+        return Ok((search, routes));
+    }
+    let routes = routes
+        .into_iter()
+        .map(|route| route.with_reliability(&data, &history))
+        .collect();
+    Ok((search, routes))
+}
+
+/// Gives the found routes as a single route for single route searches, or as an array of routes otherwise
+fn found_routes<T, F: Fn(&OwnedRoute) -> T>(
+    search: SearchKind,
+    mut routes: Vec<OwnedRoute>,
+    respond: F,
+) -> Result<FoundRoutes<T>, ApiError> {
+    Ok(match search {
+        SearchKind::Best | SearchKind::Latest | SearchKind::ArriveBy => FoundRoutes::Route(
+            respond(&routes.pop().ok_or(ApiError::new(ErrorCode::NoRouteFound))?),
+        ),
+        SearchKind::Multiple | SearchKind::Pareto | SearchKind::Alternatives(_) => {
+            FoundRoutes::Routes(routes.iter().map(respond).collect())
+        }
+    })
 }
 
 #[get("/routes/find?<options..>")]
-fn find_route(
-    data: &State<RailroadData>,
+async fn find_route(
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    history: &State<LiveHistory>,
+    metrics: &State<Metrics>,
+    graphs: Option<&State<GraphCache>>,
+    deadline: SearchDeadline,
+    language: Language,
+    _key: Authorized,
+    _limit: RateLimited,
     options: FindOptions,
-) -> Result<RawJson<String>, status::NotFound<String>> {
-    let start_station = data
-        .station(options.start_station)
-        .ok_or_else(|| status::NotFound(String::from("start station not found")))?;
-    let start_time = options.start_time.0;
-    let end_station = data
-        .station(options.end_station)
-        .ok_or_else(|| status::NotFound(String::from("end station not found")))?;
-    let end_time = options.end_time.0;
-    Ok(RawJson(match options.search {
-        SearchType::Best => {
-            harail::get_best_single_route(data, start_time, start_station, end_time, end_station)
-                .ok_or_else(|| status::NotFound(String::from("no possible route found")))?
-                .to_json()
-                .dump()
-        }
-        SearchType::Latest => harail::get_latest_good_single_route(
-            data,
-            start_time,
-            start_station,
-            end_time,
-            end_station,
-        )
-        .ok_or_else(|| status::NotFound(String::from("no possible route found")))?
-        .to_json()
-        .dump(),
-        SearchType::Multi => JsonValue::Array(
-            harail::get_multiple_routes(data, start_time, start_station, end_time, end_station)
-                .into_iter()
-                .map(|r| r.to_json())
-                .collect(),
-        )
-        .dump(),
-    }))
-}
-
-fn rocket(data: RailroadData, static_path: Option<&Path>) -> rocket::Rocket<rocket::Build> {
+) -> Result<Json<FoundRoutes<RouteResponse>>, ApiError> {
+    let realtime = live.current();
+    let data = Arc::clone(data.current().data());
+    let graphs = graphs.map(|g| g.inner());
+    let (search, routes) = search_routes(
+        Arc::clone(&data),
+        Arc::clone(&realtime),
+        history.current(),
+        metrics,
+        graphs,
+        deadline.0,
+        options,
+    )
+    .await?;
+    found_routes(search, routes, |r| {
+        RouteResponse::new(r, &data, &realtime, language)
+    })
+    .map(Json)
+}
+
+/// Finds routes like GET /routes/find, for searches whose options are easier to send as a JSON body
+#[post("/routes/find", data = "<options>", format = "json")]
+async fn find_route_json(
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    history: &State<LiveHistory>,
+    metrics: &State<Metrics>,
+    graphs: Option<&State<GraphCache>>,
+    deadline: SearchDeadline,
+    language: Language,
+    key: Authorized,
+    limit: RateLimited,
+    options: Json<FindOptions>,
+) -> Result<Json<FoundRoutes<RouteResponse>>, ApiError> {
+    find_route(
+        data,
+        live,
+        history,
+        metrics,
+        graphs,
+        deadline,
+        language,
+        key,
+        limit,
+        options.into_inner(),
+    )
+    .await
+}
+
+/// A train ride of a route which was found before, as given in the route's JSON
+#[derive(FromForm)]
+struct PlannedPart {
+    train: TrainId,
+    start_station: StationId,
+    start_time: HaDateTime,
+    end_station: StationId,
+}
+
+/// Streams the state of a planned route as server-sent events, sending it again whenever real-time updates change it.
+///
+/// Each event holds the route as formatted by /routes/find, or {"cancelled":true} once any of its train rides no longer
+/// takes place.
+#[get("/routes/live?<parts>")]
+fn live_route(
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    language: Language,
+    parts: Vec<PlannedPart>,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let mut updates = live.subscribe();
+    EventStream! {
+        let mut last = None;
+        loop {
+            let realtime = Arc::clone(&updates.borrow_and_update());
+            let data = data.current();
+            let route = parts
+                .iter()
+                .map(|p| {
+                    OwnedRoutePart::from_ride(
+                        &data,
+                        &realtime,
+                        &p.train,
+                        p.start_station,
+                        p.start_time.0,
+                        p.end_station,
+                    )
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(OwnedRoute::from_parts);
+            let json = match route {
+                Some(route) => json::to_string(&RouteResponse::new(&route, &data, &realtime, language)).unwrap(),
+                None => object! { cancelled: true }.dump(),
+            };
+            if last.as_ref() != Some(&json) {
+                yield Event::data(json.clone());
+                last = Some(json);
+            }
+            select! {
+                changed = updates.changed() => if changed.is_err() { break },
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+}
+
+#[get("/routes/geometry?<options..>")]
+async fn find_route_geometry(
+    data: FeedData,
+    live: &State<LiveUpdates>,
+    history: &State<LiveHistory>,
+    metrics: &State<Metrics>,
+    graphs: Option<&State<GraphCache>>,
+    deadline: SearchDeadline,
+    _key: Authorized,
+    _limit: RateLimited,
+    options: FindOptions,
+) -> Result<RawJson<String>, ApiError> {
+    let data = data.current();
+    let (search, routes) = search_routes(
+        Arc::clone(data.data()),
+        live.current(),
+        history.current(),
+        metrics,
+        graphs.map(|g| g.inner()),
+        deadline.0,
+        options,
+    )
+    .await?;
+    let json = match found_routes(search, routes, |r| r.to_geojson(&data))? {
+        FoundRoutes::Route(route) => route,
+        FoundRoutes::Routes(routes) => JsonValue::Array(routes),
+    };
+    Ok(RawJson(json.dump()))
+}
+
+#[catch(401)]
+fn unauthorized() -> ApiError {
+    ApiError::new(ErrorCode::Unauthorized)
+}
+
+#[catch(429)]
+fn too_many_requests() -> ApiError {
+    ApiError::new(ErrorCode::RateLimited)
+}
+
+fn rocket(data: impl Into<Database>, static_path: Option<&Path>) -> rocket::Rocket<rocket::Build> {
+    let metrics = Metrics::new();
     let rocket = rocket::build()
-        .manage(data)
-        .mount("/harail", routes![list_stations, get_train, find_route]);
+        .manage(LiveData::new(data.into()))
+        .manage(LiveUpdates::default())
+        .manage(LiveHistory::default())
+        .manage(graphql::schema())
+        .manage(metrics.clone())
+        .attach(metrics)
+        .mount("/", routes![metrics::metrics])
+        .mount(
+            "/harail",
+            routes![
+                health,
+                version,
+                list_stations,
+                search_stations,
+                nearby_stations,
+                get_station,
+                get_departures,
+                live_departures,
+                list_trains,
+                get_train,
+                find_route,
+                find_route_json,
+                live_route,
+                find_route_geometry,
+                graphql::get_graphql,
+                graphql::post_graphql
+            ],
+        );
+    let rocket = rocket.register("/", catchers![unauthorized, too_many_requests]);
     match static_path {
         Some(path) => rocket.mount("/", FileServer::from(path)),
         None => rocket,
@@ -139,16 +828,50 @@ fn rocket(data: RailroadData, static_path: Option<&Path>) -> rocket::Rocket<rock
 
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
     let matches = Command::new("HaRail Server")
         .version(VERSION.unwrap_or_default())
         .author("Yuval Deutscher")
         .about("Because the Israel Railways app sucks™ (server edition)")
         .arg(
             Arg::new("DATABASE")
-                .help("The HaRail database to use")
-                .required(true)
+                .help("The HaRail database to use (required unless given in the configuration file)")
                 .index(1),
         )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .value_name("FILE")
+                .help("TOML configuration file, whose settings are overridden by command line options (optional)"),
+        )
+        .arg(
+            Arg::new("address")
+                .long("address")
+                .value_name("ADDRESS")
+                .value_parser(clap::value_parser!(IpAddr))
+                .help("IP address to listen on (optional)"),
+        )
+        .arg(
+            Arg::new("port")
+                .short('p')
+                .long("port")
+                .value_name("PORT")
+                .value_parser(clap::value_parser!(u16))
+                .help("Port to listen on (optional)"),
+        )
+        .arg(
+            Arg::new("feed")
+                .long("feed")
+                .value_name("NAME=FILE")
+                .action(ArgAction::Append)
+                .value_parser(config::parse_feed)
+                .help("Another HaRail database to serve under /harail/NAME/ (optional, may be repeated)"),
+        )
         .arg(
             Arg::new("static")
                 .short('s')
@@ -156,17 +879,176 @@ async fn main() -> Result<(), rocket::Error> {
                 .value_name("STATIC")
                 .help("Path to static assets (optional)"),
         )
+        .arg(
+            Arg::new("cors-origin")
+                .long("cors-origin")
+                .value_name("ORIGIN")
+                .action(ArgAction::Append)
+                .help("Origin allowed to call the API from browsers, or * for any (optional, may be repeated)"),
+        )
+        .arg(
+            Arg::new("rate-limit")
+                .long("rate-limit")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .help("Maximum number of route searches per minute from a single IP address (optional)"),
+        )
+        .arg(
+            Arg::new("graph-cache")
+                .long("graph-cache")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Number of route search graphs to keep for later searches on the same days, or 0 to build a graph per search (optional, 8 by default)"),
+        )
+        .arg(
+            Arg::new("search-timeout")
+                .long("search-timeout")
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Time after which route searches are abandoned, or 0 to let them run to completion (optional, 30 by default)"),
+        )
+        .arg(
+            Arg::new("api-keys")
+                .long("api-keys")
+                .value_name("FILE")
+                .help("File listing the API keys allowed to search for routes, one per line (optional, searches are open to anyone without it)"),
+        )
+        .arg(
+            Arg::new("realtime-url")
+                .long("realtime-url")
+                .value_name("URL")
+                .action(ArgAction::Append)
+                .help("GTFS-Realtime feed to take delays, cancellations and service alerts from (optional, may be repeated)"),
+        )
+        .arg(
+            Arg::new("realtime-interval")
+                .long("realtime-interval")
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Interval between downloads of the real-time feed (optional, 60 by default)"),
+        )
+        .arg(
+            Arg::new("delay-history")
+                .long("delay-history")
+                .value_name("FILE")
+                .help("File of observed delays to rate found routes' reliability by, which real-time feeds are recorded into (optional)"),
+        )
+        .arg(
+            Arg::new("gtfs-url")
+                .long("gtfs-url")
+                .value_name("URL")
+                .help("GTFS feed to download periodically, rebuilding the database file from it (optional)"),
+        )
+        .arg(
+            Arg::new("gtfs-interval")
+                .long("gtfs-interval")
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Interval between downloads of the GTFS feed (optional, a day by default)"),
+        )
+        .arg(
+            Arg::new("gtfs-agency")
+                .long("gtfs-agency")
+                .value_name("AGENCY")
+                .action(ArgAction::Append)
+                .help("Name or ID of an agency whose trips to load from the GTFS feed (optional, may be repeated, Israel Railways by default)"),
+        )
+        .arg(
+            Arg::new("gtfs-all-agencies")
+                .long("gtfs-all-agencies")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("gtfs-agency")
+                .help("Load the trips of every agency in the GTFS feed"),
+        )
+        .arg(
+            Arg::new("gtfs-mode")
+                .long("gtfs-mode")
+                .value_name("MODE")
+                .value_parser(["light_rail", "subway", "rail", "bus", "ferry", "other"])
+                .action(ArgAction::Append)
+                .help("Only load trips of MODE from the GTFS feed, one of light_rail, subway, rail, bus, ferry or other (optional, may be repeated, all modes by default)"),
+        )
         .get_matches();
 
-    let static_path = matches.get_one::<String>("static").map(PathBuf::from);
-    let path = Path::new(matches.get_one::<String>("DATABASE").unwrap());
-    let file = File::open(path).unwrap();
-    let reader = BufReader::new(file);
-    let data: RailroadData = deserialize_from(reader).unwrap();
-    rocket(data, static_path.as_deref())
-        .ignite()
-        .await?
-        .launch()
-        .await?;
+    let mut config = match matches.get_one::<String>("config") {
+        Some(path) => Config::load(Path::new(path)).unwrap(),
+        None => Config::default(),
+    };
+    config.apply_args(&matches);
+    let path = config
+        .database
+        .clone()
+        .expect("no database given on the command line or in the configuration file");
+    let data = database::load(&path).unwrap();
+    let mut rocket = rocket(data, config.static_path.as_deref());
+    let mut databases = vec![(rocket.state::<LiveData>().unwrap().clone(), path.clone())];
+    if !config.feeds.is_empty() {
+        let mut feeds = HashMap::new();
+        for (name, path) in &config.feeds {
+            let data = LiveData::new(database::load(path).unwrap());
+            databases.push((data.clone(), path.clone()));
+            feeds.insert(name.clone(), data);
+        }
+        rocket = rocket.attach(Feeds::new(feeds));
+    }
+    let mut figment = rocket.figment().clone();
+    if let Some(address) = config.address {
+        figment = figment.merge(("address", address));
+    }
+    if let Some(port) = config.port {
+        figment = figment.merge(("port", port));
+    }
+    rocket = rocket.configure(figment);
+    if !config.cors_origins.is_empty() {
+        rocket = rocket.attach(Cors::new(config.cors_origins.clone()));
+    }
+    if let Some(keys) = &config.api_keys {
+        rocket = rocket.manage(ApiKeys::load(keys).unwrap());
+    }
+    if let Some(per_minute) = config.rate_limit {
+        rocket = rocket.manage(RateLimiter::new(per_minute));
+    }
+    if config.search_timeout() > 0 {
+        rocket = rocket.manage(SearchTimeout(Duration::from_secs(config.search_timeout())));
+    }
+    if config.graph_cache() > 0 {
+        let graphs = GraphCache::new(config.graph_cache());
+        for (data, path) in &databases {
+            graphs.preload(data.current().data(), path);
+        }
+        rocket = rocket.manage(graphs);
+    }
+    if let Some(history_path) = &config.delay_history {
+        let loaded = history::load(history_path).unwrap();
+        rocket.state::<LiveHistory>().unwrap().publish(loaded);
+    }
+    let rocket = rocket.ignite().await?;
+    #[cfg(unix)]
+    rocket::tokio::spawn(database::reload_on_hangup(databases));
+    if !config.realtime_urls.is_empty() {
+        rocket::tokio::spawn(realtime::poll(
+            rocket.state::<LiveUpdates>().unwrap().clone(),
+            config.realtime_urls.clone(),
+            Duration::from_secs(config.realtime_interval()),
+        ));
+    }
+    if let (Some(history_path), false) = (&config.delay_history, config.realtime_urls.is_empty()) {
+        rocket::tokio::spawn(history::record(
+            rocket.state::<LiveHistory>().unwrap().clone(),
+            rocket.state::<LiveUpdates>().unwrap().clone(),
+            rocket.state::<LiveData>().unwrap().clone(),
+            history_path.clone(),
+        ));
+    }
+    if let Some(url) = &config.gtfs_url {
+        rocket::tokio::spawn(refresh::poll(
+            rocket.state::<LiveData>().unwrap().clone(),
+            url.clone(),
+            path,
+            config.gtfs_options().unwrap(),
+            Duration::from_secs(config.gtfs_interval()),
+        ));
+    }
+    rocket.launch().await?;
     Ok(())
 }
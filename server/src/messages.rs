@@ -0,0 +1,147 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use rocket::http::HeaderMap;
+use rocket::request::{FromRequest, Outcome, Request};
+
+/// A language in which user-facing messages can be shown
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Language {
+    English,
+    Hebrew,
+}
+
+impl Language {
+    /// The IETF language tag of the language
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Hebrew => "he",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split('-').next()?.trim().to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Language::English),
+            // "iw" is the deprecated code for Hebrew, still sent by some older clients
+            "he" | "iw" => Some(Language::Hebrew),
+            _ => None,
+        }
+    }
+
+    /// Selects the most preferred supported language from an Accept-Language header value, defaulting to English
+    pub fn from_accept_language(header: &str) -> Self {
+        let mut candidates: Vec<(f32, Language)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let language = Self::from_tag(parts.next()?)?;
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                Some((quality, language))
+            })
+            .filter(|(quality, _)| *quality > 0.0)
+            .collect();
+        // Stable sort keeps the header order between languages of equal quality
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates
+            .first()
+            .map_or(Language::English, |(_, language)| *language)
+    }
+
+    /// Selects the language requested by the given request headers
+    pub fn from_headers(headers: &HeaderMap<'_>) -> Self {
+        headers
+            .get_one("Accept-Language")
+            .map_or(Language::English, Self::from_accept_language)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Language {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Language::from_headers(req.headers()))
+    }
+}
+
+/// The catalog of errors reported by the server
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ErrorCode {
+    StartStationNotFound,
+    EndStationNotFound,
+    AmbiguousStartStation,
+    AmbiguousEndStation,
+    NoRouteFound,
+    TrainNotFound,
+    StationNotFound,
+    SearchFailed,
+    SearchTimedOut,
+    RateLimited,
+    Unauthorized,
+}
+
+impl ErrorCode {
+    /// A stable machine-readable identifier for the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::StartStationNotFound => "start_station_not_found",
+            ErrorCode::EndStationNotFound => "end_station_not_found",
+            ErrorCode::AmbiguousStartStation => "ambiguous_start_station",
+            ErrorCode::AmbiguousEndStation => "ambiguous_end_station",
+            ErrorCode::NoRouteFound => "no_route_found",
+            ErrorCode::TrainNotFound => "train_not_found",
+            ErrorCode::StationNotFound => "station_not_found",
+            ErrorCode::SearchFailed => "search_failed",
+            ErrorCode::SearchTimedOut => "search_timed_out",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::Unauthorized => "unauthorized",
+        }
+    }
+
+    /// The user-facing message for the error in the given language
+    pub fn message(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (ErrorCode::StartStationNotFound, Language::English) => "start station not found",
+            (ErrorCode::StartStationNotFound, Language::Hebrew) => "תחנת המוצא לא נמצאה",
+            (ErrorCode::EndStationNotFound, Language::English) => "end station not found",
+            (ErrorCode::EndStationNotFound, Language::Hebrew) => "תחנת היעד לא נמצאה",
+            (ErrorCode::AmbiguousStartStation, Language::English) => {
+                "start station name matches several stations, pick one of the candidates"
+            }
+            (ErrorCode::AmbiguousStartStation, Language::Hebrew) => {
+                "שם תחנת המוצא מתאים לכמה תחנות, בחרו אחת מהאפשרויות"
+            }
+            (ErrorCode::AmbiguousEndStation, Language::English) => {
+                "end station name matches several stations, pick one of the candidates"
+            }
+            (ErrorCode::AmbiguousEndStation, Language::Hebrew) => {
+                "שם תחנת היעד מתאים לכמה תחנות, בחרו אחת מהאפשרויות"
+            }
+            (ErrorCode::NoRouteFound, Language::English) => "no possible route found",
+            (ErrorCode::NoRouteFound, Language::Hebrew) => "לא נמצא מסלול אפשרי",
+            (ErrorCode::TrainNotFound, Language::English) => "train not found",
+            (ErrorCode::TrainNotFound, Language::Hebrew) => "הרכבת לא נמצאה",
+            (ErrorCode::StationNotFound, Language::English) => "station not found",
+            (ErrorCode::StationNotFound, Language::Hebrew) => "התחנה לא נמצאה",
+            (ErrorCode::SearchFailed, Language::English) => "route search failed",
+            (ErrorCode::SearchFailed, Language::Hebrew) => "חיפוש המסלול נכשל",
+            (ErrorCode::SearchTimedOut, Language::English) => {
+                "route search took too long, try a shorter time window"
+            }
+            (ErrorCode::SearchTimedOut, Language::Hebrew) => {
+                "חיפוש המסלול ארך זמן רב מדי, נסו טווח זמנים קצר יותר"
+            }
+            (ErrorCode::RateLimited, Language::English) => "too many requests, try again later",
+            (ErrorCode::RateLimited, Language::Hebrew) => "יותר מדי בקשות, נסו שוב מאוחר יותר",
+            (ErrorCode::Unauthorized, Language::English) => "a valid API key is required",
+            (ErrorCode::Unauthorized, Language::Hebrew) => "נדרש מפתח API תקף",
+        }
+    }
+}
@@ -0,0 +1,204 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use harail::{Engine, SearchKind, SearchObserver};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::Response;
+use rocket::{Data, State};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Route searches take anywhere from milliseconds to many seconds for long search windows
+const SEARCH_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Counters and histograms describing the server's work, exported in the Prometheus text format at /metrics.
+///
+/// Attached as a fairing, it counts and times every request.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    route_searches: HistogramVec,
+    graph_builds: HistogramVec,
+    cache: IntCounterVec,
+    graph_cache: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new("harail_http_requests_total", "HTTP requests handled"),
+            &["route", "status"],
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "harail_http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            &["route"],
+        )
+        .unwrap();
+        let route_searches = HistogramVec::new(
+            HistogramOpts::new(
+                "harail_route_search_seconds",
+                "Time taken by route searches",
+            )
+            .buckets(SEARCH_BUCKETS.to_vec()),
+            &["search"],
+        )
+        .unwrap();
+        let graph_builds = HistogramVec::new(
+            HistogramOpts::new(
+                "harail_graph_build_seconds",
+                "Time taken to build the graph or timetable searched by each engine",
+            )
+            .buckets(SEARCH_BUCKETS.to_vec()),
+            &["engine"],
+        )
+        .unwrap();
+        let cache = IntCounterVec::new(
+            Opts::new(
+                "harail_cache_requests_total",
+                "Cacheable responses, by whether the client's copy was still fresh",
+            ),
+            &["result"],
+        )
+        .unwrap();
+        let graph_cache = IntCounterVec::new(
+            Opts::new(
+                "harail_graph_cache_requests_total",
+                "Route searches run on a cached graph, by whether the graph had to be built",
+            ),
+            &["result"],
+        )
+        .unwrap();
+        let registry = Registry::new();
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(route_searches.clone())).unwrap();
+        registry.register(Box::new(graph_builds.clone())).unwrap();
+        registry.register(Box::new(cache.clone())).unwrap();
+        registry.register(Box::new(graph_cache.clone())).unwrap();
+        Metrics {
+            registry,
+            requests,
+            request_duration,
+            route_searches,
+            graph_builds,
+            cache,
+            graph_cache,
+        }
+    }
+
+    /// Records how long a route search took
+    pub fn observe_search(&self, search: SearchKind, elapsed: Duration) {
+        let search = match search {
+            SearchKind::Best => "best",
+            SearchKind::Latest => "latest",
+            SearchKind::Multiple => "multi",
+            SearchKind::Pareto => "pareto",
+            SearchKind::Alternatives(_) => "alternatives",
+            SearchKind::ArriveBy => "arrive_by",
+        };
+        self.route_searches
+            .with_label_values(&[search])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records whether a cacheable response was answered with 304 Not Modified
+    pub fn observe_cache(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.cache.with_label_values(&[result]).inc();
+    }
+
+    /// Records whether a route search found its graph in the graph cache
+    pub fn observe_graph_cache(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.graph_cache.with_label_values(&[result]).inc();
+    }
+
+    fn export(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl SearchObserver for Metrics {
+    fn graph_built(&self, engine: Engine, elapsed: Duration) {
+        let engine = match engine {
+            Engine::TimeExpanded => "time_expanded",
+            Engine::AStar => "astar",
+            Engine::Raptor => "raptor",
+        };
+        self.graph_builds
+            .with_label_values(&[engine])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+/// The time at which a request arrived
+struct RequestStart(Instant);
+
+#[rocket::async_trait]
+impl Fairing for Metrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let RequestStart(start) = req.local_cache(|| RequestStart(Instant::now()));
+        // Requests which matched no route are lumped together, rather than creating a series per URI
+        let route = req
+            .route()
+            .and_then(|r| r.name.as_deref())
+            .unwrap_or("none");
+        self.requests
+            .with_label_values(&[route, res.status().code.to_string().as_str()])
+            .inc();
+        self.request_duration
+            .with_label_values(&[route])
+            .observe(start.elapsed().as_secs_f64());
+    }
+}
+
+#[get("/metrics")]
+pub fn metrics(metrics: &State<Metrics>) -> (ContentType, String) {
+    (
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        metrics.export(),
+    )
+}
@@ -0,0 +1,47 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::live::Live;
+use chrono::Utc;
+use harail::gtfs_rt::RealtimeUpdates;
+use std::error::Error;
+use std::time::Duration;
+use tracing::warn;
+
+/// The latest real-time updates, shared between the feed poller and the request handlers
+pub type LiveUpdates = Live<RealtimeUpdates>;
+
+async fn fetch(
+    client: &reqwest::Client,
+    urls: &[String],
+) -> Result<RealtimeUpdates, Box<dyn Error>> {
+    let mut result = RealtimeUpdates::new();
+    for url in urls {
+        let feed = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        result.add_feed(feed.as_ref(), harail::to_feed_time(&Utc::now()).date())?;
+    }
+    Ok(result)
+}
+
+/// Downloads the GTFS-Realtime feeds at the given URLs every interval, and publishes their combined updates.
+///
+/// Failed downloads are reported and keep the previously published updates in place.
+pub async fn poll(updates: LiveUpdates, urls: Vec<String>, interval: Duration) {
+    let client = reqwest::Client::new();
+    loop {
+        match fetch(&client, &urls).await {
+            Ok(feed) => updates.publish(feed),
+            Err(e) => warn!("Failed to fetch real-time updates: {}", e),
+        }
+        rocket::tokio::time::sleep(interval).await;
+    }
+}
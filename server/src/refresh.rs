@@ -0,0 +1,78 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::database::{self, LiveData};
+use harail::{GtfsOptions, RailroadData};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// The zstd level refreshed databases are written with, as parse-gtfs does by default
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Parses a downloaded GTFS feed with the given options and writes the database to its file.
+///
+/// The database is written next to the file and renamed over it once complete, so the file is never partially written.
+pub fn rebuild(
+    feed: &[u8],
+    path: &Path,
+    options: &GtfsOptions,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // The feed is written to a newly created file under a random name, so links planted in the shared temporary
+    // directory are never followed
+    let mut gtfs = tempfile::Builder::new().prefix("harail-gtfs-").tempfile()?;
+    gtfs.write_all(feed)?;
+    let data = RailroadData::from_gtfs_path_with_options(gtfs.path(), options)
+        .map_err(|e| e.to_string())?;
+    let partial = path.with_extension("partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    data.save_compressed(&mut writer, COMPRESSION_LEVEL)?;
+    writer.flush()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+async fn refresh(
+    client: &reqwest::Client,
+    url: &str,
+    path: PathBuf,
+    options: GtfsOptions,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let feed = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    info!("Downloaded GTFS feed, rebuilding database");
+    rocket::tokio::task::spawn_blocking(move || rebuild(&feed, &path, &options)).await?
+}
+
+/// Downloads the GTFS feed at the given URL every interval, rebuilds the database file from it with the given options
+/// and publishes it.
+///
+/// A feed which fails to download or parse is reported and the current database stays in place.
+pub async fn poll(
+    data: LiveData,
+    url: String,
+    path: PathBuf,
+    options: GtfsOptions,
+    interval: Duration,
+) {
+    let client = reqwest::Client::new();
+    loop {
+        // The database was loaded when the server started, so the first refresh waits for a whole interval
+        rocket::tokio::time::sleep(interval).await;
+        match refresh(&client, &url, path.clone(), options.clone()).await {
+            Ok(()) => database::reload(&data, path.clone()).await,
+            Err(e) => error!("Failed to refresh the GTFS feed: {}", e),
+        }
+    }
+}
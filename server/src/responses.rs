@@ -0,0 +1,400 @@
+/* Copyright (C) 2020 Yuval Deutscher
+
+* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The bodies of the API's JSON responses.
+//!
+//! Times are given in RFC 3339 in Asia/Jerusalem, the timezone of the feed, and fields which are unknown are left out unless noted otherwise.
+
+use crate::messages::{ErrorCode, Language};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use harail::gtfs_rt::RealtimeUpdates;
+use harail::pricing::Fare;
+use harail::{
+    Explanation, OwnedRoute, OwnedRoutePart, OwnedStop, RailroadData, RouteSummary, Station,
+    StationId, Stop, Train, TrainId,
+};
+use rocket::serde::Serialize;
+
+fn zoned(time: NaiveDateTime) -> String {
+    harail::localize(time).to_rfc3339()
+}
+
+/// The body of an error response
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorResponse {
+    /// A code which clients can tell the error by, as opposed to the message
+    error: &'static str,
+    /// A description of the error in the request's language
+    message: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    candidates: Vec<CandidateResponse>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, language: Language) -> Self {
+        ErrorResponse {
+            error: code.code(),
+            message: code.message(language),
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn with_candidates(mut self, candidates: Vec<CandidateResponse>) -> Self {
+        self.candidates = candidates;
+        self
+    }
+}
+
+/// A station an ambiguous station name could refer to
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct CandidateResponse {
+    id: StationId,
+    name: String,
+}
+
+impl From<&Station> for CandidateResponse {
+    fn from(station: &Station) -> Self {
+        CandidateResponse {
+            id: station.id(),
+            name: station.name().to_owned(),
+        }
+    }
+}
+
+/// A train's stop at a station
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StopResponse {
+    station: StationId,
+    arrival: String,
+    departure: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+}
+
+impl From<&Stop<'_>> for StopResponse {
+    fn from(stop: &Stop<'_>) -> Self {
+        StopResponse {
+            station: stop.station().id(),
+            arrival: zoned(stop.arrival()),
+            departure: zoned(stop.departure()),
+            platform: stop.platform().map(str::to_owned),
+        }
+    }
+}
+
+impl From<&OwnedStop> for StopResponse {
+    fn from(stop: &OwnedStop) -> Self {
+        StopResponse {
+            station: stop.station(),
+            arrival: zoned(stop.arrival()),
+            departure: zoned(stop.departure()),
+            platform: stop.platform().map(str::to_owned),
+        }
+    }
+}
+
+/// A train's stop at a station, as listed in the station's record
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StationTrainResponse {
+    train: TrainId,
+    /// The number passengers know the train by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    train_number: Option<String>,
+    /// The GTFS route of the train's trip
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route_id: Option<u64>,
+    arrival: String,
+    departure: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    /// The last station of the train
+    destination: StationId,
+}
+
+/// A station's full record, along with the trains stopping at it on a given date
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StationResponse {
+    id: StationId,
+    name: String,
+    /// Other names the station is known by
+    aliases: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lon: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wheelchair_boarding: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fare_zone: Option<String>,
+    /// The platforms at which any train stops at the station
+    platforms: Vec<String>,
+    /// Ordered by departure time
+    trains: Vec<StationTrainResponse>,
+}
+
+impl StationResponse {
+    pub fn new(data: &RailroadData, station: &Station, date: NaiveDate) -> Self {
+        let mut stops: Vec<_> = data
+            .trains_stopping_at(station.id(), date)
+            .into_iter()
+            .flat_map(|train| {
+                let destination = train.stops().last().unwrap().station();
+                train
+                    .stops()
+                    .filter(|s| s.station() == station.id())
+                    .map(move |s| (train, Stop::from_stop_schedule(data, s, date), destination))
+            })
+            .collect();
+        stops.sort_by_key(|(train, stop, _)| (stop.departure(), train.id()));
+        StationResponse {
+            id: station.id(),
+            name: station.name().to_owned(),
+            aliases: harail::station_aliases(station),
+            lat: station.lat(),
+            lon: station.lon(),
+            wheelchair_boarding: station.wheelchair_boarding(),
+            fare_zone: station.fare_zone().map(str::to_owned),
+            platforms: data
+                .platforms(station.id())
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            trains: stops
+                .into_iter()
+                .map(|(train, stop, destination)| StationTrainResponse {
+                    train: train.id().to_owned(),
+                    train_number: train.short_name().map(str::to_owned),
+                    route_id: train.route_id(),
+                    arrival: zoned(stop.arrival()),
+                    departure: zoned(stop.departure()),
+                    platform: stop.platform().map(str::to_owned),
+                    destination,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A train's run on a given date, from its first stop to its last
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TrainSummaryResponse {
+    train: TrainId,
+    /// The number passengers know the train by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    train_number: Option<String>,
+    start_station: StationId,
+    start_time: String,
+    end_station: StationId,
+    end_time: String,
+}
+
+impl TrainSummaryResponse {
+    fn new(train: &Train, first: &Stop<'_>, last: &Stop<'_>) -> Self {
+        TrainSummaryResponse {
+            train: train.id().to_owned(),
+            train_number: train.short_name().map(str::to_owned),
+            start_station: first.station().id(),
+            start_time: zoned(first.departure()),
+            end_station: last.station().id(),
+            end_time: zoned(last.arrival()),
+        }
+    }
+}
+
+/// A page of the trains running on a date
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TrainsResponse {
+    /// The number of trains on all pages
+    total: usize,
+    /// Ordered by departure from their first stop
+    trains: Vec<TrainSummaryResponse>,
+}
+
+impl TrainsResponse {
+    /// Pages the given trains' runs on the given date, skipping the first offset runs and listing at most limit runs
+    pub fn new(
+        data: &RailroadData,
+        trains: Vec<&Train>,
+        date: NaiveDate,
+        offset: usize,
+        limit: usize,
+    ) -> Self {
+        let mut runs: Vec<_> = trains
+            .into_iter()
+            .filter_map(|train| {
+                let first = Stop::from_stop_schedule(data, train.stops().next()?, date);
+                let last = Stop::from_stop_schedule(data, train.stops().last()?, date);
+                Some((train, first, last))
+            })
+            .collect();
+        runs.sort_by_key(|(train, first, _)| (first.departure(), train.id()));
+        TrainsResponse {
+            total: runs.len(),
+            trains: runs
+                .iter()
+                .skip(offset)
+                .take(limit)
+                .map(|(train, first, last)| TrainSummaryResponse::new(train, first, last))
+                .collect(),
+        }
+    }
+}
+
+/// A service alert about a train ride
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AlertResponse {
+    header: String,
+    /// Null if the alert has no description
+    description: Option<String>,
+}
+
+/// A single train ride of a route
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RoutePartResponse {
+    train: TrainId,
+    /// The number passengers know the train by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    train_number: Option<String>,
+    start_time: String,
+    start_station: StationId,
+    end_time: String,
+    end_station: StationId,
+    /// The seconds from boarding the train to unboarding it
+    duration_seconds: i64,
+    /// The seconds waited since arriving on the previous part, left out for the first part
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bikes_allowed: Option<bool>,
+    /// The expected crowding of the train when boarding it, e.g. few_seats_available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    occupancy: Option<&'static str>,
+    /// The service alerts about the train or its boarding and unboarding stations
+    alerts: Vec<AlertResponse>,
+    /// The stops the train makes between boarding and unboarding it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    intermediate_stops: Vec<StopResponse>,
+}
+
+impl RoutePartResponse {
+    fn new(
+        part: &OwnedRoutePart,
+        wait: Option<Duration>,
+        data: &RailroadData,
+        realtime: &RealtimeUpdates,
+        language: Language,
+    ) -> Self {
+        RoutePartResponse {
+            train: part.train().to_owned(),
+            train_number: part.train_number().map(str::to_owned),
+            start_time: zoned(part.start().departure()),
+            start_station: part.start().station(),
+            end_time: zoned(part.end().arrival()),
+            end_station: part.end().station(),
+            duration_seconds: part.duration().num_seconds(),
+            wait_seconds: wait.map(|w| w.num_seconds()),
+            start_platform: part.start().platform().map(str::to_owned),
+            end_platform: part.end().platform().map(str::to_owned),
+            bikes_allowed: part.bikes_allowed(),
+            occupancy: part.occupancy(data, realtime).map(|o| o.name()),
+            alerts: part
+                .alerts(realtime)
+                .into_iter()
+                .map(|alert| AlertResponse {
+                    header: alert.header(language.tag()).to_owned(),
+                    description: alert.description(language.tag()).map(str::to_owned),
+                })
+                .collect(),
+            intermediate_stops: part
+                .intermediate_stops()
+                .iter()
+                .map(StopResponse::from)
+                .collect(),
+        }
+    }
+}
+
+/// A ticket price
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PriceResponse {
+    agorot: u32,
+    /// The price as shown to passengers, in shekels
+    display: String,
+}
+
+impl From<Fare> for PriceResponse {
+    fn from(fare: Fare) -> Self {
+        PriceResponse {
+            agorot: fare.agorot(),
+            display: fare.to_string(),
+        }
+    }
+}
+
+/// A route along with its real-time state
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RouteResponse {
+    parts: Vec<RoutePartResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<PriceResponse>,
+    /// Whether real-time updates changed the times of any of the route's train rides
+    delayed: bool,
+    /// How the route's time is spent and how it compares to the other routes found by the search
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+    /// The chance of making all of the route's train switches, if the server keeps a delay history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reliability: Option<f64>,
+    /// The route's total times, train switches and stations passed
+    summary: RouteSummary,
+}
+
+impl RouteResponse {
+    pub fn new(
+        route: &OwnedRoute,
+        data: &RailroadData,
+        realtime: &RealtimeUpdates,
+        language: Language,
+    ) -> Self {
+        RouteResponse {
+            parts: route
+                .parts()
+                .enumerate()
+                .map(|(i, part)| {
+                    RoutePartResponse::new(part, route.wait_before(i), data, realtime, language)
+                })
+                .collect(),
+            price: route.price().map(PriceResponse::from),
+            delayed: route.is_delayed(),
+            explanation: route.explanation().cloned(),
+            reliability: route.reliability().map(|r| r.probability()),
+            summary: route.summary(),
+        }
+    }
+}
+
+/// The routes found by a search, a single route for searches finding the best one and an array of routes otherwise
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum FoundRoutes<T> {
+    Route(T),
+    Routes(Vec<T>),
+}
@@ -4,11 +4,26 @@
 * License, v. 2.0. If a copy of the MPL was not distributed with this
 * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use super::auth::ApiKeys;
+use super::config::Config;
+use super::cors::Cors;
+use super::database::{self, LiveData};
+use super::feeds::Feeds;
+use super::graphs::GraphCache;
+use super::limits::RateLimiter;
+use super::realtime::LiveUpdates;
+use super::refresh;
 use super::rocket;
-use chrono::NaiveDate;
-use harail::{HaDuration, RailroadData, Station, StopSchedule, Train};
-use rocket::http::Status;
+use chrono::{Duration, NaiveDate};
+use harail::gtfs_rt::{Alert, RealtimeUpdates};
+use harail::{
+    AgencyFilter, GtfsOptions, HaDuration, Mode, PreparedGraph, RailroadData, RoutingOptions,
+    Station, StopSchedule, Train,
+};
+use rocket::http::{ContentType, Header, Status};
 use rocket::local::blocking::Client;
+use std::collections::HashMap;
+use std::path::Path;
 
 pub fn stations() -> Vec<Station> {
     vec![
@@ -47,6 +62,179 @@ fn stations_list() {
     }
 }
 
+#[test]
+fn health_and_version() {
+    let trains = vec![Train::from_stops_dates(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ],
+        vec![test_date().succ_opt().unwrap(), test_date()],
+    )];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client.get("/harail/health").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"status":"ok","stations":6,"trains":1,"first_date":"2000-01-01","last_date":"2000-01-02"}"#
+        ))
+    );
+
+    let response = client.get("/harail/version").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert!(json["database_built"].is_null());
+}
+
+#[test]
+fn station_search() {
+    let data = RailroadData::from_stations_trains(
+        vec![
+            Station::new(3700, "תל אביב - סבידור מרכז"),
+            Station::new(2300, "חיפה - חוף הכרמל"),
+        ],
+        vec![],
+    );
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/stations/search?q=tel%20aviv")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"[{"id":3700,"name":"תל אביב - סבידור מרכז"}]"#
+        ))
+    );
+    let response = client.get("/harail/stations/search?q=xyz").dispatch();
+    assert_eq!(response.into_string(), Some(String::from("[]")));
+}
+
+#[test]
+fn nearby_stations() {
+    let data = RailroadData::from_stations_trains(
+        vec![
+            Station::with_coordinates(3700, "stat_a", 32.0837, 34.7982),
+            Station::with_coordinates(4600, "stat_b", 32.0733, 34.7931),
+            Station::with_coordinates(2300, "stat_c", 32.7935, 34.9571),
+        ],
+        vec![],
+    );
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/stations/nearby?lat=32.075&lon=34.79")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json.len(), 2);
+    assert_eq!(json[0]["id"], 4600);
+    assert_eq!(json[0]["distance"], 348.0);
+    assert_eq!(json[1]["id"], 3700);
+
+    let response = client
+        .get("/harail/stations/nearby?lat=32.075&lon=34.79&radius=500")
+        .dispatch();
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json.len(), 1);
+
+    let response = client
+        .get("/harail/stations/nearby?lat=north&lon=34.79")
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn conditional_requests() {
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client.get("/harail/stations").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_owned();
+    let last_modified = response
+        .headers()
+        .get_one("Last-Modified")
+        .unwrap()
+        .to_owned();
+
+    let response = client
+        .get("/harail/stations")
+        .header(Header::new("If-None-Match", etag.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    assert_eq!(response.into_string(), None);
+
+    let response = client
+        .get("/harail/stations")
+        .header(Header::new("If-None-Match", "\"stale\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/harail/stations")
+        .header(Header::new("If-Modified-Since", last_modified))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+}
+
+#[test]
+fn feeds() {
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let north = RailroadData::from_stations_trains(vec![Station::new(100, "stat_a")], vec![]);
+    let feeds = HashMap::from([(String::from("north"), LiveData::new(north.into()))]);
+    let client = Client::tracked(rocket(data, None).attach(Feeds::new(feeds)))
+        .expect("valid rocket instance");
+    let response = client.get("/harail/stations").dispatch();
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json.len(), 6);
+    let response = client.get("/harail/north/stations").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json.len(), 1);
+    // The query string is kept when the feed's name is taken out of the path
+    let response = client
+        .get("/harail/north/stations/search?q=stat_b")
+        .dispatch();
+    assert_eq!(response.into_string(), Some(String::from("[]")));
+    let response = client.get("/harail/south/stations").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn metrics() {
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client.get("/harail/stations").dispatch();
+    let etag = response.headers().get_one("ETag").unwrap().to_owned();
+    client
+        .get("/harail/stations")
+        .header(Header::new("If-None-Match", etag))
+        .dispatch();
+    client.get("/harail/nonexistent").dispatch();
+    client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+
+    let response = client.get("/metrics").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let metrics = response.into_string().unwrap();
+    for line in [
+        r#"harail_http_requests_total{route="list_stations",status="200"} 1"#,
+        r#"harail_http_requests_total{route="list_stations",status="304"} 1"#,
+        r#"harail_http_requests_total{route="none",status="404"} 1"#,
+        r#"harail_cache_requests_total{result="hit"} 1"#,
+        r#"harail_cache_requests_total{result="miss"} 1"#,
+        r#"harail_route_search_seconds_count{search="best"} 1"#,
+        r#"harail_graph_build_seconds_count{engine="time_expanded"} 1"#,
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "missing {}", line);
+    }
+}
+
 #[test]
 fn train_stops() {
     let trains = vec![Train::from_stops_dates(
@@ -68,9 +256,166 @@ fn train_stops() {
     assert_eq!(
         response.into_string(),
         Some(String::from(
-            r#"[{"station":100,"arrival":"2000-01-01T10:00:00+00:00","departure":"2000-01-01T10:00:00+00:00"},{"station":200,"arrival":"2000-01-01T10:30:00+00:00","departure":"2000-01-01T10:30:00+00:00"},{"station":300,"arrival":"2000-01-01T11:00:00+00:00","departure":"2000-01-01T11:00:00+00:00"},{"station":400,"arrival":"2000-01-01T11:30:00+00:00","departure":"2000-01-01T11:30:00+00:00"}]"#
+            r#"[{"station":100,"arrival":"2000-01-01T10:00:00+02:00","departure":"2000-01-01T10:00:00+02:00"},{"station":200,"arrival":"2000-01-01T10:30:00+02:00","departure":"2000-01-01T10:30:00+02:00"},{"station":300,"arrival":"2000-01-01T11:00:00+02:00","departure":"2000-01-01T11:00:00+02:00"},{"station":400,"arrival":"2000-01-01T11:30:00+02:00","departure":"2000-01-01T11:30:00+02:00"}]"#
+        ))
+    );
+}
+
+#[test]
+fn train_stops_by_number() {
+    let stops = || {
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ]
+    };
+    let trains = vec![
+        Train::from_stops_date("1", stops(), test_date()).with_short_name("5101"),
+        Train::from_stops_date("2", stops(), test_date().succ_opt().unwrap())
+            .with_short_name("5101"),
+    ];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/trains/5101/stops/2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response
+        .into_string()
+        .unwrap()
+        .contains(r#""departure":"2000-01-02T10:00:00+02:00""#));
+    let response = client
+        .get("/harail/trains/5101/stops/2000-01-03T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn station_details() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None).with_platform("2"),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+        ],
+        test_date(),
+    )
+    .with_short_name("115")];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/stations/100?date=2000-01-01T08:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"id":100,"name":"stat_a","aliases":[],"platforms":["2"],"trains":[{"train":"1","train_number":"115","arrival":"2000-01-01T10:00:00+02:00","departure":"2000-01-01T10:00:00+02:00","platform":"2","destination":200}]}"#
+        ))
+    );
+    // No trains stop at the station on other days
+    let response = client
+        .get("/harail/stations/100?date=2000-01-02T08:00:00Z")
+        .dispatch();
+    assert!(response.into_string().unwrap().ends_with(r#""trains":[]}"#));
+    let response = client.get("/harail/stations/700").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn list_trains() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(11, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(300, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        )
+        .with_short_name("202"),
+    ];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/trains?date=2000-01-01T08:00:00Z&limit=1")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"total":2,"trains":[{"train":"2","train_number":"202","start_station":300,"start_time":"2000-01-01T10:00:00+02:00","end_station":400,"end_time":"2000-01-01T10:30:00+02:00"}]}"#
+        ))
+    );
+    let response = client
+        .get("/harail/trains?date=2000-01-01T08:00:00Z&offset=1")
+        .dispatch();
+    assert!(response
+        .into_string()
+        .unwrap()
+        .contains(r#"{"total":2,"trains":[{"train":"1","#));
+    let response = client
+        .get("/harail/trains?date=2000-01-01T08:00:00Z&station=200")
+        .dispatch();
+    assert!(response
+        .into_string()
+        .unwrap()
+        .starts_with(r#"{"total":1,"trains":[{"train":"1","#));
+    let response = client
+        .get("/harail/trains?date=2000-01-02T08:00:00Z")
+        .dispatch();
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(r#"{"total":0,"trains":[]}"#))
+    );
+}
+
+#[test]
+fn departures() {
+    let trains = vec![
+        Train::from_stops_dates(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            vec![test_date(), test_date().succ_opt().unwrap()],
+        ),
+        Train::from_stops_dates(
+            "2",
+            vec![
+                StopSchedule::new(400, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 15, 00), None),
+            ],
+            vec![test_date()],
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/stations/200/departures?from=2000-01-01T10:20:00%2B02:00&limit=2")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"[{"train":"1","departure":"2000-01-01T10:30:00+02:00","destination":300,"destination_name":"stat_c"},{"train":"1","departure":"2000-01-02T10:30:00+02:00","destination":300,"destination_name":"stat_c"}]"#
         ))
     );
+
+    let response = client
+        .get("/harail/stations/999/departures?from=2000-01-01T10:20:00%2B02:00")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
 }
 
 #[test]
@@ -94,7 +439,681 @@ fn find_routes() {
     assert_eq!(
         response.into_string(),
         Some(String::from(
-            r#"{"parts":[{"train":"1","start_time":"2000-01-01T10:00:00+00:00","start_station":100,"end_time":"2000-01-01T11:30:00+00:00","end_station":400}]}"#
+            r#"{"parts":[{"train":"1","start_time":"2000-01-01T10:00:00+02:00","start_station":100,"end_time":"2000-01-01T11:30:00+02:00","end_station":400,"duration_seconds":5400,"alerts":[],"intermediate_stops":[{"station":200,"arrival":"2000-01-01T10:30:00+02:00","departure":"2000-01-01T10:30:00+02:00"},{"station":300,"arrival":"2000-01-01T11:00:00+02:00","departure":"2000-01-01T11:00:00+02:00"}]}],"delayed":false,"explanation":{"ride_seconds":5400,"wait_seconds":[],"transfers":0,"fastest":true,"fewest_transfers":true,"tied":false},"summary":{"duration_seconds":5400,"ride_seconds":5400,"wait_seconds":0,"transfers":0,"stations_passed":2}}"#
+        ))
+    );
+
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z&avoid_trains=1")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn find_routes_json_body() {
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(11, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 30, 00), None),
+                StopSchedule::new(400, HaDuration::from_hms(12, 00, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let query = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch()
+        .into_string();
+    let response = client
+        .post("/harail/routes/find")
+        .header(ContentType::JSON)
+        .body(r#"{"search":"best","start_station":100,"start_time":"2000-01-01T00:00:00Z","end_station":"stat_d","end_time":"2000-01-02T00:00:00Z"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string(), query);
+
+    let response = client
+        .post("/harail/routes/find")
+        .header(ContentType::JSON)
+        .body(r#"{"search":"best","start_station":100,"start_time":"2000-01-01T00:00:00Z","end_station":400,"end_time":"2000-01-02T00:00:00Z","avoid_trains":["1"],"weights":{"board":120}}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json["parts"][0]["train"], "2");
+
+    let response = client
+        .post("/harail/routes/find")
+        .header(ContentType::JSON)
+        .body(r#"{"search":"best","start_station":100}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn find_route_by_station_name() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(400, HaDuration::from_hms(11, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=STAT_A&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json["parts"][0]["start_station"], 100);
+
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=stat&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"error":"ambiguous_end_station","message":"end station name matches several stations, pick one of the candidates","candidates":[{"id":100,"name":"stat_a"},{"id":200,"name":"stat_b"},{"id":300,"name":"stat_c"},{"id":400,"name":"stat_d"},{"id":500,"name":"stat_e"}]}"#
         ))
     );
+
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=qwertyuiop&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn cached_graphs() {
+    let trains = vec![Train::from_stops_dates(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(400, HaDuration::from_hms(11, 30, 00), None),
+        ],
+        vec![test_date(), test_date().succ_opt().unwrap()],
+    )];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None).manage(GraphCache::new(1)))
+        .expect("valid rocket instance");
+    let find = |query: &str| {
+        client
+            .get(format!(
+                "/harail/routes/find?start_station=100&end_station=400&{}",
+                query
+            ))
+            .dispatch()
+            .into_string()
+            .unwrap()
+    };
+    let first_day = "start_time=2000-01-01T00:00:00Z&end_time=2000-01-02T00:00:00Z";
+    let route = r#"{"parts":[{"train":"1","start_time":"2000-01-01T10:00:00+02:00","start_station":100,"end_time":"2000-01-01T11:30:00+02:00","end_station":400,"duration_seconds":5400,"alerts":[]}],"delayed":false,"explanation":{"ride_seconds":5400,"wait_seconds":[],"transfers":0,"fastest":true,"fewest_transfers":true,"tied":false},"summary":{"duration_seconds":5400,"ride_seconds":5400,"wait_seconds":0,"transfers":0,"stations_passed":0}}"#;
+    assert_eq!(find(&format!("search=best&{}", first_day)), route);
+    // The cached graph spans the second day too, but routes outside of the searched window are left out
+    assert_eq!(
+        find(&format!("search=multi&{}", first_day)),
+        format!("[{}]", route)
+    );
+    find("search=best&start_time=2000-01-02T00:00:00Z&end_time=2000-01-03T00:00:00Z");
+    assert_eq!(find(&format!("search=best&{}", first_day)), route);
+    find(&format!("search=best&{}&avoid_trains=2", first_day));
+
+    let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+    for line in [
+        r#"harail_graph_cache_requests_total{result="hit"} 1"#,
+        r#"harail_graph_cache_requests_total{result="miss"} 3"#,
+        r#"harail_graph_build_seconds_count{engine="time_expanded"} 4"#,
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "missing {}", line);
+    }
+
+    // New real-time updates are patched into the cached graph instead of building it again
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), None, Duration::minutes(5));
+    client
+        .rocket()
+        .state::<LiveUpdates>()
+        .unwrap()
+        .publish(updates);
+    let json = jzon::parse(&find(&format!("search=best&{}", first_day))).unwrap();
+    assert_eq!(json["parts"][0]["end_time"], "2000-01-01T11:35:00+02:00");
+    assert_eq!(json["delayed"], true);
+    let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+    for line in [
+        r#"harail_graph_cache_requests_total{result="hit"} 2"#,
+        r#"harail_graph_build_seconds_count{engine="time_expanded"} 4"#,
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "missing {}", line);
+    }
+}
+
+#[test]
+fn preloaded_graphs() {
+    let trains = vec![Train::from_stops_date(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(400, HaDuration::from_hms(11, 30, 00), None),
+        ],
+        test_date(),
+    )];
+    let rocket = rocket(RailroadData::from_stations_trains(stations(), trains), None);
+    let data = rocket.state::<LiveData>().unwrap().current();
+    let path = std::env::temp_dir().join(format!("harail-server-graph-{}.db", std::process::id()));
+    let start = test_date().and_hms_opt(0, 0, 0).unwrap();
+    let graph = PreparedGraph::new(
+        data.data(),
+        start,
+        start + Duration::days(1),
+        &RoutingOptions::new(),
+    );
+    let graph_path = path.with_extension("graph");
+    graph
+        .save(std::fs::File::create(&graph_path).unwrap())
+        .unwrap();
+    let graphs = GraphCache::new(1);
+    graphs.preload(data.data(), &path);
+    std::fs::remove_file(&graph_path).unwrap();
+    let client = Client::tracked(rocket.manage(graphs)).expect("valid rocket instance");
+
+    // The first search on the graph's day finds it cached
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json["parts"][0]["train"], "1");
+    let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+    assert!(metrics
+        .lines()
+        .any(|l| l == r#"harail_graph_cache_requests_total{result="hit"} 1"#));
+    assert!(!metrics.contains(r#"result="miss""#));
+}
+
+#[rocket::async_test]
+async fn live_route() {
+    use rocket::local::asynchronous::Client;
+    use rocket::tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines};
+
+    async fn next_event<R: AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> jzon::JsonValue {
+        loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if let Some(data) = line.strip_prefix("data:") {
+                return jzon::parse(data.trim()).unwrap();
+            }
+        }
+    }
+
+    let trains = vec![Train::from_stops_dates(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+        ],
+        vec![test_date()],
+    )];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).await.unwrap();
+    let response = client
+        .get("/harail/routes/live?parts.0.train=1&parts.0.start_station=100&parts.0.start_time=2000-01-01T10:00:00%2B02:00&parts.0.end_station=300")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let mut lines = BufReader::new(response).lines();
+    let event = next_event(&mut lines).await;
+    assert_eq!(event["delayed"], false);
+    assert_eq!(event["parts"][0]["end_time"], "2000-01-01T11:00:00+02:00");
+
+    let live = client.rocket().state::<LiveUpdates>().unwrap();
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), Some(200), Duration::minutes(5));
+    live.publish(updates);
+    let event = next_event(&mut lines).await;
+    assert_eq!(event["delayed"], true);
+    assert_eq!(event["parts"][0]["end_time"], "2000-01-01T11:05:00+02:00");
+
+    let mut updates = RealtimeUpdates::new();
+    updates.cancel("1", test_date());
+    live.publish(updates);
+    let event = next_event(&mut lines).await;
+    assert_eq!(event["cancelled"], true);
+}
+
+#[rocket::async_test]
+async fn live_departures() {
+    use rocket::local::asynchronous::Client;
+    use rocket::tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines};
+
+    async fn next_event<R: AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> jzon::JsonValue {
+        loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if let Some(data) = line.strip_prefix("data:") {
+                return jzon::parse(data.trim()).unwrap();
+            }
+        }
+    }
+
+    let trains = vec![
+        Train::from_stops_date(
+            "1",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+                StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            ],
+            test_date(),
+        ),
+        Train::from_stops_date(
+            "2",
+            vec![
+                StopSchedule::new(100, HaDuration::from_hms(10, 15, 00), None),
+                StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            ],
+            test_date(),
+        ),
+    ];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).await.unwrap();
+    let response = client
+        .get("/harail/stations/999/departures/live")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get("/harail/stations/100/departures/live?from=2000-01-01T09:00:00%2B02:00")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let mut lines = BufReader::new(response).lines();
+    let event = next_event(&mut lines).await;
+    assert_eq!(event.len(), 2);
+    assert_eq!(event[0]["train"], "1");
+    assert!(event[0]["expected_departure"].is_null());
+
+    let live = client.rocket().state::<LiveUpdates>().unwrap();
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), None, Duration::minutes(5));
+    updates.cancel("2", test_date());
+    live.publish(updates);
+    let event = next_event(&mut lines).await;
+    assert_eq!(event[0]["expected_departure"], "2000-01-01T10:05:00+02:00");
+    assert_eq!(event[1]["cancelled"], true);
+}
+
+#[test]
+fn graphql() {
+    let trains = vec![Train::from_stops_dates(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+        ],
+        vec![test_date()],
+    )];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .post("/harail/graphql")
+        .header(ContentType::JSON)
+        .body(r#"{"query":"{ station(id: 200) { name } train(id: \"1\") { stops(date: \"2000-01-01\") { station { id } } } }"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"data":{"station":{"name":"stat_b"},"train":{"stops":[{"station":{"id":100}},{"station":{"id":200}},{"station":{"id":300}}]}}}"#
+        ))
+    );
+
+    let response = client
+        .get("/harail/graphql?query=%7B%20routes(search%3A%20BEST%2C%20startStation%3A%20100%2C%20startTime%3A%20%222000-01-01T00%3A00%3A00Z%22%2C%20endStation%3A%20300%2C%20endTime%3A%20%222000-01-02T00%3A00%3A00Z%22)%20%7B%20delayed%20parts%20%7B%20train%20%7B%20id%20%7D%20end%20%7B%20station%20%7B%20name%20%7D%20%7D%20%7D%20%7D%20%7D")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"data":{"routes":[{"delayed":false,"parts":[{"train":{"id":"1"},"end":{"station":{"name":"stat_c"}}}]}]}}"#
+        ))
+    );
+
+    let response = client
+        .get("/harail/graphql?query=%7B%20routes(search%3A%20BEST%2C%20startStation%3A%201%2C%20startTime%3A%20%222000-01-01T00%3A00%3A00Z%22%2C%20endStation%3A%20300%2C%20endTime%3A%20%222000-01-02T00%3A00%3A00Z%22)%20%7B%20delayed%20%7D%20%7D")
+        .dispatch();
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(
+        json["errors"][0]["extensions"]["code"],
+        "start_station_not_found"
+    );
+}
+
+#[test]
+fn realtime_routes() {
+    let trains = vec![Train::from_stops_dates(
+        "1",
+        vec![
+            StopSchedule::new(100, HaDuration::from_hms(10, 00, 00), None),
+            StopSchedule::new(200, HaDuration::from_hms(10, 30, 00), None),
+            StopSchedule::new(300, HaDuration::from_hms(11, 00, 00), None),
+            StopSchedule::new(400, HaDuration::from_hms(11, 30, 00), None),
+        ],
+        vec![test_date(), test_date().succ_opt().unwrap()],
+    )];
+    let data = RailroadData::from_stations_trains(stations(), trains);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let live = client.rocket().state::<LiveUpdates>().unwrap();
+
+    let mut updates = RealtimeUpdates::new();
+    updates.delay("1", test_date(), Some(300), Duration::minutes(10));
+    live.publish(updates);
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"parts":[{"train":"1","start_time":"2000-01-01T10:00:00+02:00","start_station":100,"end_time":"2000-01-01T11:40:00+02:00","end_station":400,"duration_seconds":6000,"alerts":[],"intermediate_stops":[{"station":200,"arrival":"2000-01-01T10:30:00+02:00","departure":"2000-01-01T10:30:00+02:00"},{"station":300,"arrival":"2000-01-01T11:10:00+02:00","departure":"2000-01-01T11:10:00+02:00"}]}],"delayed":true,"explanation":{"ride_seconds":6000,"wait_seconds":[],"transfers":0,"fastest":true,"fewest_transfers":true,"tied":false},"summary":{"duration_seconds":6000,"ride_seconds":6000,"wait_seconds":0,"transfers":0,"stations_passed":2}}"#
+        ))
+    );
+
+    let mut updates = RealtimeUpdates::new();
+    updates.add_alert(
+        Alert::new("Elevator out of service")
+            .with_description("Use the stairs")
+            .with_station(400),
+    );
+    updates.add_alert(Alert::new("Station closed").with_station(500));
+    live.publish(updates);
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"parts":[{"train":"1","start_time":"2000-01-01T10:00:00+02:00","start_station":100,"end_time":"2000-01-01T11:30:00+02:00","end_station":400,"duration_seconds":5400,"alerts":[{"header":"Elevator out of service","description":"Use the stairs"}],"intermediate_stops":[{"station":200,"arrival":"2000-01-01T10:30:00+02:00","departure":"2000-01-01T10:30:00+02:00"},{"station":300,"arrival":"2000-01-01T11:00:00+02:00","departure":"2000-01-01T11:00:00+02:00"}]}],"delayed":false,"explanation":{"ride_seconds":5400,"wait_seconds":[],"transfers":0,"fastest":true,"fewest_transfers":true,"tied":false},"summary":{"duration_seconds":5400,"ride_seconds":5400,"wait_seconds":0,"transfers":0,"stations_passed":2}}"#
+        ))
+    );
+
+    let mut updates = RealtimeUpdates::new();
+    updates.cancel("1", test_date());
+    live.publish(updates);
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn reload_database() {
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let path = std::env::temp_dir().join(format!("harail-server-test-{}.db", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    let reloaded = RailroadData::from_stations_trains(vec![Station::new(100, "stat_a")], vec![]);
+    reloaded.save(file).unwrap();
+    let live = client.rocket().state::<LiveData>().unwrap();
+    rocket::tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(database::reload(live, path.clone()));
+    std::fs::remove_file(&path).unwrap();
+    let response = client.get("/harail/stations").dispatch();
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json.len(), 1);
+
+    // A database which fails to load leaves the current one in place
+    rocket::tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(database::reload(live, path));
+    let response = client.get("/harail/stations").dispatch();
+    let json = jzon::parse(&response.into_string().unwrap()).unwrap();
+    assert_eq!(json.len(), 1);
+}
+
+#[test]
+fn refresh_database() {
+    let feed = std::fs::read("../lib/fixtures/israel-public-transportation-min.zip").unwrap();
+    let path =
+        std::env::temp_dir().join(format!("harail-server-refresh-{}.db", std::process::id()));
+    refresh::rebuild(&feed, &path, &GtfsOptions::default()).unwrap();
+    let data = database::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(data.stations().count(), 66);
+
+    // A feed which fails to parse leaves the database file alone
+    assert!(refresh::rebuild(b"not a feed", &path, &GtfsOptions::default()).is_err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn cors() {
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let rocket = rocket(data, None).attach(Cors::new(vec!["https://example.com".to_owned()]));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let response = client
+        .get("/harail/stations")
+        .header(Header::new("Origin", "https://example.com"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        Some("https://example.com")
+    );
+
+    let response = client
+        .options("/harail/routes/find")
+        .header(Header::new("Origin", "https://example.com"))
+        .header(Header::new("Access-Control-Request-Method", "GET"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Methods"),
+        Some("GET, POST, OPTIONS")
+    );
+
+    // Searches may also be posted as JSON, which browsers preflight
+    let response = client
+        .options("/harail/routes/find")
+        .header(Header::new("Origin", "https://example.com"))
+        .header(Header::new("Access-Control-Request-Method", "POST"))
+        .header(Header::new(
+            "Access-Control-Request-Headers",
+            "content-type",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert!(response
+        .headers()
+        .get_one("Access-Control-Allow-Methods")
+        .unwrap()
+        .contains("POST"));
+    assert!(response
+        .headers()
+        .get_one("Access-Control-Allow-Headers")
+        .unwrap()
+        .contains("Content-Type"));
+
+    // GraphQL queries are posted as JSON too
+    let response = client
+        .options("/harail/graphql")
+        .header(Header::new("Origin", "https://example.com"))
+        .header(Header::new("Access-Control-Request-Method", "POST"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert!(response
+        .headers()
+        .get_one("Access-Control-Allow-Methods")
+        .unwrap()
+        .contains("POST"));
+
+    let response = client
+        .get("/harail/stations")
+        .header(Header::new("Origin", "https://evil.example"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+}
+
+#[test]
+fn rate_limit() {
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let rocket = rocket(data, None).manage(RateLimiter::new(2));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let find = || {
+        client
+            .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+            .dispatch()
+            .status()
+    };
+    assert_eq!(find(), Status::NotFound);
+    assert_eq!(find(), Status::NotFound);
+    assert_eq!(find(), Status::TooManyRequests);
+    // Cheap endpoints are not limited
+    let response = client.get("/harail/stations").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn api_keys() {
+    let path = std::env::temp_dir().join(format!("harail-server-keys-{}", std::process::id()));
+    std::fs::write(&path, "# Partners\nsecret\n\nother\n").unwrap();
+    let keys = ApiKeys::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let rocket = rocket(data, None).manage(keys).manage(RateLimiter::new(1));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let find = |header: Option<Header<'static>>| {
+        let mut request = client.get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z");
+        if let Some(header) = header {
+            request = request.header(header);
+        }
+        request.dispatch()
+    };
+    let response = find(None);
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"error":"unauthorized","message":"a valid API key is required"}"#
+        ))
+    );
+    assert_eq!(
+        find(Some(Header::new("Authorization", "Bearer wrong"))).status(),
+        Status::Unauthorized
+    );
+    assert_eq!(
+        find(Some(Header::new("Authorization", "Bearer secret"))).status(),
+        Status::NotFound
+    );
+    // Each key has a rate limit bucket of its own
+    assert_eq!(
+        find(Some(Header::new("Authorization", "Bearer secret"))).status(),
+        Status::TooManyRequests
+    );
+    assert_eq!(
+        find(Some(Header::new("X-Api-Key", "other"))).status(),
+        Status::NotFound
+    );
+    // Reading the timetable needs no key
+    let response = client.get("/harail/stations").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn config_file() {
+    let path = std::env::temp_dir().join(format!("harail-server-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+database = "/var/lib/harail/harail.db"
+port = 8080
+cors-origins = ["https://example.com"]
+realtime-urls = ["https://example.com/tripupdates"]
+search-timeout = 10
+gtfs-all-agencies = true
+gtfs-modes = ["rail", "light_rail"]
+
+[feeds]
+north = "/var/lib/harail/north.db"
+"#,
+    )
+    .unwrap();
+    let config = Config::load(&path).unwrap();
+    assert_eq!(
+        config.database.as_deref(),
+        Some(Path::new("/var/lib/harail/harail.db"))
+    );
+    assert_eq!(config.port, Some(8080));
+    assert_eq!(config.address, None);
+    assert_eq!(config.cors_origins, vec!["https://example.com"]);
+    assert_eq!(config.realtime_interval(), 60);
+    assert_eq!(config.search_timeout(), 10);
+    assert_eq!(config.graph_cache(), 8);
+    assert_eq!(
+        config.feeds.get("north").map(|p| p.as_path()),
+        Some(Path::new("/var/lib/harail/north.db"))
+    );
+    let gtfs = config.gtfs_options().unwrap();
+    assert_eq!(gtfs.agencies(), &AgencyFilter::All);
+    assert!(gtfs.loads_mode(Mode::Rail));
+    assert!(!gtfs.loads_mode(Mode::Bus));
+
+    std::fs::write(&path, "gtfs-modes = [\"train\"]\n").unwrap();
+    assert!(Config::load(&path).unwrap().gtfs_options().is_err());
+
+    std::fs::write(&path, "databse = \"typo.db\"\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+    assert!(Config::load(&path).is_err());
+}
+
+#[test]
+fn localized_errors() {
+    let data = RailroadData::from_stations_trains(stations(), vec![]);
+    let client = Client::tracked(rocket(data, None)).expect("valid rocket instance");
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=999&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"error":"start_station_not_found","message":"start station not found"}"#
+        ))
+    );
+
+    let response = client
+        .get("/harail/routes/find?search=best&start_station=100&start_time=2000-01-01T00:00:00Z&end_station=400&end_time=2000-01-02T00:00:00Z")
+        .header(Header::new("Accept-Language", "en-US;q=0.5, he-IL"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.headers().get_one("Content-Language"), Some("he"));
+    assert_eq!(
+        response.into_string(),
+        Some(String::from(
+            r#"{"error":"no_route_found","message":"לא נמצא מסלול אפשרי"}"#
+        ))
+    );
+
+    let response = client
+        .get("/harail/trains/1/stops/2000-01-01T00:00:00Z")
+        .header(Header::new("Accept-Language", "fr, en;q=0.8"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.headers().get_one("Content-Language"), Some("en"));
 }